@@ -0,0 +1,164 @@
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use crate::convert::{batch_summary, FileConversionResult};
+use crate::history::History;
+
+/// What of a conversion `copy_to_clipboard` copies
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum ClipboardKind {
+    /// The converted files, one after the other
+    Output,
+    /// The summary of the batch, as written for it
+    Summary,
+    /// Errors and warnings of the files, by line. Their messages never quote
+    /// account numbers.
+    Errors,
+}
+
+/// A conversion as it was returned, whose errors the history does not keep
+struct KeptConversion {
+    id: u64,
+    finished_at: NaiveDateTime,
+    results: Vec<FileConversionResult>,
+}
+
+/// The most recent conversion, kept in memory until the next one, so that
+/// all of it can be copied and not only what the history keeps
+#[derive(Default)]
+pub struct LatestConversion {
+    kept: Mutex<Option<KeptConversion>>,
+}
+
+impl LatestConversion {
+    /// Keeps the `results` of the conversion recorded in the history as
+    /// `id`, in place of the one before
+    pub fn keep(&self, id: u64, finished_at: NaiveDateTime, results: Vec<FileConversionResult>) {
+        *self.kept.lock().unwrap_or_else(|e| e.into_inner()) = Some(KeptConversion {
+            id,
+            finished_at,
+            results,
+        });
+    }
+}
+
+fn dollars(cents: u64) -> String {
+    format!("{}.{:0>2}", cents / 100, cents % 100)
+}
+
+/// The converted files of `outputs`, one after the other
+fn read_outputs<'a>(id: u64, outputs: impl IntoIterator<Item = &'a str>) -> Result<String, String> {
+    let mut text = String::new();
+
+    for output in outputs {
+        let contents = std::fs::read_to_string(output)
+            .map_err(|e| format!("Cannot read {} of conversion {}: {}", output, id, e))?;
+
+        text.push_str(&contents);
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+
+    if text.is_empty() {
+        return Err(format!("Conversion {} wrote no files", id));
+    }
+
+    Ok(text)
+}
+
+/// Each error and warning of `results`, on a line of its own
+fn describe_issues(results: &[FileConversionResult]) -> String {
+    let mut text = String::new();
+
+    for result in results {
+        let issues = result
+            .errors
+            .iter()
+            .map(|i| ("error", i))
+            .chain(result.warnings.iter().map(|i| ("warning", i)));
+
+        for (kind, issue) in issues {
+            // Writing to a String cannot fail
+            let _ = writeln!(
+                text,
+                "{}: {}: {}",
+                issue.file.as_deref().unwrap_or(&result.input),
+                kind,
+                issue
+            );
+        }
+    }
+
+    text
+}
+
+/// The text of `kind` of the conversion `id`, as copied by
+/// `copy_to_clipboard`. It is taken from `latest` if that is the conversion,
+/// and otherwise from its entry in `history`, which keeps the files written
+/// and the totals but not the errors.
+pub fn clipboard_text(
+    kind: ClipboardKind,
+    id: u64,
+    latest: &LatestConversion,
+    history: &History,
+    generated_by: &str,
+) -> Result<String, String> {
+    let kept = latest.kept.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some(kept) = kept.as_ref().filter(|k| k.id == id) {
+        return match kind {
+            ClipboardKind::Output => {
+                read_outputs(id, kept.results.iter().filter_map(|r| r.output.as_deref()))
+            }
+            ClipboardKind::Summary => {
+                Ok(batch_summary(&kept.results, kept.finished_at, generated_by).render_text())
+            }
+            ClipboardKind::Errors => Ok(describe_issues(&kept.results)),
+        };
+    }
+
+    let entry = history
+        .find(id)
+        .ok_or_else(|| format!("Conversion {} is no longer in the history", id))?;
+
+    match kind {
+        ClipboardKind::Output => read_outputs(id, entry.outputs.iter().map(String::as_str)),
+        ClipboardKind::Summary => {
+            let mut text = String::new();
+            let totals = &entry.totals;
+
+            let _ = writeln!(text, "Conversion summary");
+            let _ = writeln!(text, "Converted: {}", entry.timestamp);
+            let _ = writeln!(text);
+            let _ = writeln!(
+                text,
+                "Files: {} converted, {} failed",
+                entry.converted, entry.failed
+            );
+            let _ = writeln!(
+                text,
+                "Credits: {} items, {}",
+                totals.credit_count,
+                dollars(totals.credit_amount)
+            );
+            let _ = writeln!(
+                text,
+                "Debits: {} items, {}",
+                totals.debit_count,
+                dollars(totals.debit_amount)
+            );
+            for output in &entry.outputs {
+                let _ = writeln!(text, "Output: {}", output);
+            }
+
+            Ok(text)
+        }
+        ClipboardKind::Errors => Err(format!(
+            "Errors of conversion {} are only kept until the next conversion. Convert it again to see them.",
+            id
+        )),
+    }
+}
@@ -0,0 +1,961 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::csvconv::csv::{
+    build_conversion, convert_merged, convert_reader_to_record, convert_reader_with_progress,
+    read_client_number, Conversion, SourceBreakdown,
+};
+use crate::csvconv::error::ErrorLog;
+use crate::csvconv::header::CPA005Record;
+use crate::csvconv::layout::mask_record_accounts;
+use crate::csvconv::lint::{Severity, ValidationIssue};
+use crate::csvconv::naming::{
+    check_output_name, render_output_name, sanitize_stem, DEFAULT_OUTPUT_NAME,
+};
+use crate::csvconv::options::ConversionOptions;
+use crate::csvconv::parallel::map_in_parallel;
+use crate::csvconv::report::ConversionReport;
+use crate::csvconv::summary::{BatchSummary, SummaryFile};
+use crate::csvconv::types::{PaymentType, RecordType};
+use crate::numbering::BatchNumbering;
+
+/// Stem of the converted file when nothing usable is left of the input's
+const FALLBACK_STEM: &str = "cpa005";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversionStatus {
+    Converted,
+    /// Checked without being written, and would be converted
+    Valid,
+    Failed,
+}
+
+/// Outcome of converting or checking one input file
+#[derive(Clone, Debug, Serialize)]
+pub struct FileConversionResult {
+    /// The CSV file, as given
+    pub input: String,
+    /// `PDS` or `PAD`, the type the file was converted into, or the type as
+    /// given if it is neither
+    pub record_type: String,
+    pub status: ConversionStatus,
+    /// Where the converted file was written, once converted. Files that are
+    /// only checked are never written.
+    pub output: Option<String>,
+    /// Totals of the converted file
+    pub report: Option<ConversionReport>,
+    /// Why the file was not converted, by the line of the CSV file where
+    /// known
+    pub errors: Vec<ValidationIssue>,
+    pub warnings: Vec<ValidationIssue>,
+}
+
+impl FileConversionResult {
+    fn failed(
+        input: &str,
+        record_type: &str,
+        errors: Vec<ValidationIssue>,
+        warnings: Vec<ValidationIssue>,
+    ) -> Self {
+        Self {
+            input: input.to_string(),
+            record_type: record_type.to_string(),
+            status: ConversionStatus::Failed,
+            output: None,
+            report: None,
+            errors,
+            warnings,
+        }
+    }
+}
+
+/// Outcome of converting a batch, with the summary written for it if one
+/// was asked for
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchConversion {
+    /// A result for each file, in the order given
+    pub results: Vec<FileConversionResult>,
+    #[serde(flatten)]
+    pub summary: WrittenSummary,
+    /// Id the batch is recorded as in the history, for its output, summary
+    /// or errors to be copied by, unless it could not be recorded
+    pub conversion_id: Option<u64>,
+}
+
+/// Where the summary of a batch was written
+#[derive(Clone, Debug, Serialize)]
+pub struct SummaryPaths {
+    pub text: String,
+    pub json: String,
+}
+
+/// The summary written for a batch, if one was asked for
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct WrittenSummary {
+    /// Paths of the summary written into the output directory
+    pub summary: Option<SummaryPaths>,
+    /// Why the summary could not be written, in which case the files are
+    /// converted all the same
+    pub summary_error: Option<String>,
+}
+
+/// Outcome of merging several CSV files into one converted file
+#[derive(Clone, Debug, Serialize)]
+pub struct MergeResult {
+    /// The converted file, whose `input` lists the CSV files merged into it
+    #[serde(flatten)]
+    pub result: FileConversionResult,
+    /// Payment counts and totals of each CSV file, in the order given, once
+    /// converted
+    pub sources: Vec<SourceBreakdown>,
+    #[serde(flatten)]
+    pub summary: WrittenSummary,
+}
+
+/// Settings of one file of a batch that differ from those of the batch
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileOptions {
+    /// Report validation failures as warnings instead of errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lenient: Option<bool>,
+    /// Reject amounts with cents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whole_dollars_only: Option<bool>,
+}
+
+/// A file of a batch to convert, which may also be given as just its path
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "FileRequestInput")]
+pub struct FileRequest {
+    /// The CSV file
+    pub path: String,
+    /// `PDS` or `PAD`, the record type of the batch if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<FileOptions>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FileRequestInput {
+    Path(String),
+    File {
+        path: String,
+        #[serde(default)]
+        record_type: Option<String>,
+        #[serde(default)]
+        options: Option<FileOptions>,
+    },
+}
+
+impl From<FileRequestInput> for FileRequest {
+    fn from(input: FileRequestInput) -> Self {
+        match input {
+            FileRequestInput::Path(path) => Self::new(&path),
+            FileRequestInput::File {
+                path,
+                record_type,
+                options,
+            } => Self {
+                path,
+                record_type,
+                options,
+            },
+        }
+    }
+}
+
+impl FileRequest {
+    /// `path`, converted as the rest of its batch is
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            record_type: None,
+            options: None,
+        }
+    }
+
+    /// The options of the batch, with those the file sets in their place
+    fn conversion_options(&self, batch: &ConversionOptions) -> ConversionOptions {
+        let mut options = batch.clone();
+
+        if let Some(file) = &self.options {
+            if let Some(lenient) = file.lenient {
+                options.validation.lenient = lenient;
+            }
+            if let Some(whole_dollars_only) = file.whole_dollars_only {
+                options.whole_dollars_only = whole_dollars_only;
+            }
+        }
+
+        options
+    }
+}
+
+/// The summary of a batch that converted into `results`, generated at
+/// `generated_at` by `generated_by`
+pub fn batch_summary(
+    results: &[FileConversionResult],
+    generated_at: NaiveDateTime,
+    generated_by: &str,
+) -> BatchSummary {
+    let issues = |issues: &[ValidationIssue]| issues.iter().map(|i| i.to_string()).collect();
+
+    let files = results
+        .iter()
+        .map(|r| SummaryFile {
+            input: r.input.clone(),
+            record_type: r.record_type.clone(),
+            output: r.output.clone(),
+            report: r.report.clone(),
+            errors: issues(&r.errors),
+            warnings: issues(&r.warnings),
+        })
+        .collect();
+
+    BatchSummary::new(generated_at, generated_by, files)
+}
+
+/// Writes the summary of a batch that converted into `results` into
+/// `output_directory`, as text and as JSON
+pub fn write_batch_summary(
+    results: &[FileConversionResult],
+    output_directory: &str,
+    generated_at: NaiveDateTime,
+    generated_by: &str,
+) -> Result<SummaryPaths, String> {
+    let (text, json) =
+        batch_summary(results, generated_at, generated_by).write(Path::new(output_directory))?;
+
+    Ok(SummaryPaths {
+        text: text.to_string_lossy().to_string(),
+        json: json.to_string_lossy().to_string(),
+    })
+}
+
+impl WrittenSummary {
+    /// Writes the summary of a batch as [`write_batch_summary`] does, keeping
+    /// why it could not be written rather than failing the batch
+    pub fn write(
+        results: &[FileConversionResult],
+        output_directory: &str,
+        generated_at: NaiveDateTime,
+        generated_by: &str,
+    ) -> Self {
+        match write_batch_summary(results, output_directory, generated_at, generated_by) {
+            Ok(paths) => Self {
+                summary: Some(paths),
+                summary_error: None,
+            },
+            Err(e) => Self {
+                summary: None,
+                summary_error: Some(e),
+            },
+        }
+    }
+}
+
+/// Asks where a batch of converted files is to be written
+pub trait DirectoryPicker {
+    /// The directory chosen, starting from `suggested` if given, or none if
+    /// the choice was cancelled
+    fn pick_directory(&self, suggested: Option<&str>) -> Option<String>;
+}
+
+/// Outcome of converting a batch into a directory chosen for it
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum ChosenConversion {
+    /// No directory was chosen, and nothing was converted
+    Cancelled,
+    /// The batch was converted into the `output_directory` chosen, with a
+    /// result for each file
+    Chosen {
+        output_directory: String,
+        results: Vec<FileConversionResult>,
+        #[serde(flatten)]
+        summary: WrittenSummary,
+    },
+}
+
+/// Progress of converting a batch of files, reported as each file is
+/// started, as its rows are read and once it is done. `index` counts the
+/// files of the batch from 0.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ConversionProgress {
+    Started {
+        index: usize,
+        input: String,
+    },
+    /// `rows` of the file have been read so far
+    Rows {
+        index: usize,
+        input: String,
+        rows: usize,
+    },
+    Finished {
+        index: usize,
+        result: Box<FileConversionResult>,
+    },
+}
+
+/// A record of a previewed file
+#[derive(Clone, Debug, Serialize)]
+pub struct PreviewRecord {
+    /// `A` for the header, `C` or `D` for a detail record and `Z` for the
+    /// trailer
+    pub record_type: char,
+    pub text: String,
+}
+
+/// A file as it would be converted, without writing it
+#[derive(Clone, Debug, Serialize)]
+pub struct ConversionPreview {
+    pub input: String,
+    pub status: ConversionStatus,
+    /// Totals of the converted file, which its trailer record states
+    pub report: Option<ConversionReport>,
+    pub errors: Vec<ValidationIssue>,
+    pub warnings: Vec<ValidationIssue>,
+    /// The first records of the converted file
+    pub records: Vec<PreviewRecord>,
+    /// Records of the converted file in all, which may be more than are
+    /// previewed
+    pub record_count: usize,
+}
+
+/// An error that is not about any one line of the input
+fn issue(message: String) -> ValidationIssue {
+    ValidationIssue {
+        file: None,
+        line: None,
+        field: None,
+        severity: Severity::Error,
+        message,
+    }
+}
+
+/// The errors and warnings of `log`, apart
+fn split_log(log: &ErrorLog) -> (Vec<ValidationIssue>, Vec<ValidationIssue>) {
+    ValidationIssue::from_log(None, log)
+        .into_iter()
+        .partition(|i| i.severity == Severity::Error)
+}
+
+fn parse_record_type(record_type: &str) -> Result<RecordType, String> {
+    record_type
+        .parse::<PaymentType>()
+        .map(|t| t.record_type())
+        .map_err(|e| format!("Record type {}", e))
+}
+
+/// Converted files are only written once a directory is chosen for them,
+/// rather than wherever the app happens to run from
+fn check_output_directory(output_directory: &str) -> Result<(), String> {
+    if output_directory.trim().is_empty() {
+        return Err("Choose an output directory for the converted files".to_string());
+    }
+
+    Ok(())
+}
+
+fn create_output_directory(directory: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(directory).map_err(|e| {
+        format!(
+            "Cannot create output directory {}: {}. Choose another output directory.",
+            directory.display(),
+            e
+        )
+    })
+}
+
+/// Checks that files can be written to `output_directory`, creating it if
+/// need be, so that a batch fails before any of it is converted rather than
+/// part way through
+fn check_writable(output_directory: &str) -> Result<(), String> {
+    let directory = Path::new(output_directory);
+    create_output_directory(directory)?;
+
+    let probe = directory.join(format!(".rbc-write-check-{}", std::process::id()));
+    File::create(&probe).map_err(|e| {
+        format!(
+            "Cannot write to output directory {}: {}. Choose another output directory.",
+            directory.display(),
+            e
+        )
+    })?;
+
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Number of the next output written aside, so that files of one batch
+/// written at once to the same path never share a partial file
+static PARTIAL_WRITES: AtomicUsize = AtomicUsize::new(0);
+
+/// Where the output `path` is written before it is renamed into place,
+/// beside it so that the rename never crosses file systems
+fn partial_output(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let write = PARTIAL_WRITES.fetch_add(1, Ordering::Relaxed);
+
+    path.with_file_name(format!(
+        ".{}.{}.{}.partial",
+        name,
+        std::process::id(),
+        write
+    ))
+}
+
+/// Writes `contents` to `path`, creating the directories leading to it if
+/// they do not exist yet. They are written aside, flushed to disk and then
+/// renamed into place, so that a full disk or a crash never leaves a
+/// truncated payment file behind, nor spoils the file it replaces.
+pub fn write_output(path: &Path, contents: &str) -> Result<(), String> {
+    let directory = path.parent().filter(|d| !d.as_os_str().is_empty());
+    if let Some(directory) = directory {
+        create_output_directory(directory)?;
+    }
+
+    let partial = partial_output(path);
+    let written = File::create(&partial)
+        .and_then(|mut f| {
+            f.write_all(contents.as_bytes())?;
+            f.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&partial, path));
+
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&partial);
+        return Err(format!(
+            "Cannot write output file {}: {}",
+            path.display(),
+            e
+        ));
+    }
+
+    // The rename itself is only lasting once the directory is flushed too,
+    // which not every platform allows
+    #[cfg(unix)]
+    if let Some(directory) = directory {
+        let _ = File::open(directory).and_then(|d| d.sync_all());
+    }
+
+    Ok(())
+}
+
+/// Checks that each CSV file of `inputs` would convert into a `record_type`
+/// (`PDS` or `PAD`) file as [`convert_files_numbered`] would, with its
+/// totals, errors and warnings, but without writing anything
+pub fn validate_files(
+    inputs: &[&str],
+    record_type: &str,
+    options: &ConversionOptions,
+) -> Vec<FileConversionResult> {
+    let parsed = parse_record_type(record_type);
+
+    inputs
+        .iter()
+        .map(|input| {
+            let conversion = match &parsed {
+                Ok(parsed) => convert_input(input, *parsed, options, &|_| ()),
+                Err(e) => Err((vec![issue(e.clone())], Vec::new())),
+            };
+
+            match conversion {
+                Ok(c) => FileConversionResult {
+                    input: input.to_string(),
+                    record_type: record_type.to_string(),
+                    status: ConversionStatus::Valid,
+                    output: None,
+                    report: Some(c.report),
+                    errors: Vec::new(),
+                    warnings: split_log(&c.log).1,
+                },
+                Err((errors, warnings)) => {
+                    FileConversionResult::failed(input, record_type, errors, warnings)
+                }
+            }
+        })
+        .collect()
+}
+
+/// A file of a batch once converted in memory
+enum Converted {
+    /// Written, or failed
+    Finished(FileConversionResult),
+    /// Converted but not yet written, waiting for the file creation number
+    /// of its client
+    Unnumbered(CPA005Record, ErrorLog),
+}
+
+/// Converts each file of `files` into a `record_type` (`PDS` or `PAD`)
+/// file in `output_directory`, named after `output_name`, with the record
+/// type and options of each file where it sets them. A file failing, or of
+/// an unknown record type, does not stop the others being converted. The
+/// progress of the batch is passed to `progress` as it goes.
+///
+/// Up to `jobs` files are converted at once, giving each file the file
+/// creation number `numbering` has for its client if given, or that of
+/// `options` otherwise. The files are converted in memory all at once, but
+/// numbered and written one after the other in the order given, so that
+/// each batch numbers its files the same way. A number is only used up once
+/// its file is written.
+#[allow(clippy::too_many_arguments)]
+pub fn convert_files_numbered(
+    files: &[FileRequest],
+    record_type: &str,
+    output_directory: &str,
+    output_name: Option<&str>,
+    options: &ConversionOptions,
+    jobs: usize,
+    mut numbering: Option<&mut BatchNumbering>,
+    progress: &(dyn Fn(ConversionProgress) + Sync),
+) -> Vec<FileConversionResult> {
+    let output_name = output_name.unwrap_or(DEFAULT_OUTPUT_NAME);
+    let numbered = numbering.is_some();
+
+    let checked = check_output_directory(output_directory)
+        .and_then(|_| check_output_name(output_name))
+        .and_then(|_| check_writable(output_directory));
+
+    let finished = |index: usize, result: FileConversionResult| {
+        progress(ConversionProgress::Finished {
+            index,
+            result: Box::new(result.clone()),
+        });
+        result
+    };
+
+    let converted = map_in_parallel(files, jobs, |index, file| {
+        let input = file.path.as_str();
+        let record_type = file.record_type.as_deref().unwrap_or(record_type);
+
+        progress(ConversionProgress::Started {
+            index,
+            input: input.to_string(),
+        });
+
+        let rows = |rows| {
+            progress(ConversionProgress::Rows {
+                index,
+                input: input.to_string(),
+                rows,
+            })
+        };
+
+        let parsed = match checked.clone().and_then(|_| parse_record_type(record_type)) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let result =
+                    FileConversionResult::failed(input, record_type, vec![issue(e)], Vec::new());
+                return Converted::Finished(finished(index, result));
+            }
+        };
+
+        let options = file.conversion_options(options);
+
+        if !numbered {
+            let result = convert_file(
+                input,
+                (record_type, parsed),
+                output_directory,
+                output_name,
+                &options,
+                &rows,
+            );
+            return Converted::Finished(finished(index, result));
+        }
+
+        match convert_input_to_record(input, parsed, &options, &rows) {
+            Ok((record, log)) => Converted::Unnumbered(record, log),
+            Err((errors, warnings)) => {
+                let result = FileConversionResult::failed(input, record_type, errors, warnings);
+                Converted::Finished(finished(index, result))
+            }
+        }
+    });
+
+    converted
+        .into_iter()
+        .zip(files)
+        .enumerate()
+        .map(|(index, (converted, file))| match converted {
+            Converted::Finished(result) => result,
+            Converted::Unnumbered(record, log) => {
+                let record_type = file.record_type.as_deref().unwrap_or(record_type);
+                let result = write_numbered_file(
+                    &file.path,
+                    record_type,
+                    record,
+                    log,
+                    output_directory,
+                    output_name,
+                    &file.conversion_options(options),
+                    numbering.as_deref_mut().expect("only numbered files wait"),
+                );
+
+                finished(index, result)
+            }
+        })
+        .collect()
+}
+
+/// Converts like [`convert_files_numbered`] into the directory `picker`
+/// chooses, once for the whole batch. Nothing is converted if the choice is
+/// cancelled, and every file fails if the directory cannot be written to.
+#[allow(clippy::too_many_arguments)]
+pub fn convert_files_to_chosen_directory(
+    files: &[FileRequest],
+    record_type: &str,
+    output_name: Option<&str>,
+    options: &ConversionOptions,
+    jobs: usize,
+    numbering: Option<&mut BatchNumbering>,
+    picker: &dyn DirectoryPicker,
+    suggested: Option<&str>,
+    progress: &(dyn Fn(ConversionProgress) + Sync),
+) -> ChosenConversion {
+    let output_directory = match picker.pick_directory(suggested) {
+        Some(directory) => directory,
+        None => return ChosenConversion::Cancelled,
+    };
+
+    let results = convert_files_numbered(
+        files,
+        record_type,
+        &output_directory,
+        output_name,
+        options,
+        jobs,
+        numbering,
+        progress,
+    );
+
+    ChosenConversion::Chosen {
+        output_directory,
+        results,
+        summary: WrittenSummary::default(),
+    }
+}
+
+/// Errors and then warnings of a file that was not converted
+type Failure = (Vec<ValidationIssue>, Vec<ValidationIssue>);
+
+/// Converts the CSV file `input` in memory
+fn convert_input(
+    input: &str,
+    record_type: RecordType,
+    options: &ConversionOptions,
+    progress: &dyn Fn(usize),
+) -> Result<Conversion, Failure> {
+    let file = File::open(input).map_err(|e| {
+        (
+            vec![issue(format!("Cannot read {}: {}", input, e))],
+            Vec::new(),
+        )
+    })?;
+
+    convert_reader_with_progress(BufReader::new(file), record_type, options, progress)
+        .map_err(|log| split_log(&log))
+}
+
+/// Converts the CSV file `input` in memory like [`convert_input`], without
+/// building the file of the record
+fn convert_input_to_record(
+    input: &str,
+    record_type: RecordType,
+    options: &ConversionOptions,
+    progress: &dyn Fn(usize),
+) -> Result<(CPA005Record, ErrorLog), Failure> {
+    let file = File::open(input).map_err(|e| {
+        (
+            vec![issue(format!("Cannot read {}: {}", input, e))],
+            Vec::new(),
+        )
+    })?;
+
+    convert_reader_to_record(BufReader::new(file), record_type, options, progress)
+        .map_err(|log| split_log(&log))
+}
+
+fn convert_file(
+    input: &str,
+    (record_type, parsed): (&str, RecordType),
+    output_directory: &str,
+    output_name: &str,
+    options: &ConversionOptions,
+    progress: &dyn Fn(usize),
+) -> FileConversionResult {
+    match convert_input(input, parsed, options, progress) {
+        Ok(c) => write_conversion(input, record_type, output_directory, output_name, c),
+        Err((errors, warnings)) => {
+            FileConversionResult::failed(input, record_type, errors, warnings)
+        }
+    }
+}
+
+/// Writes the converted file `c` of `input` into `output_directory`, named
+/// after `output_name`
+fn write_conversion(
+    input: &str,
+    record_type: &str,
+    output_directory: &str,
+    output_name: &str,
+    c: Conversion,
+) -> FileConversionResult {
+    let (_, warnings) = split_log(&c.log);
+
+    // Named after the input file, never the converted contents
+    let stem = sanitize_stem(input).unwrap_or_else(|| FALLBACK_STEM.to_string());
+    let output = Path::new(output_directory).join(render_output_name(
+        output_name,
+        &stem,
+        c.report.file_creation_number,
+    ));
+
+    if let Err(e) = write_output(&output, &c.output) {
+        return FileConversionResult::failed(input, record_type, vec![issue(e)], warnings);
+    }
+
+    FileConversionResult {
+        input: input.to_string(),
+        record_type: record_type.to_string(),
+        status: ConversionStatus::Converted,
+        output: Some(output.to_string_lossy().to_string()),
+        report: Some(c.report),
+        errors: Vec::new(),
+        warnings,
+    }
+}
+
+/// Writes the converted `record` of `input` like [`write_conversion`], with
+/// the file creation number `numbering` has for its client. The numbers are
+/// held until the file is written, so that no other conversion is given the
+/// same one, and the number is only used up if it is.
+#[allow(clippy::too_many_arguments)]
+fn write_numbered_file(
+    input: &str,
+    record_type: &str,
+    mut record: CPA005Record,
+    log: ErrorLog,
+    output_directory: &str,
+    output_name: &str,
+    options: &ConversionOptions,
+    numbering: &mut BatchNumbering,
+) -> FileConversionResult {
+    let mut numbers = match numbering.store().lock() {
+        Ok(numbers) => numbers,
+        Err(e) => {
+            let (_, warnings) = split_log(&log);
+            return FileConversionResult::failed(input, record_type, vec![issue(e)], warnings);
+        }
+    };
+
+    let client_number = record.client_number.clone();
+    let number = numbering.number_for(&numbers, &client_number);
+    record.set_file_creation_number(number);
+
+    let c = build_conversion(&record, options, log);
+    let mut result = write_conversion(input, record_type, output_directory, output_name, c);
+
+    if result.status == ConversionStatus::Converted {
+        if let Err(e) = numbering.used(&mut numbers, &client_number, number) {
+            result.warnings.push(ValidationIssue {
+                severity: Severity::Warning,
+                ..issue(e)
+            });
+        }
+    }
+
+    result
+}
+
+/// Merges the CSV files of `inputs` into a single `record_type` (`PDS` or
+/// `PAD`) file in `output_directory`, named after `output_name` with the
+/// stem of the first file followed by `_merged`. The files must agree on
+/// their client number, processing centre, currency and payment date.
+///
+/// The file is given the file creation number `numbering` has for its client
+/// if given, or that of `options` otherwise, which is only used up once the
+/// file is written. Nothing is written if any of the files fails.
+pub fn merge_files(
+    inputs: &[&str],
+    record_type: &str,
+    output_directory: &str,
+    output_name: Option<&str>,
+    options: &ConversionOptions,
+    numbering: Option<&mut BatchNumbering>,
+) -> MergeResult {
+    let input = inputs.join(", ");
+    let failed = |errors, warnings| MergeResult {
+        result: FileConversionResult::failed(&input, record_type, errors, warnings),
+        sources: Vec::new(),
+        summary: WrittenSummary::default(),
+    };
+
+    let output_name = output_name.unwrap_or(DEFAULT_OUTPUT_NAME);
+    let parsed = check_output_directory(output_directory)
+        .and_then(|_| check_output_name(output_name))
+        .and_then(|_| check_writable(output_directory))
+        .and_then(|_| parse_record_type(record_type));
+
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => return failed(vec![issue(e)], Vec::new()),
+    };
+
+    let mut contents = Vec::with_capacity(inputs.len());
+    let mut unreadable = Vec::new();
+
+    for input in inputs {
+        match std::fs::read_to_string(input) {
+            Ok(csv) => contents.push(csv),
+            Err(e) => unreadable.push(issue(format!("Cannot read {}: {}", input, e))),
+        }
+    }
+
+    if !unreadable.is_empty() {
+        return failed(unreadable, Vec::new());
+    }
+
+    let client_number = contents
+        .first()
+        .and_then(|csv| read_client_number(csv.as_bytes(), options));
+
+    // Held until the file is written, as for a file converted alone
+    let mut numbered = match (numbering, client_number) {
+        (Some(numbering), Some(client_number)) => match numbering.store().lock() {
+            Ok(numbers) => Some((numbering, numbers, client_number)),
+            Err(e) => return failed(vec![issue(e)], Vec::new()),
+        },
+        _ => None,
+    };
+
+    let options = match &numbered {
+        Some((numbering, numbers, client_number)) => ConversionOptions {
+            starting_creation_number: numbering.number_for(numbers, client_number),
+            ..options.clone()
+        },
+        None => options.clone(),
+    };
+
+    let sources: Vec<(&str, &str)> = inputs
+        .iter()
+        .zip(&contents)
+        .map(|(input, csv)| (*input, csv.as_str()))
+        .collect();
+
+    let merged = match convert_merged(&sources, parsed, &options) {
+        Ok(merged) => merged,
+        Err(issues) => {
+            let (errors, warnings) = issues
+                .into_iter()
+                .partition(|i| i.severity == Severity::Error);
+            return failed(errors, warnings);
+        }
+    };
+
+    let stem = inputs
+        .first()
+        .and_then(|input| sanitize_stem(input))
+        .unwrap_or_else(|| FALLBACK_STEM.to_string());
+    let output = Path::new(output_directory).join(render_output_name(
+        output_name,
+        &format!("{}_merged", stem),
+        merged.report.file_creation_number,
+    ));
+
+    let mut warnings = merged.warnings;
+
+    if let Err(e) = write_output(&output, &merged.output) {
+        return failed(vec![issue(e)], warnings);
+    }
+
+    if let Some((numbering, numbers, client_number)) = numbered.as_mut() {
+        let number = merged.report.file_creation_number;
+
+        if let Err(e) = numbering.used(numbers, client_number, number) {
+            warnings.push(ValidationIssue {
+                severity: Severity::Warning,
+                ..issue(e)
+            });
+        }
+    }
+
+    MergeResult {
+        result: FileConversionResult {
+            input,
+            record_type: record_type.to_string(),
+            status: ConversionStatus::Converted,
+            output: Some(output.to_string_lossy().to_string()),
+            report: Some(merged.report),
+            errors: Vec::new(),
+            warnings,
+        },
+        sources: merged.sources,
+        summary: WrittenSummary::default(),
+    }
+}
+
+/// Converts the CSV file `input` into a `record_type` (`PDS` or `PAD`) file
+/// as [`convert_files_numbered`] would, but only in memory, returning at most
+/// `max_records` of its records, their account numbers masked. Nothing is
+/// written.
+pub fn preview_conversion(
+    input: &str,
+    record_type: &str,
+    max_records: usize,
+    options: &ConversionOptions,
+) -> ConversionPreview {
+    let conversion = parse_record_type(record_type)
+        .map_err(|e| (vec![issue(e)], Vec::new()))
+        .and_then(|t| convert_input(input, t, options, &|_| ()));
+
+    let c = match conversion {
+        Ok(c) => c,
+        Err((errors, warnings)) => {
+            return ConversionPreview {
+                input: input.to_string(),
+                status: ConversionStatus::Failed,
+                report: None,
+                errors,
+                warnings,
+                records: Vec::new(),
+                record_count: 0,
+            }
+        }
+    };
+
+    let (_, warnings) = split_log(&c.log);
+    let records = c
+        .output
+        .lines()
+        .take(max_records)
+        .map(|line| PreviewRecord {
+            record_type: line.chars().next().unwrap_or(' '),
+            text: mask_record_accounts(line),
+        })
+        .collect();
+
+    ConversionPreview {
+        input: input.to_string(),
+        status: ConversionStatus::Converted,
+        report: Some(c.report),
+        errors: Vec::new(),
+        warnings,
+        records,
+        record_count: c.output.lines().count(),
+    }
+}
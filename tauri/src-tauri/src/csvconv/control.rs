@@ -0,0 +1,123 @@
+use super::error::ErrorLog;
+use std::io::{self, Read};
+
+/// A control character found in the input
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlCharacter {
+    /// Line of the input, counting from 1
+    pub line: u64,
+    /// Character of the line it is, counting from 1
+    pub column: u64,
+    pub character: char,
+}
+
+impl ControlCharacter {
+    fn name(&self) -> Option<&'static str> {
+        match self.character {
+            '\0' => Some("null"),
+            '\u{8}' => Some("backspace"),
+            '\u{b}' => Some("vertical tab"),
+            '\u{c}' => Some("form feed"),
+            '\u{1b}' => Some("escape"),
+            '\u{7f}' => Some("delete"),
+            _ => None,
+        }
+    }
+}
+
+/// Passes on what is read from another reader, noting the control
+/// characters in it, such as the form feeds and vertical tabs a bad copy and
+/// paste leaves behind. They would otherwise be written into the fixed-width
+/// fields of the converted file. Tabs and line breaks are allowed.
+pub struct ControlCharacterScan<R> {
+    inner: R,
+    line: u64,
+    column: u64,
+    /// Whether the last byte read starts a character of U+0080 to U+00BF,
+    /// which include the second set of control characters
+    after_lead: bool,
+    found: Vec<ControlCharacter>,
+}
+
+impl<R: Read> ControlCharacterScan<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: 1,
+            column: 0,
+            after_lead: false,
+            found: Vec::new(),
+        }
+    }
+
+    /// An error on its line for each control character read so far
+    pub fn log(&self) -> ErrorLog {
+        let mut log = ErrorLog::new();
+
+        for found in &self.found {
+            let code = format!("U+{:04X}", found.character as u32);
+            let character = match found.name() {
+                Some(name) => format!("{} ({})", code, name),
+                None => code,
+            };
+
+            log.write_error_at(
+                found.line,
+                format!(
+                    "Control character {} at column {}. Remove it from the file, it may have been pasted in with the text.",
+                    character, found.column
+                )
+                .as_str(),
+            );
+        }
+
+        log
+    }
+
+    fn scan(&mut self, byte: u8) {
+        let after_lead = std::mem::replace(&mut self.after_lead, false);
+
+        match byte {
+            b'\n' => {
+                self.line += 1;
+                self.column = 0;
+                return;
+            }
+            // Continuing a character, which is not counted again
+            0x80..=0xbf => {
+                if after_lead && byte < 0xa0 {
+                    self.note(char::from(byte));
+                }
+                return;
+            }
+            _ => self.column += 1,
+        }
+
+        match byte {
+            b'\t' | b'\r' => (),
+            0x00..=0x1f | 0x7f => self.note(char::from(byte)),
+            0xc2 => self.after_lead = true,
+            _ => (),
+        }
+    }
+
+    fn note(&mut self, character: char) {
+        self.found.push(ControlCharacter {
+            line: self.line,
+            column: self.column,
+            character,
+        });
+    }
+}
+
+impl<R: Read> Read for ControlCharacterScan<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        for &byte in &buf[..read] {
+            self.scan(byte);
+        }
+
+        Ok(read)
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use super::csv::{parse_dollar_amount_to_cents, payment_rows, PaymentRow};
+use super::options::AmountFormat;
+use super::parse::{parse_cpa005, ParsedFile};
+
+/// How the payment rows of a CSV file were split between a credit and a
+/// debit file converted from it
+#[derive(Clone, Debug, Default)]
+pub struct SplitCoverage {
+    /// Rows found in neither file
+    pub missing: Vec<PaymentRow>,
+    /// Rows found in both files
+    pub in_both: Vec<PaymentRow>,
+}
+
+impl SplitCoverage {
+    /// Whether every row ended up in exactly one of the files
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.in_both.is_empty()
+    }
+}
+
+/// What a payment is matched by: the customer, the account without the zeros
+/// it may be padded with, and the amount in cents
+type PaymentKey = (String, String, Option<u64>);
+
+fn key(customer_number: &str, account: &str, amount: Option<u64>) -> PaymentKey {
+    (
+        customer_number.trim().to_string(),
+        account.trim().trim_start_matches('0').to_string(),
+        amount,
+    )
+}
+
+/// The number of payments of `file` by what they are matched by
+fn payment_counts(file: &ParsedFile) -> HashMap<PaymentKey, usize> {
+    let mut counts = HashMap::new();
+
+    for segment in file.details.iter().flat_map(|d| &d.segments) {
+        *counts
+            .entry(key(
+                &segment.customer_number,
+                &segment.account_number,
+                Some(segment.amount),
+            ))
+            .or_insert(0) += 1;
+    }
+
+    counts
+}
+
+/// Takes one payment matching `key` from `counts`, if any is left
+fn take(counts: &mut HashMap<PaymentKey, usize>, key: &PaymentKey) -> bool {
+    match counts.get_mut(key) {
+        Some(count) if *count > 0 => {
+            *count -= 1;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Checks that each payment row of `csv` that is converted ended up in
+/// exactly one of `credits` and `debits`, CPA-005 files converted from it,
+/// such as when some rows of one spreadsheet are paid out and others
+/// collected. Rows are matched to payments by customer number, account and
+/// amount, read with `amount_format`.
+pub fn check_split_coverage(
+    csv: &str,
+    credits: &str,
+    debits: &str,
+    amount_format: &AmountFormat,
+) -> Result<SplitCoverage, Vec<String>> {
+    let rows = payment_rows(csv.as_bytes()).map_err(|log| log.get_error_list())?;
+
+    let parse = |name: &str, file: &str| -> Result<ParsedFile, Vec<String>> {
+        parse_cpa005(file)
+            .map_err(|errors| errors.iter().map(|e| format!("{}: {}", name, e)).collect())
+    };
+    let mut credits = payment_counts(&parse("Credit file", credits)?);
+    let mut debits = payment_counts(&parse("Debit file", debits)?);
+
+    let mut coverage = SplitCoverage::default();
+
+    for row in rows {
+        let amount = parse_dollar_amount_to_cents(&row.amount, amount_format);
+        let key = key(&row.customer_number, &row.account, amount);
+
+        match (take(&mut credits, &key), take(&mut debits, &key)) {
+            (false, false) => coverage.missing.push(row),
+            (true, true) => coverage.in_both.push(row),
+            _ => (),
+        }
+    }
+
+    Ok(coverage)
+}
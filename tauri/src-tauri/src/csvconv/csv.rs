@@ -1,14 +1,37 @@
+use super::control::ControlCharacterScan;
 use super::error::ErrorLog;
-use super::header::CPA005Record;
-use super::payment::{BasicPayment, BasicPaymentSegment};
+use super::header::{CPA005Record, MAX_FILE_CREATION_NUMBER};
+use super::lint::{Severity, ValidationIssue};
+use super::options::{AmountFormat, BlankAmount, ConversionOptions, HeaderDefault};
+use super::payment::{
+    BasicPayment, BasicPaymentSegment, MAX_AMOUNT, MAX_NAME_LEN, MAX_SHORT_NAME_LEN,
+};
+use super::report::ConversionReport;
+use super::schema::{
+    self, CLIENT_NAME, CLIENT_NUMBER, COLUMN_COUNT, CURRENCY_CODE, HEADER_LABELS, PAYMENT_DATE,
+    PAYMENT_DATE_FORMAT, PROCESSING_CENTRE, REQUIRED_COLUMN_COUNT, TRANSACTION_CODE,
+};
 use super::types::{CurrencyType, ProcessingCentre, RecordType};
-use chrono::{Datelike, NaiveDate};
+use super::utils::{strip_client_number_separators, to_cpa_date, truncate_field};
+use super::validate::ValidationOptions;
+use chrono::{Local, NaiveDate};
 use csv::{Reader, ReaderBuilder, StringRecord};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
 
-fn validate_csv_header<'a>(
-    rdr: &'a mut Reader<&[u8]>,
+/// Payment rows read between calls of the callback of
+/// [`convert_reader_with_progress`]
+pub const PROGRESS_INTERVAL: usize = 100;
+
+/// Spreadsheet programs on Windows start CSV exports with a byte order mark,
+/// which ends up ahead of the first label
+const BYTE_ORDER_MARK: char = '\u{feff}';
+
+fn validate_csv_header<R: Read>(
+    rdr: &mut Reader<R>,
     header_name: &str,
+    defaults: &HashMap<&'static str, HeaderDefault>,
 ) -> Result<String, String> {
     let mut record = StringRecord::new();
 
@@ -26,7 +49,7 @@ fn validate_csv_header<'a>(
 
     match header {
         Some(s) => {
-            if s.trim() != header_name {
+            if s.trim_start_matches(BYTE_ORDER_MARK).trim() != header_name {
                 return Err(format!(
                     "Expected header {}, got {} instead\n",
                     header_name, s
@@ -41,10 +64,86 @@ fn validate_csv_header<'a>(
 
     let value = record.get(1);
 
-    match value {
-        Some(s) => return Ok(s.to_string()),
-        None => return Err(format!("Expected value for header {}\n", header_name)),
+    match (value, defaults.get(header_name)) {
+        (value, Some(default)) => header_value(header_name, value.unwrap_or_default(), default),
+        (Some(s), None) => Ok(s.to_string()),
+        (None, None) => Err(format!("Expected value for header {}\n", header_name)),
+    }
+}
+
+/// The value of the row labelled `label` that `default` is supplied for,
+/// given the file's own `value` for it
+fn header_value(label: &str, value: &str, default: &HeaderDefault) -> Result<String, String> {
+    let value = value.trim();
+
+    if value.is_empty() {
+        return Ok(default.value.clone());
+    }
+
+    let same = match label {
+        PROCESSING_CENTRE => format!("{:0>5}", value) == format!("{:0>5}", default.value),
+        CURRENCY_CODE => value.eq_ignore_ascii_case(&default.value),
+        CLIENT_NUMBER => {
+            strip_client_number_separators(value) == strip_client_number_separators(&default.value)
+        }
+        _ => value == default.value,
+    };
+
+    if default.overridable || same {
+        return Ok(value.to_string());
+    }
+
+    Err(format!(
+        "{} is fixed to {} for this client and cannot be changed to {}",
+        label, default.value, value
+    ))
+}
+
+/// Checks that the labelled rows at the top of the CSV data read from
+/// `reader` give no value that `options` fixes to another, so that such
+/// files can be refused before converting them
+pub fn check_header_overrides<R: Read>(
+    reader: R,
+    options: &ConversionOptions,
+) -> Result<(), String> {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+
+    for rec in rdr.records().take(HEADER_LABELS.len()) {
+        let rec = match rec {
+            Ok(rec) => rec,
+            Err(_) => break,
+        };
+
+        let label = rec
+            .get(0)
+            .unwrap_or_default()
+            .trim_start_matches(BYTE_ORDER_MARK)
+            .trim();
+
+        if let Some(default) = options.header_defaults.get(label) {
+            header_value(label, rec.get(1).unwrap_or_default(), default)?;
+        }
     }
+
+    Ok(())
+}
+
+/// The client number of the CSV data read from `reader`, without the
+/// separators grouping its digits, as converting it with `options` would
+/// read it. `None` if the labelled rows leading up to it cannot be read.
+pub fn read_client_number<R: Read>(reader: R, options: &ConversionOptions) -> Option<String> {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+
+    validate_csv_header(&mut rdr, CLIENT_NAME, &options.header_defaults).ok()?;
+    validate_csv_header(&mut rdr, CLIENT_NUMBER, &options.header_defaults)
+        .ok()
+        .map(|number| strip_client_number_separators(number.trim()))
 }
 
 #[derive(Debug)]
@@ -70,28 +169,127 @@ impl CSVHeader {
     }
 }
 
-fn parse_dollar_amount_to_cents(amount: &String) -> Option<u64> {
+pub fn parse_dollar_amount_to_cents(amount: &str, format: &AmountFormat) -> Option<u64> {
+    parse_dollar_amount_with_direction(amount, format)
+        .ok()
+        .map(|(cents, _)| cents)
+}
+
+/// Why a dollar amount could not be read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AmountError {
+    /// Not a number of dollars, in the format given
+    Malformed,
+    /// More than the [`MAX_AMOUNT`] cents a record can hold
+    TooLarge,
+}
+
+/// Suffixes marking an amount as a credit or a debit, e.g. `50.00CR`
+pub const DIRECTION_INDICATORS: [(&str, RecordType); 2] =
+    [("CR", RecordType::Credit), ("DR", RecordType::Debit)];
+
+/// `amount` without the `CR` or `DR` it ends in, in any case, and the record
+/// type that marks it as
+fn split_direction(amount: &str) -> (&str, Option<RecordType>) {
+    let trimmed = amount.trim_end();
+
+    for (suffix, record_type) in DIRECTION_INDICATORS {
+        let split = trimmed.len().checked_sub(suffix.len());
+
+        if let Some(at) = split.filter(|at| trimmed.is_char_boundary(*at)) {
+            if trimmed[at..].eq_ignore_ascii_case(suffix) {
+                return (trimmed[..at].trim_end(), Some(record_type));
+            }
+        }
+    }
+
+    (amount, None)
+}
+
+/// Reads a dollar amount as [`parse_dollar_amount_to_cents`] does, along with
+/// whether a trailing `CR` or `DR` marks it as a credit or a debit. The
+/// suffix is only recognized if `format` allows for it.
+pub fn parse_dollar_amount_with_direction(
+    amount: &str,
+    format: &AmountFormat,
+) -> Result<(u64, Option<RecordType>), AmountError> {
+    let (amount, direction) = if format.direction_indicators() {
+        split_direction(amount)
+    } else {
+        (amount, None)
+    };
+
     let mut sanitized_amount = String::new();
 
     for c in amount.chars() {
-        if c == '.' {
+        if c == '.' || c.is_ascii_digit() {
             sanitized_amount.push(c);
-        } else if ('0' as u8) <= (c as u8) && (c as u8) <= ('9' as u8) {
-            sanitized_amount.push(c);
-        } else if c == ',' || c == ' ' || c == '$' {
+        } else if format.is_ignored(c) {
             continue;
         } else {
-            println!("Error {}", c);
-            return None;
+            return Err(AmountError::Malformed);
         }
     }
 
-    match sanitized_amount.parse::<f64>() {
-        Ok(s) => return Some((s * 100.0).round() as u64),
-        Err(_) => return None,
+    let cents = match sanitized_amount.parse::<f64>() {
+        Ok(s) => (s * 100.0).round(),
+        Err(_) => return Err(AmountError::Malformed),
+    };
+
+    // Checked before the cast, which would saturate an amount of hundreds
+    // of digits to u64::MAX
+    if !cents.is_finite() || cents > MAX_AMOUNT as f64 {
+        return Err(AmountError::TooLarge);
     }
+
+    Ok((cents as u64, direction))
 }
 
+fn record_type_name(record_type: RecordType) -> &'static str {
+    match record_type {
+        RecordType::Debit => "debit",
+        _ => "credit",
+    }
+}
+
+fn dollars(cents: u64) -> String {
+    format!("{}.{:0>2}", cents / 100, cents % 100)
+}
+
+/// Brings a payment row to exactly [`COLUMN_COUNT`] columns. Trailing empty
+/// columns, as spreadsheets tend to add, are dropped and the optional columns
+/// filled in if left out. Rows with any other number of columns are an error,
+/// and entirely empty rows are skipped with `None`.
+fn fit_columns(rec: &StringRecord) -> Option<Result<StringRecord, String>> {
+    if rec.iter().all(|field| field.trim().is_empty()) {
+        return None;
+    }
+
+    let line = rec.position().map_or(0, |p| p.line());
+
+    if rec.len() < REQUIRED_COLUMN_COUNT
+        || rec
+            .iter()
+            .skip(COLUMN_COUNT)
+            .any(|field| !field.trim().is_empty())
+    {
+        return Some(Err(format!(
+            "Row on line {} has {} columns, expected {}",
+            line,
+            rec.len(),
+            COLUMN_COUNT
+        )));
+    }
+
+    let mut fitted: StringRecord = rec.iter().take(COLUMN_COUNT).collect();
+    while fitted.len() < COLUMN_COUNT {
+        fitted.push_field("");
+    }
+
+    Some(Ok(fitted))
+}
+
+/// A payment row, with the columns described by [`schema::schema`]
 #[derive(Deserialize, Debug)]
 struct CSVRow {
     customer_number: String,
@@ -105,15 +303,503 @@ struct CSVRow {
     _total: String,
 }
 
+/// A payment row of a CSV file that is converted, being neither suspended
+/// nor without a customer number
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaymentRow {
+    /// Line of the CSV file the row is on
+    pub line: u64,
+    pub customer_number: String,
+    pub account: String,
+    pub amount: String,
+}
+
+/// Reads the payment rows of the CSV data read from `reader` that a
+/// conversion would include, skipping the labelled rows at the top without
+/// checking them
+pub fn payment_rows<R: Read>(reader: R) -> Result<Vec<PaymentRow>, ErrorLog> {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+
+    let mut rows = Vec::new();
+    let mut errors = ErrorLog::new();
+
+    // The labelled rows and then the column headings
+    for rec in rdr.records().skip(HEADER_LABELS.len() + 1) {
+        let rec = match rec {
+            Ok(rec) => rec,
+            Err(e) => {
+                errors.write_error(e.to_string().as_str());
+                continue;
+            }
+        };
+
+        let line = rec.position().map_or(0, |p| p.line());
+
+        let rec = match fit_columns(&rec) {
+            Some(Ok(rec)) => rec,
+            Some(Err(e)) => {
+                errors.write_error_at(line, e.as_str());
+                continue;
+            }
+            None => continue,
+        };
+
+        let row: CSVRow = match rec.deserialize(None) {
+            Ok(row) => row,
+            Err(e) => {
+                errors.write_error_at(line, e.to_string().as_str());
+                continue;
+            }
+        };
+
+        if row.customer_number.trim().is_empty() || schema::is_suspended(&row.suspend) {
+            continue;
+        }
+
+        rows.push(PaymentRow {
+            line,
+            customer_number: row.customer_number,
+            account: row.account,
+            amount: row.amount,
+        });
+    }
+
+    if errors.get_error_list().is_empty() {
+        Ok(rows)
+    } else {
+        Err(errors)
+    }
+}
+
 pub fn convert_to_cpa005(csv: String, record_type: RecordType) -> Result<String, ErrorLog> {
+    convert_to_record(csv, record_type, &ConversionOptions::default()).map(|r| r.build())
+}
+
+/// Converts like [`convert_to_cpa005_with_options`], but returns the record
+/// of the payments, in the order the file would have them, rather than the
+/// file built from it, so that its totals and segments can be read or
+/// changed without parsing the file again. Warnings raised converting are
+/// kept in its `error_log`. [`CPA005Record::build`] writes the file.
+pub fn convert_to_record(
+    csv: String,
+    record_type: RecordType,
+    options: &ConversionOptions,
+) -> Result<CPA005Record, ErrorLog> {
+    let (mut record, log) =
+        convert_reader_to_record(csv.as_bytes(), record_type, options, &|_| ())?;
+    // Already holds whatever the record's own log did
+    record.error_log = log;
+
+    Ok(record)
+}
+
+/// Converts like [`convert_reader_with_progress`], but returns the record of
+/// the payments and the warnings raised rather than the file, so that what
+/// is left to set on it, such as a file creation number handed out once it
+/// is known to convert, can be before [`build_conversion`] builds the file
+pub fn convert_reader_to_record<R: Read>(
+    reader: R,
+    record_type: RecordType,
+    options: &ConversionOptions,
+    progress: &dyn Fn(usize),
+) -> Result<(CPA005Record, ErrorLog), ErrorLog> {
+    let (mut record, log) = assemble_record(reader, record_type, options, None, Some(progress))?;
+    record.sort_payments(options.sort);
+
+    Ok((record, log))
+}
+
+pub fn convert_to_cpa005_with_options(
+    csv: String,
+    record_type: RecordType,
+    options: &ConversionOptions,
+) -> Result<String, ErrorLog> {
+    convert_reader_to_cpa005(csv.as_bytes(), record_type, options)
+}
+
+/// Converts CSV data read incrementally from `reader`, so that callers holding
+/// large inputs on disk need not load them into memory first.
+pub fn convert_reader_to_cpa005<R: Read>(
+    reader: R,
+    record_type: RecordType,
+    options: &ConversionOptions,
+) -> Result<String, ErrorLog> {
+    convert_reader_with_report(reader, record_type, options).map(|c| c.output)
+}
+
+/// A successfully converted file along with its totals and any warnings raised
+/// during conversion
+pub struct Conversion {
+    pub output: String,
+    pub report: ConversionReport,
+    pub log: ErrorLog,
+}
+
+pub fn convert_reader_with_report<R: Read>(
+    reader: R,
+    record_type: RecordType,
+    options: &ConversionOptions,
+) -> Result<Conversion, ErrorLog> {
+    convert_reader_with_hook(reader, record_type, options, None)
+}
+
+/// Converts like [`convert_reader_with_report`], passing each payment
+/// segment to `segment_hook` once it is populated so the caller can change
+/// its fields. Segments are validated again after the hook has run.
+pub fn convert_reader_with_hook<R: Read>(
+    reader: R,
+    record_type: RecordType,
+    options: &ConversionOptions,
+    segment_hook: Option<&dyn Fn(&mut BasicPaymentSegment)>,
+) -> Result<Conversion, ErrorLog> {
+    convert_rows(reader, record_type, options, segment_hook, None)
+}
+
+/// Converts like [`convert_reader_with_report`], calling `progress` with the
+/// number of payment rows read so far after every [`PROGRESS_INTERVAL`] of
+/// them and after the last. Suspended and blank rows are counted too, as
+/// they are read all the same.
+pub fn convert_reader_with_progress<R: Read>(
+    reader: R,
+    record_type: RecordType,
+    options: &ConversionOptions,
+    progress: &dyn Fn(usize),
+) -> Result<Conversion, ErrorLog> {
+    convert_rows(reader, record_type, options, None, Some(progress))
+}
+
+/// Converts like [`convert_reader_with_report`], writing the payments
+/// `payments_per_file` at a time into files of their own, each with a
+/// header and trailer of its own. The files are numbered on from
+/// `options.starting_creation_number`, and none are written if the numbers
+/// would run past 9999, as a number of another file would be reused.
+pub fn convert_reader_split<R: Read>(
+    reader: R,
+    record_type: RecordType,
+    options: &ConversionOptions,
+    payments_per_file: usize,
+) -> Result<Vec<Conversion>, ErrorLog> {
+    if payments_per_file == 0 {
+        let mut errors = ErrorLog::new();
+        errors.write_error("Payments per file must be at least 1");
+        return Err(errors);
+    }
+
+    let (mut whole, log) = assemble_record(reader, record_type, options, None, None)?;
+    whole.sort_payments(options.sort);
+
+    let payments = std::mem::take(&mut whole.basic_payment);
+    let file_count = payments.len().div_ceil(payments_per_file).max(1);
+
+    let last = options.starting_creation_number as u64 + file_count as u64 - 1;
+    if last > MAX_FILE_CREATION_NUMBER as u64 {
+        let mut errors = ErrorLog::new();
+        errors.write_error(
+            format!(
+                "File creation numbers {} to {} of the {} split files run past {}. Start from a lower file creation number.",
+                options.starting_creation_number, last, file_count, MAX_FILE_CREATION_NUMBER
+            )
+            .as_str(),
+        );
+        return Err(errors);
+    }
+
+    let mut payments = payments.into_iter();
+    let conversions = (0..file_count)
+        .map(|i| {
+            let mut record = CPA005Record::new();
+            record
+                .set_client_number(whole.client_number.clone())
+                .set_destination_currency_code(whole.destination_currency_code)
+                .set_file_creation_number(options.starting_creation_number + i as u32);
+            record.file_creation_date = whole.file_creation_date;
+            record.rbc_processing_centre = whole.rbc_processing_centre;
+            record.returns_institution = whole.returns_institution.clone();
+            record.returns_account = whole.returns_account.clone();
+
+            for payment in payments.by_ref().take(payments_per_file) {
+                record.add_basic_payment(payment);
+            }
+
+            // The warnings are of the input, which every file came from
+            let mut warnings = ErrorLog::new();
+            warnings.merge_log(&log);
+
+            build_conversion(&record, options, warnings)
+        })
+        .collect();
+
+    Ok(conversions)
+}
+
+/// Labelled rows that CSV files merged into one file must agree on, as the
+/// file has a single header
+const MERGED_LABELS: [&str; 4] = [
+    CLIENT_NUMBER,
+    PROCESSING_CENTRE,
+    CURRENCY_CODE,
+    PAYMENT_DATE,
+];
+
+/// Payment counts and totals, in cents, that one CSV file adds to a merged
+/// file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SourceBreakdown {
+    /// The CSV file, by the name it was given
+    pub source: String,
+    pub credit_amount: u64,
+    pub credit_count: u64,
+    pub debit_amount: u64,
+    pub debit_count: u64,
+}
+
+/// Several CSV files converted into one file
+pub struct MergedConversion {
+    pub output: String,
+    pub report: ConversionReport,
+    /// Warnings raised, each naming the CSV file it was raised for
+    pub warnings: Vec<ValidationIssue>,
+    /// What each CSV file adds to the merged file, in the order given
+    pub sources: Vec<SourceBreakdown>,
+}
+
+/// The values of the labelled rows at the top of the CSV data read from
+/// `reader`, by label, as converting it with `options` would read them, up
+/// to the first that cannot be read
+fn read_header_values<R: Read>(
+    reader: R,
+    options: &ConversionOptions,
+) -> HashMap<&'static str, String> {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+
+    HEADER_LABELS
+        .iter()
+        .map_while(|label| {
+            validate_csv_header(&mut rdr, label, &options.header_defaults)
+                .ok()
+                .map(|value| (*label, value))
+        })
+        .collect()
+}
+
+/// `value` of the labelled row `label` as merging compares it, so that
+/// values written differently that mean the same agree
+fn merge_key(label: &str, value: &str) -> String {
+    let value = value.trim();
+
+    match label {
+        CLIENT_NUMBER => strip_client_number_separators(value),
+        PROCESSING_CENTRE => format!("{:0>5}", value),
+        CURRENCY_CODE => value.to_uppercase(),
+        PAYMENT_DATE => NaiveDate::parse_from_str(value, PAYMENT_DATE_FORMAT)
+            .map(|d| d.to_string())
+            .unwrap_or_else(|_| value.to_string()),
+        _ => value.to_string(),
+    }
+}
+
+fn merge_error(source: Option<&str>, message: String) -> ValidationIssue {
+    ValidationIssue {
+        file: source.map(str::to_string),
+        line: None,
+        field: None,
+        severity: Severity::Error,
+        message,
+    }
+}
+
+/// Converts the CSV files of `sources`, each given by its name and
+/// contents, into a single file holding the payments of them all, in the
+/// order given. The files must agree on their client number, processing
+/// centre, currency and payment date, and a file that does not is an error
+/// naming it and the first file, with both values. The control total of
+/// `options` is that of the merged file.
+///
+/// Errors and warnings name the file they were found in. No file is
+/// converted if any of them has errors.
+pub fn convert_merged(
+    sources: &[(&str, &str)],
+    record_type: RecordType,
+    options: &ConversionOptions,
+) -> Result<MergedConversion, Vec<ValidationIssue>> {
+    if sources.is_empty() {
+        return Err(vec![merge_error(
+            None,
+            "Choose the CSV files to merge".to_string(),
+        )]);
+    }
+
+    let headers: Vec<_> = sources
+        .iter()
+        .map(|(_, csv)| read_header_values(csv.as_bytes(), options))
+        .collect();
+
+    let mut issues = Vec::new();
+
+    // Files whose header cannot be read fail to convert below instead
+    for label in MERGED_LABELS {
+        let mut first: Option<(&str, &str)> = None;
+
+        for ((name, _), values) in sources.iter().zip(&headers) {
+            let value = match values.get(label) {
+                Some(value) => value.trim(),
+                None => continue,
+            };
+
+            match first {
+                None => first = Some((name, value)),
+                Some((first_name, first_value))
+                    if merge_key(label, value) != merge_key(label, first_value) =>
+                {
+                    issues.push(merge_error(
+                        Some(name),
+                        format!(
+                            "{} {} of {} does not match {} of {}. Merged files must have the same {}.",
+                            label, value, name, first_value, first_name, label
+                        ),
+                    ));
+                }
+                Some(_) => (),
+            }
+        }
+    }
+
+    if !issues.is_empty() {
+        return Err(issues);
+    }
+
+    let source_options = ConversionOptions {
+        control_total_cents: None,
+        ..options.clone()
+    };
+    let mut records = Vec::with_capacity(sources.len());
+
+    for (name, csv) in sources {
+        match assemble_record(csv.as_bytes(), record_type, &source_options, None, None) {
+            Ok((record, log)) => {
+                issues.extend(ValidationIssue::from_log(Some(name), &log));
+                records.push((name, record));
+            }
+            Err(log) => issues.extend(ValidationIssue::from_log(Some(name), &log)),
+        }
+    }
+
+    if issues.iter().any(|i| i.severity == Severity::Error) {
+        return Err(issues);
+    }
+
+    let breakdown = records
+        .iter()
+        .map(|(name, record)| SourceBreakdown {
+            source: name.to_string(),
+            credit_amount: record.total_credit_amount,
+            credit_count: record.total_credit_count,
+            debit_amount: record.total_debit_amount,
+            debit_count: record.total_debit_count,
+        })
+        .collect();
+
+    let mut records = records.into_iter().map(|(_, record)| record);
+    let mut merged = records.next().expect("at least one source");
+
+    for record in records {
+        for payment in record.basic_payment {
+            merged.add_basic_payment(payment);
+        }
+    }
+
+    if let Some(control_total) = options.control_total_cents {
+        let total = merged.total_credit_amount + merged.total_debit_amount;
+
+        if total != control_total {
+            issues.push(merge_error(
+                None,
+                format!(
+                    "Payment total of {} does not match the control total of {}",
+                    dollars(total),
+                    dollars(control_total)
+                ),
+            ));
+            return Err(issues);
+        }
+    }
+
+    merged.sort_payments(options.sort);
+    let conversion = build_conversion(&merged, options, ErrorLog::new());
+
+    Ok(MergedConversion {
+        output: conversion.output,
+        report: conversion.report,
+        warnings: issues,
+        sources: breakdown,
+    })
+}
+
+/// The file of `record`, with its records ended as `options` has them, its
+/// totals and the warnings of `log`
+pub fn build_conversion(
+    record: &CPA005Record,
+    options: &ConversionOptions,
+    log: ErrorLog,
+) -> Conversion {
+    let output =
+        record.build_with_terminator(options.record_terminator.as_deref(), options.terminate_last);
+
+    Conversion {
+        report: ConversionReport::new(record, &output),
+        output,
+        log,
+    }
+}
+
+fn convert_rows<R: Read>(
+    reader: R,
+    record_type: RecordType,
+    options: &ConversionOptions,
+    segment_hook: Option<&dyn Fn(&mut BasicPaymentSegment)>,
+    progress: Option<&dyn Fn(usize)>,
+) -> Result<Conversion, ErrorLog> {
+    let (mut record, log) = assemble_record(reader, record_type, options, segment_hook, progress)?;
+    record.sort_payments(options.sort);
+
+    Ok(build_conversion(&record, options, log))
+}
+
+/// The record of the payments read from `reader`, along with the warnings
+/// raised, or every error and warning if any errors were
+fn assemble_record<R: Read>(
+    reader: R,
+    record_type: RecordType,
+    options: &ConversionOptions,
+    segment_hook: Option<&dyn Fn(&mut BasicPaymentSegment)>,
+    progress: Option<&dyn Fn(usize)>,
+) -> Result<(CPA005Record, ErrorLog), ErrorLog> {
+    let mut scan = ControlCharacterScan::new(reader);
+
+    // Rows are checked against the expected columns by `fit_columns`
+    // rather than against the width of the first row
     let mut rdr = ReaderBuilder::new()
         .has_headers(false)
-        .from_reader(csv.as_bytes());
+        .flexible(true)
+        .from_reader(&mut scan);
 
     let mut csv_header = CSVHeader::new();
     let mut errors = ErrorLog::new();
 
-    match validate_csv_header(&mut rdr, "Client Name") {
+    match validate_csv_header(&mut rdr, CLIENT_NAME, &options.header_defaults) {
+        Ok(s) if options.truncate_names => {
+            let (name, warning) = truncate_field("client_name", &s, MAX_NAME_LEN);
+            if let Some(warning) = warning {
+                errors.write_warning(&warning);
+            }
+            csv_header.client_name = name;
+        }
         Ok(s) => {
             csv_header.client_name = s.to_string();
         }
@@ -122,7 +808,7 @@ pub fn convert_to_cpa005(csv: String, record_type: RecordType) -> Result<String,
         }
     }
 
-    match validate_csv_header(&mut rdr, "Client Number") {
+    match validate_csv_header(&mut rdr, CLIENT_NUMBER, &options.header_defaults) {
         Ok(s) => {
             csv_header.client_number = s;
         }
@@ -131,20 +817,17 @@ pub fn convert_to_cpa005(csv: String, record_type: RecordType) -> Result<String,
         }
     }
 
-    match validate_csv_header(&mut rdr, "Processing Centre") {
+    match validate_csv_header(&mut rdr, PROCESSING_CENTRE, &options.header_defaults) {
         Ok(s) => {
-            csv_header.processing_centre = match format!("{:0>5}", s).as_str() {
-                "00330" => ProcessingCentre::Halifax,
-                "00310" => ProcessingCentre::Montreal,
-                "00320" => ProcessingCentre::Toronto,
-                "00278" => ProcessingCentre::Regina,
-                "00370" => ProcessingCentre::Winnipeg,
-                "00390" => ProcessingCentre::Calgary,
-                "00300" => ProcessingCentre::Vancouver,
-                s => {
+            csv_header.processing_centre = match schema::processing_centre(&s) {
+                Some(centre) => centre,
+                None => {
                     errors.write_error(
-                        format!("Invalid Processing Centre: {} specified in CSV header\n", s)
-                            .as_str(),
+                        format!(
+                            "Invalid Processing Centre: {:0>5} specified in CSV header\n",
+                            s
+                        )
+                        .as_str(),
                     );
                     ProcessingCentre::Vancouver
                 }
@@ -155,14 +838,17 @@ pub fn convert_to_cpa005(csv: String, record_type: RecordType) -> Result<String,
         }
     }
 
-    match validate_csv_header(&mut rdr, "Currency Code") {
+    match validate_csv_header(&mut rdr, CURRENCY_CODE, &options.header_defaults) {
         Ok(s) => {
-            csv_header.currency_code = match s.to_uppercase().as_str() {
-                "CAD" => CurrencyType::CAD,
-                "USD" => CurrencyType::USD,
-                s => {
+            csv_header.currency_code = match schema::currency(&s) {
+                Some(currency) => currency,
+                None => {
                     errors.write_error(
-                        format!("Invalid Currency Code: {} specified in CSV header\n", s).as_str(),
+                        format!(
+                            "Invalid Currency Code: {} specified in CSV header\n",
+                            s.to_uppercase()
+                        )
+                        .as_str(),
                     );
                     CurrencyType::CAD
                 }
@@ -173,22 +859,30 @@ pub fn convert_to_cpa005(csv: String, record_type: RecordType) -> Result<String,
         }
     }
 
-    match validate_csv_header(&mut rdr, "Payment Date") {
+    match validate_csv_header(&mut rdr, PAYMENT_DATE, &options.header_defaults) {
         Ok(s) => {
-            csv_header.payment_date = match NaiveDate::parse_from_str(s.as_str(), "%Y/%m/%d") {
-                Ok(d) => (d.year() as u64, d.ordinal() as u64),
-                Err(s) => {
-                    errors.write_error(format!("Could not parse payment date. Date should be in the form of YYYY/MM/DD: {}\n", s.to_string().as_str()).as_str());
-                    (0, 0)
-                }
-            };
+            csv_header.payment_date =
+                match NaiveDate::parse_from_str(s.as_str(), PAYMENT_DATE_FORMAT) {
+                    Ok(d) => to_cpa_date(d),
+                    Err(s) => {
+                        errors.write_error(
+                            format!(
+                            "Could not parse payment date. Date should be in the form of {}: {}\n",
+                            schema::display_date_format(PAYMENT_DATE_FORMAT),
+                            s.to_string().as_str()
+                        )
+                            .as_str(),
+                        );
+                        (0, 0)
+                    }
+                };
         }
         Err(s) => {
             errors.write_error(s.as_str());
         }
     }
 
-    match validate_csv_header(&mut rdr, "Transaction Code") {
+    match validate_csv_header(&mut rdr, TRANSACTION_CODE, &options.header_defaults) {
         Ok(s) => {
             csv_header.transaction_code = s;
         }
@@ -197,82 +891,292 @@ pub fn convert_to_cpa005(csv: String, record_type: RecordType) -> Result<String,
         }
     }
 
+    let short_name = match &options.originator_display_name {
+        Some(name) if options.truncate_names => {
+            let (name, warning) = truncate_field("short_name", name, MAX_SHORT_NAME_LEN);
+            if let Some(warning) = warning {
+                errors.write_warning(&warning);
+            }
+            name
+        }
+        Some(name) if name.chars().count() > MAX_SHORT_NAME_LEN => {
+            errors.write_error(
+                format!(
+                    "Originator display name must not exceed {} characters: {}",
+                    MAX_SHORT_NAME_LEN, name
+                )
+                .as_str(),
+            );
+            String::new()
+        }
+        Some(name) => name.clone(),
+        None => {
+            let short_name: String = csv_header
+                .client_name
+                .chars()
+                .take(MAX_SHORT_NAME_LEN)
+                .collect();
+
+            if short_name.len() < csv_header.client_name.len() {
+                errors.write_warning(
+                    format!(
+                        "Client short name \"{}\" was derived by truncating the client name to {} characters; dropped \"{}\"",
+                        short_name,
+                        MAX_SHORT_NAME_LEN,
+                        &csv_header.client_name[short_name.len()..]
+                    )
+                    .as_str(),
+                );
+            }
+
+            short_name
+        }
+    };
+
     let mut cpa005_record = CPA005Record::new();
+    let (creation_year, creation_day) = to_cpa_date(
+        options
+            .file_creation_date
+            .unwrap_or_else(|| Local::now().date_naive()),
+    );
 
     cpa005_record
         .set_client_number(csv_header.client_number.clone())
         .set_destination_currency_code(csv_header.currency_code)
-        .set_file_creation_number(1)
-        .set_file_creation_date(2023, 1);
+        .set_file_creation_number(options.starting_creation_number)
+        .set_file_creation_date(creation_year as u32, creation_day as u32);
+
+    let mut rows_read = 0;
+    let mut in_repeated_header = false;
 
     for rec in rdr.records().skip(1) {
+        rows_read += 1;
+        if let Some(progress) = progress.filter(|_| rows_read % PROGRESS_INTERVAL == 0) {
+            progress(rows_read);
+        }
+
         let mut payment = BasicPayment::new();
         payment.record_type = record_type;
 
         let rec = match rec {
             Ok(rec) => rec,
             Err(e) => {
-                errors.write_error(e.to_string().as_str());
+                match e.position() {
+                    Some(p) => errors.write_error_at(p.line(), e.to_string().as_str()),
+                    None => errors.write_error(e.to_string().as_str()),
+                }
+                continue;
+            }
+        };
+
+        let line = rec.position().map_or(0, |p| p.line());
+
+        // Header rows pasted in again are reported once for the block, rather
+        // than each failing to read as a payment
+        let first = rec.get(0).unwrap_or_default();
+        if schema::is_header_label(first) {
+            if !in_repeated_header {
+                errors.write_error_at(
+                    line,
+                    format!(
+                        "Header rows are repeated from here, starting with {}. Remove the repeated rows.",
+                        first.trim()
+                    )
+                    .as_str(),
+                );
+            }
+            in_repeated_header = true;
+            continue;
+        }
+        in_repeated_header = false;
+
+        let rec = match fit_columns(&rec) {
+            Some(Ok(rec)) => rec,
+            Some(Err(e)) => {
+                errors.write_error_at(line, e.as_str());
                 continue;
             }
+            None => continue,
         };
 
         let row: CSVRow = match rec.deserialize(None) {
             Ok(s) => s,
             Err(e) => {
-                errors.write_error(e.to_string().as_str());
+                errors.write_error_at(line, e.to_string().as_str());
                 continue;
             }
         };
 
-        if row.customer_number.trim().len() == 0 {
+        if row.customer_number.trim().is_empty() {
+            continue;
+        }
+
+        if schema::is_suspended(&row.suspend) {
             continue;
         }
 
-        if row.suspend.trim().to_ascii_uppercase() == "Y" {
+        // A prenote moves no money, so its amounts are never read
+        if row.amount.trim().is_empty() && !options.prenote {
+            match options.on_blank_amount {
+                BlankAmount::Skip => errors.write_warning_at(
+                    line,
+                    format!(
+                        "Skipped customer {} with a blank payment amount",
+                        row.customer_number
+                    )
+                    .as_str(),
+                ),
+                BlankAmount::Error => errors.write_error_at(
+                    line,
+                    format!(
+                        "Payment amount is blank for customer {}",
+                        row.customer_number
+                    )
+                    .as_str(),
+                ),
+            }
             continue;
         }
 
         payment.set_client_number(csv_header.client_number.clone());
 
+        let customer_name = if options.truncate_names {
+            let (name, warning) = truncate_field("customer_name", &row.customer_name, MAX_NAME_LEN);
+            if let Some(warning) = warning {
+                errors.write_warning_at(line, &warning);
+            }
+            name
+        } else {
+            row.customer_name
+        };
+
+        let transaction_code = if options.prenote {
+            options.prenote_transaction_code.clone()
+        } else {
+            csv_header.transaction_code.clone()
+        };
+
         let mut payment_segment = BasicPaymentSegment::new();
 
         payment_segment
-            .set_transaction_code(csv_header.transaction_code.clone())
+            .set_transaction_code(transaction_code)
             .set_client_name(csv_header.client_name.clone())
             .set_customer_number(row.customer_number)
-            .set_customer_name(row.customer_name)
+            .set_customer_name(customer_name)
             .set_financial_institution_number(row.bank)
             .set_financial_institution_branch_number(row.branch)
             .set_account_number(row.account)
+            .set_account_justify(options.account_justify)
             .set_payment_date(csv_header.payment_date.0, csv_header.payment_date.1)
             .set_client_number(csv_header.client_number.clone())
-            .set_client_short_name(if csv_header.client_name.len() > 15 {
-                csv_header.client_name[0..15].to_string()
-            } else {
-                csv_header.client_name.to_string()
-            })
-            .set_amount(match parse_dollar_amount_to_cents(&row.amount) {
-                Some(d) => d,
-                None => {
-                    errors.write_error(
-                        format!("Failed to parse payment amount: {}", row.amount).as_str(),
-                    );
-                    continue;
+            .set_client_short_name(short_name.clone())
+            .set_amount(
+                match parse_dollar_amount_with_direction(&row.amount, &options.amount_format) {
+                    _ if options.prenote => 0,
+                    Ok((_, Some(direction))) if direction != record_type => {
+                        errors.write_error_at(
+                            line,
+                            format!(
+                                "Payment amount {} is marked as a {}, but this is a {} file",
+                                row.amount.trim(),
+                                record_type_name(direction),
+                                record_type_name(record_type)
+                            )
+                            .as_str(),
+                        );
+                        continue;
+                    }
+                    Ok((d, _)) if options.whole_dollars_only && d % 100 != 0 => {
+                        errors.write_error_at(
+                            line,
+                            format!("Payment amount must be whole dollars: {}", row.amount)
+                                .as_str(),
+                        );
+                        continue;
+                    }
+                    Ok((d, _)) => d,
+                    Err(AmountError::TooLarge) => {
+                        errors.write_error_at(
+                            line,
+                            format!(
+                                "Payment amount {} is more than the {} a payment can be",
+                                row.amount.trim(),
+                                dollars(MAX_AMOUNT)
+                            )
+                            .as_str(),
+                        );
+                        continue;
+                    }
+                    Err(AmountError::Malformed) => {
+                        errors.write_error_at(
+                            line,
+                            format!("Failed to parse payment amount: {}", row.amount).as_str(),
+                        );
+                        continue;
+                    }
+                },
+            );
+
+        if let Some(hook) = segment_hook {
+            // A segment that was already invalid is not checked again, so
+            // its errors are not reported twice
+            let was_valid = payment_segment.error_log.get_error_list().is_empty();
+
+            hook(&mut payment_segment);
+
+            if options.truncate_names {
+                for warning in payment_segment.truncate_names() {
+                    errors.write_warning_at(line, &warning);
                 }
-            });
+            }
+
+            if was_valid {
+                let log = payment_segment.validate();
+                payment_segment.error_log.merge_log(&log);
+            }
+        }
 
         payment.error_log.merge_log(&payment_segment.error_log);
-        cpa005_record.error_log.merge_log(&payment.error_log);
+        cpa005_record
+            .error_log
+            .merge_log_at(&payment.error_log, line);
 
         payment.segments.push(payment_segment);
         cpa005_record.add_basic_payment(payment);
     }
 
+    if let Some(progress) = progress.filter(|_| rows_read % PROGRESS_INTERVAL != 0) {
+        progress(rows_read);
+    }
+
+    // Reported first, as the other errors of their lines may follow from them
+    let mut control = scan.log();
+    control.merge_log(&errors);
+    errors = control;
+
     errors.merge_log(&cpa005_record.error_log);
+    errors.merge_log(&cpa005_record.validate(&ValidationOptions {
+        record_type: Some(record_type),
+        ..options.validation.clone()
+    }));
+
+    if let Some(control_total) = options.control_total_cents {
+        let total = cpa005_record.total_credit_amount + cpa005_record.total_debit_amount;
+
+        if total != control_total {
+            errors.write_error(
+                format!(
+                    "Payment total of {} does not match the control total of {}",
+                    dollars(total),
+                    dollars(control_total)
+                )
+                .as_str(),
+            );
+        }
+    }
 
     if errors.has_errors() {
-        Ok(cpa005_record.build())
+        Ok((cpa005_record, errors))
     } else {
         Err(errors)
     }
@@ -1,29 +1,105 @@
+use std::fmt::Display;
+
+/// An error or warning, with the line of the input it was found on where
+/// that is known
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+#[derive(Debug)]
 pub struct ErrorLog {
-    errors: Vec<String>,
+    errors: Vec<LogEntry>,
+    warnings: Vec<LogEntry>,
 }
 
 impl ErrorLog {
     pub fn new() -> Self {
-        Self { errors: Vec::new() }
+        Self {
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
     }
 
     pub fn write_error(&mut self, error: &str) {
-        self.errors.push(error.to_string());
+        self.errors.push(LogEntry {
+            line: None,
+            message: error.to_string(),
+        });
+    }
+
+    /// Records a problem that does not prevent the file from being produced
+    pub fn write_warning(&mut self, warning: &str) {
+        self.warnings.push(LogEntry {
+            line: None,
+            message: warning.to_string(),
+        });
+    }
+
+    /// Records an error found on `line` of the input
+    pub fn write_error_at(&mut self, line: u64, error: &str) {
+        self.errors.push(LogEntry {
+            line: Some(line),
+            message: error.to_string(),
+        });
+    }
+
+    /// Records a warning about `line` of the input
+    pub fn write_warning_at(&mut self, line: u64, warning: &str) {
+        self.warnings.push(LogEntry {
+            line: Some(line),
+            message: warning.to_string(),
+        });
     }
 
     pub fn merge_log(&mut self, log: &Self) {
         self.errors.extend(log.errors.clone());
+        self.warnings.extend(log.warnings.clone());
     }
 
-    pub fn has_errors(&self) -> bool {
-        return self.errors.len() == 0;
+    /// Merges the log of something read from `line` of the input, such as a
+    /// payment row, placing its entries on that line unless they already
+    /// have one
+    pub fn merge_log_at(&mut self, log: &Self, line: u64) {
+        let at_line = |entry: &LogEntry| LogEntry {
+            line: entry.line.or(Some(line)),
+            message: entry.message.clone(),
+        };
+
+        self.errors.extend(log.errors.iter().map(at_line));
+        self.warnings.extend(log.warnings.iter().map(at_line));
     }
 
-    pub fn to_string(&self) -> String {
-        self.errors.join("\n")
+    /// Discards every error and warning, keeping the allocations
+    pub fn clear(&mut self) {
+        self.errors.clear();
+        self.warnings.clear();
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors.is_empty()
     }
 
     pub fn get_error_list(&self) -> Vec<String> {
-        return self.errors.clone();
+        self.errors.iter().map(|e| e.message.clone()).collect()
+    }
+
+    pub fn get_warning_list(&self) -> Vec<String> {
+        self.warnings.iter().map(|w| w.message.clone()).collect()
+    }
+
+    pub fn errors(&self) -> &[LogEntry] {
+        &self.errors
+    }
+
+    pub fn warnings(&self) -> &[LogEntry] {
+        &self.warnings
+    }
+}
+
+impl Display for ErrorLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.get_error_list().join("\n"))
     }
 }
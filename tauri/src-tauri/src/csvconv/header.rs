@@ -1,7 +1,16 @@
 use super::error::ErrorLog;
-use super::payment::BasicPayment;
-use super::types::{CurrencyType, ProcessingCentre, RecordType};
-use super::utils::n_digits;
+use super::layout::{RecordWriter, HEADER_FIELDS, TRAILER_FIELDS};
+use super::lint::{MAX_SEGMENTS, RECORD_LENGTH};
+use super::payment::{BasicPayment, CLIENT_NUMBER_LEN};
+use super::types::{CurrencyType, ProcessingCentre, RecordType, SortOrder};
+use super::utils::{is_all_ascii_digits, n_digits, strip_client_number_separators};
+
+/// Separates the records of a file unless another terminator is asked for
+pub const DEFAULT_RECORD_TERMINATOR: &str = "\n";
+
+/// Largest file creation number, which has four digits
+pub const MAX_FILE_CREATION_NUMBER: u32 = 9999;
+
 pub struct CPA005Record {
     pub current_record_no: u32,
     pub client_number: String,
@@ -9,6 +18,8 @@ pub struct CPA005Record {
     pub file_creation_date: (u32, u32),
     pub rbc_processing_centre: ProcessingCentre,
     pub destination_currency_code: CurrencyType,
+    pub returns_institution: String,
+    pub returns_account: String,
     pub total_debit_amount: u64,
     pub total_debit_count: u64,
     pub total_credit_amount: u64,
@@ -27,6 +38,8 @@ impl CPA005Record {
             file_creation_date: (0, 0),
             destination_currency_code: CurrencyType::CAD,
             rbc_processing_centre: ProcessingCentre::Vancouver,
+            returns_institution: String::new(),
+            returns_account: String::new(),
             total_debit_amount: 0,
             total_debit_count: 0,
             total_credit_amount: 0,
@@ -36,10 +49,33 @@ impl CPA005Record {
         }
     }
 
+    /// Returns the record to its `new()` state so that it can be reused for
+    /// another file, keeping the allocations of its payments and error log.
+    pub fn reset(&mut self) -> &mut Self {
+        let fresh = Self::new();
+
+        self.current_record_no = fresh.current_record_no;
+        self.client_number.clear();
+        self.file_creation_number = fresh.file_creation_number;
+        self.file_creation_date = fresh.file_creation_date;
+        self.rbc_processing_centre = fresh.rbc_processing_centre;
+        self.destination_currency_code = fresh.destination_currency_code;
+        self.returns_institution.clear();
+        self.returns_account.clear();
+        self.total_debit_amount = fresh.total_debit_amount;
+        self.total_debit_count = fresh.total_debit_count;
+        self.total_credit_amount = fresh.total_credit_amount;
+        self.total_credit_count = fresh.total_credit_count;
+        self.basic_payment.clear();
+        self.error_log.clear();
+
+        self
+    }
+
     pub fn _allocate_record_no(&mut self) -> u32 {
         self.current_record_no += 1;
 
-        return self.current_record_no;
+        self.current_record_no
     }
 
     pub fn add_basic_payment(&mut self, mut payment: BasicPayment) -> &mut Self {
@@ -57,7 +93,25 @@ impl CPA005Record {
             }
         }
 
-        payment.set_file_creation_number(payment.record_count);
+        // Every record of the file carries its creation number
+        payment.file_creation_number = self.file_creation_number;
+
+        if payment.segments.len() > MAX_SEGMENTS {
+            self.error_log.write_error(
+                format!(
+                    "Detail record {} has {} segments, at most {} fit in a record",
+                    payment.record_count,
+                    payment.segments.len(),
+                    MAX_SEGMENTS
+                )
+                .as_str(),
+            );
+        }
+
+        for seg in &mut payment.segments {
+            seg.returns_institution = self.returns_institution.clone();
+            seg.returns_account = self.returns_account.clone();
+        }
 
         for rec in &payment.segments {
             match payment.record_type {
@@ -78,8 +132,36 @@ impl CPA005Record {
         self
     }
 
+    /// Puts the payments added so far in `order` and numbers them again to
+    /// match. Payments that compare equal keep the order they were added in.
+    pub fn sort_payments(&mut self, order: SortOrder) -> &mut Self {
+        match order {
+            SortOrder::SourceOrder => return self,
+            SortOrder::ByAccount => self.basic_payment.sort_by_cached_key(|p| {
+                p.segments.first().map(|s| {
+                    (
+                        s.account_number.parse::<u64>().unwrap_or(0),
+                        s.account_number.clone(),
+                    )
+                })
+            }),
+            SortOrder::ByAmountDesc => self.basic_payment.sort_by_key(|p| {
+                std::cmp::Reverse(p.segments.iter().map(|s| s.amount).sum::<u64>())
+            }),
+        }
+
+        // Detail records follow the header, which is record 1
+        for (i, payment) in self.basic_payment.iter_mut().enumerate() {
+            payment.record_count = i as u32 + 2;
+        }
+
+        self
+    }
+
     pub fn set_client_number(&mut self, client_number: String) -> &mut Self {
-        if client_number.parse::<u64>().is_err() {
+        let client_number = strip_client_number_separators(&client_number);
+
+        if client_number.len() != CLIENT_NUMBER_LEN || !is_all_ascii_digits(&client_number) {
             self.error_log
                 .write_error("Client number must be exactly 10 numeric digits long");
             return self;
@@ -91,7 +173,12 @@ impl CPA005Record {
     }
 
     pub fn set_file_creation_number(&mut self, no: u32) -> &mut Self {
-        if n_digits(no) > 4 {
+        if no == 0 {
+            self.error_log.write_error("File creation number is 0");
+            return self;
+        }
+
+        if no > MAX_FILE_CREATION_NUMBER {
             self.error_log
                 .write_error("File creation number exceeds 4 digits");
             return self;
@@ -99,19 +186,35 @@ impl CPA005Record {
 
         self.file_creation_number = no;
 
+        for payment in &mut self.basic_payment {
+            payment.file_creation_number = no;
+        }
+
         self
     }
 
     pub fn set_file_creation_date(&mut self, year: u32, day: u32) -> &mut Self {
-        if n_digits(year) > 4 {
+        if year == 0 {
+            self.error_log
+                .write_error("File Creation Date: Year number is 0");
+            return self;
+        }
+
+        if n_digits(year.into()) > 4 {
             self.error_log
                 .write_error("File Creation Date: Year number exceeds 4 digits");
             return self;
         }
 
-        if n_digits(day) > 3 {
+        if day == 0 {
+            self.error_log
+                .write_error("File Creation Date: Day number is 0");
+            return self;
+        }
+
+        if n_digits(day.into()) > 3 {
             self.error_log
-                .write_error("File Creation Date: Day number exceeds 4 digits");
+                .write_error("File Creation Date: Day number exceeds 3 digits");
             return self;
         }
 
@@ -120,61 +223,86 @@ impl CPA005Record {
         self
     }
 
+    /// Sets the institution, branch and account that rejected or recalled
+    /// payments are returned to. The RBC header has no room for these, so they
+    /// are carried in fields 17 and 18 of every payment segment, where CPA-005
+    /// places the returns account. Payments already added are updated too.
+    pub fn set_returns_account(
+        &mut self,
+        institution: String,
+        branch: String,
+        account: String,
+    ) -> &mut Self {
+        if !is_all_ascii_digits(&institution) || institution.len() > 4 {
+            self.error_log
+                .write_error("Returns institution number must be at most 4 digits");
+            return self;
+        }
+
+        if !is_all_ascii_digits(&branch) || branch.len() > 5 {
+            self.error_log
+                .write_error("Returns branch number must be at most 5 digits");
+            return self;
+        }
+
+        if !is_all_ascii_digits(&account) || account.len() > 12 {
+            self.error_log
+                .write_error("Returns account number must be at most 12 digits");
+            return self;
+        }
+
+        self.returns_institution = format!("{:0>4}{:0>5}", institution, branch);
+        self.returns_account = account;
+
+        for payment in &mut self.basic_payment {
+            for seg in &mut payment.segments {
+                seg.returns_institution = self.returns_institution.clone();
+                seg.returns_account = self.returns_account.clone();
+            }
+        }
+
+        self
+    }
+
     pub fn set_destination_currency_code(&mut self, t: CurrencyType) -> &mut Self {
         self.destination_currency_code = t;
         self
     }
 
+    /// Builds the trailer record, numbered after the payments added so far
+    /// and totalling them. A file assembled piece by piece ends with this,
+    /// built once every payment has been added.
     pub fn build_trailer_record(&self) -> String {
-        let mut payload = String::new();
-        payload.push_str(format!("{}", RecordType::Trailer).as_str());
-
-        payload.push_str(format!("{:0>9}", self.current_record_no + 1).as_str());
-        payload.push_str(format!("{}", self.client_number).as_str());
-        payload.push_str(format!("{:<4}", self.file_creation_number).as_str());
-
-        payload.push_str(
-            format!(
-                "{:0>12}{:0>2}",
-                self.total_debit_amount / 100,
-                self.total_debit_amount % 100
-            )
-            .as_str(),
-        );
-        payload.push_str(format!("{:0>8}", self.total_debit_count).as_str());
-
-        payload.push_str(
-            format!(
-                "{:0>12}{:0>2}",
-                self.total_credit_amount / 100,
-                self.total_credit_amount % 100
-            )
-            .as_str(),
-        );
-        payload.push_str(format!("{:0>8}", self.total_credit_count).as_str());
-
-        payload.push_str("0".repeat(1396).as_str());
+        self.write_trailer_record(String::with_capacity(RECORD_LENGTH))
+    }
 
-        return payload;
+    /// Writes the trailer record after what `text` holds already
+    fn write_trailer_record(&self, text: String) -> String {
+        RecordWriter::append_to(TRAILER_FIELDS, text)
+            .text("record_type", RecordType::Trailer)
+            .number("record_number", self.current_record_no + 1)
+            .text("client_number", &self.client_number)
+            .text("file_creation_number", self.file_creation_number)
+            // Debit totals, reserved with zero fill in a file of credits
+            .number("total_debit_amount", self.total_debit_amount)
+            .number("total_debit_count", self.total_debit_count)
+            // Credit totals, reserved with zero fill in a file of debits
+            .number("total_credit_amount", self.total_credit_amount)
+            .number("total_credit_count", self.total_credit_count)
+            .fill("filler", '0')
+            .finish()
     }
 
+    /// Builds the header record from the client, creation and currency
+    /// settings, none of which depend on the payments. A file assembled piece
+    /// by piece starts with this, built once those are set.
     pub fn build_header_record(&self) -> String {
-        let mut payload = String::new();
-
-        payload.push_str(format!("{}", RecordType::Header).as_str());
-        payload.push_str(format!("{:0>9}", 1).as_str());
-
-        payload.push_str(&self.client_number);
-        payload.push_str(format!("{:<4}", self.file_creation_number).as_str());
-        payload.push_str(
-            format!(
-                "0{:0>2}{:0>3}",
-                self.file_creation_date.0, self.file_creation_date.1
-            )
-            .as_str(),
-        );
+        self.write_header_record(String::with_capacity(RECORD_LENGTH))
+    }
 
-        payload.push_str(match self.rbc_processing_centre {
+    /// Writes the header record after what `text` holds already
+    fn write_header_record(&self, text: String) -> String {
+        let processing_centre = match self.rbc_processing_centre {
             ProcessingCentre::Halifax => "00330",
             ProcessingCentre::Montreal => "00310",
             ProcessingCentre::Toronto => "00320",
@@ -182,31 +310,73 @@ impl CPA005Record {
             ProcessingCentre::Winnipeg => "00370",
             ProcessingCentre::Calgary => "00390",
             ProcessingCentre::Vancouver => "00300",
-        });
+        };
 
-        payload.push_str(" ".repeat(20).as_str());
-
-        payload.push_str(match self.destination_currency_code {
+        let currency_code = match self.destination_currency_code {
             CurrencyType::CAD => "CAD",
             CurrencyType::USD => "USD",
-        });
+        };
+
+        RecordWriter::append_to(HEADER_FIELDS, text)
+            .text("record_type", RecordType::Header)
+            .number("record_number", 1)
+            .text("client_number", &self.client_number)
+            .text("file_creation_number", self.file_creation_number)
+            // 0YYDDD
+            .number(
+                "file_creation_date",
+                (self.file_creation_date.0 % 100) * 1000 + self.file_creation_date.1,
+            )
+            .text("processing_centre", processing_centre)
+            // Reserved: blank
+            .fill("filler", ' ')
+            .text("currency_code", currency_code)
+            .fill("filler", ' ')
+            .finish()
+    }
 
-        payload.push_str(" ".repeat(1406).as_str());
-        return payload;
+    /// Builds the detail record of `payment`, which must have been added with
+    /// [`add_basic_payment`](Self::add_basic_payment) so that it is numbered
+    /// and carries the file's creation number and returns account. A file
+    /// assembled piece by piece has these between its header and trailer, in
+    /// the order the payments were added.
+    pub fn build_detail(&self, payment: &BasicPayment) -> String {
+        payment.build()
     }
 
+    /// Builds the whole file, the same as its header, detail and trailer
+    /// records built one by one and joined by newlines
     pub fn build(&self) -> String {
-        let mut payload = String::new();
+        self.build_with_terminator(Some(DEFAULT_RECORD_TERMINATOR), false)
+    }
 
-        payload.push_str(&self.build_header_record());
-        payload.push_str("\n");
+    /// Builds the file with `record_terminator` between records, and after
+    /// the trailer as well if `terminate_last` is set. Without a terminator
+    /// the records are written back to back.
+    pub fn build_with_terminator(
+        &self,
+        record_terminator: Option<&str>,
+        terminate_last: bool,
+    ) -> String {
+        let terminator = record_terminator.unwrap_or("");
+        // Every record written into the one buffer, which detail records of
+        // fewer than six segments leave some of unused
+        let records = self.basic_payment.len() + 2;
+        let mut payload = String::with_capacity(records * (RECORD_LENGTH + terminator.len()));
+
+        payload = self.write_header_record(payload);
+        payload.push_str(terminator);
         for payment in &self.basic_payment {
-            payload.push_str(&payment.build());
-            payload.push_str("\n");
+            payload = payment.write(payload);
+            payload.push_str(terminator);
         }
 
-        payload.push_str(&&self.build_trailer_record());
+        payload = self.write_trailer_record(payload);
+
+        if terminate_last {
+            payload.push_str(terminator);
+        }
 
-        return payload;
+        payload
     }
 }
@@ -0,0 +1,94 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+use super::tables::holiday;
+
+/// Holidays observed on a fixed day of the year. One falling on a weekend is
+/// observed on the next weekday that is not already a holiday.
+const FIXED_HOLIDAYS: [(u32, u32, &str); 5] = [
+    (1, 1, "New Year's Day"),
+    (7, 1, "Canada Day"),
+    (11, 11, "Remembrance Day"),
+    (12, 25, "Christmas Day"),
+    (12, 26, "Boxing Day"),
+];
+
+/// Observed from 2021 on, otherwise as the holidays above
+const TRUTH_AND_RECONCILIATION: (u32, u32, &str) =
+    (9, 30, "National Day for Truth and Reconciliation");
+const TRUTH_AND_RECONCILIATION_FROM: i32 = 2021;
+
+/// Easter Sunday of `year` in the Gregorian calendar
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).expect("Easter is a valid date")
+}
+
+/// The `n`th `weekday` of `month`, counting from 1
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).expect("month has the weekday")
+}
+
+/// The last Monday before `day` of `month`
+fn monday_before(year: i32, month: u32, day: u32) -> NaiveDate {
+    let date =
+        NaiveDate::from_ymd_opt(year, month, day).expect("day is a valid date") - Duration::days(1);
+
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+fn is_weekend(date: NaiveDate) -> bool {
+    matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// The federal statutory holidays of `year` on the days they are observed.
+/// Provincial holidays, such as Family Day or the Civic Holiday, are not
+/// included as they vary by province.
+pub fn statutory_holidays(year: i32) -> Vec<(NaiveDate, &'static str)> {
+    let mut holidays = vec![
+        (easter(year) - Duration::days(2), "Good Friday"),
+        (monday_before(year, 5, 25), "Victoria Day"),
+        (nth_weekday(year, 9, Weekday::Mon, 1), "Labour Day"),
+        (nth_weekday(year, 10, Weekday::Mon, 2), "Thanksgiving"),
+    ];
+
+    let mut fixed = FIXED_HOLIDAYS.to_vec();
+    if year >= TRUTH_AND_RECONCILIATION_FROM {
+        fixed.push(TRUTH_AND_RECONCILIATION);
+    }
+
+    for (month, day, name) in fixed {
+        let mut date = NaiveDate::from_ymd_opt(year, month, day).expect("holiday is a valid date");
+        while is_weekend(date) || holidays.iter().any(|(d, _)| *d == date) {
+            date += Duration::days(1);
+        }
+
+        holidays.push((date, name));
+    }
+
+    holidays.sort();
+    holidays
+}
+
+/// Why payments dated `date` would not be processed on that day, if they
+/// would not: the day of the week for a weekend, or the name of the holiday
+pub fn non_business_day(date: NaiveDate) -> Option<String> {
+    if is_weekend(date) {
+        return Some(format!("a {}", date.format("%A")));
+    }
+
+    holiday(date).map(str::to_string)
+}
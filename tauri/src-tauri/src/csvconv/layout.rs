@@ -0,0 +1,207 @@
+use std::fmt::{Display, Write};
+
+use super::utils::mask_account;
+
+/// A field of a record: its name, the character it starts at counting from
+/// 0, and its length. Fields named `filler` are reserved by the bank.
+pub type FieldLayout = (&'static str, usize, usize);
+
+/// Fields of the header record, type `A`
+pub const HEADER_FIELDS: &[FieldLayout] = &[
+    ("record_type", 0, 1),
+    ("record_number", 1, 9),
+    ("client_number", 10, 10),
+    ("file_creation_number", 20, 4),
+    ("file_creation_date", 24, 6),
+    ("processing_centre", 30, 5),
+    ("filler", 35, 20),
+    ("currency_code", 55, 3),
+    ("filler", 58, 1406),
+];
+
+/// Fields of a detail record, type `C` or `D`. Each segment is laid out as
+/// [`SEGMENT_FIELDS`], and a record may end after any segment but the
+/// first.
+pub const DETAIL_FIELDS: &[FieldLayout] = &[
+    ("record_type", 0, 1),
+    ("record_number", 1, 9),
+    ("client_number", 10, 10),
+    ("file_creation_number", 20, 4),
+    ("segment_1", 24, 240),
+    ("segment_2", 264, 240),
+    ("segment_3", 504, 240),
+    ("segment_4", 744, 240),
+    ("segment_5", 984, 240),
+    ("segment_6", 1224, 240),
+];
+
+/// Fields of a segment of a detail record, from the start of the segment
+pub const SEGMENT_FIELDS: &[FieldLayout] = &[
+    ("transaction_code", 0, 3),
+    ("amount", 3, 10),
+    ("payment_date", 13, 6),
+    ("institution_number", 19, 4),
+    ("branch_number", 23, 5),
+    ("account_number", 28, 12),
+    ("filler", 40, 22),
+    ("filler", 62, 3),
+    ("client_short_name", 65, 15),
+    ("customer_name", 80, 30),
+    ("client_name", 110, 30),
+    ("client_number", 140, 10),
+    ("customer_number", 150, 19),
+    ("returns_institution", 169, 9),
+    ("returns_account", 178, 12),
+    ("client_sundry_information", 190, 15),
+    ("filler", 205, 22),
+    // Settlement code, filled in by the bank
+    ("filler", 227, 2),
+    ("filler", 229, 11),
+];
+
+/// Fields of the trailer record, type `Z`
+pub const TRAILER_FIELDS: &[FieldLayout] = &[
+    ("record_type", 0, 1),
+    ("record_number", 1, 9),
+    ("client_number", 10, 10),
+    ("file_creation_number", 20, 4),
+    ("total_debit_amount", 24, 14),
+    ("total_debit_count", 38, 8),
+    ("total_credit_amount", 46, 14),
+    ("total_credit_count", 60, 8),
+    ("filler", 68, 1396),
+];
+
+/// Fields of a segment that hold account numbers, which are masked wherever
+/// a record is shown rather than written
+pub const ACCOUNT_FIELDS: [&str; 2] = ["account_number", "returns_account"];
+
+/// `record` with the account numbers of each of its segments masked by
+/// [`mask_account`], for showing it. Only detail records carry account
+/// numbers; others are returned as they are.
+pub fn mask_record_accounts(record: &str) -> String {
+    if !record.starts_with(['C', 'D']) {
+        return record.to_string();
+    }
+
+    let segments = DETAIL_FIELDS
+        .iter()
+        .filter(|(name, _, _)| name.starts_with("segment_"));
+    let accounts = SEGMENT_FIELDS
+        .iter()
+        .filter(|(name, _, _)| ACCOUNT_FIELDS.contains(name));
+
+    let mut masked = String::with_capacity(record.len());
+    let mut shown = 0;
+
+    for (_, segment, _) in segments {
+        for (_, start, length) in accounts.clone() {
+            let start = segment + start;
+            let account = match record.get(start..start + length) {
+                Some(account) => account,
+                None => continue,
+            };
+
+            masked.push_str(&record[shown..start]);
+            masked.push_str(&mask_account(account));
+            shown = start + length;
+        }
+    }
+
+    masked.push_str(&record[shown..]);
+    masked
+}
+
+/// Writes the fields of a record in order, each padded to the length its
+/// layout gives it. Values longer than their field are written whole, and
+/// are for the record's own checks to catch.
+pub(super) struct RecordWriter {
+    fields: &'static [FieldLayout],
+    next: usize,
+    text: String,
+}
+
+impl RecordWriter {
+    /// A writer of a record laid out as `fields`, into a buffer sized to
+    /// hold all of it
+    pub(super) fn new(fields: &'static [FieldLayout]) -> Self {
+        let length = fields.last().map_or(0, |(_, start, length)| start + length);
+
+        Self::append_to(fields, String::with_capacity(length))
+    }
+
+    /// A writer of a record laid out as `fields`, following what `text`
+    /// holds already, so that the records of a file can share one buffer
+    pub(super) fn append_to(fields: &'static [FieldLayout], text: String) -> Self {
+        Self {
+            fields,
+            next: 0,
+            text,
+        }
+    }
+
+    /// Length of the next field
+    fn next_field(&mut self) -> usize {
+        let (_, _, length) = self.fields[self.next];
+
+        self.next += 1;
+        length
+    }
+
+    /// Length of the next field, which must be `name`
+    fn field(&mut self, name: &str) -> usize {
+        debug_assert_eq!(
+            name, self.fields[self.next].0,
+            "fields written out of order"
+        );
+
+        self.next_field()
+    }
+
+    /// Writes `value` to the next field, left justified and padded with
+    /// blanks
+    pub(super) fn text(&mut self, name: &str, value: impl Display) -> &mut Self {
+        let width = self.field(name);
+        write!(self.text, "{:<width$}", value, width = width).expect("writing to a string");
+        self
+    }
+
+    /// Writes `value` to the next field, right justified and padded with
+    /// zeros
+    pub(super) fn number(&mut self, name: &str, value: impl Display) -> &mut Self {
+        let width = self.field(name);
+        write!(self.text, "{:0>width$}", value, width = width).expect("writing to a string");
+        self
+    }
+
+    /// Fills the next field with `fill`
+    pub(super) fn fill(&mut self, name: &str, fill: char) -> &mut Self {
+        let width = self.field(name);
+        self.text.extend(std::iter::repeat_n(fill, width));
+        self
+    }
+
+    /// Writes the next field as a record of its own laid out as `fields`,
+    /// by `write`, straight into this record and padded with blanks
+    pub(super) fn nested(
+        &mut self,
+        fields: &'static [FieldLayout],
+        write: impl FnOnce(&mut RecordWriter),
+    ) -> &mut Self {
+        let width = self.next_field();
+        let start = self.text.len();
+
+        let mut nested = Self::append_to(fields, std::mem::take(&mut self.text));
+        write(&mut nested);
+        self.text = nested.text;
+
+        let written = self.text[start..].chars().count();
+        self.text
+            .extend(std::iter::repeat_n(' ', width.saturating_sub(written)));
+        self
+    }
+
+    pub(super) fn finish(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+}
@@ -0,0 +1,524 @@
+use serde::Serialize;
+use std::fmt::Display;
+
+use super::error::{ErrorLog, LogEntry};
+use super::layout::{self, FieldLayout};
+use super::utils::mask_account;
+
+/// Length of the header and trailer records, and of a detail record carrying
+/// the most segments it can
+pub const RECORD_LENGTH: usize = 1464;
+/// Characters of a detail record ahead of its first segment
+pub const DETAIL_PREFIX_LENGTH: usize = 24;
+pub const SEGMENT_LENGTH: usize = 240;
+pub const MAX_SEGMENTS: usize = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The file would be rejected
+    Error,
+    /// The file may be accepted, but is worth a second look
+    Warning,
+}
+
+/// A way in which a CPA-005 file breaks the format
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ValidationIssue {
+    /// File the issue was found in, when issues of several files are
+    /// gathered together
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Line of the record at fault, counting from 1, or none for the file as
+    /// a whole
+    pub line: Option<usize>,
+    /// Name of the field at fault, if it is a single field
+    pub field: Option<&'static str>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    /// The errors and warnings of converting `file`, as issues placed on the
+    /// line of the input they were found on
+    pub fn from_log(file: Option<&str>, log: &ErrorLog) -> Vec<Self> {
+        let issue = |severity| {
+            move |entry: &LogEntry| ValidationIssue {
+                file: file.map(str::to_string),
+                line: entry.line.map(|l| l as usize),
+                field: None,
+                severity,
+                message: entry.message.clone(),
+            }
+        };
+
+        log.errors()
+            .iter()
+            .map(issue(Severity::Error))
+            .chain(log.warnings().iter().map(issue(Severity::Warning)))
+            .collect()
+    }
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+
+        if let Some(field) = self.field {
+            write!(f, "{}: ", field)?;
+        }
+
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    /// Only the digits 0-9
+    Digits,
+    /// Digits followed by blanks
+    LeftDigits,
+    /// Printable characters, already checked for the whole record
+    Text,
+    Blank,
+    Zeros,
+    /// One of a fixed list of values
+    Code(&'static [&'static str]),
+}
+
+/// A field of a record, at `start` counting from 0
+pub(super) struct Field {
+    pub(super) name: &'static str,
+    pub(super) start: usize,
+    pub(super) length: usize,
+    kind: Kind,
+}
+
+/// The field laid out as `layout`, holding values of `kind`
+const fn field(layout: FieldLayout, kind: Kind) -> Field {
+    let (name, start, length) = layout;
+
+    Field {
+        name,
+        start,
+        length,
+        kind,
+    }
+}
+
+/// Fields every record starts with, after its record type
+pub(super) const PREFIX_FIELDS: [Field; 3] = [
+    field(layout::HEADER_FIELDS[1], Kind::Digits),
+    field(layout::HEADER_FIELDS[2], Kind::Digits),
+    field(layout::HEADER_FIELDS[3], Kind::LeftDigits),
+];
+
+pub(super) const HEADER_FIELDS: [Field; 5] = [
+    field(layout::HEADER_FIELDS[4], Kind::Digits),
+    field(layout::HEADER_FIELDS[5], Kind::Digits),
+    field(layout::HEADER_FIELDS[6], Kind::Blank),
+    field(layout::HEADER_FIELDS[7], Kind::Code(&["CAD", "USD"])),
+    field(layout::HEADER_FIELDS[8], Kind::Blank),
+];
+
+/// Fields of a segment, from the start of the segment
+pub(super) const SEGMENT_FIELDS: [Field; 19] = [
+    field(layout::SEGMENT_FIELDS[0], Kind::Digits),
+    field(layout::SEGMENT_FIELDS[1], Kind::Digits),
+    field(layout::SEGMENT_FIELDS[2], Kind::Digits),
+    field(layout::SEGMENT_FIELDS[3], Kind::Digits),
+    field(layout::SEGMENT_FIELDS[4], Kind::Digits),
+    field(layout::SEGMENT_FIELDS[5], Kind::LeftDigits),
+    field(layout::SEGMENT_FIELDS[6], Kind::Zeros),
+    field(layout::SEGMENT_FIELDS[7], Kind::Zeros),
+    field(layout::SEGMENT_FIELDS[8], Kind::Text),
+    field(layout::SEGMENT_FIELDS[9], Kind::Text),
+    field(layout::SEGMENT_FIELDS[10], Kind::Text),
+    field(layout::SEGMENT_FIELDS[11], Kind::Digits),
+    field(layout::SEGMENT_FIELDS[12], Kind::Text),
+    field(layout::SEGMENT_FIELDS[13], Kind::Digits),
+    field(layout::SEGMENT_FIELDS[14], Kind::LeftDigits),
+    field(layout::SEGMENT_FIELDS[15], Kind::Text),
+    field(layout::SEGMENT_FIELDS[16], Kind::Blank),
+    field(layout::SEGMENT_FIELDS[17], Kind::Blank),
+    field(layout::SEGMENT_FIELDS[18], Kind::Blank),
+];
+
+pub(super) const TRAILER_FIELDS: [Field; 5] = [
+    field(layout::TRAILER_FIELDS[4], Kind::Digits),
+    field(layout::TRAILER_FIELDS[5], Kind::Digits),
+    field(layout::TRAILER_FIELDS[6], Kind::Digits),
+    field(layout::TRAILER_FIELDS[7], Kind::Digits),
+    field(layout::TRAILER_FIELDS[8], Kind::Zeros),
+];
+
+/// Payments of one type found in the detail records
+#[derive(Default)]
+struct Totals {
+    amount: u64,
+    count: u64,
+}
+
+struct Linter {
+    issues: Vec<ValidationIssue>,
+}
+
+impl Linter {
+    fn report(&mut self, line: Option<usize>, field: Option<&'static str>, message: String) {
+        self.issues.push(ValidationIssue {
+            file: None,
+            line,
+            field,
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warn(&mut self, line: Option<usize>, field: Option<&'static str>, message: String) {
+        self.issues.push(ValidationIssue {
+            file: None,
+            line,
+            field,
+            severity: Severity::Warning,
+            message,
+        });
+    }
+
+    /// Checks the characters of each field that lies within `record`, with
+    /// the fields starting `offset` characters in
+    fn check_fields(&mut self, line: usize, record: &str, offset: usize, fields: &[Field]) {
+        for f in fields {
+            let start = offset + f.start;
+            let value = match record.get(start..start + f.length) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let digits = |v: &str| v.bytes().all(|b| b.is_ascii_digit());
+
+            let expected = match f.kind {
+                Kind::Digits if !digits(value) => "digits".to_string(),
+                Kind::LeftDigits if !digits(value.trim_end_matches(' ')) => {
+                    "digits followed by blanks".to_string()
+                }
+                Kind::Blank if value.bytes().any(|b| b != b' ') => "blanks".to_string(),
+                Kind::Zeros if value.bytes().any(|b| b != b'0') => "zeros".to_string(),
+                Kind::Code(codes) if !codes.contains(&value) => {
+                    format!("one of {}", codes.join(", "))
+                }
+                _ => continue,
+            };
+
+            // Account numbers are never quoted in full
+            let found = if layout::ACCOUNT_FIELDS.contains(&f.name) {
+                mask_account(value)
+            } else {
+                value.to_string()
+            };
+
+            self.report(
+                Some(line),
+                Some(f.name),
+                format!(
+                    "Expected {} at column {}, found \"{}\"",
+                    expected,
+                    start + 1,
+                    found
+                ),
+            );
+        }
+    }
+}
+
+pub(super) fn number(record: &str, start: usize, length: usize) -> Option<u64> {
+    record.get(start..start + length)?.parse().ok()
+}
+
+fn dollars(cents: u64) -> String {
+    format!("{}.{:0>2}", cents / 100, cents % 100)
+}
+
+/// A segment left unused at the end of a detail record
+pub(super) fn is_unused(segment: &str) -> bool {
+    segment.bytes().all(|b| b == b' ') || segment.bytes().all(|b| b == b'0')
+}
+
+/// Checks a CPA-005 file against the rules of the format, whatever produced
+/// it: the length and order of its records, their numbering, the characters
+/// each field may hold and the totals in its trailer. Records are expected
+/// on separate lines.
+pub fn lint_cpa005(input: &str) -> Vec<ValidationIssue> {
+    let mut linter = Linter { issues: Vec::new() };
+    let records: Vec<&str> = input.lines().collect();
+
+    if records.is_empty() {
+        linter.report(None, None, "File is empty".to_string());
+        return linter.issues;
+    }
+
+    let last = records.len();
+    let mut header: Option<&str> = None;
+    let mut trailer: Option<(usize, &str)> = None;
+    let mut credits = Totals::default();
+    let mut debits = Totals::default();
+    let mut previous_number: Option<u64> = None;
+
+    for (i, record) in records.iter().enumerate() {
+        let line = i + 1;
+
+        if !record.bytes().all(|b| (b' '..=b'~').contains(&b)) {
+            linter.report(
+                Some(line),
+                None,
+                "Record has characters other than printable ASCII".to_string(),
+            );
+            continue;
+        }
+
+        let record_type = record.chars().next().unwrap_or(' ');
+
+        match record_type {
+            'A' => {
+                if line != 1 {
+                    linter.report(
+                        Some(line),
+                        None,
+                        "Header record must be the first record".to_string(),
+                    );
+                }
+                if header.is_some() {
+                    linter.report(
+                        Some(line),
+                        None,
+                        "File has more than one header".to_string(),
+                    );
+                } else {
+                    header = Some(record);
+                }
+            }
+            'C' | 'D' => {
+                if line == 1 {
+                    linter.report(
+                        Some(line),
+                        None,
+                        "File must start with a header record".to_string(),
+                    );
+                }
+                if trailer.is_some() {
+                    linter.report(
+                        Some(line),
+                        None,
+                        "Detail record follows the trailer".to_string(),
+                    );
+                }
+            }
+            'Z' => {
+                if line != last {
+                    linter.report(
+                        Some(line),
+                        None,
+                        "Trailer record must be the last record".to_string(),
+                    );
+                }
+                if trailer.is_some() {
+                    linter.report(
+                        Some(line),
+                        None,
+                        "File has more than one trailer".to_string(),
+                    );
+                } else {
+                    trailer = Some((line, record));
+                }
+            }
+            _ => {
+                linter.report(
+                    Some(line),
+                    Some("record_type"),
+                    format!(
+                        "Unknown record type \"{}\", expected A, C, D or Z",
+                        record_type
+                    ),
+                );
+                continue;
+            }
+        }
+
+        let length = record.len();
+        let is_detail = matches!(record_type, 'C' | 'D');
+        let segments = length.saturating_sub(DETAIL_PREFIX_LENGTH) / SEGMENT_LENGTH;
+        let whole_segments = length > DETAIL_PREFIX_LENGTH
+            && (length - DETAIL_PREFIX_LENGTH).is_multiple_of(SEGMENT_LENGTH)
+            && segments <= MAX_SEGMENTS;
+
+        if is_detail && whole_segments && length < RECORD_LENGTH {
+            linter.warn(
+                Some(line),
+                None,
+                format!(
+                    "Detail record is {} characters long, with {} segment{} rather than padded to {}",
+                    length,
+                    segments,
+                    if segments == 1 { "" } else { "s" },
+                    RECORD_LENGTH
+                ),
+            );
+        } else if length != RECORD_LENGTH {
+            linter.report(
+                Some(line),
+                None,
+                format!(
+                    "Record is {} characters long, expected {}",
+                    length, RECORD_LENGTH
+                ),
+            );
+        }
+
+        linter.check_fields(line, record, 0, &PREFIX_FIELDS);
+
+        match number(record, 1, 9) {
+            Some(no) => {
+                let expected = previous_number.map_or(1, |p| p + 1);
+                if no != expected {
+                    linter.report(
+                        Some(line),
+                        Some("record_number"),
+                        format!("Record number is {}, expected {}", no, expected),
+                    );
+                }
+                previous_number = Some(no);
+            }
+            None => previous_number = previous_number.map(|p| p + 1),
+        }
+
+        if let Some(header) = header.filter(|_| record_type != 'A') {
+            for f in &PREFIX_FIELDS[1..] {
+                let range = f.start..f.start + f.length;
+                let (value, expected) = match (record.get(range.clone()), header.get(range)) {
+                    (Some(value), Some(expected)) if value != expected => (value, expected),
+                    (Some(value), None) => (value, ""),
+                    _ => continue,
+                };
+
+                linter.report(
+                    Some(line),
+                    Some(f.name),
+                    format!(
+                        "Does not match the {} of the header, expected \"{}\" but found \"{}\"",
+                        f.name.replace('_', " "),
+                        expected.trim_end(),
+                        value.trim_end()
+                    ),
+                );
+            }
+        }
+
+        match record_type {
+            'A' => linter.check_fields(line, record, 0, &HEADER_FIELDS),
+            'Z' => linter.check_fields(line, record, 0, &TRAILER_FIELDS),
+            _ => {
+                let totals = if record_type == 'C' {
+                    &mut credits
+                } else {
+                    &mut debits
+                };
+
+                for s in 0..segments.min(MAX_SEGMENTS) {
+                    let offset = DETAIL_PREFIX_LENGTH + s * SEGMENT_LENGTH;
+                    let segment = &record[offset..offset + SEGMENT_LENGTH];
+
+                    if s > 0 && is_unused(segment) {
+                        continue;
+                    }
+
+                    linter.check_fields(line, record, offset, &SEGMENT_FIELDS);
+
+                    totals.amount += number(segment, 3, 10).unwrap_or(0);
+                    totals.count += 1;
+                }
+            }
+        }
+    }
+
+    if credits.count > 0 && debits.count > 0 {
+        linter.warn(
+            None,
+            None,
+            "File has both credit and debit records".to_string(),
+        );
+    }
+
+    if header.is_none() {
+        linter.report(None, None, "File has no header record".to_string());
+    }
+
+    let (line, trailer) = match trailer {
+        Some(trailer) => trailer,
+        None => {
+            linter.report(None, None, "File has no trailer record".to_string());
+            return linter.issues;
+        }
+    };
+
+    let found = [debits.amount, debits.count, credits.amount, credits.count];
+
+    for (f, found) in TRAILER_FIELDS.iter().zip(found) {
+        let stated = match number(trailer, f.start, f.length) {
+            Some(stated) => stated,
+            None => continue,
+        };
+
+        if stated == found {
+            continue;
+        }
+
+        let (stated, found) = if f.name.ends_with("amount") {
+            (dollars(stated), dollars(found))
+        } else {
+            (stated.to_string(), found.to_string())
+        };
+
+        linter.report(
+            Some(line),
+            Some(f.name),
+            format!(
+                "Trailer states {} but the detail records add up to {}",
+                stated, found
+            ),
+        );
+    }
+
+    linter.issues
+}
+
+/// Writes `issues` as a CSV file with the columns file, line, field, severity
+/// and message, for reviewing them in a spreadsheet
+pub fn issues_to_csv(issues: &[ValidationIssue]) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    let mut write = |row: [&str; 5]| {
+        writer.write_record(row).expect("CSV is written to memory");
+    };
+
+    write(["file", "line", "field", "severity", "message"]);
+
+    for issue in issues {
+        let line = issue.line.map(|l| l.to_string()).unwrap_or_default();
+        let severity = match issue.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        write([
+            issue.file.as_deref().unwrap_or_default(),
+            &line,
+            issue.field.unwrap_or_default(),
+            severity,
+            &issue.message,
+        ]);
+    }
+
+    let bytes = writer.into_inner().expect("CSV is written to memory");
+    String::from_utf8(bytes).expect("issues are UTF-8")
+}
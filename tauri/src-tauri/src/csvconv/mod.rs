@@ -1,6 +1,22 @@
+pub mod control;
+pub mod coverage;
 pub mod csv;
 pub mod error;
 pub mod header;
+pub mod holidays;
+pub mod layout;
+pub mod lint;
+pub mod naming;
+pub mod options;
+pub mod parallel;
+pub mod parse;
 pub mod payment;
+pub mod report;
+pub mod schema;
+pub mod summary;
+pub mod tables;
+pub mod template;
+pub mod transits;
 pub mod types;
 pub mod utils;
+pub mod validate;
@@ -0,0 +1,83 @@
+/// Name a converted file is given unless another template is chosen
+pub const DEFAULT_OUTPUT_NAME: &str = "{stem}.txt";
+
+/// Placeholders an output name template may contain
+pub const PLACEHOLDERS: [&str; 2] = ["{stem}", "{seq}"];
+
+/// Digits the file creation number is padded to in `{seq}`, as wide as it is
+/// in the header record
+pub const SEQ_WIDTH: usize = 4;
+
+const MAX_STEM_LENGTH: usize = 64;
+
+/// Reduces the name of an input file to a stem that is safe to name its
+/// converted file after, whether in a `Content-Disposition` header or on
+/// disk, or `None` if nothing usable is left.
+pub fn sanitize_stem(file_name: &str) -> Option<String> {
+    // Clients may send a full path, only the final component is of interest
+    let base = file_name.rsplit(['/', '\\']).next().unwrap_or("");
+
+    let stem = match base.rfind('.') {
+        Some(i) if i > 0 => &base[..i],
+        _ => base,
+    };
+
+    let sanitized: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.' | '(' | ')') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_STEM_LENGTH)
+        .collect();
+
+    let sanitized = sanitized.trim_matches(|c: char| c == '.' || c == ' ');
+
+    if sanitized.chars().all(|c| c == '_') {
+        None
+    } else {
+        Some(sanitized.to_string())
+    }
+}
+
+/// Checks that `template` is not blank and only contains known placeholders
+pub fn check_output_name(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("Output name must not be empty".to_string());
+    }
+
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let placeholder = match rest[start..].find('}') {
+            Some(end) => &rest[start..start + end + 1],
+            None => &rest[start..],
+        };
+
+        if !PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "Unknown placeholder {} in output name \"{}\", expected one of {}",
+                placeholder,
+                template,
+                PLACEHOLDERS.join(", ")
+            ));
+        }
+
+        rest = &rest[start + placeholder.len()..];
+    }
+
+    Ok(())
+}
+
+/// Expands `template` for the converted file of an input named `stem`.
+/// `{stem}` is replaced with the input's stem and `{seq}` with the file
+/// creation number, zero-padded to [`SEQ_WIDTH`] digits.
+pub fn render_output_name(template: &str, stem: &str, file_creation_number: u32) -> String {
+    template.replace("{stem}", stem).replace(
+        "{seq}",
+        &format!("{:0>width$}", file_creation_number, width = SEQ_WIDTH),
+    )
+}
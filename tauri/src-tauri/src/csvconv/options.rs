@@ -0,0 +1,144 @@
+use super::header::DEFAULT_RECORD_TERMINATOR;
+use super::payment::PRENOTE_TRANSACTION_CODE;
+use super::types::{AccountJustify, SortOrder};
+use super::validate::ValidationOptions;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+
+/// Characters skipped when reading dollar amounts, such as grouping
+/// separators and currency symbols. `.` is always the decimal separator.
+#[derive(Clone, Debug)]
+pub struct AmountFormat {
+    ignored_chars: Vec<char>,
+    /// Whether amounts may end in `CR` or `DR`, as some exports mark them
+    direction_indicators: bool,
+}
+
+impl AmountFormat {
+    pub fn new<I: IntoIterator<Item = char>>(ignored_chars: I) -> Result<Self, String> {
+        let ignored_chars: Vec<char> = ignored_chars.into_iter().collect();
+
+        if let Some(c) = ignored_chars
+            .iter()
+            .find(|c| **c == '.' || c.is_ascii_digit())
+        {
+            return Err(format!("'{}' cannot be ignored in dollar amounts", c));
+        }
+
+        Ok(Self {
+            ignored_chars,
+            direction_indicators: false,
+        })
+    }
+
+    /// Also reads amounts ending in `CR` or `DR`, such as `50.00CR`, which
+    /// mark them as credits or debits
+    pub fn with_direction_indicators(mut self, direction_indicators: bool) -> Self {
+        self.direction_indicators = direction_indicators;
+        self
+    }
+
+    pub fn direction_indicators(&self) -> bool {
+        self.direction_indicators
+    }
+
+    pub fn is_ignored(&self, c: char) -> bool {
+        self.ignored_chars.contains(&c)
+    }
+
+    pub fn ignored_chars(&self) -> &[char] {
+        &self.ignored_chars
+    }
+}
+
+impl Default for AmountFormat {
+    /// Comma grouping with an optional dollar sign, e.g. `$1,250.00`
+    fn default() -> Self {
+        Self {
+            ignored_chars: vec![',', ' ', '$'],
+            direction_indicators: false,
+        }
+    }
+}
+
+/// What to do with a payment row whose amount is blank
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlankAmount {
+    /// Leave the row out of the file, with a warning
+    Skip,
+    #[default]
+    Error,
+}
+
+/// A value for one of the labelled rows at the top of a CSV file supplied
+/// from outside the file, such as by a client profile
+#[derive(Clone, Debug)]
+pub struct HeaderDefault {
+    pub value: String,
+    /// Whether the file may give a value of its own instead. Files giving a
+    /// different value for a row that may not be overridden are rejected.
+    pub overridable: bool,
+}
+
+/// Options controlling how a CSV file is converted into CPA-005
+#[derive(Clone, Debug)]
+pub struct ConversionOptions {
+    pub account_justify: AccountJustify,
+    /// Date the file is created on, today if unset
+    pub file_creation_date: Option<NaiveDate>,
+    /// File creation number of the converted file, or of the first of the
+    /// files it is split into, the others being numbered on from it
+    pub starting_creation_number: u32,
+    pub validation: ValidationOptions,
+    /// Trade name shown to recipients in place of the client name, at most
+    /// 15 characters
+    pub originator_display_name: Option<String>,
+    pub amount_format: AmountFormat,
+    pub on_blank_amount: BlankAmount,
+    /// Written between records, `None` writes them back to back
+    pub record_terminator: Option<String>,
+    /// Whether the trailer record is followed by the terminator too
+    pub terminate_last: bool,
+    /// Expected sum of every payment amount, a file adding up to anything
+    /// else is rejected
+    pub control_total_cents: Option<u64>,
+    /// Reject amounts with cents, for programs that only pay whole dollars
+    pub whole_dollars_only: bool,
+    /// Cut names longer than their fields to fit, with a warning giving the
+    /// text dropped, rather than rejecting them
+    pub truncate_names: bool,
+    /// Write a prenote file, verifying the accounts of each row before live
+    /// payments are sent: every amount is zero and every segment carries
+    /// the prenote transaction code
+    pub prenote: bool,
+    /// Transaction code of every segment of a prenote file
+    pub prenote_transaction_code: String,
+    /// Order the detail records are written in
+    pub sort: SortOrder,
+    /// Values of the labelled rows at the top of the file by their label,
+    /// used when the file leaves a row blank or without a value
+    pub header_defaults: HashMap<&'static str, HeaderDefault>,
+}
+
+impl Default for ConversionOptions {
+    fn default() -> Self {
+        Self {
+            account_justify: AccountJustify::default(),
+            file_creation_date: None,
+            starting_creation_number: 1,
+            validation: ValidationOptions::default(),
+            originator_display_name: None,
+            amount_format: AmountFormat::default(),
+            on_blank_amount: BlankAmount::default(),
+            record_terminator: Some(DEFAULT_RECORD_TERMINATOR.to_string()),
+            terminate_last: false,
+            control_total_cents: None,
+            whole_dollars_only: false,
+            truncate_names: false,
+            prenote: false,
+            prenote_transaction_code: PRENOTE_TRANSACTION_CODE.to_string(),
+            sort: SortOrder::default(),
+            header_defaults: HashMap::new(),
+        }
+    }
+}
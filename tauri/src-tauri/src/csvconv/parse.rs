@@ -0,0 +1,393 @@
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+
+use super::header::CPA005Record;
+use super::lint::{
+    is_unused, lint_cpa005, number, Field, Severity, DETAIL_PREFIX_LENGTH, HEADER_FIELDS,
+    MAX_SEGMENTS, PREFIX_FIELDS, SEGMENT_FIELDS, SEGMENT_LENGTH, TRAILER_FIELDS,
+};
+use super::payment::{BasicPayment, BasicPaymentSegment};
+use super::report::{sha256_hex, ConversionReport};
+use super::types::{CurrencyType, ProcessingCentre, RecordType};
+use super::utils::mask_account;
+
+/// The header record of a parsed file
+#[derive(Clone, Debug, Serialize)]
+pub struct ParsedHeader {
+    pub client_number: String,
+    pub file_creation_number: u32,
+    /// YYYY-MM-DD
+    pub file_creation_date: String,
+    pub processing_centre: String,
+    pub currency_code: String,
+}
+
+/// A payment segment of a detail record. Text fields are trimmed of the
+/// blanks they are padded with.
+#[derive(Clone, Debug, Serialize)]
+pub struct ParsedSegment {
+    pub transaction_code: String,
+    /// In cents
+    pub amount: u64,
+    /// YYYY-MM-DD
+    pub payment_date: String,
+    pub institution_number: String,
+    pub branch_number: String,
+    pub account_number: String,
+    pub client_short_name: String,
+    pub customer_name: String,
+    pub client_name: String,
+    pub customer_number: String,
+    pub returns_institution: String,
+    pub returns_account: String,
+    pub client_sundry_information: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ParsedDetail {
+    /// Line of the record, counting from 1
+    pub line: usize,
+    /// `C` for credits, `D` for debits
+    pub record_type: char,
+    pub record_number: u64,
+    /// The segments in use, unused ones at the end of the record are left
+    /// out
+    pub segments: Vec<ParsedSegment>,
+}
+
+/// Totals as stated by the trailer record
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ParsedTrailer {
+    pub total_debit_amount: u64,
+    pub total_debit_count: u64,
+    pub total_credit_amount: u64,
+    pub total_credit_count: u64,
+}
+
+/// A CPA-005 file read back into its records
+#[derive(Clone, Debug, Serialize)]
+pub struct ParsedFile {
+    pub header: ParsedHeader,
+    pub details: Vec<ParsedDetail>,
+    pub trailer: ParsedTrailer,
+    /// Issues found in the file that do not stop it being read
+    pub warnings: Vec<String>,
+    /// SHA-256 of the file as read, in lowercase hex
+    pub sha256: String,
+}
+
+impl ParsedFile {
+    pub fn segment_count(&self) -> usize {
+        self.details.iter().map(|d| d.segments.len()).sum()
+    }
+
+    /// Summary of the file, with its totals added up from the detail
+    /// records rather than taken from the trailer
+    pub fn report(&self) -> ConversionReport {
+        let mut report = ConversionReport {
+            client_number: self.header.client_number.clone(),
+            file_creation_number: self.header.file_creation_number,
+            file_creation_date: self.header.file_creation_date.clone(),
+            total_debit_amount: 0,
+            total_debit_count: 0,
+            total_credit_amount: 0,
+            total_credit_count: 0,
+            output_sha256: self.sha256.clone(),
+        };
+
+        for detail in &self.details {
+            let (amount, count) = if detail.record_type == 'C' {
+                (
+                    &mut report.total_credit_amount,
+                    &mut report.total_credit_count,
+                )
+            } else {
+                (
+                    &mut report.total_debit_amount,
+                    &mut report.total_debit_count,
+                )
+            };
+
+            for segment in &detail.segments {
+                *amount += segment.amount;
+                *count += 1;
+            }
+        }
+
+        report
+    }
+
+    /// The file as a record that builds it again. Records are numbered
+    /// afresh and the trailer's totals added up from the detail records.
+    pub fn to_record(&self) -> CPA005Record {
+        let mut record = CPA005Record::new();
+        let header = &self.header;
+
+        record.client_number = header.client_number.clone();
+        record.file_creation_number = header.file_creation_number;
+        if let Some(date) = ymd_date(&header.file_creation_date) {
+            record.file_creation_date = (date.year() as u32, date.ordinal());
+        }
+        record.rbc_processing_centre = match header.processing_centre.as_str() {
+            "00330" => ProcessingCentre::Halifax,
+            "00310" => ProcessingCentre::Montreal,
+            "00320" => ProcessingCentre::Toronto,
+            "00278" => ProcessingCentre::Regina,
+            "00370" => ProcessingCentre::Winnipeg,
+            "00390" => ProcessingCentre::Calgary,
+            _ => ProcessingCentre::Vancouver,
+        };
+        record.destination_currency_code = match header.currency_code.as_str() {
+            "USD" => CurrencyType::USD,
+            _ => CurrencyType::CAD,
+        };
+
+        // Every segment of a file returns to the same account
+        if let Some(segment) = self.details.iter().flat_map(|d| &d.segments).next() {
+            record.returns_institution = segment.returns_institution.clone();
+            record.returns_account = segment.returns_account.clone();
+        }
+
+        for detail in &self.details {
+            let mut payment = BasicPayment::new();
+            payment.record_type = if detail.record_type == 'D' {
+                RecordType::Debit
+            } else {
+                RecordType::Credit
+            };
+            payment.client_number = header.client_number.clone();
+
+            for s in &detail.segments {
+                let mut segment = BasicPaymentSegment::new();
+                segment.transaction_code = s.transaction_code.clone();
+                segment.amount = s.amount;
+                if let Some(date) = ymd_date(&s.payment_date) {
+                    segment.payment_date = (date.year() as u64 % 100, date.ordinal() as u64);
+                }
+                segment.financial_institution_number = s.institution_number.clone();
+                segment.financial_institution_branch_number = s.branch_number.clone();
+                segment.account_number = s.account_number.clone();
+                segment.client_short_name = s.client_short_name.clone();
+                segment.customer_name = s.customer_name.clone();
+                segment.client_name = s.client_name.clone();
+                segment.client_number = header.client_number.clone();
+                segment.customer_number = s.customer_number.clone();
+                segment.client_sundry_information = s.client_sundry_information.clone();
+                payment.segments.push(segment);
+            }
+
+            record.add_basic_payment(payment);
+        }
+
+        record
+    }
+
+    /// The file as newline-delimited JSON: a `summary` object of its header
+    /// and trailer, then a `payment` object for each segment, with the
+    /// record it is in. Each object is followed by a newline.
+    pub fn to_ndjson(&self) -> String {
+        let summary = NdjsonLine::Summary {
+            header: &self.header,
+            trailer: &self.trailer,
+            segment_count: self.segment_count(),
+            sha256: &self.sha256,
+        };
+
+        let payments = self.details.iter().flat_map(|detail| {
+            detail
+                .segments
+                .iter()
+                .enumerate()
+                .map(move |(index, segment)| NdjsonLine::Payment {
+                    client_number: &self.header.client_number,
+                    file_creation_number: self.header.file_creation_number,
+                    line: detail.line,
+                    record_type: detail.record_type,
+                    record_number: detail.record_number,
+                    segment: index + 1,
+                    payment: segment,
+                })
+        });
+
+        let mut ndjson = String::new();
+        for line in std::iter::once(summary).chain(payments) {
+            ndjson.push_str(&serde_json::to_string(&line).expect("parsed records serialize"));
+            ndjson.push('\n');
+        }
+
+        ndjson
+    }
+
+    /// Masks the account numbers of every segment by [`mask_account`], for
+    /// showing the file rather than converting it again
+    pub fn mask_accounts(&mut self) {
+        for segment in self.details.iter_mut().flat_map(|d| &mut d.segments) {
+            segment.account_number = mask_account(&segment.account_number);
+            segment.returns_account = mask_account(&segment.returns_account);
+        }
+    }
+}
+
+/// An object of [`ParsedFile::to_ndjson`], told apart by its `kind`
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum NdjsonLine<'a> {
+    Summary {
+        header: &'a ParsedHeader,
+        trailer: &'a ParsedTrailer,
+        segment_count: usize,
+        sha256: &'a str,
+    },
+    Payment {
+        client_number: &'a str,
+        file_creation_number: u32,
+        line: usize,
+        record_type: char,
+        record_number: u64,
+        /// Segment of the record, counting from 1
+        segment: usize,
+        #[serde(flatten)]
+        payment: &'a ParsedSegment,
+    },
+}
+
+fn ymd_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+}
+
+/// The value of the field called `name`, starting `offset` characters into
+/// `record`
+fn value<'a>(record: &'a str, offset: usize, fields: &[Field], name: &str) -> &'a str {
+    let f = fields
+        .iter()
+        .find(|f| f.name == name)
+        .expect("field is in the table");
+    let start = offset + f.start;
+
+    record.get(start..start + f.length).unwrap_or_default()
+}
+
+fn text(record: &str, offset: usize, fields: &[Field], name: &str) -> String {
+    value(record, offset, fields, name).trim_end().to_string()
+}
+
+fn numeric(record: &str, offset: usize, fields: &[Field], name: &str) -> u64 {
+    value(record, offset, fields, name)
+        .trim_end()
+        .parse()
+        .unwrap_or(0)
+}
+
+/// Renders a date written as 0YYDDD as YYYY-MM-DD, taking the year to be of
+/// this century
+fn julian_date(value: &str) -> String {
+    let date = value
+        .get(1..3)
+        .zip(value.get(3..6))
+        .and_then(|(year, day)| {
+            NaiveDate::from_yo_opt(2000 + year.parse::<i32>().ok()?, day.parse().ok()?)
+        });
+
+    date.map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+fn parse_segment(record: &str, offset: usize) -> ParsedSegment {
+    let text = |name| text(record, offset, &SEGMENT_FIELDS, name);
+
+    ParsedSegment {
+        transaction_code: text("transaction_code"),
+        amount: numeric(record, offset, &SEGMENT_FIELDS, "amount"),
+        payment_date: julian_date(value(record, offset, &SEGMENT_FIELDS, "payment_date")),
+        institution_number: text("institution_number"),
+        branch_number: text("branch_number"),
+        account_number: text("account_number"),
+        client_short_name: text("client_short_name"),
+        customer_name: text("customer_name"),
+        client_name: text("client_name"),
+        customer_number: text("customer_number"),
+        returns_institution: text("returns_institution"),
+        returns_account: text("returns_account"),
+        client_sundry_information: text("client_sundry_information"),
+    }
+}
+
+fn parse_detail(line: usize, record: &str) -> ParsedDetail {
+    let segments = (0..MAX_SEGMENTS)
+        .map(|s| DETAIL_PREFIX_LENGTH + s * SEGMENT_LENGTH)
+        .filter_map(|offset| Some((offset, record.get(offset..offset + SEGMENT_LENGTH)?)))
+        .enumerate()
+        .filter(|(s, (_, segment))| *s == 0 || !is_unused(segment))
+        .map(|(_, (offset, _))| parse_segment(record, offset))
+        .collect();
+
+    ParsedDetail {
+        line,
+        record_type: record.chars().next().unwrap_or('C'),
+        record_number: number(record, 1, 9).unwrap_or(0),
+        segments,
+    }
+}
+
+/// Reads a CPA-005 file back into its records. The file is checked with
+/// [`lint_cpa005`] first and is only read if it has no errors, which are
+/// returned otherwise.
+pub fn parse_cpa005(input: &str) -> Result<ParsedFile, Vec<String>> {
+    let issues = lint_cpa005(input);
+
+    let errors: Vec<String> = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .map(|i| i.to_string())
+        .collect();
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // Without errors the first record is the header and the last the trailer
+    let mut parsed = read_cpa005(input).expect("file has a header and trailer");
+    parsed.warnings = issues.iter().map(|i| i.to_string()).collect();
+
+    Ok(parsed)
+}
+
+/// Reads a CPA-005 file back into its records without checking it, for a
+/// file to be shown however broken it is. Every record between the first
+/// and the last is read as a detail record, and fields a record is too short
+/// to hold are left blank. Returns none unless the file starts with a header
+/// and ends with a trailer.
+pub fn read_cpa005(input: &str) -> Option<ParsedFile> {
+    let records: Vec<&str> = input.lines().collect();
+    let (header, trailer) = match records.as_slice() {
+        [header, .., trailer] if header.starts_with('A') && trailer.starts_with('Z') => {
+            (*header, *trailer)
+        }
+        _ => return None,
+    };
+
+    Some(ParsedFile {
+        header: ParsedHeader {
+            client_number: text(header, 0, &PREFIX_FIELDS, "client_number"),
+            file_creation_number: numeric(header, 0, &PREFIX_FIELDS, "file_creation_number")
+                .try_into()
+                .unwrap_or(0),
+            file_creation_date: julian_date(value(header, 0, &HEADER_FIELDS, "file_creation_date")),
+            processing_centre: text(header, 0, &HEADER_FIELDS, "processing_centre"),
+            currency_code: text(header, 0, &HEADER_FIELDS, "currency_code"),
+        },
+        details: records[1..records.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(i, record)| parse_detail(i + 2, record))
+            .collect(),
+        trailer: ParsedTrailer {
+            total_debit_amount: numeric(trailer, 0, &TRAILER_FIELDS, "total_debit_amount"),
+            total_debit_count: numeric(trailer, 0, &TRAILER_FIELDS, "total_debit_count"),
+            total_credit_amount: numeric(trailer, 0, &TRAILER_FIELDS, "total_credit_amount"),
+            total_credit_count: numeric(trailer, 0, &TRAILER_FIELDS, "total_credit_count"),
+        },
+        warnings: Vec::new(),
+        sha256: sha256_hex(input),
+    })
+}
@@ -0,0 +1,292 @@
+use super::csv::DIRECTION_INDICATORS;
+use super::options::AmountFormat;
+use super::payment::{
+    CLIENT_NUMBER_LEN, MAX_ACCOUNT_NUMBER_LEN, MAX_BRANCH_NUMBER_LEN, MAX_CUSTOMER_NUMBER_LEN,
+    MAX_INSTITUTION_NUMBER_LEN, MAX_NAME_LEN, TRANSACTION_CODE_LEN,
+};
+use super::types::{CurrencyType, ProcessingCentre};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+// Labels of the metadata rows at the top of the file, which must appear in
+// this order
+pub const CLIENT_NAME: &str = "Client Name";
+pub const CLIENT_NUMBER: &str = "Client Number";
+pub const PROCESSING_CENTRE: &str = "Processing Centre";
+pub const CURRENCY_CODE: &str = "Currency Code";
+pub const PAYMENT_DATE: &str = "Payment Date";
+pub const TRANSACTION_CODE: &str = "Transaction Code";
+pub const HEADER_LABELS: [&str; 6] = [
+    CLIENT_NAME,
+    CLIENT_NUMBER,
+    PROCESSING_CENTRE,
+    CURRENCY_CODE,
+    PAYMENT_DATE,
+    TRANSACTION_CODE,
+];
+
+/// Heading of the first payment column, in the row of column names that
+/// follows the metadata
+pub const CUSTOMER_NUMBER: &str = "Customer Number";
+
+pub const PROCESSING_CENTRES: [(&str, ProcessingCentre); 7] = [
+    ("00330", ProcessingCentre::Halifax),
+    ("00310", ProcessingCentre::Montreal),
+    ("00320", ProcessingCentre::Toronto),
+    ("00278", ProcessingCentre::Regina),
+    ("00370", ProcessingCentre::Winnipeg),
+    ("00390", ProcessingCentre::Calgary),
+    ("00300", ProcessingCentre::Vancouver),
+];
+
+pub const CURRENCY_CODES: [(&str, CurrencyType); 2] =
+    [("CAD", CurrencyType::CAD), ("USD", CurrencyType::USD)];
+
+pub const PAYMENT_DATE_FORMAT: &str = "%Y/%m/%d";
+
+/// Columns of a payment row, of which the last two may be left out
+pub const COLUMN_COUNT: usize = 9;
+pub const REQUIRED_COLUMN_COUNT: usize = 7;
+
+/// Values of the suspend column that leave a row out of the file, compared
+/// without regard to case. Any other value includes the row.
+pub const SUSPEND_TOKENS: [&str; 1] = ["Y"];
+
+/// Looks up a processing centre by its code, which may omit leading zeros
+pub fn processing_centre(code: &str) -> Option<ProcessingCentre> {
+    let code = format!("{:0>5}", code);
+
+    PROCESSING_CENTRES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, centre)| *centre)
+}
+
+/// Looks up a currency by its code, without regard to case
+pub fn currency(code: &str) -> Option<CurrencyType> {
+    CURRENCY_CODES
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, currency)| *currency)
+}
+
+/// Whether `cell`, the first of a row, starts one of the rows above the
+/// payments: a metadata row or the row of column names
+pub fn is_header_label(cell: &str) -> bool {
+    let cell = cell.trim();
+
+    cell == CUSTOMER_NUMBER || HEADER_LABELS.contains(&cell)
+}
+
+pub fn is_suspended(value: &str) -> bool {
+    SUSPEND_TOKENS
+        .iter()
+        .any(|t| t.eq_ignore_ascii_case(value.trim()))
+}
+
+/// Spells out a `chrono` date format the way people write dates, e.g.
+/// `%Y/%m/%d` as `YYYY/MM/DD`
+pub fn display_date_format(format: &str) -> String {
+    format
+        .replace("%Y", "YYYY")
+        .replace("%m", "MM")
+        .replace("%d", "DD")
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    /// Any text
+    Text,
+    /// ASCII digits only
+    Digits,
+    /// One of a fixed list of values
+    Code,
+    Date,
+    Amount,
+    Flag,
+    /// Read but not used
+    Ignored,
+}
+
+/// A metadata row or data column of the CSV file
+#[derive(Serialize, ToSchema)]
+#[schema(as = CsvField)]
+pub struct Field {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+impl Field {
+    fn new(name: &'static str, field_type: FieldType, description: &'static str) -> Self {
+        Self {
+            name,
+            field_type,
+            min_length: None,
+            max_length: None,
+            values: Vec::new(),
+            format: None,
+            required: true,
+            description,
+        }
+    }
+
+    fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+
+    fn length(mut self, min: Option<usize>, max: usize) -> Self {
+        self.min_length = min;
+        self.max_length = Some(max);
+        self
+    }
+
+    fn values<I: IntoIterator<Item = &'static str>>(mut self, values: I) -> Self {
+        self.values = values.into_iter().collect();
+        self
+    }
+
+    fn format(mut self, format: String) -> Self {
+        self.format = Some(format);
+        self
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AmountSchema {
+    pub decimal_separator: char,
+    /// Characters skipped when reading an amount, such as grouping separators
+    pub ignored_characters: Vec<char>,
+    /// Suffixes an amount may end in to mark it as a credit or a debit
+    pub direction_indicators: Vec<&'static str>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SuspendSchema {
+    pub tokens: Vec<&'static str>,
+    pub case_sensitive: bool,
+}
+
+/// What a CSV file must look like to be converted
+#[derive(Serialize, ToSchema)]
+#[schema(as = CsvSchema)]
+pub struct Schema {
+    /// Rows of a label and a value, one per row in this order
+    pub metadata: Vec<Field>,
+    /// Whether a row of column names, which is skipped, follows the metadata
+    pub column_names_row: bool,
+    /// The columns of each payment row, in this order
+    pub columns: Vec<Field>,
+    pub amount: AmountSchema,
+    pub date_formats: Vec<String>,
+    pub suspend: SuspendSchema,
+}
+
+/// Describes the input accepted by the converter with `amount_format`
+pub fn schema(amount_format: &AmountFormat) -> Schema {
+    let date_format = display_date_format(PAYMENT_DATE_FORMAT);
+
+    let metadata = vec![
+        Field::new(CLIENT_NAME, FieldType::Text, "Name of the originator")
+            .length(None, MAX_NAME_LEN),
+        Field::new(
+            CLIENT_NUMBER,
+            FieldType::Digits,
+            "Originator number assigned by the bank",
+        )
+        .length(Some(CLIENT_NUMBER_LEN), CLIENT_NUMBER_LEN),
+        Field::new(
+            PROCESSING_CENTRE,
+            FieldType::Code,
+            "Data centre the file is sent to, leading zeros may be left out",
+        )
+        .values(PROCESSING_CENTRES.iter().map(|(code, _)| *code)),
+        Field::new(
+            CURRENCY_CODE,
+            FieldType::Code,
+            "Currency of every payment in the file, in any case",
+        )
+        .values(CURRENCY_CODES.iter().map(|(code, _)| *code)),
+        Field::new(PAYMENT_DATE, FieldType::Date, "Date the payments are due")
+            .format(date_format.clone()),
+        Field::new(
+            TRANSACTION_CODE,
+            FieldType::Text,
+            "CPA transaction code applied to every payment",
+        )
+        .length(Some(TRANSACTION_CODE_LEN), TRANSACTION_CODE_LEN),
+    ];
+
+    let columns = vec![
+        Field::new(
+            CUSTOMER_NUMBER,
+            FieldType::Text,
+            "Identifies the payee or payor, rows where it is blank are skipped",
+        )
+        .length(None, MAX_CUSTOMER_NUMBER_LEN),
+        Field::new(
+            "Customer Name",
+            FieldType::Text,
+            "Name of the payee or payor",
+        )
+        .length(None, MAX_NAME_LEN),
+        Field::new(
+            "Bank",
+            FieldType::Digits,
+            "Institution number, padded with leading zeros",
+        )
+        .length(None, MAX_INSTITUTION_NUMBER_LEN),
+        Field::new(
+            "Branch",
+            FieldType::Digits,
+            "Branch transit number, padded with leading zeros",
+        )
+        .length(None, MAX_BRANCH_NUMBER_LEN),
+        Field::new("Account", FieldType::Digits, "Account number")
+            .length(None, MAX_ACCOUNT_NUMBER_LEN),
+        Field::new("Amount", FieldType::Amount, "Payment amount in dollars"),
+        Field::new(
+            "Suspend",
+            FieldType::Flag,
+            "Leaves the row out of the file when set to a suspend token",
+        )
+        .values(SUSPEND_TOKENS),
+        Field::new("Todo", FieldType::Ignored, "").optional(),
+        Field::new("Total", FieldType::Ignored, "").optional(),
+    ];
+
+    Schema {
+        metadata,
+        column_names_row: true,
+        columns,
+        amount: AmountSchema {
+            decimal_separator: '.',
+            ignored_characters: amount_format.ignored_chars().to_vec(),
+            direction_indicators: if amount_format.direction_indicators() {
+                DIRECTION_INDICATORS
+                    .iter()
+                    .map(|(suffix, _)| *suffix)
+                    .collect()
+            } else {
+                Vec::new()
+            },
+        },
+        date_formats: vec![date_format],
+        suspend: SuspendSchema {
+            tokens: SUSPEND_TOKENS.to_vec(),
+            case_sensitive: false,
+        },
+    }
+}
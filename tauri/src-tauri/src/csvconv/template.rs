@@ -0,0 +1,72 @@
+use chrono::NaiveDate;
+use std::collections::HashMap;
+
+use super::options::{AmountFormat, HeaderDefault};
+use super::schema::{
+    self, CLIENT_NAME, CLIENT_NUMBER, CURRENCY_CODE, PAYMENT_DATE, PAYMENT_DATE_FORMAT,
+    PROCESSING_CENTRE, TRANSACTION_CODE,
+};
+
+/// Values the labelled rows of a template are filled in with where no client
+/// profile gives them, the payment date aside
+const EXAMPLE_HEADER: [(&str, &str); 5] = [
+    (CLIENT_NAME, "Your Company Name"),
+    (CLIENT_NUMBER, "0123456789"),
+    (PROCESSING_CENTRE, "00320"),
+    (CURRENCY_CODE, "CAD"),
+    (TRANSACTION_CODE, "200"),
+];
+
+/// A payment row showing how each column is filled in
+const EXAMPLE_ROW: [&str; 7] = [
+    "1001",
+    "Jane Smith",
+    "003",
+    "01234",
+    "1234567",
+    "100.00",
+    "N",
+];
+
+/// A CSV file to start a spreadsheet of payments from: the labelled rows,
+/// filled in from `defaults` where given and with example values otherwise,
+/// the column headings and an example payment on `payment_date`. It
+/// converts as it is, for its rows to be replaced with the client's own.
+pub fn csv_template(
+    defaults: &HashMap<&'static str, HeaderDefault>,
+    payment_date: NaiveDate,
+) -> String {
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+
+    let payment_date = payment_date.format(PAYMENT_DATE_FORMAT).to_string();
+    let schema = schema::schema(&AmountFormat::default());
+
+    for field in &schema.metadata {
+        let example = match field.name {
+            PAYMENT_DATE => payment_date.as_str(),
+            label => EXAMPLE_HEADER
+                .iter()
+                .find(|(l, _)| *l == label)
+                .map_or("", |(_, value)| value),
+        };
+        let value = defaults
+            .get(field.name)
+            .map_or(example, |d| d.value.as_str());
+
+        writer
+            .write_record([field.name, value])
+            .expect("CSV is written to memory");
+    }
+
+    writer
+        .write_record(schema.columns.iter().map(|c| c.name))
+        .expect("CSV is written to memory");
+    writer
+        .write_record(EXAMPLE_ROW)
+        .expect("CSV is written to memory");
+
+    String::from_utf8(writer.into_inner().expect("CSV is written to memory"))
+        .expect("CSV of UTF-8 text")
+}
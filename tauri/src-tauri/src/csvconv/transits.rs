@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+
+/// Institution and branch numbers known to belong together, as supplied by
+/// the user. Checked against the transit of each payment, as a branch
+/// entered under the wrong institution is otherwise passed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransitList {
+    transits: HashSet<(u32, u32)>,
+}
+
+fn parse_number(number: &str, max_len: usize) -> Option<u32> {
+    let number = number.trim();
+
+    if number.is_empty() || number.len() > max_len || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    number.parse().ok()
+}
+
+/// An institution number of up to 4 digits and a branch number of up to 5,
+/// leading zeros or not
+fn parse_transit(institution: &str, branch: &str) -> Option<(u32, u32)> {
+    Some((parse_number(institution, 4)?, parse_number(branch, 5)?))
+}
+
+impl TransitList {
+    /// Reads a list with an institution and a branch number on each line,
+    /// separated by a comma, such as `003,01234`. Blank lines, lines
+    /// starting with `#` and a first line of column headings are skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut transits = HashSet::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let transit = line
+                .split_once(',')
+                .and_then(|(institution, branch)| parse_transit(institution, branch));
+
+            match transit {
+                Some(transit) => {
+                    transits.insert(transit);
+                }
+                None if i == 0 => continue,
+                None => {
+                    return Err(format!(
+                        "Line {} of the transit list is not an institution and branch number: {}",
+                        i + 1,
+                        line
+                    ))
+                }
+            }
+        }
+
+        Ok(Self { transits })
+    }
+
+    /// Whether `branch` is listed as a branch of `institution`. Numbers that
+    /// are not numbers at all are never listed.
+    pub fn contains(&self, institution: &str, branch: &str) -> bool {
+        parse_transit(institution, branch).is_some_and(|t| self.transits.contains(&t))
+    }
+
+    pub fn len(&self) -> usize {
+        self.transits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transits.is_empty()
+    }
+}
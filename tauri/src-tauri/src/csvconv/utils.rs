@@ -1,10 +1,73 @@
-pub fn n_digits(mut v: u32) -> usize {
-    let mut count = 0usize;
-    while v != 0 {
-        count += 1;
+use chrono::{Datelike, NaiveDate};
 
-        v /= 10;
+/// Number of decimal digits `v` is written with. Zero is written with one
+/// digit, so callers that do not accept zero must check for it themselves.
+pub fn n_digits(v: u64) -> usize {
+    v.checked_ilog10().map_or(1, |log| log as usize + 1)
+}
+
+/// Whether `s` is made up only of the digits 0-9. Unlike parsing it as a
+/// number, signs are rejected and there is no limit on length. An empty
+/// string is not a number.
+pub fn is_all_ascii_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Characters people write client numbers with to group their digits, as in
+/// `123-456-7890`
+const CLIENT_NUMBER_SEPARATORS: [char; 2] = ['-', ' '];
+
+/// A client number without the separators grouping its digits
+pub fn strip_client_number_separators(client_number: &str) -> String {
+    client_number
+        .chars()
+        .filter(|c| !CLIENT_NUMBER_SEPARATORS.contains(c))
+        .collect()
+}
+
+/// `value` cut to its first `max_len` characters, along with a warning
+/// naming `field` and the text dropped from its end, if any was
+pub fn truncate_field(field: &str, value: &str, max_len: usize) -> (String, Option<String>) {
+    match value.char_indices().nth(max_len) {
+        Some((at, _)) => (
+            value[..at].to_string(),
+            Some(format!(
+                "{} truncated to {} characters; dropped \"{}\"",
+                field,
+                max_len,
+                &value[at..]
+            )),
+        ),
+        None => (value.to_string(), None),
     }
+}
+
+/// The `(year, day of year)` a date is encoded as in CPA-005 records, where
+/// January 1 is day 1
+pub fn to_cpa_date(d: NaiveDate) -> (u64, u64) {
+    (d.year() as u64, d.ordinal() as u64)
+}
+
+/// Characters of an account number left showing once it is masked
+const ACCOUNT_CHARS_SHOWN: usize = 4;
+
+/// `account` with all but its last four characters replaced by `*`, to be
+/// shown in logs and previews in place of the number. Blanks padding it out
+/// to its field are kept. A number of four characters or fewer is masked
+/// whole, so that no number is ever shown in full.
+pub fn mask_account(account: &str) -> String {
+    let number = account.trim_end_matches(' ');
+    let length = number.chars().count();
+    let masked = if length > ACCOUNT_CHARS_SHOWN {
+        length - ACCOUNT_CHARS_SHOWN
+    } else {
+        length
+    };
 
-    return count;
+    number
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i < masked { '*' } else { c })
+        .chain(account[number.len()..].chars())
+        .collect()
 }
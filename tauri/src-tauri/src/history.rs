@@ -0,0 +1,256 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::convert::{
+    convert_files_numbered, ConversionProgress, ConversionStatus, FileConversionResult,
+    FileRequest, WrittenSummary,
+};
+use crate::csvconv::options::ConversionOptions;
+use crate::numbering::{BatchNumbering, CreationNumberStore};
+use crate::settings::replace_file;
+
+/// Name of the history file in the app's data directory
+pub const HISTORY_FILE: &str = "history.json";
+
+/// Conversions kept in the history, the oldest being dropped first
+pub const MAX_HISTORY: usize = 100;
+
+/// What a batch of files was converted with, enough to convert it again
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversionRequest {
+    /// The CSV files, each with its own record type and options if it has
+    /// them. Histories kept before files had these name them `inputs`.
+    #[serde(alias = "inputs")]
+    pub files: Vec<FileRequest>,
+    /// `PDS` or `PAD`, for the files that do not give their own
+    pub record_type: String,
+    pub output_directory: String,
+    pub output_name: Option<String>,
+}
+
+/// Payment counts and totals of the files a batch converted, in cents
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryTotals {
+    pub debit_amount: u64,
+    pub debit_count: u64,
+    pub credit_amount: u64,
+    pub credit_count: u64,
+}
+
+/// A batch of files converted. Only paths and totals are kept, never the
+/// payments themselves.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// When the batch finished converting, in RFC 3339
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub request: ConversionRequest,
+    /// The files written
+    pub outputs: Vec<String>,
+    pub totals: HistoryTotals,
+    /// Files of the batch converted and not
+    pub converted: usize,
+    pub failed: usize,
+}
+
+/// The latest conversions, oldest first
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    next_id: u64,
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// Reads the history saved at `path`, which is empty if none has been
+    /// saved yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Cannot read history {}: {}", path.display(), e)),
+        };
+
+        serde_json::from_str(&text)
+            .map_err(|e| format!("History {} is not valid: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).expect("history serializes");
+
+        replace_file(path, &text)
+            .map_err(|e| format!("Cannot save history {}: {}", path.display(), e))
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, id: u64) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    /// Adds the batch converted by `request` into `results`, dropping the
+    /// oldest conversions beyond [`MAX_HISTORY`]
+    pub fn record(
+        &mut self,
+        request: ConversionRequest,
+        results: &[FileConversionResult],
+    ) -> &HistoryEntry {
+        let converted: Vec<&FileConversionResult> = results
+            .iter()
+            .filter(|r| r.status == ConversionStatus::Converted)
+            .collect();
+
+        let mut totals = HistoryTotals::default();
+        for report in converted.iter().filter_map(|r| r.report.as_ref()) {
+            totals.debit_amount += report.total_debit_amount;
+            totals.debit_count += report.total_debit_count;
+            totals.credit_amount += report.total_credit_amount;
+            totals.credit_count += report.total_credit_count;
+        }
+
+        self.next_id += 1;
+        self.entries.push(HistoryEntry {
+            id: self.next_id,
+            timestamp: chrono::Local::now().to_rfc3339(),
+            request,
+            outputs: converted.iter().filter_map(|r| r.output.clone()).collect(),
+            totals,
+            converted: converted.len(),
+            failed: results.len() - converted.len(),
+        });
+
+        let excess = self.entries.len().saturating_sub(MAX_HISTORY);
+        self.entries.drain(..excess);
+
+        self.entries.last().expect("entry just recorded")
+    }
+}
+
+/// The history kept between launches. Conversions are added to it one at a
+/// time, so that batches finishing together never drop each other's entry.
+pub struct HistoryStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl HistoryStore {
+    /// History kept at `path`, empty if none has been saved yet
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// The history as last saved
+    pub fn load(&self) -> Result<History, String> {
+        History::load(&self.path)
+    }
+
+    /// Adds a converted batch to the history, returning the id it is
+    /// recorded as. A history that cannot be read is started over rather
+    /// than lose the conversion.
+    pub fn record_conversion(
+        &self,
+        request: ConversionRequest,
+        results: &[FileConversionResult],
+    ) -> Result<u64, String> {
+        // A conversion that panicked leaves nothing half written, as the
+        // history is only saved whole
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut history = History::load(&self.path).unwrap_or_default();
+
+        let id = history.record(request, results).id;
+        history.save(&self.path)?;
+
+        Ok(id)
+    }
+
+    /// Removes the history
+    pub fn clear(&self) -> Result<(), String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!(
+                "Cannot clear history {}: {}",
+                self.path.display(),
+                e
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Outcome of converting a batch of the history again
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Rerun {
+    /// A result for each file, as for the first conversion, converted into
+    /// the same `output_directory`
+    Converted {
+        output_directory: String,
+        results: Vec<FileConversionResult>,
+        #[serde(flatten)]
+        summary: WrittenSummary,
+    },
+    /// Nothing was converted, as these files no longer exist. They are to be
+    /// chosen again.
+    MissingInputs {
+        request: ConversionRequest,
+        missing: Vec<String>,
+    },
+}
+
+/// Converts the batch `id` of `history` again, with the same files and
+/// settings, up to `jobs` files at once, and records it as a new
+/// conversion. Each file is given the next file creation number of its
+/// client from `store`, never that of the first conversion.
+pub fn rerun(
+    history: &HistoryStore,
+    id: u64,
+    options: &ConversionOptions,
+    jobs: usize,
+    store: &CreationNumberStore,
+    progress: &(dyn Fn(ConversionProgress) + Sync),
+) -> Result<Rerun, String> {
+    let request = history
+        .load()?
+        .find(id)
+        .map(|e| e.request.clone())
+        .ok_or_else(|| format!("Conversion {} is no longer in the history", id))?;
+
+    let missing: Vec<String> = request
+        .files
+        .iter()
+        .filter(|file| !Path::new(&file.path).is_file())
+        .map(|file| file.path.clone())
+        .collect();
+
+    if !missing.is_empty() {
+        return Ok(Rerun::MissingInputs { request, missing });
+    }
+
+    let results = convert_files_numbered(
+        &request.files,
+        &request.record_type,
+        &request.output_directory,
+        request.output_name.as_deref(),
+        options,
+        jobs,
+        Some(&mut BatchNumbering::from_store(store)),
+        progress,
+    );
+
+    let output_directory = request.output_directory.clone();
+    history.record_conversion(request, &results)?;
+
+    Ok(Rerun::Converted {
+        output_directory,
+        results,
+        summary: WrittenSummary::default(),
+    })
+}
@@ -0,0 +1,115 @@
+use serde::Serialize;
+use std::path::Path;
+
+use crate::csvconv::lint::{lint_cpa005, Severity, ValidationIssue};
+use crate::csvconv::parse::{read_cpa005, ParsedHeader, ParsedTrailer};
+use crate::csvconv::utils::mask_account;
+
+/// Payments `inspect` returns at a time when not told how many
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// A payment segment of an inspected file, as shown in a table of them
+#[derive(Clone, Debug, Serialize)]
+pub struct InspectedPayment {
+    /// Line of the record the payment is in, counting from 1
+    pub line: usize,
+    pub record_type: char,
+    pub customer_number: String,
+    pub customer_name: String,
+    /// Masked by [`mask_account`]
+    pub account_number: String,
+    pub institution_number: String,
+    pub branch_number: String,
+    /// In cents
+    pub amount: u64,
+    /// YYYY-MM-DD
+    pub payment_date: String,
+}
+
+/// A CPA-005 file read back for display, with one page of its payments
+#[derive(Clone, Debug, Serialize)]
+pub struct Inspection {
+    pub header: ParsedHeader,
+    /// Payments from `offset` on, as many as were asked for
+    pub payments: Vec<InspectedPayment>,
+    /// Index of the first payment of the page, counting from 0
+    pub offset: usize,
+    /// Payments in the file in all
+    pub payment_count: usize,
+    /// Totals as the trailer states them
+    pub trailer: ParsedTrailer,
+    /// Totals as the detail records add up to
+    pub computed: ParsedTrailer,
+    /// Whether the trailer states the totals the detail records add up to
+    pub totals_match: bool,
+    pub errors: Vec<ValidationIssue>,
+    pub warnings: Vec<ValidationIssue>,
+}
+
+/// Reads the CPA-005 file at `path` for display, with the `limit` payments
+/// from `offset` on. A file that breaks the format is still read as far as
+/// it can be, its errors given with the lines they are on. Only a file that
+/// cannot be read, or lacks a header or trailer to read it by, is an error.
+pub fn inspect_file(
+    path: &Path,
+    offset: usize,
+    limit: usize,
+) -> Result<Inspection, Vec<ValidationIssue>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        vec![ValidationIssue {
+            file: None,
+            line: None,
+            field: None,
+            severity: Severity::Error,
+            message: format!("Cannot read {}: {}", path.display(), e),
+        }]
+    })?;
+
+    let (errors, warnings): (Vec<_>, Vec<_>) = lint_cpa005(&contents)
+        .into_iter()
+        .partition(|i| i.severity == Severity::Error);
+
+    let parsed = match read_cpa005(&contents) {
+        Some(parsed) => parsed,
+        None => return Err(errors),
+    };
+
+    let report = parsed.report();
+    let computed = ParsedTrailer {
+        total_debit_amount: report.total_debit_amount,
+        total_debit_count: report.total_debit_count,
+        total_credit_amount: report.total_credit_amount,
+        total_credit_count: report.total_credit_count,
+    };
+
+    let payments = parsed
+        .details
+        .iter()
+        .flat_map(|d| d.segments.iter().map(move |s| (d, s)))
+        .skip(offset)
+        .take(limit)
+        .map(|(detail, segment)| InspectedPayment {
+            line: detail.line,
+            record_type: detail.record_type,
+            customer_number: segment.customer_number.clone(),
+            customer_name: segment.customer_name.clone(),
+            account_number: mask_account(&segment.account_number),
+            institution_number: segment.institution_number.clone(),
+            branch_number: segment.branch_number.clone(),
+            amount: segment.amount,
+            payment_date: segment.payment_date.clone(),
+        })
+        .collect();
+
+    Ok(Inspection {
+        payment_count: parsed.segment_count(),
+        totals_match: parsed.trailer == computed,
+        header: parsed.header,
+        payments,
+        offset,
+        trailer: parsed.trailer,
+        computed,
+        errors,
+        warnings,
+    })
+}
@@ -0,0 +1,134 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Event sent once files opened with the app, by double-clicking them or by
+/// a second launch, have been staged. The window takes them with
+/// `get_pending_files`, as it does the files of the launch it was opened by.
+pub const FILES_OPENED_EVENT: &str = "launch://files-opened";
+
+/// A file the app was opened with that cannot be converted
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RejectedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+/// Files the app was opened with, waiting for the window to take them
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StagedFiles {
+    /// CSV files to add to the conversion list
+    pub files: Vec<String>,
+    /// Files that were left out, to tell the user of
+    pub rejected: Vec<RejectedFile>,
+}
+
+/// Reads `arg` as a path, relative to `cwd`, or as a `file://` URL as some
+/// desktops pass files
+fn launch_path(arg: &str, cwd: &Path) -> PathBuf {
+    let path = match arg.strip_prefix("file://") {
+        Some(url) => PathBuf::from(percent_decode(url)),
+        None => PathBuf::from(arg),
+    };
+
+    cwd.join(path)
+}
+
+/// `text` with each `%XX` replaced by the byte it escapes. Escapes that do
+/// not decode to UTF-8 are kept as they are.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).unwrap_or_else(|_| text.to_string())
+}
+
+/// The files named on the command line `args` of a launch, its program name
+/// first, with relative paths taken from `cwd`. Options, which start with
+/// `-`, are not files and are passed over.
+pub fn launch_paths<I, S>(args: I, cwd: &Path) -> Vec<PathBuf>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    args.into_iter()
+        .skip(1)
+        .filter(|arg| !arg.as_ref().starts_with('-'))
+        .map(|arg| launch_path(arg.as_ref(), cwd))
+        .collect()
+}
+
+/// Why the file at `path` cannot be converted, if it cannot
+fn rejection(path: &Path) -> Option<String> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+
+    if !path.exists() {
+        Some("File does not exist".to_string())
+    } else if !path.is_file() {
+        Some("Not a file".to_string())
+    } else if !is_csv {
+        Some("Not a CSV file".to_string())
+    } else {
+        None
+    }
+}
+
+/// Files opened with the app since the window last took them. Files arrive
+/// from the command line at startup, from later launches forwarded to this
+/// one and from the platform's file open events, whether or not the window
+/// is ready for them.
+#[derive(Default)]
+pub struct PendingFiles {
+    staged: Mutex<StagedFiles>,
+}
+
+impl PendingFiles {
+    /// Stages the CSV files of `paths` and notes the rest as rejected.
+    /// Files already waiting are not staged twice. Returns what was staged
+    /// and rejected of `paths`.
+    pub fn stage<I: IntoIterator<Item = PathBuf>>(&self, paths: I) -> StagedFiles {
+        let mut pending = self.staged.lock().unwrap_or_else(|e| e.into_inner());
+        let mut staged = StagedFiles::default();
+
+        for path in paths {
+            let name = path.display().to_string();
+
+            match rejection(&path) {
+                Some(reason) => staged.rejected.push(RejectedFile { path: name, reason }),
+                None if pending.files.contains(&name) || staged.files.contains(&name) => {}
+                None => staged.files.push(name),
+            }
+        }
+
+        pending.files.extend(staged.files.iter().cloned());
+        pending.rejected.extend(staged.rejected.iter().cloned());
+
+        staged
+    }
+
+    /// The files waiting, which are no longer once taken
+    pub fn take(&self) -> StagedFiles {
+        std::mem::take(&mut *self.staged.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
@@ -1,68 +1,658 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+mod clipboard;
+mod convert;
+#[allow(dead_code)]
 mod csvconv;
+mod history;
+mod inspect;
+mod launch;
+mod numbering;
+mod outputs;
+mod settings;
+mod template;
 
-use csvconv::csv::convert_to_cpa005;
-use csvconv::types::RecordType;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
-
-#[tauri::command]
-fn convert(filename: Vec<&str>, record_type: &str, output_directory: &str) -> Vec<String> {
-    let mut errors = Vec::<String>::new();
-
-    for s in filename {
-        let csv_file = File::open(s);
-
-        match csv_file {
-            Ok(mut f) => {
-                let mut buf = String::new();
-
-                let record_type = match record_type {
-                    "PDS" => RecordType::Credit,
-                    "PAD" => RecordType::Debit,
-                    _ => panic!("invalid record type!"),
-                };
-
-                let result =
-                    convert_to_cpa005(f.read_to_string(&mut buf).unwrap().to_string(), record_type);
-
-                match result {
-                    Ok(s) => {
-                        let outfile_name = format!(
-                            "{}.txt",
-                            Path::new(&s).file_stem().unwrap().to_str().unwrap()
-                        );
-
-                        let outfile = File::create(Path::new(output_directory).join(&outfile_name));
-
-                        match outfile {
-                            Ok(mut f) => {
-                                f.write_all(s.as_bytes());
-                            }
-                            Err(e) => errors.push(format!(
-                                "error: cannot write output file {}: {}",
-                                &outfile_name, e
-                            )),
-                        };
-                    }
-                    Err(e) => errors.extend(e.get_error_list()),
-                }
-            }
-            Err(e) => {
-                errors.push(e.to_string());
-            }
+use clipboard::{clipboard_text, ClipboardKind, LatestConversion};
+use convert::{
+    convert_files_numbered, convert_files_to_chosen_directory, merge_files, preview_conversion,
+    validate_files, BatchConversion, ChosenConversion, ConversionPreview, DirectoryPicker,
+    FileConversionResult, FileRequest, MergeResult, WrittenSummary,
+};
+use csvconv::lint::ValidationIssue;
+use csvconv::parse::{parse_cpa005, ParsedFile};
+use csvconv::summary::current_user;
+use history::{ConversionRequest, History, HistoryEntry, HistoryStore, Rerun, HISTORY_FILE};
+use inspect::{inspect_file, Inspection, DEFAULT_PAGE_SIZE};
+use launch::{launch_paths, PendingFiles, StagedFiles, FILES_OPENED_EVENT};
+use numbering::{BatchNumbering, CreationNumberStore, CREATION_NUMBERS_FILE};
+use outputs::{Opener, OutputError};
+use settings::{ClientProfile, Settings, SETTINGS_FILE};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_dialog::DialogExt;
+
+/// Event `convert` reports its progress with, as each file is started
+/// (`kind` `started`), every so many of its rows (`rows`, with the number read
+/// so far) and once it is done (`finished`, with its `result`). Each names
+/// the file by its `index` in the batch.
+const PROGRESS_EVENT: &str = "conversion://progress";
+
+/// Path of the file `name` in the app's data directory
+fn app_data_file(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(name))
+        .map_err(|e| format!("Cannot find where to keep {}: {}", name, e))
+}
+
+fn settings_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app_data_file(app, SETTINGS_FILE)
+}
+
+/// The saved settings, which the other commands fall back to. Unreadable
+/// settings are an error here, so that the window can say so, but are
+/// otherwise passed over for the defaults.
+#[tauri::command]
+fn get_settings(app: AppHandle) -> Result<Settings, String> {
+    Settings::load(&settings_path(&app)?)
+}
+
+#[tauri::command]
+fn set_settings(app: AppHandle, settings: Settings) -> Result<(), String> {
+    settings.save(&settings_path(&app)?)
+}
+
+/// The file creation number the next file converted for `client_number`
+/// will be given, unless another is chosen for its conversion
+#[tauri::command]
+fn get_next_creation_number(app: AppHandle, client_number: &str) -> Result<u32, String> {
+    app.state::<CreationNumberStore>().next(client_number)
+}
+
+fn saved_settings(app: &AppHandle) -> Settings {
+    settings_path(app)
+        .and_then(|path| Settings::load(&path))
+        .unwrap_or_default()
+}
+
+/// The summary of a batch that converted into `results`, written into
+/// `output_directory` when the settings ask for one
+fn batch_summary(
+    settings: &Settings,
+    results: &[FileConversionResult],
+    output_directory: &str,
+    finished_at: chrono::NaiveDateTime,
+) -> WrittenSummary {
+    if !settings.write_summary {
+        return WrittenSummary::default();
+    }
+
+    WrittenSummary::write(results, output_directory, finished_at, &current_user())
+}
+
+/// Converts each CSV file of `files` into a `record_type` (`PDS` or `PAD`)
+/// file in `output_directory`, named after `output_name`, which defaults to
+/// `{stem}.txt`. The record type and directory default to those of the
+/// settings, whose client profile supplies values the files leave blank.
+///
+/// Each file is its `path`, or `{ path, record_type, options }` to convert it
+/// into a record type of its own, or `lenient` or `whole_dollars_only` where
+/// `options` sets them. A file of an unknown record type fails alone.
+///
+/// Returns the `results` of the files, one for each, in the order given:
+///
+/// - `input`: the file, as given
+/// - `record_type`: the record type it was converted into
+/// - `status`: `converted` or `failed`
+/// - `output`: path of the file written, once converted
+/// - `report`: client number, file creation number and date, and the
+///   payment counts and totals of the converted file
+/// - `errors` and `warnings`: each with the `line` of the CSV file it was
+///   found on, if any, and its `message`
+///
+/// Each file is given the next file creation number of its client, which is
+/// used up once the file is written, so that files that fail do not use up
+/// numbers. `creation_number` numbers the files of each client from it
+/// instead, leaving the next numbers as they were unless
+/// `advance_creation_number` is set.
+///
+/// With `write_summary` set, a summary of the batch is also written into the
+/// output directory as `conversion_summary_<timestamp>.txt` and `.json`,
+/// followed by `_2` and so on if a batch of the same second wrote one too,
+/// whose paths are returned as `summary`, or why they could not be written
+/// as `summary_error`. The other commands converting files write one too.
+///
+/// Progress is reported as [`PROGRESS_EVENT`] events while the files are
+/// converted. The command is async so that it runs off the main thread,
+/// leaving the window free to show them. The batch is then added to the
+/// history.
+#[tauri::command]
+async fn convert(
+    app: AppHandle,
+    files: Vec<FileRequest>,
+    record_type: Option<String>,
+    output_directory: Option<String>,
+    output_name: Option<String>,
+    creation_number: Option<u32>,
+    advance_creation_number: Option<bool>,
+) -> BatchConversion {
+    let settings = saved_settings(&app);
+    let request = ConversionRequest {
+        record_type: record_type
+            .or(settings.record_type.clone())
+            .unwrap_or_default(),
+        output_directory: output_directory
+            .or(settings.output_directory.clone())
+            .unwrap_or_default(),
+        output_name,
+        files,
+    };
+
+    let store = app.state::<CreationNumberStore>();
+    let mut numbering = BatchNumbering::overridden(
+        &store,
+        creation_number,
+        advance_creation_number.unwrap_or(false),
+    );
+
+    let results = convert_files_numbered(
+        &request.files,
+        &request.record_type,
+        &request.output_directory,
+        request.output_name.as_deref(),
+        &settings.conversion_options(),
+        settings.jobs(),
+        Some(&mut numbering),
+        &|event| {
+            // The files are converted whether or not the window hears of it
+            let _ = app.emit(PROGRESS_EVENT, event);
+        },
+    );
+
+    let finished_at = chrono::Local::now().naive_local();
+    let summary = batch_summary(&settings, &results, &request.output_directory, finished_at);
+
+    // The files are converted whether or not the history can be kept
+    let conversion_id = app
+        .state::<HistoryStore>()
+        .record_conversion(request, &results)
+        .ok();
+
+    if let Some(id) = conversion_id {
+        app.state::<LatestConversion>()
+            .keep(id, finished_at, results.clone());
+    }
+
+    BatchConversion {
+        results,
+        summary,
+        conversion_id,
+    }
+}
+
+/// Merges the CSV files of `filename` into a single `record_type` file in
+/// `output_directory`, for payrolls kept in several spreadsheets that are
+/// sent as one file. The record type and directory default to those of the
+/// settings, and the file is named after `output_name` with the stem of the
+/// first CSV file followed by `_merged`.
+///
+/// The files must have the same client number, processing centre, currency
+/// and payment date, and an error names each file that does not, with its
+/// value and that of the first file. Nothing is written if any file fails.
+///
+/// Returns the result of the merged file as `convert` does, whose `input`
+/// lists the files, its errors and warnings each naming the `file` they were
+/// found in, and the `sources`, each with the credit and debit counts and
+/// amounts of one file. The file is numbered, and summarized, as `convert`
+/// numbers and summarizes files.
+#[tauri::command]
+async fn merge_convert(
+    app: AppHandle,
+    filename: Vec<String>,
+    record_type: Option<String>,
+    output_directory: Option<String>,
+    output_name: Option<String>,
+    creation_number: Option<u32>,
+    advance_creation_number: Option<bool>,
+) -> MergeResult {
+    let settings = saved_settings(&app);
+    let inputs: Vec<&str> = filename.iter().map(String::as_str).collect();
+
+    let store = app.state::<CreationNumberStore>();
+    let mut numbering = BatchNumbering::overridden(
+        &store,
+        creation_number,
+        advance_creation_number.unwrap_or(false),
+    );
+
+    let output_directory = output_directory
+        .or(settings.output_directory.clone())
+        .unwrap_or_default();
+
+    let mut merged = merge_files(
+        &inputs,
+        &record_type
+            .or(settings.record_type.clone())
+            .unwrap_or_default(),
+        &output_directory,
+        output_name.as_deref(),
+        &settings.conversion_options(),
+        Some(&mut numbering),
+    );
+
+    merged.summary = batch_summary(
+        &settings,
+        std::slice::from_ref(&merged.result),
+        &output_directory,
+        chrono::Local::now().naive_local(),
+    );
+
+    merged
+}
+
+/// Asks with the folder dialog where converted files are to be written
+struct FolderDialog<'a>(&'a AppHandle);
+
+impl DirectoryPicker for FolderDialog<'_> {
+    fn pick_directory(&self, suggested: Option<&str>) -> Option<String> {
+        let mut dialog = self
+            .0
+            .dialog()
+            .file()
+            .set_title("Choose where to save the converted files");
+
+        if let Some(directory) = suggested {
+            dialog = dialog.set_directory(directory);
+        }
+
+        dialog.blocking_pick_folder().map(|path| path.to_string())
+    }
+}
+
+/// Converts as `convert` does, numbering and summarizing the files as it
+/// does, first asking with a folder dialog where the files are to be
+/// written, starting from the directory of the settings.
+/// Returns `status` `cancelled` if no directory is chosen, in which case
+/// nothing is converted, or `chosen` with the `output_directory` chosen and
+/// the `results` of the files, which all fail if it cannot be written to.
+#[tauri::command]
+async fn convert_choosing_directory(
+    app: AppHandle,
+    files: Vec<FileRequest>,
+    record_type: Option<String>,
+    output_name: Option<String>,
+    creation_number: Option<u32>,
+    advance_creation_number: Option<bool>,
+) -> ChosenConversion {
+    let settings = saved_settings(&app);
+    let record_type = record_type
+        .or(settings.record_type.clone())
+        .unwrap_or_default();
+
+    let store = app.state::<CreationNumberStore>();
+    let mut numbering = BatchNumbering::overridden(
+        &store,
+        creation_number,
+        advance_creation_number.unwrap_or(false),
+    );
+
+    let mut conversion = convert_files_to_chosen_directory(
+        &files,
+        &record_type,
+        output_name.as_deref(),
+        &settings.conversion_options(),
+        settings.jobs(),
+        Some(&mut numbering),
+        &FolderDialog(&app),
+        settings.output_directory.as_deref(),
+        &|event| {
+            let _ = app.emit(PROGRESS_EVENT, event);
+        },
+    );
+
+    if let ChosenConversion::Chosen {
+        output_directory,
+        results,
+        summary,
+    } = &mut conversion
+    {
+        let finished_at = chrono::Local::now().naive_local();
+        *summary = batch_summary(&settings, results, output_directory, finished_at);
+
+        let request = ConversionRequest {
+            files,
+            record_type,
+            output_directory: output_directory.clone(),
+            output_name,
+        };
+
+        if let Ok(id) = app
+            .state::<HistoryStore>()
+            .record_conversion(request, results)
+        {
+            app.state::<LatestConversion>()
+                .keep(id, finished_at, results.clone());
         }
     }
 
-    return errors;
+    conversion
+}
+
+/// Copies the `kind` of the conversion `conversion_id` of the history to
+/// the clipboard, to be pasted into an email or the bank's portal: its
+/// converted files (`Output`), its summary (`Summary`) or its errors and
+/// warnings (`Errors`). The latest conversion is copied from its results as
+/// returned, and earlier ones from the history, which does not keep their
+/// errors. Returns the number of characters copied.
+#[tauri::command]
+fn copy_to_clipboard(
+    app: AppHandle,
+    kind: ClipboardKind,
+    conversion_id: u64,
+) -> Result<usize, String> {
+    // Only conversions before the latest are looked up in the history
+    let history = app.state::<HistoryStore>().load().unwrap_or_default();
+
+    let text = clipboard_text(
+        kind,
+        conversion_id,
+        &app.state::<LatestConversion>(),
+        &history,
+        &current_user(),
+    )?;
+    let copied = text.chars().count();
+
+    app.clipboard()
+        .write_text(text)
+        .map_err(|e| format!("Cannot copy to the clipboard: {}", e))?;
+
+    Ok(copied)
+}
+
+/// The latest conversions, newest first, each with its `id`, `timestamp`,
+/// `files`, `record_type`, `output_directory` and `output_name`, the
+/// `outputs` written, the `totals` of their payments and how many files were
+/// `converted` and `failed`
+#[tauri::command]
+fn get_history(app: AppHandle) -> Result<Vec<HistoryEntry>, String> {
+    let history = app.state::<HistoryStore>().load()?;
+
+    Ok(history.entries().iter().rev().cloned().collect())
+}
+
+#[tauri::command]
+fn clear_history(app: AppHandle) -> Result<(), String> {
+    app.state::<HistoryStore>().clear()
+}
+
+/// Converts the batch `history_id` of the history again, with the same files,
+/// record type and output directory, reporting progress and writing a summary
+/// as `convert` does. Returns `kind` `converted` with the `output_directory`
+/// and its `results`, or `missing_inputs` with the `request` and the
+/// `missing` files, which are to be chosen again.
+#[tauri::command]
+async fn rerun(app: AppHandle, history_id: u64) -> Result<Rerun, String> {
+    let settings = saved_settings(&app);
+    let store = app.state::<CreationNumberStore>();
+
+    let mut rerun = history::rerun(
+        &app.state::<HistoryStore>(),
+        history_id,
+        &settings.conversion_options(),
+        settings.jobs(),
+        &store,
+        &|event| {
+            let _ = app.emit(PROGRESS_EVENT, event);
+        },
+    )?;
+
+    if let Rerun::Converted {
+        output_directory,
+        results,
+        summary,
+    } = &mut rerun
+    {
+        let finished_at = chrono::Local::now().naive_local();
+        *summary = batch_summary(&settings, results, output_directory, finished_at);
+    }
+
+    Ok(rerun)
+}
+
+/// Shows files with the platform's file manager and default viewers
+struct PlatformOpener;
+
+impl Opener for PlatformOpener {
+    fn reveal(&self, path: &Path) -> Result<(), String> {
+        tauri_plugin_opener::reveal_item_in_dir(path).map_err(|e| e.to_string())
+    }
+
+    fn open(&self, path: &Path) -> Result<(), String> {
+        tauri_plugin_opener::open_path(path, None::<&str>).map_err(|e| e.to_string())
+    }
+}
+
+/// The history that converted files are checked against before they are
+/// shown. One that cannot be read records nothing, so nothing is shown.
+fn output_history(app: &AppHandle) -> History {
+    app.state::<HistoryStore>().load().unwrap_or_default()
+}
+
+/// Shows the converted file `path` selected in its folder. Only files the
+/// history records converting are shown. Fails with `kind` `not_produced` for
+/// any other path, `moved` if the file is no longer there, or `failed` with a
+/// `message` if it cannot be shown.
+#[tauri::command]
+fn reveal_in_folder(app: AppHandle, path: &str) -> Result<(), OutputError> {
+    outputs::reveal_output(&output_history(&app), path, &PlatformOpener)
+}
+
+/// Opens the converted file `path` in the default text viewer, failing as
+/// `reveal_in_folder` does
+#[tauri::command]
+fn open_output(app: AppHandle, path: &str) -> Result<(), OutputError> {
+    outputs::open_output(&output_history(&app), path, &PlatformOpener)
+}
+
+/// Checks each CSV file of `filename` as `convert` would convert it into a
+/// `record_type` file, without writing anything. Returns a result for each
+/// file as `convert` does, with a `status` of `valid` for files that would be
+/// converted and no `output`.
+#[tauri::command]
+fn validate(
+    app: AppHandle,
+    filename: Vec<&str>,
+    record_type: Option<String>,
+) -> Vec<FileConversionResult> {
+    let settings = saved_settings(&app);
+
+    validate_files(
+        &filename,
+        &record_type
+            .or(settings.record_type.clone())
+            .unwrap_or_default(),
+        &settings.conversion_options(),
+    )
+}
+
+/// Converts the CSV file `filename` as `convert` would, with the same
+/// `record_type`, without writing anything. Returns its totals, warnings or
+/// errors and the first `records` records of the converted file, each with
+/// its `record_type` and `text`, so that it can be checked before saving.
+#[tauri::command]
+fn preview(
+    app: AppHandle,
+    filename: &str,
+    record_type: Option<String>,
+    records: usize,
+) -> ConversionPreview {
+    let settings = saved_settings(&app);
+
+    preview_conversion(
+        filename,
+        &record_type
+            .or(settings.record_type.clone())
+            .unwrap_or_default(),
+        records,
+        &settings.conversion_options(),
+    )
+}
+
+/// The starter CSV template, its labelled rows filled in from the client
+/// profile of the settings where it gives them, and otherwise with example
+/// values, followed by the column headings and an example payment dated
+/// today. It converts as it is.
+#[tauri::command]
+fn template_contents(app: AppHandle) -> String {
+    template::template_contents(
+        saved_settings(&app).client.as_ref(),
+        chrono::Local::now().date_naive(),
+    )
+}
+
+/// Writes the template of `template_contents` to `path`, filled in from
+/// `client_profile` if given, or else from that of the settings. An existing
+/// file is refused unless `overwrite` is set. Returns the path written.
+#[tauri::command]
+fn write_template(
+    app: AppHandle,
+    path: String,
+    client_profile: Option<ClientProfile>,
+    overwrite: Option<bool>,
+) -> Result<String, String> {
+    let profile = client_profile.or(saved_settings(&app).client);
+
+    template::write_template(
+        Path::new(&path),
+        profile.as_ref(),
+        chrono::Local::now().date_naive(),
+        overwrite.unwrap_or(false),
+    )?;
+
+    Ok(path)
+}
+
+/// A previously converted file, read back for review
+type PreviewData = ParsedFile;
+
+/// Reads back a CPA-005 file, such as one written by `convert`
+fn read_cpa005_file(path: &Path) -> Result<PreviewData, Vec<String>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| vec![format!("error: cannot read {}: {}", path.display(), e)])?;
+
+    parse_cpa005(&contents)
+}
+
+/// Reads back the CPA-005 file at `path` for review, with its account
+/// numbers masked but for their last four characters
+#[tauri::command]
+fn preview_file(path: &str) -> Result<PreviewData, Vec<String>> {
+    let mut parsed = read_cpa005_file(Path::new(path))?;
+    parsed.mask_accounts();
+
+    Ok(parsed)
+}
+
+/// Reads back the CPA-005 file at `path` for display, even one that breaks
+/// the format: its header, trailer and the totals its detail records add up
+/// to, with `limit` of its payments from `offset` on and its errors and
+/// warnings by line. Account numbers are masked but for their last four
+/// characters. Fails only if the file cannot be read or has no header or
+/// trailer to read it by.
+#[tauri::command]
+fn inspect(
+    path: &str,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<Inspection, Vec<ValidationIssue>> {
+    inspect_file(
+        Path::new(path),
+        offset.unwrap_or(0),
+        limit.unwrap_or(DEFAULT_PAGE_SIZE),
+    )
+}
+
+/// Stages the files of `paths`, which the app was opened with, and lets the
+/// window know. Files that cannot be converted are staged as rejected, for
+/// the window to say why.
+fn stage_opened_files(app: &AppHandle, paths: Vec<PathBuf>) {
+    if paths.is_empty() {
+        return;
+    }
+
+    let staged = app.state::<PendingFiles>().stage(paths);
+    // The files stay staged for the window to take when it is ready
+    let _ = app.emit(FILES_OPENED_EVENT, staged);
+}
+
+/// The CSV files the app was opened with since this was last asked, by
+/// double-clicking them or by launching it with them, for the window to add
+/// to its conversion list, along with the files that were left out and why
+#[tauri::command]
+fn get_pending_files(app: AppHandle) -> StagedFiles {
+    app.state::<PendingFiles>().take()
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        // Files opened while the app is running are forwarded to it rather
+        // than opening another window
+        .plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+            stage_opened_files(app, launch_paths(args, Path::new(&cwd)));
+
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.unminimize();
+                let _ = window.set_focus();
+            }
+        }))
+        .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_dialog::init())
-        .invoke_handler(tauri::generate_handler![convert])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .manage(LatestConversion::default())
+        .manage(PendingFiles::default())
+        .setup(|app| {
+            let path = app_data_file(app.handle(), CREATION_NUMBERS_FILE)?;
+            app.manage(CreationNumberStore::new(path));
+            let path = app_data_file(app.handle(), HISTORY_FILE)?;
+            app.manage(HistoryStore::new(path));
+
+            let cwd = std::env::current_dir().unwrap_or_default();
+            stage_opened_files(app.handle(), launch_paths(std::env::args(), &cwd));
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            clear_history,
+            convert,
+            convert_choosing_directory,
+            copy_to_clipboard,
+            get_history,
+            get_next_creation_number,
+            get_pending_files,
+            get_settings,
+            inspect,
+            merge_convert,
+            open_output,
+            preview,
+            preview_file,
+            rerun,
+            reveal_in_folder,
+            set_settings,
+            template_contents,
+            validate,
+            write_template
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, _event| {
+            // macOS hands over the files a launch was opened with as an
+            // event rather than on the command line
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            if let tauri::RunEvent::Opened { urls } = _event {
+                let paths = urls
+                    .iter()
+                    .filter_map(|url| url.to_file_path().ok())
+                    .collect();
+                stage_opened_files(_app, paths);
+            }
+        });
 }
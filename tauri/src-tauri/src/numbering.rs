@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::csvconv::header::MAX_FILE_CREATION_NUMBER;
+use crate::csvconv::utils::strip_client_number_separators;
+use crate::settings::replace_file;
+
+/// Name of the file creation number file in the app's data directory
+pub const CREATION_NUMBERS_FILE: &str = "creation_numbers.json";
+
+/// The number after `number`, starting over from 1 after 9999
+pub fn following(number: u32) -> u32 {
+    if number >= MAX_FILE_CREATION_NUMBER {
+        1
+    } else {
+        number + 1
+    }
+}
+
+/// Client numbers are written with or without separators, and count as one
+/// client either way
+fn client_key(client_number: &str) -> String {
+    strip_client_number_separators(client_number.trim())
+}
+
+/// The next file creation number of each client, by client number
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+struct CreationNumbers {
+    next: BTreeMap<String, u32>,
+}
+
+/// File creation numbers kept between launches, so that each file sent for a
+/// client has a number of its own. Numbers are handed out one conversion at
+/// a time, so that conversions running together never share one.
+pub struct CreationNumberStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+/// The numbers of the store, held by one conversion until it is dropped
+pub struct LockedNumbers<'a> {
+    _guard: MutexGuard<'a, ()>,
+    path: &'a Path,
+    numbers: CreationNumbers,
+}
+
+impl CreationNumberStore {
+    /// Numbers kept at `path`, all starting from 1 if none have been yet
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Holds the numbers until the result is dropped, so that no other
+    /// conversion is handed the same one
+    pub fn lock(&self) -> Result<LockedNumbers<'_>, String> {
+        // A conversion that panicked leaves nothing half written, as the
+        // numbers are only saved whole
+        let guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        let numbers = match std::fs::read_to_string(&self.path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| {
+                format!(
+                    "File creation numbers {} are not valid: {}",
+                    self.path.display(),
+                    e
+                )
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => CreationNumbers::default(),
+            Err(e) => {
+                return Err(format!(
+                    "Cannot read file creation numbers {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            }
+        };
+
+        Ok(LockedNumbers {
+            _guard: guard,
+            path: &self.path,
+            numbers,
+        })
+    }
+
+    /// The number the next file of `client_number` is given, without using
+    /// it up
+    pub fn next(&self, client_number: &str) -> Result<u32, String> {
+        Ok(self.lock()?.next(client_number))
+    }
+}
+
+impl LockedNumbers<'_> {
+    pub fn next(&self, client_number: &str) -> u32 {
+        self.numbers
+            .next
+            .get(&client_key(client_number))
+            .copied()
+            .unwrap_or(1)
+    }
+
+    /// Uses up `number` of `client_number`, the client's next file being
+    /// given the one after it. Saved at once, so that a number is never
+    /// handed out again once a file has been written with it.
+    pub fn advance_past(&mut self, client_number: &str, number: u32) -> Result<(), String> {
+        self.numbers
+            .next
+            .insert(client_key(client_number), following(number));
+
+        let text = serde_json::to_string_pretty(&self.numbers).expect("numbers serialize");
+        replace_file(self.path, &text).map_err(|e| {
+            format!(
+                "Cannot save file creation numbers {}: {}",
+                self.path.display(),
+                e
+            )
+        })
+    }
+}
+
+/// How the files of one batch are numbered
+pub struct BatchNumbering<'a> {
+    store: &'a CreationNumberStore,
+    /// Number of the first file of each client, in place of the store's
+    first: Option<u32>,
+    /// Whether the numbers given with `first` are used up in the store too
+    advance: bool,
+    /// Number of the latest file of each client of the batch
+    given: HashMap<String, u32>,
+}
+
+impl<'a> BatchNumbering<'a> {
+    /// Each file given the next number of its client from `store`
+    pub fn from_store(store: &'a CreationNumberStore) -> Self {
+        Self::overridden(store, None, true)
+    }
+
+    /// Files numbered from `first` if given, and from `store` otherwise.
+    /// Numbers given with `first` only advance the store if `advance` is
+    /// set.
+    pub fn overridden(store: &'a CreationNumberStore, first: Option<u32>, advance: bool) -> Self {
+        Self {
+            store,
+            first,
+            advance,
+            given: HashMap::new(),
+        }
+    }
+
+    pub fn store(&self) -> &'a CreationNumberStore {
+        self.store
+    }
+
+    /// The number of the next file of `client_number`. Files of a client
+    /// that appears more than once in a batch are numbered on from each
+    /// other.
+    pub fn number_for(&self, numbers: &LockedNumbers, client_number: &str) -> u32 {
+        match self.given.get(&client_key(client_number)) {
+            Some(latest) => following(*latest),
+            None => self.first.unwrap_or_else(|| numbers.next(client_number)),
+        }
+    }
+
+    /// Records that a file of `client_number` was written with `number`
+    pub fn used(
+        &mut self,
+        numbers: &mut LockedNumbers,
+        client_number: &str,
+        number: u32,
+    ) -> Result<(), String> {
+        self.given.insert(client_key(client_number), number);
+
+        if self.first.is_none() || self.advance {
+            numbers.advance_past(client_number, number)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+use crate::history::History;
+
+/// Shows files to the user with what the platform provides
+pub trait Opener {
+    /// Shows `path` selected in its folder, in Explorer or Finder
+    fn reveal(&self, path: &Path) -> Result<(), String>;
+    /// Opens `path` in the default viewer for its type
+    fn open(&self, path: &Path) -> Result<(), String>;
+}
+
+/// Why a converted file could not be shown
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OutputError {
+    /// The app never wrote `path`, so it is not opened
+    NotProduced { path: String },
+    /// `path` was written, but has since been moved or removed
+    Moved { path: String },
+    /// The platform could not show `path`
+    Failed { path: String, message: String },
+}
+
+impl Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotProduced { path } => write!(f, "{} was not converted by this app", path),
+            Self::Moved { path } => write!(
+                f,
+                "{} no longer exists. It may have been moved or removed.",
+                path
+            ),
+            Self::Failed { path, message } => write!(f, "Cannot show {}: {}", path, message),
+        }
+    }
+}
+
+/// `path`, if it is a file `history` records converting and it is still
+/// there. Paths are matched as written rather than resolved, so that none
+/// outside of those recorded can be reached through `..` or links.
+pub fn produced_output(history: &History, path: &str) -> Result<PathBuf, OutputError> {
+    let produced = history
+        .entries()
+        .iter()
+        .flat_map(|e| &e.outputs)
+        .any(|output| Path::new(output) == Path::new(path));
+
+    if !produced {
+        return Err(OutputError::NotProduced {
+            path: path.to_string(),
+        });
+    }
+
+    let output = PathBuf::from(path);
+    if !output.is_file() {
+        return Err(OutputError::Moved {
+            path: path.to_string(),
+        });
+    }
+
+    Ok(output)
+}
+
+/// Shows the converted file `path` in its folder with `opener`
+pub fn reveal_output(
+    history: &History,
+    path: &str,
+    opener: &dyn Opener,
+) -> Result<(), OutputError> {
+    let output = produced_output(history, path)?;
+
+    opener
+        .reveal(&output)
+        .map_err(|message| OutputError::Failed {
+            path: path.to_string(),
+            message,
+        })
+}
+
+/// Opens the converted file `path` in the default viewer with `opener`
+pub fn open_output(history: &History, path: &str, opener: &dyn Opener) -> Result<(), OutputError> {
+    let output = produced_output(history, path)?;
+
+    opener.open(&output).map_err(|message| OutputError::Failed {
+        path: path.to_string(),
+        message,
+    })
+}
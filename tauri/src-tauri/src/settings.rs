@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::csvconv::options::{ConversionOptions, HeaderDefault};
+use crate::csvconv::parallel::default_jobs;
+use crate::csvconv::schema::{
+    CLIENT_NAME, CLIENT_NUMBER, CURRENCY_CODE, PAYMENT_DATE, PROCESSING_CENTRE, TRANSACTION_CODE,
+};
+use crate::csvconv::types::PaymentType;
+
+/// Name of the settings file in the app's data directory
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// Version of the settings written by this build
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// Upgrades settings of each version to the next, the first entry taking
+/// version 1 to 2 and so on. Fields added with a default need no entry.
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+
+/// Writes `contents` to `path`, creating its directory if need be. They are
+/// written aside and renamed over the old file, so that a failed write
+/// leaves it as it was.
+pub fn replace_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, path)
+}
+
+/// Values of the labelled rows at the top of a CSV file, used for a client
+/// whose files leave them blank
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientProfile {
+    pub client_name: Option<String>,
+    pub client_number: Option<String>,
+    pub processing_centre: Option<String>,
+    pub currency_code: Option<String>,
+    /// `YYYY/MM/DD`
+    pub payment_date: Option<String>,
+    pub transaction_code: Option<String>,
+}
+
+impl ClientProfile {
+    /// The profile's values by the label of the row they are used for. The
+    /// file's own values take precedence.
+    pub fn header_defaults(&self) -> HashMap<&'static str, HeaderDefault> {
+        [
+            (CLIENT_NAME, &self.client_name),
+            (CLIENT_NUMBER, &self.client_number),
+            (PROCESSING_CENTRE, &self.processing_centre),
+            (CURRENCY_CODE, &self.currency_code),
+            (PAYMENT_DATE, &self.payment_date),
+            (TRANSACTION_CODE, &self.transaction_code),
+        ]
+        .into_iter()
+        .filter_map(|(label, value)| {
+            let value = value.as_deref()?.trim();
+
+            (!value.is_empty()).then(|| {
+                (
+                    label,
+                    HeaderDefault {
+                        value: value.to_string(),
+                        overridable: true,
+                    },
+                )
+            })
+        })
+        .collect()
+    }
+}
+
+/// What the app remembers between launches
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    /// Where converted files are written when no other directory is chosen
+    pub output_directory: Option<String>,
+    /// `PDS` or `PAD`, used when no other record type is chosen
+    pub record_type: Option<String>,
+    /// Report validation failures as warnings instead of errors
+    pub lenient: bool,
+    /// Write a summary of each batch converted into its output directory
+    pub write_summary: bool,
+    /// Language the app is shown in, such as `en-CA`
+    pub locale: Option<String>,
+    pub client: Option<ClientProfile>,
+    /// Files converted at once, one for each processor if not set
+    pub jobs: Option<usize>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            output_directory: None,
+            record_type: None,
+            lenient: false,
+            write_summary: false,
+            locale: None,
+            client: None,
+            jobs: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings saved at `path`, or the defaults if none have been
+    /// saved yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Cannot read settings {}: {}", path.display(), e)),
+        };
+
+        let unreadable =
+            |e: serde_json::Error| format!("Settings {} are not valid: {}", path.display(), e);
+        let mut value: Value = serde_json::from_str(&text).map_err(unreadable)?;
+
+        // Settings saved before they were versioned are of the first version
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(1) as u32;
+
+        if version > SETTINGS_VERSION {
+            return Err(format!(
+                "Settings {} were saved by a newer version of the app (version {})",
+                path.display(),
+                version
+            ));
+        }
+
+        for migrate in MIGRATIONS.iter().skip(version.saturating_sub(1) as usize) {
+            migrate(&mut value);
+        }
+
+        let mut settings: Self = serde_json::from_value(value).map_err(unreadable)?;
+        settings.version = SETTINGS_VERSION;
+
+        Ok(settings)
+    }
+
+    /// Saves the settings to `path`, creating its directory if need be.
+    /// Settings naming an unknown record type are refused.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(record_type) = &self.record_type {
+            record_type
+                .parse::<PaymentType>()
+                .map_err(|e| format!("Record type {}", e))?;
+        }
+
+        let settings = Self {
+            version: SETTINGS_VERSION,
+            ..self.clone()
+        };
+        let text = serde_json::to_string_pretty(&settings).expect("settings serialize");
+
+        replace_file(path, &text)
+            .map_err(|e| format!("Cannot save settings {}: {}", path.display(), e))
+    }
+
+    /// Conversion options following the settings
+    pub fn conversion_options(&self) -> ConversionOptions {
+        let mut options = ConversionOptions::default();
+
+        options.validation.lenient = self.lenient;
+        if let Some(client) = &self.client {
+            options.header_defaults = client.header_defaults();
+        }
+
+        options
+    }
+
+    /// Files converted at once, at least one
+    pub fn jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(default_jobs).max(1)
+    }
+}
@@ -0,0 +1,47 @@
+use chrono::NaiveDate;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use crate::csvconv::template::csv_template;
+use crate::settings::ClientProfile;
+
+/// The starter CSV template, its labelled rows filled in from `profile`
+/// where it gives them, with an example payment on `payment_date`
+pub fn template_contents(profile: Option<&ClientProfile>, payment_date: NaiveDate) -> String {
+    let defaults = profile
+        .map(ClientProfile::header_defaults)
+        .unwrap_or_default();
+
+    csv_template(&defaults, payment_date)
+}
+
+/// Writes the template of [`template_contents`] to `path`. A file already
+/// there is only replaced if `overwrite` is set, so that a spreadsheet is
+/// never lost to a template by mistake.
+pub fn write_template(
+    path: &Path,
+    profile: Option<&ClientProfile>,
+    payment_date: NaiveDate,
+    overwrite: bool,
+) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    options
+        .open(path)
+        .and_then(|mut f| f.write_all(template_contents(profile, payment_date).as_bytes()))
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => format!(
+                "{} already exists. Choose another name, or replace it.",
+                path.display()
+            ),
+            _ => format!("Cannot write template {}: {}", path.display(), e),
+        })
+}
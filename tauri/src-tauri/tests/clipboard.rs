@@ -0,0 +1,160 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/convert.rs"]
+#[allow(dead_code)]
+mod convert;
+
+#[path = "../src/settings.rs"]
+#[allow(dead_code)]
+mod settings;
+
+#[path = "../src/numbering.rs"]
+#[allow(dead_code)]
+mod numbering;
+
+#[path = "../src/history.rs"]
+#[allow(dead_code)]
+mod history;
+
+#[path = "../src/clipboard.rs"]
+mod clipboard;
+
+mod common;
+
+use chrono::NaiveDate;
+use clipboard::{clipboard_text, ClipboardKind, LatestConversion};
+use common::test_dir;
+use convert::{convert_files_numbered, FileConversionResult, FileRequest};
+use csvconv::options::ConversionOptions;
+use history::{ConversionRequest, History, HistoryStore, HISTORY_FILE};
+use std::path::Path;
+
+const PAYROLL: &str = "Client Name,Acme Widgets
+Client Number,0123456789
+Processing Centre,00320
+Currency Code,CAD
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+1001,Jane Smith,003,01234,1234567,\"$1,250.00\",N,,
+1002,John Doe,003,01234,7654321,$500.00,N,,
+";
+
+/// Converts the payroll and another with an account number that is not one
+/// into `dir`, recording them in its history as conversion 1
+fn convert_batch(dir: &Path) -> Vec<FileConversionResult> {
+    let payroll = dir.join("payroll.csv");
+    let invalid = dir.join("invalid.csv");
+    std::fs::write(&payroll, PAYROLL).unwrap();
+    std::fs::write(&invalid, PAYROLL.replace("7654321", "76x4321")).unwrap();
+
+    let inputs = [payroll.to_str().unwrap(), invalid.to_str().unwrap()];
+    let results = convert_files_numbered(
+        &inputs.map(FileRequest::new),
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    let request = ConversionRequest {
+        files: inputs.iter().map(|i| FileRequest::new(i)).collect(),
+        record_type: "PDS".to_string(),
+        output_directory: dir.to_str().unwrap().to_string(),
+        output_name: None,
+    };
+    let id = HistoryStore::new(dir.join(HISTORY_FILE))
+        .record_conversion(request, &results)
+        .unwrap();
+    assert_eq!(id, 1);
+
+    results
+}
+
+fn finished_at() -> chrono::NaiveDateTime {
+    NaiveDate::from_ymd_opt(2045, 1, 31)
+        .unwrap()
+        .and_hms_opt(9, 30, 0)
+        .unwrap()
+}
+
+#[test]
+fn latest_conversion_copied_by_kind() {
+    let tmp = test_dir("latest");
+    let dir = tmp.path();
+    let results = convert_batch(dir);
+    let history = History::load(&dir.join(HISTORY_FILE)).unwrap();
+
+    let latest = LatestConversion::default();
+    latest.keep(1, finished_at(), results);
+
+    let copy = |kind| clipboard_text(kind, 1, &latest, &history, "payroll-clerk").unwrap();
+
+    let output = copy(ClipboardKind::Output);
+    assert_eq!(
+        output,
+        std::fs::read_to_string(dir.join("payroll.txt")).unwrap() + "\n"
+    );
+
+    let summary = copy(ClipboardKind::Summary);
+    assert!(summary.starts_with("Conversion summary\nGenerated: 2045-01-31 09:30:00\n"));
+    assert!(summary.contains("Generated by: payroll-clerk\n"));
+    assert!(summary.contains("Files: 1 converted, 1 failed\n"));
+    assert!(summary.contains("Credits: 2 items, 1750.00\n"));
+
+    let errors = copy(ClipboardKind::Errors);
+    let invalid = dir.join("invalid.csv");
+    assert_eq!(
+        errors.lines().next().unwrap(),
+        format!(
+            "{}: error: line 9: Account number must only include digits",
+            invalid.display()
+        )
+    );
+    assert!(!errors.contains("76x4321"));
+}
+
+#[test]
+fn earlier_conversion_copied_from_history() {
+    let tmp = test_dir("history");
+    let dir = tmp.path();
+    convert_batch(dir);
+    let history = History::load(&dir.join(HISTORY_FILE)).unwrap();
+
+    // Nothing kept in memory, as after the app is started again
+    let latest = LatestConversion::default();
+    let copy = |kind| clipboard_text(kind, 1, &latest, &history, "payroll-clerk");
+
+    assert_eq!(
+        copy(ClipboardKind::Output).unwrap(),
+        std::fs::read_to_string(dir.join("payroll.txt")).unwrap() + "\n"
+    );
+
+    let summary = copy(ClipboardKind::Summary).unwrap();
+    assert!(summary.contains("Files: 1 converted, 1 failed\n"));
+    assert!(summary.contains("Credits: 2 items, 1750.00\n"));
+    assert!(summary.contains(&format!("Output: {}\n", dir.join("payroll.txt").display())));
+
+    let errors = copy(ClipboardKind::Errors).unwrap_err();
+    assert!(errors.contains("only kept until the next conversion"));
+}
+
+#[test]
+fn unknown_conversion_not_copied() {
+    let tmp = test_dir("unknown");
+    let dir = tmp.path();
+    let results = convert_batch(dir);
+    let history = History::load(&dir.join(HISTORY_FILE)).unwrap();
+
+    let latest = LatestConversion::default();
+    latest.keep(1, finished_at(), results);
+
+    let error =
+        clipboard_text(ClipboardKind::Output, 7, &latest, &history, "payroll-clerk").unwrap_err();
+    assert_eq!(error, "Conversion 7 is no longer in the history");
+}
@@ -0,0 +1,10 @@
+use tempfile::TempDir;
+
+/// A directory of its own for each test, removed when it is dropped, even
+/// when the test fails
+pub fn test_dir(name: &str) -> TempDir {
+    tempfile::Builder::new()
+        .prefix(&format!("rbc-{}-", name))
+        .tempdir()
+        .unwrap()
+}
@@ -0,0 +1,657 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/settings.rs"]
+#[allow(dead_code)]
+mod settings;
+
+#[path = "../src/numbering.rs"]
+#[allow(dead_code)]
+mod numbering;
+
+#[path = "../src/convert.rs"]
+#[allow(dead_code)]
+mod convert;
+
+mod common;
+
+use common::test_dir;
+use convert::{
+    convert_files_numbered, convert_files_to_chosen_directory, preview_conversion, validate_files,
+    write_output, ChosenConversion, ConversionProgress, ConversionStatus, DirectoryPicker,
+    FileOptions, FileRequest,
+};
+use csvconv::options::ConversionOptions;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const PAYROLL: &str = "Client Name,Acme Widgets Ltd
+Client Number,0123456789
+Processing Centre,00320
+Currency Code,CAD
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+1001,Jane Smith,003,01234,1234567,\"$1,250.00\",N,,
+1002,John Doe,003,01234,7654321,$500.00,N,,
+";
+
+#[test]
+fn mixed_batch_reports_each_file() {
+    let tmp = test_dir("mixed");
+    let dir = tmp.path();
+    let good = dir.join("payroll.csv");
+    let bad = dir.join("broken.csv");
+    std::fs::write(&good, PAYROLL).unwrap();
+    std::fs::write(&bad, PAYROLL.replace("7654321", "76543X1")).unwrap();
+
+    let good = good.to_str().unwrap();
+    let bad = bad.to_str().unwrap();
+    let results = convert_files_numbered(
+        &[good, bad].map(FileRequest::new),
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    assert_eq!(results.len(), 2);
+
+    let converted = &results[0];
+    assert_eq!(converted.input, good);
+    assert_eq!(converted.status, ConversionStatus::Converted);
+    assert!(converted.errors.is_empty());
+    let output = converted.output.as_deref().unwrap();
+    assert_eq!(output, dir.join("payroll.txt").to_str().unwrap());
+    assert!(std::fs::read_to_string(output).unwrap().starts_with('A'));
+    let report = converted.report.as_ref().unwrap();
+    assert_eq!(report.total_credit_count, 2);
+    assert_eq!(report.total_credit_amount, 175000);
+
+    let failed = &results[1];
+    assert_eq!(failed.input, bad);
+    assert_eq!(failed.status, ConversionStatus::Failed);
+    assert!(failed.output.is_none() && failed.report.is_none());
+    assert_eq!(failed.errors.len(), 1);
+    assert_eq!(failed.errors[0].line, Some(9));
+    assert!(!dir.join("broken.txt").exists());
+
+    let json = serde_json::to_value(&results).unwrap();
+    assert_eq!(json[0]["status"], "converted");
+    assert_eq!(json[1]["status"], "failed");
+    assert_eq!(json[1]["errors"][0]["line"], 9);
+}
+
+#[test]
+fn invalid_record_type_fails_every_file() {
+    let results = convert_files_numbered(
+        &["a.csv", "b.csv"].map(FileRequest::new),
+        "XYZ",
+        "/tmp",
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    assert_eq!(results.len(), 2);
+    for (result, input) in results.iter().zip(["a.csv", "b.csv"]) {
+        assert_eq!(result.input, input);
+        assert_eq!(result.status, ConversionStatus::Failed);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(
+            result.errors[0].message,
+            "Record type must be PDS or PAD, not \"XYZ\""
+        );
+    }
+}
+
+#[test]
+fn each_file_converted_into_its_own_record_type() {
+    let tmp = test_dir("record-types");
+    let dir = tmp.path();
+    let credits = dir.join("payroll.csv");
+    let debits = dir.join("collections.csv");
+    let unknown = dir.join("unknown.csv");
+    for input in [&credits, &debits, &unknown] {
+        std::fs::write(input, PAYROLL).unwrap();
+    }
+
+    let files: Vec<FileRequest> = serde_json::from_value(serde_json::json!([
+        credits.to_str().unwrap(),
+        { "path": debits.to_str().unwrap(), "record_type": "PAD" },
+        { "path": unknown.to_str().unwrap(), "record_type": "XYZ" },
+    ]))
+    .unwrap();
+    assert_eq!(files[0], FileRequest::new(credits.to_str().unwrap()));
+
+    let results = convert_files_numbered(
+        &files,
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    let record_types: Vec<&str> = results.iter().map(|r| r.record_type.as_str()).collect();
+    assert_eq!(record_types, ["PDS", "PAD", "XYZ"]);
+
+    for (result, detail) in results[..2].iter().zip(['C', 'D']) {
+        assert_eq!(result.status, ConversionStatus::Converted);
+        let text = std::fs::read_to_string(result.output.as_deref().unwrap()).unwrap();
+        let records: Vec<&str> = text.lines().collect();
+        assert!(records[1..records.len() - 1]
+            .iter()
+            .all(|r| r.starts_with(detail)));
+    }
+    let debit = results[1].report.as_ref().unwrap();
+    assert_eq!((debit.total_debit_count, debit.total_credit_count), (2, 0));
+
+    assert_eq!(results[2].status, ConversionStatus::Failed);
+    assert_eq!(
+        results[2].errors[0].message,
+        "Record type must be PDS or PAD, not \"XYZ\""
+    );
+    assert!(!dir.join("unknown.txt").exists());
+}
+
+#[test]
+fn file_options_override_batch_options() {
+    let tmp = test_dir("file-options");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    std::fs::write(&input, PAYROLL.replace("$500.00", "$500.25")).unwrap();
+
+    let strict = FileRequest {
+        options: Some(FileOptions {
+            whole_dollars_only: Some(true),
+            ..FileOptions::default()
+        }),
+        ..FileRequest::new(input.to_str().unwrap())
+    };
+    let results = convert_files_numbered(
+        &[strict, FileRequest::new(input.to_str().unwrap())],
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    assert_eq!(results[0].status, ConversionStatus::Failed);
+    assert_eq!(results[1].status, ConversionStatus::Converted);
+}
+
+#[test]
+fn output_named_after_input_file() {
+    let tmp = test_dir("named");
+    let dir = tmp.path();
+    let input = dir.join("fixture.csv");
+    let awkward = dir.join("pay:roll?.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+    std::fs::write(&awkward, PAYROLL).unwrap();
+
+    let results = convert_files_numbered(
+        &[input.to_str().unwrap(), awkward.to_str().unwrap()].map(FileRequest::new),
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    let expected = dir.join("fixture.txt");
+    assert_eq!(results[0].output.as_deref(), expected.to_str());
+    assert!(expected.is_file());
+
+    let expected = dir.join("pay_roll_.txt");
+    assert_eq!(results[1].output.as_deref(), expected.to_str());
+    assert!(expected.is_file());
+
+    let mut names: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "fixture.csv",
+            "fixture.txt",
+            "pay:roll?.csv",
+            "pay_roll_.txt"
+        ]
+    );
+}
+
+#[test]
+fn missing_output_directory_created() {
+    let tmp = test_dir("missing");
+    let dir = tmp.path();
+    let output = dir.join("nested/deeper/payroll.txt");
+
+    write_output(&output, "A000000001").unwrap();
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "A000000001");
+
+    // A file standing where the directory should be
+    let blocked = dir.join("blocked");
+    std::fs::write(&blocked, "").unwrap();
+    let err = write_output(&blocked.join("payroll.txt"), "A000000001").unwrap_err();
+    assert!(
+        err.starts_with(&format!(
+            "Cannot create output directory {}:",
+            blocked.display()
+        )),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn progress_reported_for_each_file() {
+    let tmp = test_dir("progress");
+    let dir = tmp.path();
+    let small = dir.join("small.csv");
+    let large = dir.join("large.csv");
+    std::fs::write(&small, PAYROLL).unwrap();
+    let rows: String = (0..250)
+        .map(|i| format!("{},Customer,003,01234,1234567,$1.00,N,,\n", 2000 + i))
+        .collect();
+    std::fs::write(&large, format!("{}{}", PAYROLL, rows)).unwrap();
+
+    let events = Mutex::new(Vec::new());
+    let results = convert_files_numbered(
+        &[
+            FileRequest::new(small.to_str().unwrap()),
+            FileRequest::new(large.to_str().unwrap()),
+        ],
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        2,
+        None,
+        &|event| events.lock().unwrap().push(event),
+    );
+
+    // Files are converted at once, so only the events of each are in order
+    let mut events = events.into_inner().unwrap();
+    events.sort_by_key(|event| match event {
+        ConversionProgress::Started { index, .. }
+        | ConversionProgress::Rows { index, .. }
+        | ConversionProgress::Finished { index, .. } => *index,
+    });
+
+    let events: Vec<String> = events
+        .into_iter()
+        .map(|event| match event {
+            ConversionProgress::Started { index, .. } => format!("{} started", index),
+            ConversionProgress::Rows { index, rows, .. } => format!("{} rows {}", index, rows),
+            ConversionProgress::Finished { index, result } => {
+                assert_eq!(result.status, results[index].status);
+                format!("{} finished", index)
+            }
+        })
+        .collect();
+
+    assert_eq!(
+        events,
+        [
+            "0 started",
+            "0 rows 2",
+            "0 finished",
+            "1 started",
+            "1 rows 100",
+            "1 rows 200",
+            "1 rows 252",
+            "1 finished",
+        ]
+    );
+    assert!(results
+        .iter()
+        .all(|r| r.status == ConversionStatus::Converted));
+
+    let json = serde_json::to_value(ConversionProgress::Rows {
+        index: 1,
+        input: "large.csv".to_string(),
+        rows: 100,
+    })
+    .unwrap();
+    assert_eq!(json["kind"], "rows");
+    assert_eq!(json["rows"], 100);
+}
+
+#[test]
+fn preview_writes_nothing_and_matches_conversion() {
+    let tmp = test_dir("preview");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+    let input = input.to_str().unwrap();
+
+    let preview = preview_conversion(input, "PDS", 2, &ConversionOptions::default());
+    assert_eq!(preview.status, ConversionStatus::Converted);
+    assert_eq!(preview.record_count, 4);
+    let types: Vec<char> = preview.records.iter().map(|r| r.record_type).collect();
+    assert_eq!(types, ['A', 'C']);
+    assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+
+    // Account numbers are masked when previewed, but not when written
+    let detail = &preview.records[1].text;
+    assert_eq!(&detail[52..64], "***4567     ");
+
+    let whole = preview_conversion(input, "PDS", 10, &ConversionOptions::default());
+    let previewed_trailer = &whole.records.last().unwrap().text;
+    assert!(previewed_trailer.starts_with('Z'));
+
+    let results = convert_files_numbered(
+        &[input].map(FileRequest::new),
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+    let written = std::fs::read_to_string(results[0].output.as_deref().unwrap()).unwrap();
+    assert_eq!(written.lines().last().unwrap(), previewed_trailer);
+    assert_eq!(&written.lines().nth(1).unwrap()[52..64], "1234567     ");
+
+    let (previewed, converted) = (whole.report.unwrap(), results[0].report.clone().unwrap());
+    assert_eq!(previewed.total_credit_count, converted.total_credit_count);
+    assert_eq!(previewed.total_credit_amount, converted.total_credit_amount);
+}
+
+#[test]
+fn preview_reports_errors() {
+    let preview = preview_conversion(
+        "/nonexistent/payroll.csv",
+        "PDS",
+        5,
+        &ConversionOptions::default(),
+    );
+    assert_eq!(preview.status, ConversionStatus::Failed);
+    assert!(preview.records.is_empty() && preview.report.is_none());
+    assert!(preview.errors[0].message.starts_with("Cannot read"));
+}
+
+#[test]
+fn validate_checks_files_without_writing() {
+    let tmp = test_dir("validate");
+    let dir = tmp.path();
+    let clean = dir.join("clean.csv");
+    let warned = dir.join("warned.csv");
+    let broken = dir.join("broken.csv");
+    std::fs::write(&clean, PAYROLL.replace("Acme Widgets Ltd", "Acme Widgets")).unwrap();
+    // A client name too long for the short name is truncated with a warning
+    std::fs::write(&warned, PAYROLL).unwrap();
+    std::fs::write(&broken, PAYROLL.replace("7654321", "76543X1")).unwrap();
+
+    let results = validate_files(
+        &[
+            clean.to_str().unwrap(),
+            warned.to_str().unwrap(),
+            broken.to_str().unwrap(),
+        ],
+        "PDS",
+        &ConversionOptions::default(),
+    );
+
+    let clean = &results[0];
+    assert_eq!(clean.status, ConversionStatus::Valid);
+    assert!(clean.errors.is_empty() && clean.warnings.is_empty());
+    assert_eq!(clean.report.as_ref().unwrap().total_credit_count, 2);
+
+    let warned = &results[1];
+    assert_eq!(warned.status, ConversionStatus::Valid);
+    assert!(warned.errors.is_empty());
+    assert_eq!(warned.warnings.len(), 1);
+    assert!(warned.warnings[0]
+        .message
+        .contains("truncating the client name"));
+
+    let broken = &results[2];
+    assert_eq!(broken.status, ConversionStatus::Failed);
+    assert_eq!(broken.errors.len(), 1);
+    assert_eq!(broken.errors[0].line, Some(9));
+
+    assert!(results.iter().all(|r| r.output.is_none()));
+    assert_eq!(std::fs::read_dir(dir).unwrap().count(), 3);
+    assert_eq!(serde_json::to_value(clean).unwrap()["status"], "valid");
+}
+
+/// Chooses the directory it holds, or cancels if it holds none, noting the
+/// directory it was asked to start from
+struct StubPicker(Option<String>, RefCell<Option<String>>);
+
+impl DirectoryPicker for StubPicker {
+    fn pick_directory(&self, suggested: Option<&str>) -> Option<String> {
+        *self.1.borrow_mut() = suggested.map(str::to_string);
+        self.0.clone()
+    }
+}
+
+#[test]
+fn cancelled_directory_choice_converts_nothing() {
+    let tmp = test_dir("cancelled");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    let picker = StubPicker(None, RefCell::new(None));
+    let started = AtomicUsize::new(0);
+    let conversion = convert_files_to_chosen_directory(
+        &[FileRequest::new(input.to_str().unwrap())],
+        "PDS",
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &picker,
+        Some(dir.to_str().unwrap()),
+        &|_| {
+            started.fetch_add(1, Ordering::Relaxed);
+        },
+    );
+
+    assert!(matches!(conversion, ChosenConversion::Cancelled));
+    assert_eq!(picker.1.borrow().as_deref(), dir.to_str());
+    assert_eq!(started.into_inner(), 0);
+    assert!(!dir.join("payroll.txt").exists());
+}
+
+#[test]
+fn batch_converted_into_chosen_directory() {
+    let tmp = test_dir("chosen");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    let chosen = dir.join("out");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    let picker = StubPicker(
+        Some(chosen.to_str().unwrap().to_string()),
+        RefCell::new(None),
+    );
+    let conversion = convert_files_to_chosen_directory(
+        &[FileRequest::new(input.to_str().unwrap())],
+        "PDS",
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &picker,
+        None,
+        &|_| (),
+    );
+
+    match conversion {
+        ChosenConversion::Chosen {
+            output_directory,
+            results,
+            ..
+        } => {
+            assert_eq!(output_directory, chosen.to_str().unwrap());
+            assert_eq!(results[0].status, ConversionStatus::Converted);
+        }
+        ChosenConversion::Cancelled => panic!("expected a conversion"),
+    }
+
+    assert!(chosen.join("payroll.txt").is_file());
+    // Nothing is left behind by checking the directory can be written to
+    assert_eq!(std::fs::read_dir(&chosen).unwrap().count(), 1);
+}
+
+#[test]
+fn unwritable_directory_fails_batch_before_converting() {
+    let tmp = test_dir("unwritable");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    let blocked = dir.join("blocked");
+    std::fs::write(&input, PAYROLL).unwrap();
+    std::fs::write(&blocked, "").unwrap();
+
+    let chosen = blocked.join("out");
+    let picker = StubPicker(
+        Some(chosen.to_str().unwrap().to_string()),
+        RefCell::new(None),
+    );
+    let rows = AtomicUsize::new(0);
+    let conversion = convert_files_to_chosen_directory(
+        &[
+            FileRequest::new(input.to_str().unwrap()),
+            FileRequest::new(input.to_str().unwrap()),
+        ],
+        "PDS",
+        None,
+        &ConversionOptions::default(),
+        2,
+        None,
+        &picker,
+        None,
+        &|event| {
+            if let ConversionProgress::Rows { .. } = event {
+                rows.fetch_add(1, Ordering::Relaxed);
+            }
+        },
+    );
+
+    let results = match conversion {
+        ChosenConversion::Chosen { results, .. } => results,
+        ChosenConversion::Cancelled => panic!("expected a conversion"),
+    };
+
+    assert!(results.iter().all(|r| r.status == ConversionStatus::Failed
+        && r.errors[0]
+            .message
+            .contains("Choose another output directory")));
+    assert_eq!(rows.into_inner(), 0);
+}
+
+#[test]
+fn write_failure_reported_with_os_error() {
+    let tmp = test_dir("blocked");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    let out = dir.join("out");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    // A directory where the output goes cannot be replaced by a file, not
+    // even by root, for whom permissions are not enforced
+    let blocker = out.join("payroll.txt");
+    std::fs::create_dir_all(&blocker).unwrap();
+    std::fs::write(blocker.join("kept.txt"), "A000000001").unwrap();
+
+    let err = write_output(&blocker, "A000000002").unwrap_err();
+    assert!(err.starts_with("Cannot write output file"), "{}", err);
+    assert!(err.contains("os error"), "{}", err);
+
+    let results = convert_files_numbered(
+        &[input.to_str().unwrap()].map(FileRequest::new),
+        "PDS",
+        out.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+    assert_eq!(results[0].status, ConversionStatus::Failed);
+    assert!(results[0].output.is_none());
+    assert!(results[0].errors[0].message.contains("os error"));
+
+    // What was there before is left whole, with nothing half written
+    // beside it
+    let left: Vec<_> = std::fs::read_dir(&out)
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(left, ["payroll.txt"]);
+    assert_eq!(
+        std::fs::read_to_string(blocker.join("kept.txt")).unwrap(),
+        "A000000001"
+    );
+}
+
+#[test]
+fn output_replaced_without_partial_file() {
+    let tmp = test_dir("replaced");
+    let dir = tmp.path();
+    let output = dir.join("payroll.txt");
+
+    write_output(&output, "A000000001").unwrap();
+    write_output(&output, "A000000002").unwrap();
+
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "A000000002");
+    assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+}
+
+#[test]
+fn inputs_of_one_output_name_written_at_once() {
+    let tmp = test_dir("same-name");
+    let dir = tmp.path();
+    let out = dir.join("out");
+    let inputs: Vec<_> = ["march", "april"]
+        .iter()
+        .map(|month| {
+            let input = dir.join(month).join("payroll.csv");
+            std::fs::create_dir_all(input.parent().unwrap()).unwrap();
+            std::fs::write(&input, PAYROLL).unwrap();
+            FileRequest::new(input.to_str().unwrap())
+        })
+        .collect();
+
+    // Both files are written to out/payroll.txt at the same time
+    let results = convert_files_numbered(
+        &inputs,
+        "PDS",
+        out.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        2,
+        None,
+        &|_| (),
+    );
+
+    for result in &results {
+        assert_eq!(result.status, ConversionStatus::Converted, "{:?}", result);
+    }
+    let left: Vec<_> = std::fs::read_dir(&out)
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(left, ["payroll.txt"]);
+}
@@ -0,0 +1,238 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/convert.rs"]
+#[allow(dead_code)]
+mod convert;
+
+#[path = "../src/settings.rs"]
+#[allow(dead_code)]
+mod settings;
+
+#[path = "../src/numbering.rs"]
+#[allow(dead_code)]
+mod numbering;
+
+#[path = "../src/history.rs"]
+mod history;
+
+mod common;
+
+use common::test_dir;
+use convert::{convert_files_numbered, ConversionStatus, FileRequest};
+use csvconv::options::ConversionOptions;
+use history::{rerun, ConversionRequest, History, HistoryStore, Rerun, HISTORY_FILE, MAX_HISTORY};
+use numbering::{CreationNumberStore, CREATION_NUMBERS_FILE};
+use std::sync::Arc;
+
+const PAYROLL: &str = "Client Name,Acme Widgets
+Client Number,0123456789
+Processing Centre,00320
+Currency Code,CAD
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+1001,Jane Smith,003,01234,1234567,\"$1,250.00\",N,,
+1002,John Doe,003,01234,7654321,$500.00,N,,
+";
+
+fn request(inputs: &[&str], output_directory: &str) -> ConversionRequest {
+    ConversionRequest {
+        files: inputs.iter().map(|i| FileRequest::new(i)).collect(),
+        record_type: "PDS".to_string(),
+        output_directory: output_directory.to_string(),
+        output_name: None,
+    }
+}
+
+#[test]
+fn conversion_recorded_with_totals() {
+    let tmp = test_dir("recorded");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    let missing = dir.join("missing.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    let inputs = [input.to_str().unwrap(), missing.to_str().unwrap()];
+    let results = convert_files_numbered(
+        &inputs.map(FileRequest::new),
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    let path = dir.join(HISTORY_FILE);
+    let store = HistoryStore::new(path.clone());
+    store
+        .record_conversion(request(&inputs, dir.to_str().unwrap()), &results)
+        .unwrap();
+
+    let history = History::load(&path).unwrap();
+    let entry = &history.entries()[0];
+    assert_eq!(entry.id, 1);
+    assert_eq!(entry.request.files.len(), 2);
+    assert_eq!(
+        entry.outputs,
+        vec![dir.join("payroll.txt").to_str().unwrap()]
+    );
+    assert_eq!(entry.totals.credit_amount, 175000);
+    assert_eq!(entry.totals.credit_count, 2);
+    assert_eq!((entry.converted, entry.failed), (1, 1));
+
+    // Nothing of the payments themselves is kept
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(!saved.contains("Jane Smith"));
+    assert!(!saved.contains("1234567"));
+
+    store.clear().unwrap();
+    assert!(store.load().unwrap().entries().is_empty());
+    store.clear().unwrap();
+}
+
+#[test]
+fn history_keeps_latest_conversions() {
+    let mut history = History::default();
+
+    for i in 0..MAX_HISTORY + 5 {
+        history.record(request(&[&format!("{}.csv", i)], "/tmp"), &[]);
+    }
+
+    let entries = history.entries();
+    assert_eq!(entries.len(), MAX_HISTORY);
+    assert_eq!(entries[0].id, 6);
+    assert_eq!(entries[0].request.files, vec![FileRequest::new("5.csv")]);
+    assert_eq!(entries[MAX_HISTORY - 1].id, MAX_HISTORY as u64 + 5);
+}
+
+#[test]
+fn history_kept_before_file_record_types_loads() {
+    let tmp = test_dir("older");
+    let dir = tmp.path();
+    let path = dir.join(HISTORY_FILE);
+    std::fs::write(
+        &path,
+        r#"{"next_id": 1, "entries": [{"id": 1, "timestamp": "2024-01-02T03:04:05+00:00",
+            "inputs": ["payroll.csv"], "record_type": "PAD", "output_directory": "/tmp",
+            "output_name": null, "outputs": [], "totals": {"debit_amount": 0,
+            "debit_count": 0, "credit_amount": 0, "credit_count": 0},
+            "converted": 0, "failed": 0}]}"#,
+    )
+    .unwrap();
+
+    let history = History::load(&path).unwrap();
+    let request = &history.entries()[0].request;
+    assert_eq!(request.files, vec![FileRequest::new("payroll.csv")]);
+    assert_eq!(request.record_type, "PAD");
+}
+
+#[test]
+fn rerun_converts_batch_again() {
+    let tmp = test_dir("rerun");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    let history = HistoryStore::new(dir.join(HISTORY_FILE));
+    let inputs = [input.to_str().unwrap()];
+    history
+        .record_conversion(request(&inputs, dir.to_str().unwrap()), &[])
+        .unwrap();
+
+    let store = CreationNumberStore::new(dir.join(CREATION_NUMBERS_FILE));
+    let rerun = |id| {
+        rerun(
+            &history,
+            id,
+            &ConversionOptions::default(),
+            1,
+            &store,
+            &|_| (),
+        )
+    };
+
+    for expected in ["1", "2"] {
+        let results = match rerun(1).unwrap() {
+            Rerun::Converted { results, .. } => results,
+            other => panic!("expected a conversion, got {:?}", other),
+        };
+        assert_eq!(results[0].status, ConversionStatus::Converted);
+
+        // Numbered on from the store, never repeating a number sent before
+        let output = std::fs::read_to_string(dir.join("payroll.txt")).unwrap();
+        assert_eq!(output[20..24].trim(), expected);
+    }
+
+    let history = history.load().unwrap();
+    assert_eq!(history.entries().len(), 3);
+    assert_eq!(history.entries()[1].converted, 1);
+
+    assert!(rerun(7).is_err());
+}
+
+#[test]
+fn rerun_with_missing_input_asks_again() {
+    let tmp = test_dir("rerun-missing");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    let gone = dir.join("gone.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    let history = HistoryStore::new(dir.join(HISTORY_FILE));
+    let inputs = [input.to_str().unwrap(), gone.to_str().unwrap()];
+    history
+        .record_conversion(request(&inputs, dir.to_str().unwrap()), &[])
+        .unwrap();
+
+    let store = CreationNumberStore::new(dir.join(CREATION_NUMBERS_FILE));
+    match rerun(
+        &history,
+        1,
+        &ConversionOptions::default(),
+        1,
+        &store,
+        &|_| (),
+    )
+    .unwrap()
+    {
+        Rerun::MissingInputs { request, missing } => {
+            assert_eq!(request.files.len(), 2);
+            assert_eq!(missing, vec![gone.to_str().unwrap()]);
+        }
+        other => panic!("expected missing inputs, got {:?}", other),
+    }
+
+    // Nothing is converted or recorded until the files are chosen again
+    assert!(!dir.join("payroll.txt").exists());
+    assert_eq!(history.load().unwrap().entries().len(), 1);
+}
+
+#[test]
+fn conversions_finishing_together_all_recorded() {
+    let tmp = test_dir("together");
+    let dir = tmp.path();
+    let history = Arc::new(HistoryStore::new(dir.join(HISTORY_FILE)));
+
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let history = Arc::clone(&history);
+            let input = format!("{}.csv", i);
+
+            std::thread::spawn(move || {
+                history
+                    .record_conversion(request(&[&input], "/tmp"), &[])
+                    .unwrap()
+            })
+        })
+        .collect();
+
+    let mut ids: Vec<u64> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    ids.sort();
+
+    assert_eq!(ids, (1..=8).collect::<Vec<u64>>());
+    assert_eq!(history.load().unwrap().entries().len(), 8);
+}
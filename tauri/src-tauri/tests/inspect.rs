@@ -0,0 +1,118 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/inspect.rs"]
+mod inspect;
+
+mod common;
+
+use common::test_dir;
+use csvconv::csv::convert_reader_with_report;
+use csvconv::types::RecordType;
+use inspect::{inspect_file, DEFAULT_PAGE_SIZE};
+
+const PAYROLL: &str = "Client Name,Acme Widgets
+Client Number,0123456789
+Processing Centre,00320
+Currency Code,CAD
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+1001,Jane Smith,003,01234,123456789012,\"$1,250.00\",N,,
+1002,John Doe,004,00123,7654321,$500.00,N,,
+1003,Mary Major,001,00456,2345678,$75.25,N,,
+";
+
+/// The payroll converted to CPA-005, as its records
+fn converted() -> Vec<String> {
+    let conversion =
+        convert_reader_with_report(PAYROLL.as_bytes(), RecordType::Credit, &Default::default())
+            .unwrap_or_else(|e| panic!("{}", e));
+
+    conversion.output.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn converted_file_inspected_a_page_at_a_time() {
+    let tmp = test_dir("converted");
+    let dir = tmp.path();
+    let path = dir.join("payroll.txt");
+    std::fs::write(&path, converted().join("\n")).unwrap();
+
+    let inspection = inspect_file(&path, 1, 1).unwrap();
+
+    assert!(inspection.errors.is_empty(), "{:?}", inspection.errors);
+    assert_eq!(inspection.header.client_number, "0123456789");
+    assert_eq!(inspection.payment_count, 3);
+    assert_eq!(inspection.offset, 1);
+    assert_eq!(inspection.payments.len(), 1);
+
+    let payment = &inspection.payments[0];
+    assert_eq!(payment.customer_number, "1002");
+    assert_eq!(payment.customer_name, "John Doe");
+    assert_eq!(payment.account_number, "***4321");
+    assert_eq!(payment.institution_number, "0004");
+    assert_eq!(payment.branch_number, "00123");
+    assert_eq!(payment.amount, 50000);
+    assert_eq!(payment.payment_date, "2045-02-01");
+
+    assert!(inspection.totals_match);
+    assert_eq!(inspection.trailer.total_credit_amount, 182525);
+    assert_eq!(inspection.trailer.total_credit_count, 3);
+
+    let first = inspect_file(&path, 0, DEFAULT_PAGE_SIZE).unwrap();
+    assert_eq!(first.payments.len(), 3);
+    assert_eq!(first.payments[0].account_number, "********9012");
+
+    let past_the_end = inspect_file(&path, 5, 10).unwrap();
+    assert!(past_the_end.payments.is_empty());
+    assert_eq!(past_the_end.payment_count, 3);
+}
+
+#[test]
+fn corrupted_file_inspected_with_its_errors() {
+    let tmp = test_dir("corrupted");
+    let dir = tmp.path();
+    let path = dir.join("payroll.txt");
+
+    let mut records = converted();
+    let last = records.len();
+    // Cut a detail record short and misstate the trailer's credit count
+    records[1].truncate(100);
+    let trailer = &mut records[last - 1];
+    let count = trailer.find("000003").unwrap();
+    trailer.replace_range(count..count + 6, "000004");
+    std::fs::write(&path, records.join("\n")).unwrap();
+
+    let inspection = inspect_file(&path, 0, 10).unwrap();
+
+    assert!(!inspection.totals_match);
+    assert_eq!(inspection.trailer.total_credit_count, 4);
+    assert!(inspection
+        .errors
+        .iter()
+        .any(|e| e.line == Some(2) && e.message.contains("characters long")));
+    assert!(inspection
+        .errors
+        .iter()
+        .any(|e| e.line == Some(last) && e.field == Some("total_credit_count")));
+}
+
+#[test]
+fn file_without_trailer_not_inspected() {
+    let tmp = test_dir("unreadable");
+    let dir = tmp.path();
+    let path = dir.join("payroll.txt");
+
+    let records = converted();
+    std::fs::write(&path, records[..records.len() - 1].join("\n")).unwrap();
+
+    let errors = inspect_file(&path, 0, 10).unwrap_err();
+    assert!(errors
+        .iter()
+        .any(|e| e.message == "File has no trailer record"));
+
+    let missing = inspect_file(&dir.join("missing.txt"), 0, 10).unwrap_err();
+    assert!(missing[0].message.starts_with("Cannot read"));
+}
@@ -0,0 +1,97 @@
+#[path = "../src/launch.rs"]
+#[allow(dead_code)]
+mod launch;
+
+mod common;
+
+use common::test_dir;
+use launch::{launch_paths, PendingFiles, RejectedFile, StagedFiles};
+use std::path::{Path, PathBuf};
+
+#[test]
+fn files_read_from_launch_arguments() {
+    let cwd = Path::new("/home/payroll");
+
+    let paths = launch_paths(
+        [
+            "/opt/rbc/tauri-app",
+            "--flag",
+            "march.csv",
+            "/srv/april.csv",
+            "file:///srv/May%20payroll.csv",
+        ],
+        cwd,
+    );
+
+    assert_eq!(
+        paths,
+        [
+            PathBuf::from("/home/payroll/march.csv"),
+            PathBuf::from("/srv/april.csv"),
+            PathBuf::from("/srv/May payroll.csv"),
+        ]
+    );
+
+    // The program name alone is no file
+    assert!(launch_paths(["/opt/rbc/tauri-app"], cwd).is_empty());
+}
+
+#[test]
+fn only_csv_files_staged() {
+    let tmp = test_dir("staged");
+    let dir = tmp.path();
+    let march = dir.join("march.CSV");
+    let notes = dir.join("notes.txt");
+    let missing = dir.join("missing.csv");
+    std::fs::write(&march, "Client Name,Acme Widgets").unwrap();
+    std::fs::write(&notes, "not payments").unwrap();
+
+    let pending = PendingFiles::default();
+    let staged = pending.stage([
+        march.clone(),
+        notes.clone(),
+        missing.clone(),
+        dir.to_path_buf(),
+    ]);
+
+    let name = |p: &Path| p.display().to_string();
+    let expected = StagedFiles {
+        files: vec![name(&march)],
+        rejected: vec![
+            RejectedFile {
+                path: name(&notes),
+                reason: "Not a CSV file".to_string(),
+            },
+            RejectedFile {
+                path: name(&missing),
+                reason: "File does not exist".to_string(),
+            },
+            RejectedFile {
+                path: name(dir),
+                reason: "Not a file".to_string(),
+            },
+        ],
+    };
+    assert_eq!(staged, expected);
+
+    assert_eq!(pending.take(), expected);
+    assert_eq!(pending.take(), StagedFiles::default());
+}
+
+#[test]
+fn file_opened_twice_staged_once() {
+    let tmp = test_dir("twice");
+    let dir = tmp.path();
+    let march = dir.join("march.csv");
+    std::fs::write(&march, "Client Name,Acme Widgets").unwrap();
+
+    let pending = PendingFiles::default();
+    pending.stage([march.clone(), march.clone()]);
+    let again = pending.stage([march.clone()]);
+
+    assert!(again.files.is_empty());
+    assert_eq!(pending.take().files, [march.display().to_string()]);
+
+    // Once taken, the file may be opened again
+    assert_eq!(pending.stage([march.clone()]).files.len(), 1);
+}
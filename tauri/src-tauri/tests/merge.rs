@@ -0,0 +1,191 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/settings.rs"]
+#[allow(dead_code)]
+mod settings;
+
+#[path = "../src/numbering.rs"]
+#[allow(dead_code)]
+mod numbering;
+
+#[path = "../src/convert.rs"]
+#[allow(dead_code)]
+mod convert;
+
+mod common;
+
+use common::test_dir;
+use convert::{merge_files, ConversionStatus};
+use csvconv::options::ConversionOptions;
+use numbering::{BatchNumbering, CreationNumberStore, CREATION_NUMBERS_FILE};
+
+const HOURLY: &str = "Client Name,Acme Widgets
+Client Number,0123456789
+Processing Centre,00320
+Currency Code,CAD
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+1001,Jane Smith,003,01234,1234567,\"$1,250.00\",N,,
+1002,John Doe,003,01234,7654321,$500.00,N,,
+";
+
+const SALARIED: &str = "Client Name,Acme Widgets
+Client Number,012-345-6789
+Processing Centre,320
+Currency Code,cad
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+2001,Mary Major,003,01234,2345678,\"$3,000.00\",N,,
+";
+
+#[test]
+fn compatible_files_merged_into_one() {
+    let tmp = test_dir("compatible");
+    let dir = tmp.path();
+    let hourly = dir.join("hourly.csv");
+    let salaried = dir.join("salaried.csv");
+    std::fs::write(&hourly, HOURLY).unwrap();
+    std::fs::write(&salaried, SALARIED).unwrap();
+
+    let inputs = [hourly.to_str().unwrap(), salaried.to_str().unwrap()];
+    let merged = merge_files(
+        &inputs,
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        None,
+    );
+
+    assert_eq!(merged.result.status, ConversionStatus::Converted);
+    assert_eq!(merged.result.input, inputs.join(", "));
+    assert_eq!(
+        merged.result.output.as_deref(),
+        dir.join("hourly_merged.txt").to_str()
+    );
+
+    let report = merged.result.report.as_ref().unwrap();
+    assert_eq!(report.total_credit_count, 3);
+    assert_eq!(report.total_credit_amount, 475000);
+
+    assert_eq!(merged.sources.len(), 2);
+    assert_eq!(merged.sources[0].source, inputs[0]);
+    assert_eq!(merged.sources[0].credit_count, 2);
+    assert_eq!(merged.sources[0].credit_amount, 175000);
+    assert_eq!(merged.sources[1].source, inputs[1]);
+    assert_eq!(merged.sources[1].credit_count, 1);
+    assert_eq!(merged.sources[1].credit_amount, 300000);
+
+    // One header and trailer around the payments of both files
+    let output = std::fs::read_to_string(dir.join("hourly_merged.txt")).unwrap();
+    let records: Vec<&str> = output.lines().collect();
+    assert_eq!(records.len(), 5);
+    assert!(records[0].starts_with('A'));
+    assert!(records[3].contains("Mary Major"));
+    assert!(records[4].starts_with('Z'));
+}
+
+#[test]
+fn conflicting_files_rejected_naming_both() {
+    let tmp = test_dir("conflicting");
+    let dir = tmp.path();
+    let hourly = dir.join("hourly.csv");
+    let salaried = dir.join("salaried.csv");
+    std::fs::write(&hourly, HOURLY).unwrap();
+    std::fs::write(
+        &salaried,
+        SALARIED
+            .replace("012-345-6789", "9876543210")
+            .replace("2045/02/01", "2045/02/15"),
+    )
+    .unwrap();
+
+    let inputs = [hourly.to_str().unwrap(), salaried.to_str().unwrap()];
+    let merged = merge_files(
+        &inputs,
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        None,
+    );
+
+    assert_eq!(merged.result.status, ConversionStatus::Failed);
+    assert!(merged.sources.is_empty());
+    assert!(!dir.join("hourly_merged.txt").exists());
+
+    let errors: Vec<&str> = merged
+        .result
+        .errors
+        .iter()
+        .map(|e| e.message.as_str())
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            format!(
+                "Client Number 9876543210 of {} does not match 0123456789 of {}. Merged files must have the same Client Number.",
+                inputs[1], inputs[0]
+            ),
+            format!(
+                "Payment Date 2045/02/15 of {} does not match 2045/02/01 of {}. Merged files must have the same Payment Date.",
+                inputs[1], inputs[0]
+            ),
+        ]
+    );
+    assert_eq!(merged.result.errors[0].file.as_deref(), Some(inputs[1]));
+}
+
+#[test]
+fn errors_name_the_file_they_were_found_in() {
+    let tmp = test_dir("invalid");
+    let dir = tmp.path();
+    let hourly = dir.join("hourly.csv");
+    let salaried = dir.join("salaried.csv");
+    std::fs::write(&hourly, HOURLY).unwrap();
+    std::fs::write(&salaried, SALARIED.replace("2345678", "23X5678")).unwrap();
+
+    let merged = merge_files(
+        &[hourly.to_str().unwrap(), salaried.to_str().unwrap()],
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        None,
+    );
+
+    assert_eq!(merged.result.status, ConversionStatus::Failed);
+    assert!(!merged.result.errors.is_empty());
+    assert!(merged
+        .result
+        .errors
+        .iter()
+        .all(|e| e.file.as_deref() == salaried.to_str() && e.line == Some(8)));
+}
+
+#[test]
+fn merged_file_uses_up_one_creation_number() {
+    let tmp = test_dir("numbered");
+    let dir = tmp.path();
+    let hourly = dir.join("hourly.csv");
+    let salaried = dir.join("salaried.csv");
+    std::fs::write(&hourly, HOURLY).unwrap();
+    std::fs::write(&salaried, SALARIED).unwrap();
+
+    let store = CreationNumberStore::new(dir.join(CREATION_NUMBERS_FILE));
+    let merged = merge_files(
+        &[hourly.to_str().unwrap(), salaried.to_str().unwrap()],
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        Some(&mut BatchNumbering::from_store(&store)),
+    );
+
+    assert_eq!(merged.result.report.unwrap().file_creation_number, 1);
+    assert_eq!(store.next("0123456789").unwrap(), 2);
+}
@@ -0,0 +1,260 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/settings.rs"]
+#[allow(dead_code)]
+mod settings;
+
+#[path = "../src/numbering.rs"]
+mod numbering;
+
+#[path = "../src/convert.rs"]
+#[allow(dead_code)]
+mod convert;
+
+mod common;
+
+use common::test_dir;
+use convert::{
+    convert_files_numbered, ConversionProgress, ConversionStatus, FileConversionResult, FileRequest,
+};
+use csvconv::options::ConversionOptions;
+use numbering::{following, BatchNumbering, CreationNumberStore, CREATION_NUMBERS_FILE};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const PAYROLL: &str = "Client Name,Acme Widgets
+Client Number,0123456789
+Processing Centre,00320
+Currency Code,CAD
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+1001,Jane Smith,003,01234,1234567,\"$1,250.00\",N,,
+1002,John Doe,003,01234,7654321,$500.00,N,,
+";
+
+// File creation number of the header, left justified
+const CREATION_NUMBER_FIELD: std::ops::Range<usize> = 20..24;
+
+fn convert(
+    input: &Path,
+    output_name: &str,
+    numbering: &mut BatchNumbering,
+) -> FileConversionResult {
+    let mut results = convert_files_numbered(
+        &[FileRequest::new(input.to_str().unwrap())],
+        "PDS",
+        input.parent().unwrap().to_str().unwrap(),
+        Some(output_name),
+        &ConversionOptions::default(),
+        1,
+        Some(numbering),
+        &|_| (),
+    );
+
+    results.remove(0)
+}
+
+fn creation_number(result: &FileConversionResult) -> String {
+    let output = std::fs::read_to_string(result.output.as_deref().unwrap()).unwrap();
+    output[CREATION_NUMBER_FIELD].trim().to_string()
+}
+
+#[test]
+fn numbers_used_up_only_by_converted_files() {
+    let tmp = test_dir("sequential");
+    let dir = tmp.path();
+    let good = dir.join("payroll.csv");
+    let bad = dir.join("broken.csv");
+    std::fs::write(&good, PAYROLL).unwrap();
+    std::fs::write(&bad, PAYROLL.replace("7654321", "76543X1")).unwrap();
+
+    let store = CreationNumberStore::new(dir.join("app").join(CREATION_NUMBERS_FILE));
+    assert_eq!(store.next("0123456789").unwrap(), 1);
+
+    let first = convert(&good, "first.txt", &mut BatchNumbering::from_store(&store));
+    assert_eq!(first.status, ConversionStatus::Converted);
+    assert_eq!(creation_number(&first), "1");
+    assert_eq!(store.next("0123456789").unwrap(), 2);
+
+    // A file that fails does not use up its number
+    let failed = convert(&bad, "failed.txt", &mut BatchNumbering::from_store(&store));
+    assert_eq!(failed.status, ConversionStatus::Failed);
+    assert_eq!(store.next("0123456789").unwrap(), 2);
+
+    let second = convert(&good, "second.txt", &mut BatchNumbering::from_store(&store));
+    assert_eq!(creation_number(&second), "2");
+
+    // Kept between launches, for each client apart, however its number is
+    // written
+    let reopened = CreationNumberStore::new(dir.join("app").join(CREATION_NUMBERS_FILE));
+    assert_eq!(reopened.next("012-345-6789").unwrap(), 3);
+    assert_eq!(reopened.next("9876543210").unwrap(), 1);
+}
+
+#[test]
+fn files_of_one_batch_numbered_on_from_each_other() {
+    let tmp = test_dir("batch");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    let store = CreationNumberStore::new(dir.join(CREATION_NUMBERS_FILE));
+    let results = convert_files_numbered(
+        &[
+            FileRequest::new(input.to_str().unwrap()),
+            FileRequest::new(input.to_str().unwrap()),
+        ],
+        "PDS",
+        dir.to_str().unwrap(),
+        Some("{stem}_{seq}.txt"),
+        &ConversionOptions::default(),
+        2,
+        Some(&mut BatchNumbering::from_store(&store)),
+        &|_| (),
+    );
+
+    assert!(dir.join("payroll_0001.txt").exists());
+    assert!(dir.join("payroll_0002.txt").exists());
+    assert_eq!(creation_number(&results[1]), "2");
+    assert_eq!(store.next("0123456789").unwrap(), 3);
+}
+
+#[test]
+fn chosen_number_only_advances_when_asked() {
+    let tmp = test_dir("override");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    let store = CreationNumberStore::new(dir.join(CREATION_NUMBERS_FILE));
+
+    let kept = convert(
+        &input,
+        "kept.txt",
+        &mut BatchNumbering::overridden(&store, Some(42), false),
+    );
+    assert_eq!(creation_number(&kept), "42");
+    assert_eq!(store.next("0123456789").unwrap(), 1);
+
+    let advanced = convert(
+        &input,
+        "advanced.txt",
+        &mut BatchNumbering::overridden(&store, Some(42), true),
+    );
+    assert_eq!(creation_number(&advanced), "42");
+    assert_eq!(store.next("0123456789").unwrap(), 43);
+}
+
+#[test]
+fn numbers_start_over_after_9999() {
+    assert_eq!(following(1), 2);
+    assert_eq!(following(9999), 1);
+}
+
+#[test]
+fn concurrent_conversions_never_share_a_number() {
+    let tmp = test_dir("concurrent");
+    let dir = tmp.path();
+    let input = dir.join("payroll.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    let store = Arc::new(CreationNumberStore::new(dir.join(CREATION_NUMBERS_FILE)));
+
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let store = Arc::clone(&store);
+            let input = input.clone();
+
+            std::thread::spawn(move || {
+                let result = convert(
+                    &input,
+                    &format!("thread{}.txt", i),
+                    &mut BatchNumbering::from_store(&store),
+                );
+                creation_number(&result)
+            })
+        })
+        .collect();
+
+    let mut numbers: Vec<String> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    numbers.sort();
+
+    assert_eq!(numbers, ["1", "2", "3", "4"]);
+    assert_eq!(store.next("0123456789").unwrap(), 5);
+}
+
+/// Converts `inputs` into `output_directory` with a store of its own, `jobs`
+/// files at once, checking each progress event is of the file it names
+fn convert_batch(
+    inputs: &[PathBuf],
+    output_directory: &Path,
+    jobs: usize,
+) -> Vec<FileConversionResult> {
+    std::fs::create_dir_all(output_directory).unwrap();
+    let store = CreationNumberStore::new(output_directory.join(CREATION_NUMBERS_FILE));
+    let files: Vec<FileRequest> = inputs
+        .iter()
+        .map(|input| FileRequest::new(input.to_str().unwrap()))
+        .collect();
+
+    let finished = Mutex::new(Vec::new());
+    let results = convert_files_numbered(
+        &files,
+        "PDS",
+        output_directory.to_str().unwrap(),
+        Some("{stem}.txt"),
+        &ConversionOptions::default(),
+        jobs,
+        Some(&mut BatchNumbering::from_store(&store)),
+        &|event| match event {
+            ConversionProgress::Started { index, input }
+            | ConversionProgress::Rows { index, input, .. } => {
+                assert_eq!(input, files[index].path)
+            }
+            ConversionProgress::Finished { index, result } => {
+                assert_eq!(result.input, files[index].path);
+                finished.lock().unwrap().push(index);
+            }
+        },
+    );
+
+    let mut finished = finished.into_inner().unwrap();
+    finished.sort();
+    assert_eq!(finished, (0..inputs.len()).collect::<Vec<_>>());
+
+    results
+}
+
+#[test]
+fn batch_converted_at_once_numbered_in_order() {
+    let tmp = test_dir("parallel");
+    let dir = tmp.path();
+    let inputs: Vec<PathBuf> = (0..8)
+        .map(|i| {
+            let input = dir.join(format!("payroll{}.csv", i));
+            let rows: String = (0..i * 40)
+                .map(|j| format!("{},Customer,003,01234,1234567,$1.00,N,,\n", 2000 + j))
+                .collect();
+            std::fs::write(&input, format!("{}{}", PAYROLL, rows)).unwrap();
+            input
+        })
+        .collect();
+
+    let parallel = convert_batch(&inputs, &dir.join("parallel"), 4);
+    let sequential = convert_batch(&inputs, &dir.join("sequential"), 1);
+
+    let numbers: Vec<String> = parallel.iter().map(creation_number).collect();
+    assert_eq!(numbers, ["1", "2", "3", "4", "5", "6", "7", "8"]);
+
+    for ((input, parallel), sequential) in inputs.iter().zip(&parallel).zip(&sequential) {
+        assert_eq!(parallel.input, input.to_str().unwrap());
+        assert_eq!(parallel.status, ConversionStatus::Converted);
+        assert_eq!(
+            std::fs::read(parallel.output.as_deref().unwrap()).unwrap(),
+            std::fs::read(sequential.output.as_deref().unwrap()).unwrap()
+        );
+    }
+}
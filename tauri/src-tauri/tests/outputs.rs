@@ -0,0 +1,187 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/convert.rs"]
+#[allow(dead_code)]
+mod convert;
+
+#[path = "../src/settings.rs"]
+#[allow(dead_code)]
+mod settings;
+
+#[path = "../src/numbering.rs"]
+#[allow(dead_code)]
+mod numbering;
+
+#[path = "../src/history.rs"]
+#[allow(dead_code)]
+mod history;
+
+#[path = "../src/outputs.rs"]
+mod outputs;
+
+mod common;
+
+use common::test_dir;
+use convert::{convert_files_numbered, FileRequest};
+use csvconv::options::ConversionOptions;
+use history::{ConversionRequest, History};
+use outputs::{open_output, produced_output, reveal_output, Opener, OutputError};
+use std::cell::RefCell;
+use std::path::Path;
+
+const PAYROLL: &str = "Client Name,Acme Widgets
+Client Number,0123456789
+Processing Centre,00320
+Currency Code,CAD
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+1001,Jane Smith,003,01234,1234567,\"$1,250.00\",N,,
+";
+
+/// A history of converting `payroll.csv` in `dir` into `payroll.txt`
+fn converted_history(dir: &Path) -> History {
+    let input = dir.join("payroll.csv");
+    std::fs::write(&input, PAYROLL).unwrap();
+
+    let input = input.to_str().unwrap();
+    let results = convert_files_numbered(
+        &[input].map(FileRequest::new),
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    let mut history = History::default();
+    history.record(
+        ConversionRequest {
+            files: vec![FileRequest::new(input)],
+            record_type: "PDS".to_string(),
+            output_directory: dir.to_str().unwrap().to_string(),
+            output_name: None,
+        },
+        &results,
+    );
+    history
+}
+
+/// Notes what it is asked to show, rather than showing it
+#[derive(Default)]
+struct StubOpener {
+    shown: RefCell<Vec<String>>,
+    fails: bool,
+}
+
+impl StubOpener {
+    fn show(&self, how: &str, path: &Path) -> Result<(), String> {
+        if self.fails {
+            return Err("no file manager".to_string());
+        }
+        self.shown
+            .borrow_mut()
+            .push(format!("{} {}", how, path.display()));
+        Ok(())
+    }
+}
+
+impl Opener for StubOpener {
+    fn reveal(&self, path: &Path) -> Result<(), String> {
+        self.show("reveal", path)
+    }
+
+    fn open(&self, path: &Path) -> Result<(), String> {
+        self.show("open", path)
+    }
+}
+
+#[test]
+fn converted_files_shown() {
+    let tmp = test_dir("shown");
+    let dir = tmp.path();
+    let history = converted_history(dir);
+    let output = dir.join("payroll.txt");
+    let output = output.to_str().unwrap();
+
+    let opener = StubOpener::default();
+    reveal_output(&history, output, &opener).unwrap();
+    open_output(&history, output, &opener).unwrap();
+
+    assert_eq!(
+        *opener.shown.borrow(),
+        [format!("reveal {}", output), format!("open {}", output)]
+    );
+
+    let opener = StubOpener {
+        fails: true,
+        ..StubOpener::default()
+    };
+    assert_eq!(
+        open_output(&history, output, &opener),
+        Err(OutputError::Failed {
+            path: output.to_string(),
+            message: "no file manager".to_string()
+        })
+    );
+}
+
+#[test]
+fn only_converted_files_shown() {
+    let tmp = test_dir("not-produced");
+    let dir = tmp.path();
+    let history = converted_history(dir);
+
+    // Files that exist, but were not written by a conversion
+    let input = dir.join("payroll.csv");
+    let escaped = dir.join("out/../payroll.txt");
+    std::fs::create_dir_all(dir.join("out")).unwrap();
+
+    let opener = StubOpener::default();
+    for path in [
+        input.to_str().unwrap(),
+        escaped.to_str().unwrap(),
+        dir.to_str().unwrap(),
+    ] {
+        assert_eq!(
+            reveal_output(&history, path, &opener),
+            Err(OutputError::NotProduced {
+                path: path.to_string()
+            })
+        );
+    }
+    assert!(opener.shown.borrow().is_empty());
+
+    // Written the same way, however the separators fall
+    let output = format!("{}//payroll.txt", dir.display());
+    assert_eq!(
+        produced_output(&history, &output).unwrap(),
+        dir.join("payroll.txt")
+    );
+}
+
+#[test]
+fn moved_file_reported() {
+    let tmp = test_dir("moved");
+    let dir = tmp.path();
+    let history = converted_history(dir);
+    let output = dir.join("payroll.txt");
+    std::fs::rename(&output, dir.join("elsewhere.txt")).unwrap();
+
+    let error =
+        open_output(&history, output.to_str().unwrap(), &StubOpener::default()).unwrap_err();
+    assert_eq!(
+        error,
+        OutputError::Moved {
+            path: output.to_str().unwrap().to_string()
+        }
+    );
+
+    let json = serde_json::to_value(&error).unwrap();
+    assert_eq!(json["kind"], "moved");
+    assert!(error.to_string().contains("no longer exists"));
+}
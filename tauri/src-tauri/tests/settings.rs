@@ -0,0 +1,105 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/settings.rs"]
+mod settings;
+
+mod common;
+
+use common::test_dir;
+use csvconv::schema::CLIENT_NUMBER;
+use settings::{ClientProfile, Settings, SETTINGS_FILE, SETTINGS_VERSION};
+
+#[test]
+fn saved_settings_load_back() {
+    // The app's data directory does not exist before the first save
+    let tmp = test_dir("round-trip");
+    let path = tmp.path().join("app").join(SETTINGS_FILE);
+
+    let settings = Settings {
+        output_directory: Some("/home/payroll/out".to_string()),
+        record_type: Some("PAD".to_string()),
+        lenient: true,
+        locale: Some("fr-CA".to_string()),
+        jobs: Some(2),
+        client: Some(ClientProfile {
+            client_number: Some("0123456789".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    settings.save(&path).unwrap();
+    assert_eq!(Settings::load(&path).unwrap(), settings);
+
+    let options = settings.conversion_options();
+    assert!(options.validation.lenient);
+    assert_eq!(options.header_defaults[CLIENT_NUMBER].value, "0123456789");
+    assert_eq!(options.header_defaults.len(), 1);
+}
+
+#[test]
+fn missing_settings_are_the_defaults() {
+    let tmp = test_dir("missing");
+    let path = tmp.path().join(SETTINGS_FILE);
+
+    assert_eq!(Settings::load(&path).unwrap(), Settings::default());
+}
+
+#[test]
+fn corrupt_settings_are_an_error() {
+    let tmp = test_dir("corrupt");
+    let path = tmp.path().join(SETTINGS_FILE);
+    std::fs::write(&path, "{\"record_type\": \"PDS\"").unwrap();
+
+    let error = Settings::load(&path).unwrap_err();
+    assert!(error.contains("are not valid"), "{}", error);
+}
+
+#[test]
+fn unversioned_settings_are_upgraded() {
+    let tmp = test_dir("unversioned");
+    let path = tmp.path().join(SETTINGS_FILE);
+    std::fs::write(&path, "{\"record_type\": \"PDS\"}").unwrap();
+
+    let settings = Settings::load(&path).unwrap();
+    assert_eq!(settings.version, SETTINGS_VERSION);
+    assert_eq!(settings.record_type.as_deref(), Some("PDS"));
+}
+
+#[test]
+fn settings_of_a_newer_version_are_an_error() {
+    let tmp = test_dir("newer");
+    let path = tmp.path().join(SETTINGS_FILE);
+    std::fs::write(&path, format!("{{\"version\": {}}}", SETTINGS_VERSION + 1)).unwrap();
+
+    let error = Settings::load(&path).unwrap_err();
+    assert!(error.contains("newer version"), "{}", error);
+}
+
+#[test]
+fn unknown_record_type_not_saved() {
+    let tmp = test_dir("record-type");
+    let path = tmp.path().join(SETTINGS_FILE);
+
+    let settings = Settings {
+        record_type: Some("XYZ".to_string()),
+        ..Default::default()
+    };
+
+    assert!(settings.save(&path).is_err());
+    assert!(!path.exists());
+}
+
+#[test]
+fn at_least_one_file_converted_at_once() {
+    let jobs = |jobs| Settings {
+        jobs,
+        ..Default::default()
+    };
+
+    assert_eq!(jobs(Some(3)).jobs(), 3);
+    assert_eq!(jobs(Some(0)).jobs(), 1);
+    assert!(jobs(None).jobs() >= 1);
+}
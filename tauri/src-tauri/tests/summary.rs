@@ -0,0 +1,214 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/settings.rs"]
+#[allow(dead_code)]
+mod settings;
+
+#[path = "../src/numbering.rs"]
+#[allow(dead_code)]
+mod numbering;
+
+#[path = "../src/convert.rs"]
+#[allow(dead_code)]
+mod convert;
+
+mod common;
+
+use chrono::NaiveDate;
+use common::test_dir;
+use convert::{convert_files_numbered, write_batch_summary, FileRequest, WrittenSummary};
+use csvconv::options::ConversionOptions;
+use csvconv::summary::SUMMARY_PREFIX;
+
+const PAYROLL: &str = "Client Name,Acme Widgets
+Client Number,0123456789
+Processing Centre,00320
+Currency Code,CAD
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+1001,Jane Smith,003,01234,1234567,\"$1,250.00\",N,,
+1002,John Doe,003,01234,7654321,$500.00,N,,
+";
+
+const BONUS: &str = "Client Name,Acme Widgets
+Client Number,0123456789
+Processing Centre,00320
+Currency Code,CAD
+Payment Date,2045/02/01
+Transaction Code,200
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+1001,Jane Smith,003,01234,1234567,$100.00,N,,
+1002,John Doe,003,01234,7654321,$25.50,N,,
+";
+
+const EXPECTED: &str = "Conversion summary
+Generated: 2045-01-15 09:30:00
+Generated by: payroll-clerk
+
+Files: 2 converted, 0 failed
+Credits: 4 items, 1875.50
+Debits: 0 items, 0.00
+
+1. <dir>/payroll.csv (PDS)
+   Output: <dir>/payroll.txt
+   Client number: 0123456789
+   File creation: 1 on 2045-01-15
+   Credits: 2 items, 1750.00
+   Debits: 0 items, 0.00
+   SHA-256: <payroll-sha256>
+
+2. <dir>/bonus.csv (PDS)
+   Output: <dir>/bonus.txt
+   Client number: 0123456789
+   File creation: 1 on 2045-01-15
+   Credits: 2 items, 125.50
+   Debits: 0 items, 0.00
+   SHA-256: <bonus-sha256>
+";
+
+#[test]
+fn summary_written_alongside_outputs() {
+    let tmp = test_dir("batch");
+    let dir = tmp.path();
+    let payroll = dir.join("payroll.csv");
+    let bonus = dir.join("bonus.csv");
+    std::fs::write(&payroll, PAYROLL).unwrap();
+    std::fs::write(&bonus, BONUS).unwrap();
+
+    let options = ConversionOptions {
+        file_creation_date: NaiveDate::from_ymd_opt(2045, 1, 15),
+        ..Default::default()
+    };
+    let results = convert_files_numbered(
+        &[payroll.to_str().unwrap(), bonus.to_str().unwrap()].map(FileRequest::new),
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &options,
+        1,
+        None,
+        &|_| (),
+    );
+
+    let generated_at = NaiveDate::from_ymd_opt(2045, 1, 15)
+        .unwrap()
+        .and_hms_opt(9, 30, 0)
+        .unwrap();
+    let paths = write_batch_summary(
+        &results,
+        dir.to_str().unwrap(),
+        generated_at,
+        "payroll-clerk",
+    )
+    .unwrap();
+
+    let name = format!("{}20450115_093000", SUMMARY_PREFIX);
+    assert_eq!(
+        paths.text,
+        dir.join(format!("{}.txt", name)).to_str().unwrap()
+    );
+    assert_eq!(
+        paths.json,
+        dir.join(format!("{}.json", name)).to_str().unwrap()
+    );
+
+    let sha = |i: usize| results[i].report.as_ref().unwrap().output_sha256.clone();
+    let text = std::fs::read_to_string(&paths.text)
+        .unwrap()
+        .replace(dir.to_str().unwrap(), "<dir>")
+        .replace(&sha(0), "<payroll-sha256>")
+        .replace(&sha(1), "<bonus-sha256>");
+    assert_eq!(text, EXPECTED);
+
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&paths.json).unwrap()).unwrap();
+    assert_eq!(json["generated_at"], "2045-01-15T09:30:00");
+    assert_eq!(json["generated_by"], "payroll-clerk");
+    assert_eq!(json["totals"]["credit_amount"], 187550);
+    assert_eq!(json["totals"]["credit_count"], 4);
+    assert_eq!(json["files"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        json["files"][1]["output"],
+        results[1].output.as_deref().unwrap()
+    );
+}
+
+#[test]
+fn summary_lists_failed_files() {
+    let tmp = test_dir("failed");
+    let dir = tmp.path();
+    let missing = dir.join("missing.csv");
+
+    let results = convert_files_numbered(
+        &[missing.to_str().unwrap()].map(FileRequest::new),
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    let generated_at = NaiveDate::from_ymd_opt(2045, 1, 15)
+        .unwrap()
+        .and_hms_opt(9, 30, 0)
+        .unwrap();
+    let paths = write_batch_summary(
+        &results,
+        dir.to_str().unwrap(),
+        generated_at,
+        "payroll-clerk",
+    )
+    .unwrap();
+
+    let text = std::fs::read_to_string(&paths.text).unwrap();
+    assert!(text.contains("Files: 0 converted, 1 failed\n"));
+    assert!(text.contains("   Not converted\n"));
+    assert!(text.contains("   Error: Cannot read "));
+}
+
+#[test]
+fn summaries_of_one_second_kept_apart() {
+    let tmp = test_dir("same-second");
+    let dir = tmp.path();
+    let missing = dir.join("missing.csv");
+
+    let results = convert_files_numbered(
+        &[missing.to_str().unwrap()].map(FileRequest::new),
+        "PDS",
+        dir.to_str().unwrap(),
+        None,
+        &ConversionOptions::default(),
+        1,
+        None,
+        &|_| (),
+    );
+
+    let generated_at = NaiveDate::from_ymd_opt(2045, 1, 15)
+        .unwrap()
+        .and_hms_opt(9, 30, 0)
+        .unwrap();
+    let first = WrittenSummary::write(&results, dir.to_str().unwrap(), generated_at, "clerk-a");
+    let second = WrittenSummary::write(&results, dir.to_str().unwrap(), generated_at, "clerk-b");
+
+    let name = format!("{}20450115_093000", SUMMARY_PREFIX);
+    let first = first.summary.unwrap();
+    let second = second.summary.unwrap();
+    assert_eq!(
+        first.text,
+        dir.join(format!("{}.txt", name)).to_str().unwrap()
+    );
+    assert_eq!(
+        second.json,
+        dir.join(format!("{}_2.json", name)).to_str().unwrap()
+    );
+
+    // Neither batch's summary replaces the other's
+    let text = |path: &str| std::fs::read_to_string(path).unwrap();
+    assert!(text(&first.text).contains("Generated by: clerk-a"));
+    assert!(text(&second.text).contains("Generated by: clerk-b"));
+}
@@ -0,0 +1,88 @@
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/settings.rs"]
+#[allow(dead_code)]
+mod settings;
+
+#[path = "../src/template.rs"]
+mod template;
+
+mod common;
+
+use chrono::NaiveDate;
+use common::test_dir;
+use csvconv::csv::convert_reader_with_report;
+use csvconv::types::RecordType;
+use settings::{ClientProfile, Settings};
+use template::{template_contents, write_template};
+
+fn payment_date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2045, 2, 1).unwrap()
+}
+
+#[test]
+fn written_template_converts() {
+    let tmp = test_dir("converts");
+    let dir = tmp.path();
+    let path = dir.join("payroll.csv");
+
+    write_template(&path, None, payment_date(), false).unwrap();
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    assert!(csv.starts_with("Client Name,Your Company Name\n"));
+    assert!(csv.contains("Payment Date,2045/02/01\n"));
+    assert!(csv
+        .contains("Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total\n"));
+
+    let settings = Settings::default();
+    let conversion = convert_reader_with_report(
+        csv.as_bytes(),
+        RecordType::Credit,
+        &settings.conversion_options(),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    assert_eq!(conversion.report.total_credit_count, 1);
+    assert_eq!(conversion.report.total_credit_amount, 10000);
+}
+
+#[test]
+fn template_filled_in_from_client_profile() {
+    let profile = ClientProfile {
+        client_name: Some("Acme Widgets, Ltd".to_string()),
+        client_number: Some("9876543210".to_string()),
+        currency_code: Some("USD".to_string()),
+        ..Default::default()
+    };
+
+    let csv = template_contents(Some(&profile), payment_date());
+
+    assert!(csv.starts_with("Client Name,\"Acme Widgets, Ltd\"\nClient Number,9876543210\n"));
+    assert!(csv.contains("Currency Code,USD\n"));
+    // Rows the profile leaves out keep their example values
+    assert!(csv.contains("Processing Centre,00320\n"));
+
+    let conversion =
+        convert_reader_with_report(csv.as_bytes(), RecordType::Credit, &Default::default())
+            .unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(conversion.report.client_number, "9876543210");
+}
+
+#[test]
+fn existing_file_only_replaced_when_asked() {
+    let tmp = test_dir("existing");
+    let dir = tmp.path();
+    let path = dir.join("payroll.csv");
+    std::fs::write(&path, "my payroll").unwrap();
+
+    let refused = write_template(&path, None, payment_date(), false).unwrap_err();
+    assert!(refused.contains("already exists"));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "my payroll");
+
+    write_template(&path, None, payment_date(), true).unwrap();
+    assert!(std::fs::read_to_string(&path)
+        .unwrap()
+        .starts_with("Client Name,"));
+}
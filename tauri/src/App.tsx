@@ -1,7 +1,8 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import "./App.css";
-import { useState } from "react";
-import { open } from "@tauri-apps/plugin-dialog";
+import { useEffect, useState } from "react";
+import { open, save } from "@tauri-apps/plugin-dialog";
 
 declare module "react" {
   interface InputHTMLAttributes<T> extends HTMLAttributes<T> {
@@ -10,11 +11,212 @@ declare module "react" {
   }
 }
 
+// A log entry found while converting a file, as returned by `convert`
+interface ConversionIssue {
+  // The file the issue was found in, where it is one of several merged
+  file?: string;
+  line: number | null;
+  message: string;
+}
+
+// Outcome of converting or checking one file, as returned by `convert` and
+// `validate`
+interface FileConversionResult {
+  input: string;
+  record_type: string;
+  status: "converted" | "valid" | "failed";
+  output: string | null;
+  report: { total_credit_count: number; total_debit_count: number } | null;
+  errors: ConversionIssue[];
+  warnings: ConversionIssue[];
+}
+
+// A file as it would be converted, as returned by `preview`
+interface ConversionPreview {
+  input: string;
+  status: "converted" | "failed";
+  report: {
+    total_credit_count: number;
+    total_credit_amount: number;
+    total_debit_count: number;
+    total_debit_amount: number;
+  } | null;
+  errors: ConversionIssue[];
+  warnings: ConversionIssue[];
+  records: { record_type: string; text: string }[];
+  record_count: number;
+}
+
+// A file of a batch, with the record type and options it converts with if
+// they differ from those of the batch
+interface FileRequest {
+  path: string;
+  record_type?: string;
+  options?: { lenient?: boolean; whole_dollars_only?: boolean };
+}
+
+// A batch converted before, as returned by `get_history`
+interface HistoryEntry {
+  id: number;
+  timestamp: string;
+  files: FileRequest[];
+  record_type: string;
+  output_directory: string;
+  outputs: string[];
+  totals: {
+    credit_count: number;
+    credit_amount: number;
+    debit_count: number;
+    debit_amount: number;
+  };
+  converted: number;
+  failed: number;
+}
+
+// Summary written for a batch, when the settings ask for one
+interface WrittenSummary {
+  summary: { text: string; json: string } | null;
+  summary_error: string | null;
+}
+
+// Outcome of converting a batch of the history again, as returned by `rerun`
+type Rerun =
+  | (WrittenSummary & {
+      kind: "converted";
+      output_directory: string;
+      results: FileConversionResult[];
+    })
+  | {
+      kind: "missing_inputs";
+      request: { files: FileRequest[]; record_type: string; output_directory: string };
+      missing: string[];
+    };
+
+// Records shown of a previewed file
+const PREVIEW_RECORDS = 5;
+
+// Files the app was opened with, as returned by `get_pending_files`
+interface StagedFiles {
+  files: string[];
+  rejected: { path: string; reason: string }[];
+}
+
+let dollars = (cents: number) => (cents / 100).toFixed(2);
+
+// Progress of a file of the batch, as sent in `conversion://progress` events
+type ConversionProgress =
+  | { kind: "started"; index: number; input: string }
+  | { kind: "rows"; index: number; input: string; rows: number }
+  | { kind: "finished"; index: number; result: FileConversionResult };
+
+interface FileProgress {
+  input: string;
+  rows: number;
+  status: "converting" | "converted" | "failed";
+}
+
+let describeProgress = (p: FileProgress) =>
+  p.status == "converting"
+    ? `${p.input}: converting, ${p.rows} rows read`
+    : `${p.input}: ${p.status}`;
+
+let describeIssue = (input: string, kind: string, issue: ConversionIssue) =>
+  issue.line == null
+    ? `${issue.file ?? input}: ${kind}: ${issue.message}`
+    : `${issue.file ?? input}: ${kind} on line ${issue.line}: ${issue.message}`;
+
+let describeResult = (result: FileConversionResult) => {
+  let payments = result.report
+    ? result.report.total_credit_count + result.report.total_debit_count
+    : 0;
+  let lines =
+    result.status == "converted"
+      ? [`${result.input}: wrote ${result.output} with ${payments} payments`]
+      : result.status == "valid"
+      ? [`${result.input}: ready to convert, ${payments} payments`]
+      : [`${result.input}: not converted`];
+
+  return lines
+    .concat(result.errors.map((e) => describeIssue(result.input, "error", e)))
+    .concat(
+      result.warnings.map((w) => describeIssue(result.input, "warning", w))
+    );
+};
+
+let describeSummary = (written: WrittenSummary) =>
+  written.summary
+    ? [`Summary written to ${written.summary.text}`]
+    : written.summary_error
+    ? [written.summary_error]
+    : [];
+
 function App() {
   let [inputFiles, setInputFiles] = useState([]);
   let [recordType, setRecordType] = useState("PDS");
   let [outputDir, setOutputDir] = useState("");
+  let [outputName, setOutputName] = useState("{stem}.txt");
+  // File creation number chosen for the next conversion, in place of the
+  // next one of each client
+  let [creationNumber, setCreationNumber] = useState("");
+  let [advanceCreationNumber, setAdvanceCreationNumber] = useState(false);
   let [response, setResponse] = useState([]);
+  let [progress, setProgress] = useState<FileProgress[]>([]);
+  let [preview, setPreview] = useState<ConversionPreview | null>(null);
+  let [settings, setSettings] = useState<Record<string, unknown>>({});
+  let [history, setHistory] = useState<HistoryEntry[]>([]);
+  let [template, setTemplate] = useState<string | null>(null);
+
+  let loadHistory = () =>
+    invoke("get_history")
+      .then((entries) => setHistory(entries as HistoryEntry[]))
+      .catch((e) => console.error(e));
+
+  useEffect(() => {
+    loadHistory();
+  }, []);
+
+  useEffect(() => {
+    invoke("get_settings")
+      .then((saved: any) => {
+        setSettings(saved);
+        if (saved.record_type) setRecordType(saved.record_type);
+        if (saved.output_directory) setOutputDir(saved.output_directory);
+      })
+      .catch((e) => console.error(e));
+  }, []);
+
+  // Adds the files the app was opened with to the list, now and whenever
+  // more are opened while it runs
+  useEffect(() => {
+    let takePendingFiles = () =>
+      invoke("get_pending_files")
+        .then((pending) => {
+          let { files, rejected } = pending as StagedFiles;
+          if (files.length > 0) {
+            setInputFiles((current) => removeDuplicates([...current, ...files]));
+          }
+          if (rejected.length > 0) {
+            setResponse(rejected.map((r) => `${r.path}: not added: ${r.reason}`));
+          }
+        })
+        .catch((e) => console.error(e));
+
+    takePendingFiles();
+    let unlisten = listen("launch://files-opened", takePendingFiles);
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  // Remembers a choice for the next launch
+  let remember = (changes: Record<string, unknown>) => {
+    let updated = { ...settings, ...changes };
+    setSettings(updated);
+    invoke("set_settings", { settings: updated }).catch((e) =>
+      console.error(e)
+    );
+  };
 
   let removeDuplicates = (L: string[]) => {
     let unique = [];
@@ -32,7 +234,10 @@ function App() {
       multiple: false,
       directory: true,
     });
-    setOutputDir(selected);
+    if (selected) {
+      setOutputDir(selected);
+      remember({ output_directory: selected });
+    }
   };
 
   let onInputSelect = async (e) => {
@@ -48,9 +253,58 @@ function App() {
     setInputFiles(inputFiles.filter((v) => v != file));
   };
 
-  let onConvert = async () => {
-    let errorMessages = []
+  let onPreview = async (file: string) => {
+    setPreview(
+      (await invoke("preview", {
+        filename: file,
+        recordType: recordType,
+        records: PREVIEW_RECORDS,
+      })) as ConversionPreview
+    );
+  };
+
+  let onValidate = async () => {
     if (inputFiles.length == 0) {
+      setResponse(["Must select at least 1 input file."]);
+      return;
+    }
+
+    let results = (await invoke("validate", {
+      filename: inputFiles,
+      recordType: recordType,
+    })) as FileConversionResult[];
+
+    setResponse(results.flatMap(describeResult));
+  };
+
+  let trackProgress = () =>
+    listen<ConversionProgress>(
+      "conversion://progress",
+      ({ payload }) => {
+        setProgress((files) => {
+          let updated = [...files];
+          let file = updated[payload.index];
+
+          if (payload.kind == "started") {
+            updated[payload.index] = {
+              input: payload.input,
+              rows: 0,
+              status: "converting",
+            };
+          } else if (payload.kind == "rows") {
+            updated[payload.index] = { ...file, rows: payload.rows };
+          } else {
+            updated[payload.index] = { ...file, status: payload.result.status };
+          }
+
+          return updated;
+        });
+      }
+    );
+
+  let onConvert = async (files: string[] = inputFiles) => {
+    let errorMessages = []
+    if (files.length == 0) {
       errorMessages.push("Must select at least 1 input file.");
     }
 
@@ -63,13 +317,196 @@ function App() {
       return;
     }
 
-    let data = await invoke("convert", {
+    setResponse([]);
+    setProgress([]);
+
+    let unlisten = await trackProgress();
+
+    try {
+      let conversion = (await invoke("convert", {
+        files: files.map((path) => ({ path })),
+        recordType: recordType,
+        outputDirectory: outputDir,
+        outputName: outputName,
+        creationNumber: creationNumber ? Number(creationNumber) : null,
+        advanceCreationNumber: advanceCreationNumber,
+      })) as WrittenSummary & { results: FileConversionResult[] };
+
+      setResponse(
+        conversion.results
+          .flatMap(describeResult)
+          .concat(describeSummary(conversion))
+      );
+    } finally {
+      unlisten();
+      loadHistory();
+    }
+  };
+
+  // Converts into a directory chosen for the batch, rather than the one
+  // chosen above
+  let onConvertTo = async () => {
+    if (inputFiles.length == 0) {
+      setResponse(["Must select at least 1 input file."]);
+      return;
+    }
+
+    setResponse([]);
+    setProgress([]);
+
+    let unlisten = await trackProgress();
+
+    try {
+      let conversion = (await invoke("convert_choosing_directory", {
+        files: inputFiles.map((path) => ({ path })),
+        recordType: recordType,
+        outputName: outputName,
+        creationNumber: creationNumber ? Number(creationNumber) : null,
+        advanceCreationNumber: advanceCreationNumber,
+      })) as
+        | { status: "cancelled" }
+        | (WrittenSummary & {
+            status: "chosen";
+            output_directory: string;
+            results: FileConversionResult[];
+          });
+
+      if (conversion.status == "cancelled") {
+        setResponse(["No directory was chosen, nothing was converted."]);
+      } else {
+        setResponse(
+          conversion.results
+            .flatMap(describeResult)
+            .concat(describeSummary(conversion))
+        );
+      }
+    } finally {
+      unlisten();
+      loadHistory();
+    }
+  };
+
+  // Merges the files into one, for payrolls kept in several spreadsheets
+  let onMerge = async () => {
+    if (inputFiles.length < 2) {
+      setResponse(["Must select at least 2 input files to merge."]);
+      return;
+    }
+
+    let merged = (await invoke("merge_convert", {
       filename: inputFiles,
       recordType: recordType,
       outputDirectory: outputDir,
-    }) as string[];
+      outputName: outputName,
+      creationNumber: creationNumber ? Number(creationNumber) : null,
+      advanceCreationNumber: advanceCreationNumber,
+    })) as FileConversionResult & WrittenSummary & {
+      sources: {
+        source: string;
+        credit_count: number;
+        credit_amount: number;
+        debit_count: number;
+        debit_amount: number;
+      }[];
+    };
+
+    setProgress([]);
+    setResponse(
+      describeResult(merged).concat(
+        merged.sources.map(
+          (s) =>
+            `${s.source}: ${s.credit_count + s.debit_count} payments, ${dollars(
+              s.credit_amount + s.debit_amount
+            )}`
+        ),
+        describeSummary(merged)
+      )
+    );
+  };
+
+  // Copies the output, summary or errors of a conversion of the history
+  let onCopy = async (kind: "Output" | "Summary" | "Errors", id: number) => {
+    try {
+      let copied = await invoke("copy_to_clipboard", { kind, conversionId: id });
+      setResponse([`Copied ${copied} characters to the clipboard`]);
+    } catch (e) {
+      setResponse([String(e)]);
+    }
+  };
+
+  let onRerun = async (id: number) => {
+    setResponse([]);
+    setProgress([]);
+
+    let unlisten = await trackProgress();
+
+    try {
+      let rerun = (await invoke("rerun", { historyId: id })) as Rerun;
+
+      if (rerun.kind == "converted") {
+        setResponse(
+          rerun.results.flatMap(describeResult).concat(describeSummary(rerun))
+        );
+      } else {
+        // Nothing was converted: keep the files still there and ask for the
+        // others again
+        let { request, missing } = rerun;
+        setInputFiles(
+          request.files.map((f) => f.path).filter((i) => !missing.includes(i))
+        );
+        setRecordType(request.record_type);
+        setOutputDir(request.output_directory);
+        setResponse(
+          missing.map((f) => `${f} no longer exists. Choose it again and convert.`)
+        );
+      }
+    } catch (e) {
+      setResponse([String(e)]);
+    } finally {
+      unlisten();
+      loadHistory();
+    }
+  };
+
+  // Shows a converted file, which fails if it was moved since
+  let onShowOutput = async (command: "reveal_in_folder" | "open_output", path: string) => {
+    try {
+      await invoke(command, { path });
+    } catch (e) {
+      let error = e as { kind: string; path: string; message?: string };
+      setResponse([
+        error.kind == "moved"
+          ? `${error.path} no longer exists. It may have been moved or removed.`
+          : error.kind == "not_produced"
+          ? `${error.path} was not converted by this app.`
+          : `Cannot show ${error.path}: ${error.message}`,
+      ]);
+    }
+  };
+
+  let onShowTemplate = async () => {
+    setTemplate(template == null ? ((await invoke("template_contents")) as string) : null);
+  };
+
+  let onSaveTemplate = async () => {
+    let path = await save({
+      defaultPath: "payroll.csv",
+      filters: [{ name: "CSV", extensions: ["csv"] }],
+    });
+    if (!path) return;
 
-    setResponse([...data]);
+    try {
+      // The save dialog has already asked whether to replace the file
+      let written = await invoke("write_template", { path, overwrite: true });
+      setResponse([`Template written to ${written}`]);
+    } catch (e) {
+      setResponse([String(e)]);
+    }
+  };
+
+  let onClearHistory = async () => {
+    await invoke("clear_history");
+    setHistory([]);
   };
 
   return (
@@ -117,10 +554,23 @@ function App() {
               <button type="button" onClick={onInputSelect}>
                 Add Files
               </button>
+              &nbsp;
+              <button type="button" onClick={onSaveTemplate}>
+                Save Template
+              </button>
+              &nbsp;
+              <a href="#" onClick={onShowTemplate}>
+                {template == null ? "Show template" : "Hide template"}
+              </a>
+              {template != null && <pre>{template}</pre>}
               <ul>
                 {inputFiles.map((v) => (
                   <li key={v}>
                     {v} &nbsp;&nbsp;&nbsp;&nbsp;
+                    <a href="#" onClick={(_) => onPreview(v)}>
+                      Preview
+                    </a>
+                    &nbsp;&nbsp;
                     <a href="#" onClick={(_) => onRemoveInputFile(v)}>
                       Remove
                     </a>
@@ -133,6 +583,7 @@ function App() {
               <select
                 onChange={(e) => {
                   setRecordType(e.target.value);
+                  remember({ record_type: e.target.value });
                 }}
                 value={recordType}
               >
@@ -149,19 +600,178 @@ function App() {
                 &nbsp; {outputDir}
               </div>
             </div>
+            <div>
+              <h3>Output File Name</h3>
+              <input
+                type="text"
+                value={outputName}
+                onChange={(e) => {
+                  setOutputName(e.target.value);
+                }}
+              />
+              <p>
+                {"{stem}"} is replaced with the name of the CSV file and{" "}
+                {"{seq}"} with the four digit file creation number.
+              </p>
+            </div>
+            <div>
+              <h3>File Creation Number</h3>
+              <input
+                type="number"
+                min="1"
+                max="9999"
+                placeholder="Next number of each client"
+                value={creationNumber}
+                onChange={(e) => setCreationNumber(e.target.value)}
+              />
+              <label>
+                <input
+                  type="checkbox"
+                  checked={advanceCreationNumber}
+                  disabled={creationNumber == ""}
+                  onChange={(e) => setAdvanceCreationNumber(e.target.checked)}
+                />
+                &nbsp;Continue numbering from this number
+              </label>
+            </div>
+            <div>
+              <label>
+                <input
+                  type="checkbox"
+                  checked={settings.write_summary === true}
+                  onChange={(e) =>
+                    remember({ write_summary: e.target.checked })
+                  }
+                />
+                &nbsp;Write a summary of each batch to the output directory
+              </label>
+            </div>
+            <div>
+              <h3>Files Converted at Once</h3>
+              <input
+                type="number"
+                min="1"
+                placeholder="One for each processor"
+                value={(settings.jobs as number | undefined) ?? ""}
+                onChange={(e) =>
+                  remember({
+                    jobs: e.target.value == "" ? null : Number(e.target.value),
+                  })
+                }
+              />
+            </div>
           </form>
         </div>
         <div className="right">
+          {preview && (
+            <div>
+              <h3>Preview of {preview.input}</h3>
+              {preview.report && (
+                <p>
+                  {preview.report.total_credit_count} credits totalling{" "}
+                  {dollars(preview.report.total_credit_amount)},{" "}
+                  {preview.report.total_debit_count} debits totalling{" "}
+                  {dollars(preview.report.total_debit_amount)}
+                </p>
+              )}
+              <ul>
+                {preview.errors
+                  .map((e) => describeIssue(preview.input, "error", e))
+                  .concat(
+                    preview.warnings.map((w) =>
+                      describeIssue(preview.input, "warning", w)
+                    )
+                  )
+                  .map((v) => (
+                    <li key={v}>{v}</li>
+                  ))}
+              </ul>
+              <pre>
+                {preview.records
+                  .map((r) => `${r.record_type}  ${r.text.trimEnd()}`)
+                  .join("\n")}
+              </pre>
+              {preview.record_count > preview.records.length && (
+                <p>
+                  First {preview.records.length} of {preview.record_count}{" "}
+                  records
+                </p>
+              )}
+              {preview.status == "converted" && (
+                <button type="button" onClick={() => onConvert([preview.input])}>
+                  Save
+                </button>
+              )}
+            </div>
+          )}
+          <h3>Progress</h3>
+          <ul>
+            {progress.map((p, i) => (
+              <li key={i}>{describeProgress(p)}</li>
+            ))}
+          </ul>
           <h3>Log</h3>
           {response.map((v) => (
             <li>{v}</li>
           ))}
+          <h3>Recent Conversions</h3>
+          <ul>
+            {history.map((h) => (
+              <li key={h.id}>
+                {new Date(h.timestamp).toLocaleString()}: {h.record_type}{" "}
+                {h.files.map((f) => f.path).join(", ")} ({h.converted} converted, {h.failed}{" "}
+                failed, {dollars(h.totals.credit_amount + h.totals.debit_amount)}
+                ){" "}
+                <a href="#" onClick={() => onRerun(h.id)}>
+                  Run again
+                </a>
+                {(["Output", "Summary", "Errors"] as const).map((kind) => (
+                  <span key={kind}>
+                    {" "}
+                    <a href="#" onClick={() => onCopy(kind, h.id)}>
+                      Copy {kind.toLowerCase()}
+                    </a>
+                  </span>
+                ))}
+                <ul>
+                  {h.outputs.map((output) => (
+                    <li key={output}>
+                      {output}{" "}
+                      <a href="#" onClick={() => onShowOutput("reveal_in_folder", output)}>
+                        Show in folder
+                      </a>{" "}
+                      <a href="#" onClick={() => onShowOutput("open_output", output)}>
+                        Open
+                      </a>
+                    </li>
+                  ))}
+                </ul>
+              </li>
+            ))}
+          </ul>
+          {history.length > 0 && (
+            <button type="button" onClick={onClearHistory}>
+              Clear History
+            </button>
+          )}
         </div>
       </div>
       <div>
-        <button type="button" className="btn-green" onClick={onConvert}>
+        <button type="button" onClick={onValidate}>
+          Check
+        </button>
+        &nbsp;
+        <button type="button" className="btn-green" onClick={() => onConvert()}>
           Convert
         </button>
+        &nbsp;
+        <button type="button" onClick={onConvertTo}>
+          Convert To...
+        </button>
+        &nbsp;
+        <button type="button" onClick={onMerge}>
+          Merge Into One File
+        </button>
       </div>
     </main>
   );
@@ -0,0 +1,59 @@
+//! Time taken to build a file of 10,000 payments. Compare a change to the
+//! record builders against the tree before it by saving a baseline there
+//! first:
+//!
+//! ```text
+//! $ cargo bench --bench builders -- --save-baseline before
+//! $ cargo bench --bench builders -- --baseline before
+//! ```
+
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use lib::header::CPA005Record;
+use lib::payment::{BasicPayment, BasicPaymentSegment};
+
+const PAYMENTS: u64 = 10_000;
+
+/// A file of `PAYMENTS` credits, one segment each, as a payroll converts to
+fn payroll() -> CPA005Record {
+    let mut record = CPA005Record::new();
+    record
+        .set_client_number("0123456789".to_string())
+        .set_file_creation_number(1)
+        .set_file_creation_date(2045, 32);
+
+    for i in 0..PAYMENTS {
+        let mut segment = BasicPaymentSegment::new();
+        segment
+            .set_transaction_code("200".to_string())
+            .set_amount(100_000 + i)
+            .set_payment_date(2045, 32)
+            .set_financial_institution_number("003".to_string())
+            .set_financial_institution_branch_number("01234".to_string())
+            .set_account_number(format!("{}", 1_000_000 + i))
+            .set_client_short_name("Acme".to_string())
+            .set_customer_name(format!("Employee {}", i))
+            .set_client_name("Acme Widgets Ltd".to_string())
+            .set_client_number("0123456789".to_string())
+            .set_customer_number(i.to_string());
+
+        let mut payment = BasicPayment::new();
+        payment.set_client_number("0123456789".to_string());
+        payment.segments.push(segment);
+        record.add_basic_payment(payment);
+    }
+
+    record
+}
+
+fn build(c: &mut Criterion) {
+    let record = payroll();
+
+    c.bench_function("build 10k payments", |b| b.iter(|| record.build()));
+}
+
+criterion_group!(benches, build);
+criterion_main!(benches);
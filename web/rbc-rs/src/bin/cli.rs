@@ -25,4 +25,504 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-fn main() {}
+use chrono::NaiveDate;
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[path = "../lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+use lib::error::ErrorLog;
+use lib::lint::{issues_to_csv, ValidationIssue};
+use lib::payment::PRENOTE_TRANSACTION_CODE;
+use lib::report::ConversionReport;
+use lib::transits::TransitList;
+use lib::types::{RecordType, SortOrder};
+use lib::validate::ValidationOptions;
+
+#[path = "../csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+use csvconv::csv::{convert_reader_with_report, parse_dollar_amount_to_cents};
+use csvconv::naming::{check_output_name, render_output_name, DEFAULT_OUTPUT_NAME};
+use csvconv::options::{AmountFormat, BlankAmount, ConversionOptions};
+use csvconv::parallel::{default_jobs, map_in_parallel};
+use csvconv::summary::{current_user, BatchSummary, SummaryFile};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ConvType {
+    /// Credit file (ACH Direct Deposits)
+    #[value(name = "PDS")]
+    Pds,
+    /// Debit file (ACH Direct Payments)
+    #[value(name = "PAD")]
+    Pad,
+}
+
+impl ConvType {
+    fn name(&self) -> &'static str {
+        match self {
+            ConvType::Pds => "PDS",
+            ConvType::Pad => "PAD",
+        }
+    }
+
+    fn record_type(&self) -> RecordType {
+        match self {
+            ConvType::Pds => RecordType::Credit,
+            ConvType::Pad => RecordType::Debit,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OnBlankAmount {
+    /// Leave rows with a blank amount out of the file
+    Skip,
+    /// Fail the conversion
+    Error,
+}
+
+impl OnBlankAmount {
+    fn blank_amount(&self) -> BlankAmount {
+        match self {
+            OnBlankAmount::Skip => BlankAmount::Skip,
+            OnBlankAmount::Error => BlankAmount::Error,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RecordTerminator {
+    /// Unix line ending
+    Lf,
+    /// Windows line ending
+    Crlf,
+    /// Records written back to back
+    None,
+}
+
+impl RecordTerminator {
+    fn terminator(&self) -> Option<String> {
+        match self {
+            RecordTerminator::Lf => Some("\n".to_string()),
+            RecordTerminator::Crlf => Some("\r\n".to_string()),
+            RecordTerminator::None => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Sort {
+    /// The order of the rows of the CSV file
+    Source,
+    /// By account number, lowest first
+    Account,
+    /// By amount, largest first
+    AmountDesc,
+}
+
+impl Sort {
+    fn order(&self) -> SortOrder {
+        match self {
+            Sort::Source => SortOrder::SourceOrder,
+            Sort::Account => SortOrder::ByAccount,
+            Sort::AmountDesc => SortOrder::ByAmountDesc,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Converts CSV files into the CPA-005 format
+#[derive(Parser)]
+struct Args {
+    /// Type of CPA-005 file to produce
+    #[arg(short = 't', long = "type", value_enum)]
+    convtype: ConvType,
+
+    /// Directory the converted files are written to, defaults to the
+    /// directory of each input file
+    #[arg(short, long)]
+    output_dir: Option<PathBuf>,
+
+    /// Name of each converted file, where `{stem}` is replaced with the
+    /// input file's name without its extension and `{seq}` with the file
+    /// creation number padded to four digits, e.g. "ACH_{seq}.txt"
+    #[arg(long, default_value = DEFAULT_OUTPUT_NAME)]
+    output_name: String,
+
+    /// Format of the results printed to standard output
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Trade name shown to recipients instead of the client name, at most
+    /// 15 characters
+    #[arg(long)]
+    display_name: Option<String>,
+
+    /// File creation date written to the header, YYYY-MM-DD, defaults to
+    /// today
+    #[arg(long)]
+    creation_date: Option<NaiveDate>,
+
+    /// Characters skipped in dollar amounts, such as grouping separators
+    /// and currency symbols, e.g. "'$" for amounts like $1'000.00
+    #[arg(long, default_value = ", $")]
+    amount_ignore: String,
+
+    /// Read amounts ending in CR or DR, such as 50.00CR, rejecting those
+    /// marked for the other type of file
+    #[arg(long)]
+    amount_indicators: bool,
+
+    /// Dollar amount the payments of each file must add up to, files with
+    /// any other total are rejected
+    #[arg(long)]
+    control_total: Option<String>,
+
+    /// Cut names longer than their fields to fit, warning of the text
+    /// dropped, instead of rejecting the file
+    #[arg(long)]
+    truncate_names: bool,
+
+    /// Write a prenote file to verify the accounts before live payments:
+    /// every amount is zero and carries the prenote transaction code
+    #[arg(long)]
+    prenote: bool,
+
+    /// Transaction code of every segment of a prenote file
+    #[arg(long, value_name = "CODE", default_value = PRENOTE_TRANSACTION_CODE)]
+    prenote_transaction_code: String,
+
+    /// CSV file of valid institution and branch number pairs, one
+    /// `institution,branch` pair a line. Payments to any other transit are
+    /// rejected.
+    #[arg(long, value_name = "PATH")]
+    transits: Option<PathBuf>,
+
+    /// What to do with payment rows whose amount is blank
+    #[arg(long, value_enum, default_value_t = OnBlankAmount::Error)]
+    on_blank_amount: OnBlankAmount,
+
+    /// Written between records of the converted file
+    #[arg(long, value_enum, default_value_t = RecordTerminator::Lf)]
+    record_terminator: RecordTerminator,
+
+    /// Also terminate the last record of the converted file
+    #[arg(long)]
+    terminate_last: bool,
+
+    /// Order the payments are written in
+    #[arg(long, value_enum, default_value_t = Sort::Source)]
+    sort: Sort,
+
+    /// Also write the errors and warnings of every file to this CSV file,
+    /// with the file, line, field, severity and message of each
+    #[arg(long, value_name = "PATH")]
+    issues_csv: Option<PathBuf>,
+
+    /// Also write a summary of the batch into this directory, as
+    /// conversion_summary_<timestamp>.txt and .json, with the files
+    /// produced, their totals and warnings, and who converted them when
+    #[arg(long, value_name = "DIR")]
+    summary_dir: Option<PathBuf>,
+
+    /// Files converted at once, defaults to one for each processor. Results
+    /// are reported in the order the files are given either way.
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// CSV files to convert, `-` reads standard input and writes the
+    /// converted file to standard output
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
+
+/// Result of converting a single input file
+#[derive(Serialize)]
+struct FileResult {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    ok: bool,
+    summary: Option<ConversionReport>,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+    /// The errors and warnings with the line they were found on, for
+    /// `--issues-csv`
+    #[serde(skip)]
+    issues: Vec<ValidationIssue>,
+}
+
+/// Path standing for standard input, or standard output for its result
+const STDIO: &str = "-";
+
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO)
+}
+
+fn output_path(input: &Path, args: &Args, file_creation_number: u32) -> PathBuf {
+    let stem = input.file_stem().unwrap_or(input.as_os_str());
+    let dir = match &args.output_dir {
+        Some(dir) => dir,
+        None => input.parent().unwrap_or(Path::new("")),
+    };
+
+    dir.join(render_output_name(
+        &args.output_name,
+        &stem.to_string_lossy(),
+        file_creation_number,
+    ))
+}
+
+/// Converts `input`, recording what is wrong with it in `log`. Returns where
+/// the converted file was written along with its summary, unless it could
+/// not be.
+fn convert(
+    input: &Path,
+    args: &Args,
+    transits: Option<&TransitList>,
+    log: &mut ErrorLog,
+) -> Option<(PathBuf, ConversionReport)> {
+    let reader: Box<dyn Read> = if is_stdio(input) {
+        Box::new(std::io::stdin().lock())
+    } else {
+        match File::open(input) {
+            Ok(f) => Box::new(f),
+            Err(e) => {
+                log.write_error(&format!("Could not open {}: {}", input.display(), e));
+                return None;
+            }
+        }
+    };
+
+    let amount_format = match AmountFormat::new(args.amount_ignore.chars()) {
+        Ok(f) => f.with_direction_indicators(args.amount_indicators),
+        Err(e) => {
+            log.write_error(&e);
+            return None;
+        }
+    };
+
+    let control_total_cents = match &args.control_total {
+        Some(total) => match parse_dollar_amount_to_cents(total, &amount_format) {
+            Some(cents) => Some(cents),
+            None => {
+                log.write_error(&format!(
+                    "Control total must be a dollar amount, not \"{}\"",
+                    total
+                ));
+                return None;
+            }
+        },
+        None => None,
+    };
+
+    let conversion = match convert_reader_with_report(
+        BufReader::new(reader),
+        args.convtype.record_type(),
+        &ConversionOptions {
+            originator_display_name: args.display_name.clone(),
+            file_creation_date: args.creation_date,
+            amount_format,
+            on_blank_amount: args.on_blank_amount.blank_amount(),
+            record_terminator: args.record_terminator.terminator(),
+            terminate_last: args.terminate_last,
+            control_total_cents,
+            sort: args.sort.order(),
+            truncate_names: args.truncate_names,
+            prenote: args.prenote,
+            prenote_transaction_code: args.prenote_transaction_code.clone(),
+            validation: ValidationOptions {
+                transits: transits.cloned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    ) {
+        Ok(c) => c,
+        Err(errors) => {
+            log.merge_log(&errors);
+            return None;
+        }
+    };
+
+    log.merge_log(&conversion.log);
+
+    let (output, written) = if is_stdio(input) {
+        let mut stdout = std::io::stdout().lock();
+        let written = stdout
+            .write_all(conversion.output.as_bytes())
+            .and_then(|_| stdout.flush());
+
+        (PathBuf::from(STDIO), written)
+    } else {
+        let output = output_path(input, args, conversion.report.file_creation_number);
+        let written = std::fs::write(&output, conversion.output);
+
+        (output, written)
+    };
+
+    if let Err(e) = written {
+        log.write_error(&format!("Could not write {}: {}", output.display(), e));
+        return None;
+    }
+
+    Some((output, conversion.report))
+}
+
+fn convert_file(input: &Path, args: &Args, transits: Option<&TransitList>) -> FileResult {
+    let mut log = ErrorLog::new();
+    let (output, summary) = convert(input, args, transits, &mut log).unzip();
+
+    FileResult {
+        input: input.to_path_buf(),
+        ok: output.is_some(),
+        output,
+        summary,
+        errors: log.get_error_list(),
+        warnings: log.get_warning_list(),
+        issues: ValidationIssue::from_log(Some(&display(input, "<stdin>")), &log),
+    }
+}
+
+fn display(path: &Path, stdio: &str) -> String {
+    if is_stdio(path) {
+        stdio.to_string()
+    } else {
+        path.display().to_string()
+    }
+}
+
+fn print_text(result: &FileResult, out: &mut dyn Write) {
+    let input = display(&result.input, "<stdin>");
+
+    match &result.output {
+        Some(output) => {
+            let _ = writeln!(out, "{} -> {}", input, display(output, "<stdout>"));
+        }
+        None => eprintln!("{}: conversion failed", input),
+    }
+
+    for warning in &result.warnings {
+        eprintln!("{}: warning: {}", input, warning);
+    }
+
+    for error in &result.errors {
+        eprintln!("{}: error: {}", input, error);
+    }
+}
+
+fn summary_file(result: &FileResult, args: &Args) -> SummaryFile {
+    SummaryFile {
+        input: display(&result.input, "<stdin>"),
+        record_type: args.convtype.name().to_string(),
+        output: result.output.as_deref().map(|o| display(o, "<stdout>")),
+        report: result.summary.clone(),
+        errors: result.errors.clone(),
+        warnings: result.warnings.clone(),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let mut failed = false;
+
+    if let Err(e) = check_output_name(&args.output_name) {
+        eprintln!("error: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let transits = match &args.transits {
+        Some(path) => match std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+            .and_then(|text| TransitList::parse(&text))
+        {
+            Ok(transits) => Some(transits),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    // Standard output carries the converted file when reading standard
+    // input, so results are reported on standard error instead
+    let mut out: Box<dyn Write> = if args.files.iter().any(|f| is_stdio(f)) {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    };
+
+    let mut issues = Vec::new();
+    let mut summary_files = Vec::new();
+
+    let jobs = args.jobs.map_or_else(default_jobs, NonZeroUsize::get);
+    let results = map_in_parallel(&args.files, jobs, |_, input| {
+        convert_file(input, &args, transits.as_ref())
+    });
+
+    for result in &results {
+        failed |= !result.ok;
+        issues.extend(result.issues.iter().cloned());
+        summary_files.push(summary_file(result, &args));
+
+        match args.format {
+            OutputFormat::Text => print_text(result, &mut out),
+            OutputFormat::Json => match serde_json::to_string(result) {
+                Ok(s) => {
+                    let _ = writeln!(out, "{}", s);
+                }
+                Err(e) => {
+                    eprintln!("Could not serialize result: {}", e);
+                    failed = true;
+                }
+            },
+        }
+    }
+
+    if let Some(path) = &args.issues_csv {
+        if let Err(e) = std::fs::write(path, issues_to_csv(&issues)) {
+            eprintln!("Could not write {}: {}", path.display(), e);
+            failed = true;
+        }
+    }
+
+    if let Some(dir) = &args.summary_dir {
+        let summary = BatchSummary::new(
+            chrono::Local::now().naive_local(),
+            &current_user(),
+            summary_files,
+        );
+
+        match summary.write(dir) {
+            // Only text results have room for it, JSON results are a line
+            // per file
+            Ok((text, _)) => {
+                if let OutputFormat::Text = args.format {
+                    let _ = writeln!(out, "summary -> {}", text.display());
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
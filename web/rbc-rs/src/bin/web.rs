@@ -1,74 +1,285 @@
-use std::process::exit;
-
-use actix_multipart::Multipart;
-use actix_web::http::header::{ContentDisposition, ContentType};
-use actix_web::{get, post, web, App, HttpResponse, HttpServer};
-use futures::{future, StreamExt, TryStreamExt};
-use open::that;
-use serde::Deserialize;
+use actix_web::middleware::Compress;
+use actix_web::{App, HttpServer};
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[path = "../lib/mod.rs"]
+#[allow(dead_code)]
 mod lib;
-use lib::types::RecordType;
 
 #[path = "../csvconv/mod.rs"]
+#[allow(dead_code)]
 mod csvconv;
-use csvconv::csv::convert_to_cpa005;
 
-#[derive(Deserialize)]
-struct ConvertRequestQuery {
-    convtype: String,
+#[path = "../server/mod.rs"]
+mod server;
+use server::audit::AuditLog;
+use server::auth::ApiKeys;
+use server::cors::CorsConfig;
+use server::downloads::{DownloadStore, DEFAULT_DOWNLOAD_TTL};
+use server::fetch::{AllowedHosts, UrlFetcher, DEFAULT_FETCH_TIMEOUT, DEFAULT_MAX_REDIRECTS};
+use server::jobs::{JobQueue, DEFAULT_JOB_QUEUE_SIZE, DEFAULT_JOB_TTL, DEFAULT_JOB_WORKERS};
+use server::limits::{
+    UploadLimits, ZipLimits, DEFAULT_MAX_UPLOAD_SIZE, DEFAULT_MAX_ZIP_ENTRIES, DEFAULT_MAX_ZIP_SIZE,
+};
+use server::logging::{LogFormat, RequestLog};
+use server::pool::{
+    ConversionPool, DEFAULT_CONVERSION_QUEUE_SIZE, DEFAULT_CONVERSION_TIMEOUT,
+    DEFAULT_CONVERSION_WORKERS,
+};
+use server::profiles::Profiles;
+use server::ratelimit::{RateLimiter, DEFAULT_BURST, DEFAULT_REQUESTS_PER_MINUTE};
+use server::shutdown::{stop_on_signal, Connections, DEFAULT_SHUTDOWN_TIMEOUT};
+use server::ServerConfig;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormatArg {
+    /// Human readable, for development
+    Pretty,
+    /// One JSON object per line, for log collectors
+    Json,
 }
 
-#[post("/convert")]
-async fn convert(mut body: Multipart, q: web::Query<ConvertRequestQuery>) -> HttpResponse {
-    let mut file_data = String::new();
-    let mut file_name = String::new();
-    while let Ok(Some(mut p)) = body.try_next().await {
-        file_name = p.content_disposition().get_filename().unwrap().to_string();
-        while let Some(chunk) = p.next().await {
-            let chunk = chunk.unwrap();
-            file_data.push_str(&String::from_utf8_lossy(chunk.as_ref()));
+impl From<LogFormatArg> for LogFormat {
+    fn from(format: LogFormatArg) -> Self {
+        match format {
+            LogFormatArg::Pretty => LogFormat::Pretty,
+            LogFormatArg::Json => LogFormat::Json,
         }
     }
+}
 
-    let cpa_format = match q.convtype.trim() {
-        "PDS" => convert_to_cpa005(file_data, RecordType::Credit),
-        "PAD" => convert_to_cpa005(file_data, RecordType::Debit),
-        _ => {
-            return HttpResponse::BadRequest().finish();
-        }
-    };
+#[derive(Parser)]
+struct Args {
+    /// Address the server listens on
+    #[arg(long, env = "RBC_BIND", default_value = "0.0.0.0:8080")]
+    bind: String,
 
-    match cpa_format {
-        Ok(s) => HttpResponse::Ok()
-            .content_type(ContentType::plaintext())
-            .insert_header(ContentDisposition::attachment(file_name))
-            .body(s),
-        Err(log) => HttpResponse::BadRequest()
-            .content_type(ContentType::plaintext())
-            .body(log.to_string()),
-    }
-}
+    /// Seconds in-flight requests are given to finish once a shutdown signal
+    /// is received
+    #[arg(long, env = "RBC_SHUTDOWN_TIMEOUT", default_value_t = DEFAULT_SHUTDOWN_TIMEOUT)]
+    shutdown_timeout: u64,
 
-#[get("/")]
-async fn index() -> HttpResponse {
-    HttpResponse::Ok().body(include_str!("../../index.html"))
-}
+    /// SQLite database every conversion is recorded in. No audit log is kept
+    /// if unset.
+    #[arg(long, env = "RBC_AUDIT_DB")]
+    audit_db: Option<PathBuf>,
+
+    /// Number of conversions of `/convert` requests run at once
+    #[arg(long, env = "RBC_CONVERSION_WORKERS", default_value_t = DEFAULT_CONVERSION_WORKERS)]
+    conversion_workers: usize,
+
+    /// Number of conversions that may wait for a worker before further
+    /// requests are refused
+    #[arg(long, env = "RBC_CONVERSION_QUEUE_SIZE", default_value_t = DEFAULT_CONVERSION_QUEUE_SIZE)]
+    conversion_queue_size: usize,
+
+    /// Seconds a request waits for its conversion before giving up on it
+    #[arg(long, env = "RBC_CONVERSION_TIMEOUT", default_value_t = DEFAULT_CONVERSION_TIMEOUT.as_secs())]
+    conversion_timeout: u64,
+
+    /// Number of background conversion jobs run at once
+    #[arg(long, env = "RBC_JOB_WORKERS", default_value_t = DEFAULT_JOB_WORKERS)]
+    job_workers: usize,
+
+    /// Number of background conversion jobs that may wait for a worker before
+    /// new jobs are refused
+    #[arg(long, env = "RBC_JOB_QUEUE_SIZE", default_value_t = DEFAULT_JOB_QUEUE_SIZE)]
+    job_queue_size: usize,
+
+    /// Seconds a finished job and its converted file are kept for download
+    #[arg(long, env = "RBC_JOB_TTL", default_value_t = DEFAULT_JOB_TTL)]
+    job_ttl: u64,
+
+    /// Keep converted files on disk for `/convert?store=true` to hand out a
+    /// single-use download link instead of the file. Off by default, as
+    /// converted files hold payment details.
+    #[arg(long, env = "RBC_DOWNLOAD_LINKS")]
+    download_links: bool,
+
+    /// Seconds a download link works for before the file is removed
+    #[arg(long, env = "RBC_DOWNLOAD_TTL", default_value_t = DEFAULT_DOWNLOAD_TTL)]
+    download_ttl: u64,
+
+    /// Maximum size in bytes of a request body
+    #[arg(long, env = "RBC_MAX_UPLOAD_SIZE", default_value_t = DEFAULT_MAX_UPLOAD_SIZE)]
+    max_upload_size: usize,
+
+    /// Maximum size in bytes of a single multipart field, defaults to the
+    /// maximum upload size
+    #[arg(long, env = "RBC_MAX_FIELD_SIZE")]
+    max_field_size: Option<usize>,
+
+    /// Maximum number of CSV files in an uploaded zip archive
+    #[arg(long, env = "RBC_MAX_ZIP_ENTRIES", default_value_t = DEFAULT_MAX_ZIP_ENTRIES)]
+    max_zip_entries: usize,
+
+    /// Maximum decompressed size in bytes of a single file in an uploaded
+    /// zip archive
+    #[arg(long, env = "RBC_MAX_ZIP_ENTRY_SIZE", default_value_t = DEFAULT_MAX_UPLOAD_SIZE)]
+    max_zip_entry_size: usize,
+
+    /// Maximum decompressed size in bytes of all the files in an uploaded
+    /// zip archive
+    #[arg(long, env = "RBC_MAX_ZIP_SIZE", default_value_t = DEFAULT_MAX_ZIP_SIZE)]
+    max_zip_size: usize,
+
+    /// Directory uploads are temporarily written to while being converted
+    #[arg(long, env = "RBC_TEMP_DIR")]
+    temp_dir: Option<PathBuf>,
+
+    /// Origin allowed to make cross-origin requests, may be repeated or
+    /// given as a comma separated list
+    #[arg(long = "cors-origin", env = "RBC_CORS_ORIGINS", value_delimiter = ',')]
+    cors_origins: Vec<String>,
 
-// async fn start_client() {
-//     match open::that("http://localhost:8080") {
-//         Ok(_) => (),
-//         Err(_) => exit(1),
-//     }
-// }
+    /// Allow cross-origin requests to include credentials
+    #[arg(long, env = "RBC_CORS_ALLOW_CREDENTIALS")]
+    cors_allow_credentials: bool,
+
+    /// Serve the UI from this directory rather than the copy embedded at
+    /// build time, for UI development
+    #[arg(long, env = "RBC_UI_DIR")]
+    ui_dir: Option<PathBuf>,
+
+    /// API keys accepted by the conversion endpoints, comma separated.
+    /// Authentication is disabled if no keys are given.
+    #[arg(long, env = "RBC_API_KEYS", value_delimiter = ',')]
+    api_keys: Vec<String>,
+
+    /// File of API keys, one per line
+    #[arg(long, env = "RBC_API_KEYS_FILE")]
+    api_keys_file: Option<PathBuf>,
+
+    /// TOML file of client profiles, each applying its client number and
+    /// other settings to the conversions of one API key. Profile keys are
+    /// accepted along with any other API keys.
+    #[arg(long, env = "RBC_PROFILES")]
+    profiles: Option<PathBuf>,
+
+    /// Conversion requests allowed per client each minute, 0 disables rate
+    /// limiting
+    #[arg(long, env = "RBC_RATE_LIMIT", default_value_t = DEFAULT_REQUESTS_PER_MINUTE)]
+    rate_limit: u32,
+
+    /// Requests a client may make in quick succession before being limited
+    #[arg(long, env = "RBC_RATE_LIMIT_BURST", default_value_t = DEFAULT_BURST)]
+    rate_limit_burst: u32,
+
+    /// Hosts `/convert/url` may fetch files from, comma separated. A host
+    /// may be given as `*.example.com` to allow any host within the domain.
+    /// Converting by URL is disabled if no hosts are given.
+    #[arg(long, env = "RBC_FETCH_ALLOWED_HOSTS", value_delimiter = ',')]
+    fetch_allowed_hosts: Vec<String>,
+
+    /// Seconds given to fetching a file for `/convert/url`
+    #[arg(long, env = "RBC_FETCH_TIMEOUT", default_value_t = DEFAULT_FETCH_TIMEOUT.as_secs())]
+    fetch_timeout: u64,
+
+    /// Redirects followed when fetching a file for `/convert/url`
+    #[arg(long, env = "RBC_FETCH_MAX_REDIRECTS", default_value_t = DEFAULT_MAX_REDIRECTS)]
+    fetch_max_redirects: usize,
+
+    /// Format of the request log written to standard output
+    #[arg(long, env = "RBC_LOG_FORMAT", value_enum, default_value_t = LogFormatArg::Pretty)]
+    log_format: LogFormatArg,
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+
+    server::logging::init(args.log_format.into());
+
+    let profiles = match &args.profiles {
+        Some(path) => Profiles::load(path).map_err(|e| {
+            std::io::Error::other(format!("Could not read profiles {}: {}", path.display(), e))
+        })?,
+        None => Profiles::default(),
+    };
+
+    let mut keys = args.api_keys;
+    keys.extend(profiles.keys());
+    let api_keys = ApiKeys::load(keys, args.api_keys_file.as_deref())?;
+
+    if !api_keys.is_enabled() {
+        eprintln!("warning: no API keys configured, the conversion endpoints are unauthenticated");
+    }
+
+    let audit = match &args.audit_db {
+        Some(path) => Some(AuditLog::open(path).map_err(|e| {
+            std::io::Error::other(format!(
+                "Could not open audit log {}: {}",
+                path.display(),
+                e
+            ))
+        })?),
+        None => None,
+    };
+
+    let config = ServerConfig {
+        limits: UploadLimits::new(
+            args.max_upload_size,
+            args.max_field_size.unwrap_or(args.max_upload_size),
+        ),
+        zip_limits: ZipLimits {
+            max_entries: args.max_zip_entries,
+            max_entry_size: args.max_zip_entry_size,
+            max_total_size: args.max_zip_size,
+        },
+        temp_dir: args.temp_dir,
+        cors: CorsConfig {
+            allowed_origins: args.cors_origins,
+            allow_credentials: args.cors_allow_credentials,
+        },
+        ui_dir: args.ui_dir,
+        api_keys,
+        rate_limiter: RateLimiter::new(args.rate_limit, args.rate_limit_burst),
+        audit,
+        conversions: ConversionPool::new(
+            args.conversion_workers,
+            args.conversion_queue_size,
+            Duration::from_secs(args.conversion_timeout),
+        ),
+        jobs: JobQueue::new(
+            args.job_workers,
+            args.job_queue_size,
+            Duration::from_secs(args.job_ttl),
+        ),
+        downloads: args
+            .download_links
+            .then(|| DownloadStore::new(Duration::from_secs(args.download_ttl))),
+        fetcher: UrlFetcher::new(
+            AllowedHosts::new(args.fetch_allowed_hosts),
+            Duration::from_secs(args.fetch_timeout),
+            args.fetch_max_redirects,
+        ),
+        profiles,
+        #[cfg(feature = "web")]
+        metrics: Default::default(),
+    };
+
+    let connections = Connections::default();
+    let tracked = connections.clone();
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(Compress::default())
+            .wrap(RequestLog)
+            .wrap(config.cors.middleware())
+            .configure(server::configure(config.clone()))
+    })
+    .on_connect(move |_, ext| tracked.track(ext))
+    .disable_signals()
+    .bind(&args.bind)?
+    .run();
 
-    let server = HttpServer::new(|| App::new().service(index).service(convert))
-        .bind(("0.0.0.0", 8080))?
-        .run().await;
+    actix_web::rt::spawn(stop_on_signal(
+        server.handle(),
+        connections,
+        Duration::from_secs(args.shutdown_timeout),
+    ));
 
-    return Ok(());
+    server.await
 }
@@ -1 +1,9 @@
+pub mod control;
+pub mod coverage;
 pub mod csv;
+pub mod naming;
+pub mod options;
+pub mod parallel;
+pub mod schema;
+pub mod summary;
+pub mod template;
@@ -0,0 +1,58 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Files converted at once when not told otherwise: one for each processor
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// `f` of each of `items` along with its index, run on up to `jobs` threads
+/// at once. Items are taken in order as threads become free, and the
+/// results are returned in the order of `items` whichever finishes first.
+/// With one job, or one item, they are run one after the other on the
+/// calling thread.
+pub fn map_in_parallel<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(usize, &T) -> R + Sync,
+{
+    let threads = jobs.clamp(1, items.len().max(1));
+
+    if threads == 1 {
+        return items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| f(i, item))
+            .collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut finished: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut finished = Vec::new();
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        match items.get(i) {
+                            Some(item) => finished.push((i, f(i, item))),
+                            None => return finished,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            // A panic converting one item is passed on as it would be
+            // without threads
+            .flat_map(|w| w.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+
+    finished.sort_by_key(|(i, _)| *i);
+    finished.into_iter().map(|(_, result)| result).collect()
+}
@@ -0,0 +1,212 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::lib::report::ConversionReport;
+
+/// Start of the name of the summary files written for a batch
+pub const SUMMARY_PREFIX: &str = "conversion_summary_";
+
+/// One file of a batch, as the summary shows it
+#[derive(Clone, Debug, Serialize)]
+pub struct SummaryFile {
+    pub input: String,
+    /// `PDS` or `PAD`
+    pub record_type: String,
+    /// Where the converted file was written, or none if it was not
+    pub output: Option<String>,
+    pub report: Option<ConversionReport>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Payment counts and totals of every file a batch converted, in cents
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SummaryTotals {
+    pub credit_amount: u64,
+    pub credit_count: u64,
+    pub debit_amount: u64,
+    pub debit_count: u64,
+}
+
+/// Cover sheet of a batch of conversions: the files produced, their totals,
+/// what was wrong with them and who converted them when
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchSummary {
+    /// When the batch was converted, in local time
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub generated_at: NaiveDateTime,
+    /// The user who converted the batch
+    pub generated_by: String,
+    pub files: Vec<SummaryFile>,
+    pub totals: SummaryTotals,
+}
+
+fn serialize_timestamp<S: serde::Serializer>(
+    timestamp: &NaiveDateTime,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&timestamp.format("%Y-%m-%dT%H:%M:%S"))
+}
+
+fn dollars(cents: u64) -> String {
+    format!("{}.{:0>2}", cents / 100, cents % 100)
+}
+
+/// Name of the user running the conversion, as the platform knows them
+pub fn current_user() -> String {
+    ["USER", "USERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.trim().is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+impl BatchSummary {
+    /// Summary of `files`, totalling the reports of those converted
+    pub fn new(generated_at: NaiveDateTime, generated_by: &str, files: Vec<SummaryFile>) -> Self {
+        let mut totals = SummaryTotals::default();
+
+        for report in files.iter().filter_map(|f| f.report.as_ref()) {
+            totals.credit_amount += report.total_credit_amount;
+            totals.credit_count += report.total_credit_count;
+            totals.debit_amount += report.total_debit_amount;
+            totals.debit_count += report.total_debit_count;
+        }
+
+        Self {
+            generated_at,
+            generated_by: generated_by.to_string(),
+            files,
+            totals,
+        }
+    }
+
+    /// Name of the summary files, without their extension
+    pub fn file_stem(&self) -> String {
+        format!(
+            "{}{}",
+            SUMMARY_PREFIX,
+            self.generated_at.format("%Y%m%d_%H%M%S")
+        )
+    }
+
+    /// The summary as plain text, to be read and signed off by a person
+    pub fn render_text(&self) -> String {
+        let mut text = String::new();
+        let converted = self.files.iter().filter(|f| f.output.is_some()).count();
+
+        // Writing to a String cannot fail
+        let _ = writeln!(text, "Conversion summary");
+        let _ = writeln!(
+            text,
+            "Generated: {}",
+            self.generated_at.format("%Y-%m-%d %H:%M:%S")
+        );
+        let _ = writeln!(text, "Generated by: {}", self.generated_by);
+        let _ = writeln!(text);
+        let _ = writeln!(
+            text,
+            "Files: {} converted, {} failed",
+            converted,
+            self.files.len() - converted
+        );
+        let _ = writeln!(
+            text,
+            "Credits: {} items, {}",
+            self.totals.credit_count,
+            dollars(self.totals.credit_amount)
+        );
+        let _ = writeln!(
+            text,
+            "Debits: {} items, {}",
+            self.totals.debit_count,
+            dollars(self.totals.debit_amount)
+        );
+
+        for (i, file) in self.files.iter().enumerate() {
+            let _ = writeln!(text);
+            let _ = writeln!(text, "{}. {} ({})", i + 1, file.input, file.record_type);
+
+            match &file.output {
+                Some(output) => {
+                    let _ = writeln!(text, "   Output: {}", output);
+                }
+                None => {
+                    let _ = writeln!(text, "   Not converted");
+                }
+            }
+
+            if let Some(report) = &file.report {
+                let _ = writeln!(text, "   Client number: {}", report.client_number);
+                let _ = writeln!(
+                    text,
+                    "   File creation: {} on {}",
+                    report.file_creation_number, report.file_creation_date
+                );
+                let _ = writeln!(
+                    text,
+                    "   Credits: {} items, {}",
+                    report.total_credit_count,
+                    dollars(report.total_credit_amount)
+                );
+                let _ = writeln!(
+                    text,
+                    "   Debits: {} items, {}",
+                    report.total_debit_count,
+                    dollars(report.total_debit_amount)
+                );
+                let _ = writeln!(text, "   SHA-256: {}", report.output_sha256);
+            }
+
+            for error in &file.errors {
+                let _ = writeln!(text, "   Error: {}", error);
+            }
+
+            for warning in &file.warnings {
+                let _ = writeln!(text, "   Warning: {}", warning);
+            }
+        }
+
+        text
+    }
+
+    /// Writes the summary into `directory` as text and as JSON, both named
+    /// after when it was generated, followed by `_2`, `_3` and so on when a
+    /// summary of another batch generated the same second is there already.
+    /// Returns the paths of the text and the JSON file.
+    pub fn write(&self, directory: &Path) -> Result<(PathBuf, PathBuf), String> {
+        let unwritable = |path: &Path, e: std::io::Error| {
+            format!("Cannot write summary {}: {}", path.display(), e)
+        };
+
+        // Creating the text file claims its name, even against a batch
+        // finishing at the same time
+        let stem = self.file_stem();
+        let mut attempt = 1;
+        let text = loop {
+            let text = match attempt {
+                1 => directory.join(format!("{}.txt", stem)),
+                n => directory.join(format!("{}_{}.txt", stem, n)),
+            };
+
+            match OpenOptions::new().write(true).create_new(true).open(&text) {
+                Ok(_) => break text,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => attempt += 1,
+                Err(e) => return Err(unwritable(&text, e)),
+            }
+        };
+        let json = text.with_extension("json");
+
+        std::fs::write(&text, self.render_text()).map_err(|e| unwritable(&text, e))?;
+        std::fs::write(
+            &json,
+            serde_json::to_string_pretty(self).expect("summary serializes"),
+        )
+        .map_err(|e| unwritable(&json, e))?;
+
+        Ok((text, json))
+    }
+}
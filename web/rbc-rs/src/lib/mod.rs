@@ -1,5 +1,13 @@
 pub mod error;
 pub mod header;
+pub mod holidays;
+pub mod layout;
+pub mod lint;
+pub mod parse;
 pub mod payment;
+pub mod report;
+pub mod tables;
+pub mod transits;
 pub mod types;
 pub mod utils;
+pub mod validate;
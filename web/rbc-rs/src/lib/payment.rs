@@ -1,6 +1,29 @@
 use super::error::ErrorLog;
-use super::types::RecordType;
-use super::utils::n_digits;
+use super::layout::{RecordWriter, DETAIL_FIELDS, SEGMENT_FIELDS};
+use super::lint::{MAX_SEGMENTS, RECORD_LENGTH};
+use super::types::{AccountJustify, RecordType};
+use super::utils::{is_all_ascii_digits, n_digits, strip_client_number_separators, truncate_field};
+
+// Widths of the payment segment fields that are filled from user input
+pub const TRANSACTION_CODE_LEN: usize = 3;
+pub const MAX_INSTITUTION_NUMBER_LEN: usize = 4;
+pub const MAX_BRANCH_NUMBER_LEN: usize = 5;
+pub const MAX_ACCOUNT_NUMBER_LEN: usize = 12;
+pub const MAX_SHORT_NAME_LEN: usize = 15;
+pub const MAX_NAME_LEN: usize = 30;
+pub const CLIENT_NUMBER_LEN: usize = 10;
+pub const MAX_CUSTOMER_NUMBER_LEN: usize = 19;
+pub const MAX_SUNDRY_LEN: usize = 15;
+/// Most cents the 10 digit amount field can hold
+pub const MAX_AMOUNT: u64 = 9_999_999_999;
+
+/// Transaction code given by default to the segments of a prenote file,
+/// which verify the accounts of the payments to come without moving any
+/// money. The RBC PDS specification gives no prenote code, so this is only
+/// a default: set the `prenote_transaction_code` option to the code RBC
+/// assigns the client.
+pub const PRENOTE_TRANSACTION_CODE: &str = "000";
+
 pub struct BasicPaymentSegment {
     pub transaction_code: String,
     pub amount: u64,
@@ -8,12 +31,15 @@ pub struct BasicPaymentSegment {
     pub financial_institution_number: String,
     pub financial_institution_branch_number: String,
     pub account_number: String,
+    pub account_justify: AccountJustify,
     pub client_short_name: String,
     pub customer_name: String,
     pub client_name: String,
     pub client_number: String,
     pub customer_number: String,
     pub client_sundry_information: String,
+    pub returns_institution: String,
+    pub returns_account: String,
     pub error_log: ErrorLog,
 }
 impl BasicPaymentSegment {
@@ -25,18 +51,21 @@ impl BasicPaymentSegment {
             financial_institution_number: String::new(),
             financial_institution_branch_number: String::new(),
             account_number: String::new(),
+            account_justify: AccountJustify::Left,
             client_short_name: String::new(),
             customer_name: String::new(),
             client_name: String::new(),
             client_number: String::new(),
             customer_number: String::new(),
             client_sundry_information: String::new(),
+            returns_institution: String::new(),
+            returns_account: String::new(),
             error_log: ErrorLog::new(),
         }
     }
 
     pub fn set_transaction_code(&mut self, code: String) -> &mut Self {
-        if code.len() != 3 {
+        if code.len() != TRANSACTION_CODE_LEN {
             self.error_log.write_error(
                 format!(
                     "Transaction code must be 3 digits, received {} instead",
@@ -64,19 +93,39 @@ impl BasicPaymentSegment {
             return self;
         }
 
+        if n_digits(day) > 3 {
+            self.error_log
+                .write_error("Payment Date Day number exceeds 3 digits");
+            return self;
+        }
+
         self.payment_date = (year % 100, day);
 
         self
     }
 
     pub fn set_financial_institution_number(&mut self, no: String) -> &mut Self {
+        // Zeros padding the number beyond its 4 digits, as in 00010, add
+        // nothing to it
+        let excess = no.len().saturating_sub(MAX_INSTITUTION_NUMBER_LEN);
+        let no = match no.split_at_checked(excess) {
+            Some((padding, digits)) if padding.bytes().all(|b| b == b'0') => digits.to_string(),
+            _ => no,
+        };
+
+        if !is_all_ascii_digits(&no) || no.len() > MAX_INSTITUTION_NUMBER_LEN {
+            self.error_log
+                .write_error("Institution number must be at most 4 digits");
+            return self;
+        }
+
         self.financial_institution_number = format!("{:0>4}", no);
 
         self
     }
 
     pub fn set_financial_institution_branch_number(&mut self, no: String) -> &mut Self {
-        if no.parse::<u64>().is_err() {
+        if !is_all_ascii_digits(&no) || no.len() > MAX_BRANCH_NUMBER_LEN {
             self.error_log.write_error("Branch number must be 5 digits");
             return self;
         }
@@ -87,15 +136,13 @@ impl BasicPaymentSegment {
     }
 
     pub fn set_account_number(&mut self, account_no: String) -> &mut Self {
-        for c in account_no.chars() {
-            if !c.is_ascii_digit() {
-                self.error_log
-                    .write_error("Account number must only include digits");
-                return self;
-            }
+        if !is_all_ascii_digits(&account_no) {
+            self.error_log
+                .write_error("Account number must only include digits");
+            return self;
         }
 
-        if account_no.len() > 12 {
+        if account_no.len() > MAX_ACCOUNT_NUMBER_LEN {
             self.error_log
                 .write_error("Account number cannot exceed 12 digits");
             return self;
@@ -106,8 +153,14 @@ impl BasicPaymentSegment {
         self
     }
 
+    pub fn set_account_justify(&mut self, justify: AccountJustify) -> &mut Self {
+        self.account_justify = justify;
+
+        self
+    }
+
     pub fn set_client_short_name(&mut self, short_name: String) -> &mut Self {
-        if short_name.len() > 15 {
+        if short_name.chars().count() > MAX_SHORT_NAME_LEN {
             self.error_log
                 .write_error("Client Short Name must not exceed 15 characters");
             return self;
@@ -119,7 +172,7 @@ impl BasicPaymentSegment {
     }
 
     pub fn set_customer_name(&mut self, customer_name: String) -> &mut Self {
-        if customer_name.len() > 30 {
+        if customer_name.chars().count() > MAX_NAME_LEN {
             self.error_log
                 .write_error("Customer Name must not exceed 30 characters");
             return self;
@@ -130,7 +183,7 @@ impl BasicPaymentSegment {
     }
 
     pub fn set_client_name(&mut self, client_name: String) -> &mut Self {
-        if client_name.len() > 30 {
+        if client_name.chars().count() > MAX_NAME_LEN {
             self.error_log
                 .write_error("Client Name must not exceed 30 characters");
             return self;
@@ -142,13 +195,15 @@ impl BasicPaymentSegment {
     }
 
     pub fn set_client_number(&mut self, client_number: String) -> &mut Self {
-        if client_number.len() != 10 {
+        let client_number = strip_client_number_separators(&client_number);
+
+        if client_number.len() != CLIENT_NUMBER_LEN {
             self.error_log
                 .write_error("Client number must be exactly 10 numeric digits long");
             return self;
         }
 
-        if client_number.parse::<u64>().is_err() {
+        if !is_all_ascii_digits(&client_number) {
             self.error_log
                 .write_error("Client number must not contain non-numeric digits");
             return self;
@@ -160,7 +215,7 @@ impl BasicPaymentSegment {
     }
 
     pub fn set_customer_number(&mut self, customer_number: String) -> &mut Self {
-        if customer_number.len() > 19 {
+        if customer_number.len() > MAX_CUSTOMER_NUMBER_LEN {
             self.error_log
                 .write_error("Customer number must not exceed 19 characters");
             return self;
@@ -171,7 +226,7 @@ impl BasicPaymentSegment {
     }
 
     pub fn set_customer_sundry_information(&mut self, info: String) -> &mut Self {
-        if self.client_sundry_information.len() > 15 {
+        if info.chars().count() > MAX_SUNDRY_LEN {
             self.error_log
                 .write_error("Client Sundry Information must not exceed 15 characters");
             return self;
@@ -182,71 +237,110 @@ impl BasicPaymentSegment {
         self
     }
 
-    pub fn build(&self) -> String {
-        let mut payload = String::new();
-
-        // Field 5
-        payload.push_str(&self.transaction_code);
-
-        // Field 6
-        payload.push_str(format!("{:0>8}{:0>2}", self.amount / 100, self.amount % 100).as_str());
+    /// Cuts the names and sundry information to the width of their fields,
+    /// returning a warning for each that was cut with the text dropped
+    pub fn truncate_names(&mut self) -> Vec<String> {
+        let fields = [
+            ("customer_name", &mut self.customer_name, MAX_NAME_LEN),
+            ("client_name", &mut self.client_name, MAX_NAME_LEN),
+            (
+                "short_name",
+                &mut self.client_short_name,
+                MAX_SHORT_NAME_LEN,
+            ),
+            (
+                "sundry",
+                &mut self.client_sundry_information,
+                MAX_SUNDRY_LEN,
+            ),
+        ];
+
+        let mut warnings = Vec::new();
+
+        for (field, value, max_len) in fields {
+            let (kept, warning) = truncate_field(field, value, max_len);
+            *value = kept;
+            warnings.extend(warning);
+        }
 
-        // Field 7
-        payload
-            .push_str(format!("0{:0>2}{:0>3}", self.payment_date.0, self.payment_date.1).as_str());
+        warnings
+    }
 
-        // Field 8
-        payload.push_str(
-            format!(
-                "{}{}",
-                self.financial_institution_number, self.financial_institution_branch_number
+    /// Checks the segment's fields against the rules their setters apply,
+    /// for segments whose fields have been changed directly
+    pub fn validate(&self) -> ErrorLog {
+        let mut check = BasicPaymentSegment::new();
+
+        check
+            .set_transaction_code(self.transaction_code.clone())
+            .set_payment_date(self.payment_date.0, self.payment_date.1)
+            .set_financial_institution_number(self.financial_institution_number.clone())
+            .set_financial_institution_branch_number(
+                self.financial_institution_branch_number.clone(),
             )
-            .as_str(),
-        );
-
-        // Field 9
-        payload.push_str(format!("{:<12}", self.account_number).as_str());
-
-        // Field 10
-        payload.push_str("0".repeat(22).as_str());
-
-        // Field 11
-        payload.push_str("0".repeat(3).as_str());
-
-        // Field 12
-        payload.push_str(format!("{:<15}", self.client_short_name).as_str());
-
-        // Field 13
-        payload.push_str(format!("{:<30}", self.customer_name).as_str());
-
-        // Field 14
-        payload.push_str(format!("{:<30}", self.client_name).as_str());
-
-        // Field 15
-        payload.push_str(format!("{:<10}", self.client_number).as_str());
-
-        // Field 16
-        payload.push_str(format!("{:<19}", self.customer_number).as_str());
-
-        // Field 17
-        payload.push_str("0".repeat(9).as_str());
-
-        // Field 18
-        payload.push_str(" ".repeat(12).as_str());
-
-        // Field 19
-        payload.push_str(format!("{:<15}", self.client_sundry_information).as_str());
+            .set_account_number(self.account_number.clone())
+            .set_client_short_name(self.client_short_name.clone())
+            .set_customer_name(self.customer_name.clone())
+            .set_client_name(self.client_name.clone())
+            .set_client_number(self.client_number.clone())
+            .set_customer_number(self.customer_number.clone())
+            .set_customer_sundry_information(self.client_sundry_information.clone());
+
+        check.error_log
+    }
 
-        // Field 20
-        payload.push_str(" ".repeat(22).as_str());
+    pub fn build(&self) -> String {
+        let mut record = RecordWriter::new(SEGMENT_FIELDS);
+        self.write(&mut record);
 
-        // Field 21
-        payload.push_str(" ".repeat(2).as_str());
+        record.finish()
+    }
 
-        // Field 22
-        payload.push_str(" ".repeat(11).as_str());
+    /// Writes the fields of the segment to `record`, laid out as
+    /// [`SEGMENT_FIELDS`]
+    pub(super) fn write(&self, record: &mut RecordWriter) {
+        record
+            // Field 5
+            .text("transaction_code", &self.transaction_code)
+            // Field 6, in cents
+            .number("amount", self.amount)
+            // Field 7, 0YYDDD. The only date a segment carries: the funds
+            // settle on it, there being no settlement date of its own
+            .number(
+                "payment_date",
+                self.payment_date.0 * 1000 + self.payment_date.1,
+            )
+            // Field 8
+            .text("institution_number", &self.financial_institution_number)
+            .text("branch_number", &self.financial_institution_branch_number);
 
-        return payload;
+        // Field 9
+        let record = match self.account_justify {
+            AccountJustify::Left => record.text("account_number", &self.account_number),
+            AccountJustify::RightZeroPad => record.number("account_number", &self.account_number),
+        };
+
+        record
+            // Fields 10 and 11, reserved: zero fill
+            .fill("filler", '0')
+            .fill("filler", '0')
+            // Fields 12 to 16
+            .text("client_short_name", &self.client_short_name)
+            .text("customer_name", &self.customer_name)
+            .text("client_name", &self.client_name)
+            .text("client_number", &self.client_number)
+            .text("customer_number", &self.customer_number)
+            // Field 17, reserved: zero fill unless a returns account is set
+            .number("returns_institution", &self.returns_institution)
+            // Field 18, reserved: blank unless a returns account is set
+            .text("returns_account", &self.returns_account)
+            // Field 19
+            .text("client_sundry_information", &self.client_sundry_information)
+            // Fields 20 to 22, reserved: blank. Field 21 is the settlement
+            // code, which only the bank fills in, on returned items
+            .fill("filler", ' ')
+            .fill("filler", ' ')
+            .fill("filler", ' ');
     }
 }
 
@@ -272,7 +366,9 @@ impl BasicPayment {
     }
 
     pub fn set_client_number(&mut self, client_number: String) -> &mut Self {
-        if client_number.parse::<u64>().is_err() {
+        let client_number = strip_client_number_separators(&client_number);
+
+        if client_number.len() != CLIENT_NUMBER_LEN || !is_all_ascii_digits(&client_number) {
             self.error_log
                 .write_error("Client number must be exactly 10 numeric digits long");
             return self;
@@ -284,7 +380,12 @@ impl BasicPayment {
     }
 
     pub fn set_file_creation_number(&mut self, no: u32) -> &mut Self {
-        if n_digits(no) > 4 {
+        if no == 0 {
+            self.error_log.write_error("File creation number is 0");
+            return self;
+        }
+
+        if n_digits(no.into()) > 4 {
             self.error_log
                 .write_error("File creation number exceeds 4 digits");
             return self;
@@ -296,21 +397,31 @@ impl BasicPayment {
     }
 
     pub fn build(&self) -> String {
-        let mut payload = String::new();
+        self.write(String::with_capacity(RECORD_LENGTH))
+    }
 
-        payload.push(match self.record_type {
+    /// Writes the record after what `text` holds already, so that the
+    /// records of a file can be written into one buffer
+    pub(super) fn write(&self, text: String) -> String {
+        let record_type = match self.record_type {
             RecordType::Credit => 'C',
             RecordType::Debit => 'D',
             _ => panic!("Expected record of type CREDIT or DEBIT"),
-        });
-        payload.push_str(format!("{:0>9}", self.record_count).as_str());
-        payload.push_str(&self.client_number);
-        payload.push_str(format!("{:<4}", self.file_creation_number).as_str());
-
-        for seg in &self.segments {
-            payload.push_str(&seg.build())
+        };
+
+        let mut record = RecordWriter::append_to(DETAIL_FIELDS, text);
+        record
+            .text("record_type", record_type)
+            .number("record_number", self.record_count)
+            .text("client_number", &self.client_number)
+            .text("file_creation_number", self.file_creation_number);
+
+        // Segments beyond those a record holds have no field to be written
+        // to, and are reported when the payment is added to its record
+        for seg in self.segments.iter().take(MAX_SEGMENTS) {
+            record.nested(SEGMENT_FIELDS, |segment| seg.write(segment));
         }
 
-        return payload;
+        record.finish()
     }
 }
@@ -0,0 +1,50 @@
+use super::header::CPA005Record;
+use chrono::NaiveDate;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+/// Summary of a converted file, mirroring the totals written to its trailer
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ConversionReport {
+    pub client_number: String,
+    pub file_creation_number: u32,
+    /// YYYY-MM-DD, empty if the record's creation date is invalid
+    pub file_creation_date: String,
+    pub total_debit_amount: u64,
+    pub total_debit_count: u64,
+    pub total_credit_amount: u64,
+    pub total_credit_count: u64,
+    /// SHA-256 of the converted file exactly as written, record terminators
+    /// included, in lowercase hex
+    pub output_sha256: String,
+}
+
+/// SHA-256 of `text` in lowercase hex
+pub fn sha256_hex(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl ConversionReport {
+    /// Summary of `record`, which was written out as `output`
+    pub fn new(record: &CPA005Record, output: &str) -> Self {
+        Self {
+            client_number: record.client_number.clone(),
+            file_creation_number: record.file_creation_number,
+            file_creation_date: NaiveDate::from_yo_opt(
+                record.file_creation_date.0 as i32,
+                record.file_creation_date.1,
+            )
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default(),
+            total_debit_amount: record.total_debit_amount,
+            total_debit_count: record.total_debit_count,
+            total_credit_amount: record.total_credit_amount,
+            total_credit_count: record.total_credit_count,
+            output_sha256: sha256_hex(output),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use chrono::{Datelike, NaiveDate};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::holidays::statutory_holidays;
+
+/// Years whose holidays are kept in `HOLIDAYS`. Dates outside them, which
+/// no real payment falls on, have their holidays worked out when asked.
+pub const HOLIDAY_YEARS: std::ops::RangeInclusive<i32> = 2000..=2099;
+
+/// Times `HOLIDAYS` has been worked out, which is never more than once
+pub static HOLIDAY_TABLE_BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Statutory holidays of `HOLIDAY_YEARS` by the day they are observed,
+/// worked out once for every conversion rather than for every payment
+pub static HOLIDAYS: Lazy<HashMap<NaiveDate, &'static str>> = Lazy::new(|| {
+    HOLIDAY_TABLE_BUILDS.fetch_add(1, Ordering::Relaxed);
+
+    HOLIDAY_YEARS
+        .flat_map(statutory_holidays)
+        .collect::<HashMap<_, _>>()
+});
+
+/// The statutory holiday observed on `date`, if any
+pub fn holiday(date: NaiveDate) -> Option<&'static str> {
+    if HOLIDAY_YEARS.contains(&date.year()) {
+        return HOLIDAYS.get(&date).copied();
+    }
+
+    statutory_holidays(date.year())
+        .into_iter()
+        .find(|(d, _)| *d == date)
+        .map(|(_, name)| name)
+}
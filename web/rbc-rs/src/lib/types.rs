@@ -1,12 +1,14 @@
 use std::fmt::Display;
+use std::str::FromStr;
 
-#[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug)]
 pub enum CurrencyType {
     CAD,
     USD,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum ProcessingCentre {
     Halifax,
     Montreal,
@@ -17,7 +19,27 @@ pub enum ProcessingCentre {
     Vancouver,
 }
 
-#[derive(Clone, Copy)]
+/// Justification of the account number within field 9 of a payment segment
+#[derive(Clone, Copy, Debug, Default)]
+pub enum AccountJustify {
+    #[default]
+    Left,
+    RightZeroPad,
+}
+
+/// Order detail records are written in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// The order the payments were added in
+    #[default]
+    SourceOrder,
+    /// By account number, lowest first
+    ByAccount,
+    /// By amount, largest first
+    ByAmountDesc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RecordType {
     Header,
     Credit,
@@ -28,10 +50,46 @@ pub enum RecordType {
 impl Display for RecordType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RecordType::Header => write!(f, "{}", 'A'),
-            RecordType::Credit => write!(f, "{}", 'C'),
-            RecordType::Debit => write!(f, "{}", 'D'),
-            RecordType::Trailer => write!(f, "{}", 'Z'),
+            RecordType::Header => write!(f, "A"),
+            RecordType::Credit => write!(f, "C"),
+            RecordType::Debit => write!(f, "D"),
+            RecordType::Trailer => write!(f, "Z"),
+        }
+    }
+}
+
+/// Kind of file a CSV file is converted into, as named by RBC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaymentType {
+    /// Payment Distribution Service, a file of credits
+    Pds,
+    /// Pre-Authorized Debits, a file of debits
+    Pad,
+}
+
+impl PaymentType {
+    pub const NAMES: [&'static str; 2] = ["PDS", "PAD"];
+
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            PaymentType::Pds => RecordType::Credit,
+            PaymentType::Pad => RecordType::Debit,
+        }
+    }
+}
+
+impl FromStr for PaymentType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PDS" => Ok(PaymentType::Pds),
+            "PAD" => Ok(PaymentType::Pad),
+            _ => Err(format!(
+                "must be {}, not \"{}\"",
+                PaymentType::NAMES.join(" or "),
+                s
+            )),
         }
     }
 }
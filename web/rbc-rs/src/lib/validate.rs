@@ -0,0 +1,274 @@
+use chrono::NaiveDate;
+
+use super::error::ErrorLog;
+use super::header::CPA005Record;
+use super::holidays::non_business_day;
+use super::layout::{FieldLayout, HEADER_FIELDS, TRAILER_FIELDS};
+use super::payment::BasicPaymentSegment;
+use super::transits::TransitList;
+use super::types::RecordType;
+
+/// Checks applied to a fully assembled record before it is built
+#[derive(Clone, Debug, Default)]
+pub struct ValidationOptions {
+    /// Report validation failures as warnings instead of errors
+    pub lenient: bool,
+    /// Type of payment the file was declared as, checked against the
+    /// record's totals when set
+    pub record_type: Option<RecordType>,
+    /// Warn of payments dated on a weekend or statutory holiday, which the
+    /// bank processes on the next business day instead
+    pub business_days: bool,
+    /// Warn of payments dated other than the first, for files whose
+    /// payments are all meant to be made on one date
+    pub uniform_payment_date: bool,
+    /// Institution and branch pairs the transit of every payment must be one
+    /// of, when supplied
+    pub transits: Option<TransitList>,
+}
+
+impl ValidationOptions {
+    fn report(&self, log: &mut ErrorLog, message: &str) {
+        if self.lenient {
+            log.write_warning(message);
+        } else {
+            log.write_error(message);
+        }
+    }
+}
+
+/// Expands a two digit year to the century closest to `reference_year`, so
+/// that e.g. `00` is read as 2100 when the file is created late in 2099.
+/// Years are never taken back before the year 0, as for a record whose
+/// creation date was never set.
+fn expand_year(short_year: u64, reference_year: u64) -> u64 {
+    let year = reference_year - reference_year % 100 + short_year;
+
+    if year + 50 < reference_year {
+        year + 100
+    } else if year > reference_year + 50 && year >= 100 {
+        year - 100
+    } else {
+        year
+    }
+}
+
+impl CPA005Record {
+    pub fn validate(&self, options: &ValidationOptions) -> ErrorLog {
+        let mut log = ErrorLog::new();
+
+        let creation_year = self.file_creation_date.0 as u64;
+        let creation_day = self.file_creation_date.1 as u64;
+
+        for payment in &self.basic_payment {
+            for seg in &payment.segments {
+                let payment_year = expand_year(seg.payment_date.0, creation_year);
+
+                if (payment_year, seg.payment_date.1) < (creation_year, creation_day) {
+                    options.report(
+                        &mut log,
+                        format!(
+                            "Payment date {:0>4}/{:0>3} for customer {} is before the file creation date {:0>4}/{:0>3}",
+                            payment_year,
+                            seg.payment_date.1,
+                            seg.customer_number,
+                            creation_year,
+                            creation_day
+                        )
+                        .as_str(),
+                    );
+                }
+
+                // Numbers that could not be set were reported with the segment
+                let unset = seg.financial_institution_number.is_empty()
+                    || seg.financial_institution_branch_number.is_empty();
+
+                if let Some(transits) = options.transits.as_ref().filter(|_| !unset) {
+                    if !transits.contains(
+                        &seg.financial_institution_number,
+                        &seg.financial_institution_branch_number,
+                    ) {
+                        options.report(
+                            &mut log,
+                            format!(
+                                "Branch {} of institution {} for customer {} is not in the list of valid transits",
+                                seg.financial_institution_branch_number,
+                                seg.financial_institution_number,
+                                seg.customer_number
+                            )
+                            .as_str(),
+                        );
+                    }
+                }
+
+                if !options.business_days {
+                    continue;
+                }
+
+                // Advisory only, however strict the other checks
+                let date = NaiveDate::from_yo_opt(payment_year as i32, seg.payment_date.1 as u32);
+                if let Some((date, reason)) = date.and_then(|d| Some((d, non_business_day(d)?))) {
+                    log.write_warning(
+                        format!(
+                            "Payment date {} for customer {} is {}, not a business day, and will be processed on the next business day",
+                            date.format("%Y-%m-%d"),
+                            seg.customer_number,
+                            reason
+                        )
+                        .as_str(),
+                    );
+                }
+            }
+        }
+
+        if options.uniform_payment_date {
+            self.validate_uniform_payment_date(&mut log);
+        }
+
+        self.validate_opposing_totals(options, &mut log);
+        self.validate_structure(&mut log);
+        self.validate_header_trailer(&mut log);
+
+        log
+    }
+
+    /// The header is built first and the trailer last, so whatever lies
+    /// between them must be detail records numbered on from the header. A
+    /// record assembled other than through `add_basic_payment`, such as one
+    /// rebuilt from a parsed file, may break this however lenient the checks.
+    fn validate_structure(&self, log: &mut ErrorLog) {
+        for (i, payment) in self.basic_payment.iter().enumerate() {
+            let position = i + 1;
+
+            if !matches!(payment.record_type, RecordType::Credit | RecordType::Debit) {
+                log.write_error(
+                    format!(
+                        "Detail record {} has record type {}, only the header may be A and the trailer Z",
+                        position, payment.record_type
+                    )
+                    .as_str(),
+                );
+            }
+
+            // The header is record 1
+            let expected = i as u32 + 2;
+            if payment.record_count != expected {
+                log.write_error(
+                    format!(
+                        "Detail record {} is numbered {}, expected {}",
+                        position, payment.record_count, expected
+                    )
+                    .as_str(),
+                );
+            }
+        }
+
+        let trailer = self.basic_payment.len() as u32 + 2;
+        if self.current_record_no + 1 != trailer {
+            log.write_error(
+                format!(
+                    "Trailer record would be numbered {}, expected {} after {} detail records",
+                    self.current_record_no + 1,
+                    trailer,
+                    self.basic_payment.len()
+                )
+                .as_str(),
+            );
+        }
+    }
+
+    /// The header and trailer are built apart, yet must name the same client
+    /// and file, which the bank checks them for
+    fn validate_header_trailer(&self, log: &mut ErrorLog) {
+        let header = self.build_header_record();
+        let trailer = self.build_trailer_record();
+
+        let value = |record: &str, fields: &[FieldLayout], name: &str| {
+            fields
+                .iter()
+                .find(|(n, _, _)| *n == name)
+                .and_then(|(_, start, length)| record.get(*start..start + length))
+                .map(|v| v.trim_end().to_string())
+        };
+
+        for name in ["client_number", "file_creation_number"] {
+            let expected = value(&header, HEADER_FIELDS, name);
+            let found = value(&trailer, TRAILER_FIELDS, name);
+
+            if expected != found {
+                log.write_error(
+                    format!(
+                        "Trailer {} {} does not match {} of the header",
+                        name.replace('_', " "),
+                        found.unwrap_or_default(),
+                        expected.unwrap_or_default()
+                    )
+                    .as_str(),
+                );
+            }
+        }
+    }
+
+    /// Only warns, as a file may well be meant to pay on several dates
+    fn validate_uniform_payment_date(&self, log: &mut ErrorLog) {
+        let mut segments = self.basic_payment.iter().flat_map(|p| &p.segments);
+
+        let first = match segments.next() {
+            Some(seg) => seg,
+            None => return,
+        };
+
+        let year = |seg: &BasicPaymentSegment| {
+            expand_year(seg.payment_date.0, self.file_creation_date.0 as u64)
+        };
+
+        for seg in segments.filter(|s| s.payment_date != first.payment_date) {
+            log.write_warning(
+                format!(
+                    "Payment date {:0>4}/{:0>3} for customer {} differs from {:0>4}/{:0>3} for customer {}, expected every payment on one date",
+                    year(seg),
+                    seg.payment_date.1,
+                    seg.customer_number,
+                    year(first),
+                    first.payment_date.1,
+                    first.customer_number
+                )
+                .as_str(),
+            );
+        }
+    }
+
+    /// A file of one payment type must not carry totals for the other
+    fn validate_opposing_totals(&self, options: &ValidationOptions, log: &mut ErrorLog) {
+        let (kind, opposing, amount, count) = match options.record_type {
+            Some(RecordType::Credit) => (
+                "credit",
+                "debit",
+                self.total_debit_amount,
+                self.total_debit_count,
+            ),
+            Some(RecordType::Debit) => (
+                "debit",
+                "credit",
+                self.total_credit_amount,
+                self.total_credit_count,
+            ),
+            _ => return,
+        };
+
+        if amount != 0 || count != 0 {
+            options.report(
+                log,
+                format!(
+                    "File declared as {} only has {} {} payments totalling {}.{:0>2}",
+                    kind,
+                    count,
+                    opposing,
+                    amount / 100,
+                    amount % 100
+                )
+                .as_str(),
+            );
+        }
+    }
+}
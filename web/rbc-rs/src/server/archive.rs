@@ -0,0 +1,352 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use tempfile::NamedTempFile;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::audit::{actor, hex, AuditLog};
+use super::auth::RequireApiKey;
+use super::convert::UploadForm;
+use super::convert::{internal_error, receive_binary_upload, record_conversion, Upload};
+use super::filename::attachment;
+use super::jobs::read_chunks;
+use super::limits::{PayloadTooLarge, ZipLimits};
+use super::logging::ConversionOutcome;
+use super::params::{ConvertParams, ConvertSettings};
+use super::pool::Deadline;
+use super::problem::{ApiError, Problem, PROBLEM_JSON};
+use super::ratelimit::RateLimit;
+use super::ServerConfig;
+use crate::csvconv::csv::{check_header_overrides, convert_reader_with_report};
+use crate::csvconv::naming::sanitize_stem;
+use crate::lib::error::ErrorLog;
+use crate::lib::report::ConversionReport;
+
+/// Name of the summary of every conversion within the returned archive
+const REPORT_NAME: &str = "report.json";
+
+/// Result of converting a single file of the archive, as the CLI reports
+/// each of its input files
+#[derive(Serialize)]
+struct EntryResult {
+    input: String,
+    output: Option<String>,
+    ok: bool,
+    summary: Option<ConversionReport>,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// A CSV file read out of the uploaded archive
+struct Entry {
+    name: String,
+    contents: Vec<u8>,
+}
+
+fn invalid_archive(e: zip::result::ZipError) -> ApiError {
+    ApiError::BadRequest(format!("Upload is not a valid zip archive: {}", e))
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+/// Reads every `.csv` file out of `archive`, refusing archives that would
+/// expand beyond `limits` or that name files outside of themselves. Other
+/// files are ignored. Reading stops once `deadline` has passed.
+fn read_entries<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    limits: &ZipLimits,
+    deadline: Deadline,
+) -> Result<Vec<Entry>, ApiError> {
+    let mut entries = Vec::new();
+    let mut total_size = 0usize;
+
+    for i in 0..archive.len() {
+        deadline.check()?;
+
+        let mut file = archive.by_index(i).map_err(invalid_archive)?;
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let path = match file.enclosed_name() {
+            Some(path) => path,
+            None => {
+                return Err(ApiError::BadRequest(format!(
+                    "Archive entry \"{}\" has a path outside of the archive",
+                    file.name()
+                )))
+            }
+        };
+
+        if !is_csv(&path) {
+            continue;
+        }
+
+        if entries.len() == limits.max_entries {
+            return Err(ApiError::BadRequest(format!(
+                "Archive has more than {} CSV files",
+                limits.max_entries
+            )));
+        }
+
+        let name = path.to_string_lossy().replace('\\', "/");
+        let too_large = |size: usize| {
+            PayloadTooLarge::of(&format!("File {}", name), limits.max_entry_size, size)
+        };
+
+        // The sizes an archive declares are only trusted to refuse it early,
+        // reading stops at the limit whatever they say
+        if file.size() > limits.max_entry_size as u64 {
+            return Err(too_large(file.size() as usize).into());
+        }
+
+        let mut contents = Vec::new();
+        (&mut file)
+            .take(limits.max_entry_size as u64 + 1)
+            .read_to_end(&mut contents)
+            .map_err(|e| ApiError::BadRequest(format!("Could not read {}: {}", name, e)))?;
+
+        if contents.len() > limits.max_entry_size {
+            return Err(too_large(contents.len()).into());
+        }
+
+        total_size += contents.len();
+        if total_size > limits.max_total_size {
+            return Err(PayloadTooLarge::of(
+                "Decompressed archive",
+                limits.max_total_size,
+                total_size,
+            )
+            .into());
+        }
+
+        entries.push(Entry { name, contents });
+    }
+
+    if entries.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Archive has no CSV files to convert".to_string(),
+        ));
+    }
+
+    Ok(entries)
+}
+
+/// Converts every CSV file in the uploaded archive, writing the converted
+/// files and `report.json` to an archive in `output`. Files that cannot be
+/// converted are listed in the report, unless `strict` is set in which case
+/// the whole archive is rejected. Gives up between files once `deadline`
+/// has passed.
+fn convert_archive(
+    upload: Upload,
+    settings: &ConvertSettings,
+    limits: &ZipLimits,
+    deadline: Deadline,
+    audit: Option<&AuditLog>,
+    actor: String,
+    output: &mut NamedTempFile,
+) -> Result<ConversionOutcome, ApiError> {
+    let mut file = upload.file;
+    file.rewind().map_err(internal_error)?;
+
+    let mut archive = ZipArchive::new(file.as_file()).map_err(invalid_archive)?;
+    let entries = read_entries(&mut archive, limits, deadline)?;
+
+    let mut results = Vec::new();
+    let mut outputs = Vec::new();
+
+    for entry in entries {
+        deadline.check()?;
+
+        check_header_overrides(&entry.contents[..], &settings.options)
+            .map_err(|e| ApiError::Forbidden(format!("{}: {}", entry.name, e)))?;
+
+        let conversion = convert_reader_with_report(
+            &entry.contents[..],
+            settings.record_type,
+            &settings.options,
+        );
+
+        if let Some(audit) = audit {
+            record_conversion(
+                audit,
+                actor.clone(),
+                Some(entry.name.clone()),
+                hex(&Sha256::digest(&entry.contents)),
+                &conversion,
+            )?;
+        }
+
+        let result = match conversion {
+            Ok(c) => {
+                let output_name = Path::new(&entry.name)
+                    .with_extension("txt")
+                    .to_string_lossy()
+                    .to_string();
+                outputs.push((output_name.clone(), c.output));
+
+                EntryResult {
+                    input: entry.name,
+                    output: Some(output_name),
+                    ok: true,
+                    summary: Some(c.report),
+                    errors: Vec::new(),
+                    warnings: c.log.get_warning_list(),
+                }
+            }
+            Err(log) => EntryResult {
+                input: entry.name,
+                output: None,
+                ok: false,
+                summary: None,
+                errors: log.get_error_list(),
+                warnings: log.get_warning_list(),
+            },
+        };
+
+        results.push(result);
+    }
+
+    if settings.strict && results.iter().any(|r| !r.ok) {
+        let mut log = ErrorLog::new();
+        for result in &results {
+            for error in &result.errors {
+                log.write_error(format!("{}: {}", result.input, error).as_str());
+            }
+        }
+
+        return Err(ApiError::Invalid(log));
+    }
+
+    write_archive(output.as_file_mut(), &outputs, &results)
+        .map_err(|e| ApiError::Internal(format!("Could not write converted archive: {}", e)))?;
+
+    Ok(ConversionOutcome {
+        rows: results
+            .iter()
+            .filter_map(|r| r.summary.as_ref())
+            .map(|s| s.total_credit_count + s.total_debit_count)
+            .sum(),
+        errors: results.iter().map(|r| r.errors.len()).sum(),
+        warnings: results.iter().map(|r| r.warnings.len()).sum(),
+    })
+}
+
+fn write_archive(
+    output: &mut File,
+    outputs: &[(String, String)],
+    results: &[EntryResult],
+) -> zip::result::ZipResult<()> {
+    let mut zip = ZipWriter::new(output);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (name, contents) in outputs {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(contents.as_bytes())?;
+    }
+
+    zip.start_file(REPORT_NAME, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(results).unwrap_or_default())?;
+
+    zip.finish()?;
+
+    Ok(())
+}
+
+/// Name of the returned archive, after the uploaded one
+fn archive_name(uploaded: Option<&str>) -> String {
+    match uploaded.and_then(sanitize_stem) {
+        Some(stem) => format!("{}-cpa005.zip", stem),
+        None => "cpa005.zip".to_string(),
+    }
+}
+
+/// Converts every CSV file in a zip archive
+///
+/// Responds with a zip of the converted files, along with a `report.json`
+/// listing how each input fared.
+#[utoipa::path(
+    tag = "conversion",
+    params(ConvertParams),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "A zip of the converted files and their report", body = Vec<u8>, content_type = "application/zip"),
+        (status = 400, description = "The settings or the archive are not valid, or with `strict=true` a file could not be converted", body = Problem, content_type = PROBLEM_JSON),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = PROBLEM_JSON),
+        (status = 403, description = "The request changes a value the profile of its API key fixes", body = Problem, content_type = PROBLEM_JSON),
+        (status = 413, description = "The archive is too large", body = Problem, content_type = PROBLEM_JSON),
+        (status = 429, description = "Too many requests", body = Problem, content_type = PROBLEM_JSON),
+        (status = 503, description = "Too many conversions are running", body = Problem, content_type = PROBLEM_JSON),
+        (status = 504, description = "The conversion took too long", body = Problem, content_type = PROBLEM_JSON),
+    ),
+    security((), ("api_key" = []), ("bearer" = [])),
+)]
+#[post("/convert/zip", wrap = "RateLimit", wrap = "RequireApiKey")]
+async fn convert_zip(
+    req: HttpRequest,
+    payload: web::Payload,
+    q: web::Query<ConvertParams>,
+    config: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let upload = match receive_binary_upload(&req, payload, &config).await {
+        Ok(upload) => upload,
+        Err(e) => return e.respond(req.headers()),
+    };
+
+    let settings = match q.resolve(&upload.fields, config.profiles.for_request(req.headers())) {
+        Ok(settings) => settings,
+        Err(e) => return e.respond(req.headers()),
+    };
+
+    let mut output = match config.output_file() {
+        Ok(f) => f,
+        Err(e) => return internal_error(e).respond(req.headers()),
+    };
+
+    let name = archive_name(upload.file_name.as_deref());
+    let limits = config.zip_limits;
+    let deadline = config.conversions.deadline();
+    let audit = config.audit.clone();
+    let actor = actor(&req, &config.api_keys);
+
+    let converted = config
+        .conversions
+        .run(move || {
+            let outcome = convert_archive(
+                upload,
+                &settings,
+                &limits,
+                deadline,
+                audit.as_ref(),
+                actor,
+                &mut output,
+            )?;
+
+            // The open file stays readable once the temporary file is removed
+            let file = output.reopen().map_err(|e| {
+                ApiError::Internal(format!("Could not read converted archive: {}", e))
+            })?;
+
+            Ok::<_, ApiError>((file, outcome))
+        })
+        .await;
+
+    match converted {
+        Ok(Ok((file, outcome))) => outcome.attach(
+            HttpResponse::Ok()
+                .content_type("application/zip")
+                .insert_header(attachment(&name))
+                .streaming(read_chunks(file)),
+        ),
+        Ok(Err(e)) => e.respond(req.headers()),
+        Err(e) => e.respond(req.headers()),
+    }
+}
@@ -0,0 +1,322 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeZone, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use utoipa::{IntoParams, ToSchema};
+
+use super::auth::{ApiKeys, RequireApiKey};
+use super::problem::{ApiError, Problem, PROBLEM_JSON};
+use super::ServerConfig;
+use crate::lib::report::ConversionReport;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 1000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS conversions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    input_filename TEXT,
+    input_sha256 TEXT NOT NULL,
+    client_number TEXT,
+    file_creation_number INTEGER,
+    file_creation_date TEXT,
+    credit_count INTEGER,
+    credit_total INTEGER,
+    debit_count INTEGER,
+    debit_total INTEGER,
+    outcome TEXT NOT NULL,
+    error_count INTEGER NOT NULL,
+    output_sha256 TEXT
+);
+CREATE INDEX IF NOT EXISTS conversions_timestamp ON conversions (timestamp);
+CREATE INDEX IF NOT EXISTS conversions_client ON conversions (client_number);
+";
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Identifies an API key in the audit log without storing the key itself
+pub fn key_id(key: &str) -> String {
+    hex(&Sha256::digest(key.as_bytes())[..6])
+}
+
+/// Who made a request: the API key it authenticated with, or the address it
+/// came from when authentication is disabled
+pub fn actor(req: &HttpRequest, api_keys: &ApiKeys) -> String {
+    actor_presenting(req, api_keys, ApiKeys::presented(req.headers()))
+}
+
+/// Like [`actor`], for a request that presented `key` other than in its
+/// headers, such as in a form field
+pub fn actor_presenting(req: &HttpRequest, api_keys: &ApiKeys, key: Option<&str>) -> String {
+    if api_keys.is_enabled() {
+        if let Some(key) = key {
+            return format!("key:{}", key_id(key));
+        }
+    }
+
+    match req.peer_addr() {
+        Some(addr) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/// Record of one conversion. Only totals are kept, never account numbers or
+/// other payment details.
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// RFC 3339, UTC
+    pub timestamp: String,
+    /// `key:<id>` when an API key was presented, otherwise `ip:<address>`
+    pub actor: String,
+    pub input_filename: Option<String>,
+    pub input_sha256: String,
+    pub client_number: Option<String>,
+    pub file_creation_number: Option<u32>,
+    pub file_creation_date: Option<String>,
+    pub credit_count: Option<u64>,
+    pub credit_total: Option<u64>,
+    pub debit_count: Option<u64>,
+    pub debit_total: Option<u64>,
+    /// `converted` or `rejected`
+    pub outcome: String,
+    pub error_count: usize,
+    /// SHA-256 of the converted file, as sent in `X-Content-SHA256`
+    pub output_sha256: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(actor: String, input_filename: Option<String>, input_sha256: String) -> Self {
+        Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            actor,
+            input_filename,
+            input_sha256,
+            ..Default::default()
+        }
+    }
+
+    pub fn converted(mut self, report: &ConversionReport) -> Self {
+        self.outcome = "converted".to_string();
+        self.client_number = Some(report.client_number.clone());
+        self.file_creation_number = Some(report.file_creation_number);
+        self.file_creation_date = Some(report.file_creation_date.clone());
+        self.credit_count = Some(report.total_credit_count);
+        self.credit_total = Some(report.total_credit_amount);
+        self.debit_count = Some(report.total_debit_count);
+        self.debit_total = Some(report.total_debit_amount);
+        self.output_sha256 = Some(report.output_sha256.clone());
+        self
+    }
+
+    pub fn rejected(mut self, error_count: usize) -> Self {
+        self.outcome = "rejected".to_string();
+        self.error_count = error_count;
+        self
+    }
+}
+
+/// Filters for listing audit entries, newest first
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// RFC 3339 timestamp or YYYY-MM-DD date
+    pub since: Option<String>,
+    pub client: Option<String>,
+    /// Return entries older than this id, for the next page
+    pub before: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    /// Pass as `before` to fetch the next page, absent on the last page
+    pub next: Option<i64>,
+}
+
+/// Conversion audit log kept in a SQLite database. Clones share one
+/// connection.
+#[derive(Clone)]
+pub struct AuditLog {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+
+        // Logs created before converted files were hashed lack the column
+        if conn
+            .prepare("SELECT output_sha256 FROM conversions LIMIT 0")
+            .is_err()
+        {
+            conn.execute("ALTER TABLE conversions ADD COLUMN output_sha256 TEXT", [])?;
+        }
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    pub fn record(&self, entry: &AuditEntry) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO conversions (timestamp, actor, input_filename, input_sha256,
+                client_number, file_creation_number, file_creation_date, credit_count,
+                credit_total, debit_count, debit_total, outcome, error_count, output_sha256)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                entry.timestamp,
+                entry.actor,
+                entry.input_filename,
+                entry.input_sha256,
+                entry.client_number,
+                entry.file_creation_number,
+                entry.file_creation_date,
+                entry.credit_count.map(|v| v as i64),
+                entry.credit_total.map(|v| v as i64),
+                entry.debit_count.map(|v| v as i64),
+                entry.debit_total.map(|v| v as i64),
+                entry.outcome,
+                entry.error_count as i64,
+                entry.output_sha256,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// `since` must already be normalised to the stored timestamp format
+    pub fn list(
+        &self,
+        since: Option<&str>,
+        client: Option<&str>,
+        before: Option<i64>,
+        limit: u32,
+    ) -> rusqlite::Result<AuditPage> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, actor, input_filename, input_sha256, client_number,
+                file_creation_number, file_creation_date, credit_count, credit_total,
+                debit_count, debit_total, outcome, error_count, output_sha256
+             FROM conversions
+             WHERE (?1 IS NULL OR timestamp >= ?1)
+               AND (?2 IS NULL OR client_number = ?2)
+               AND (?3 IS NULL OR id < ?3)
+             ORDER BY id DESC
+             LIMIT ?4",
+        )?;
+
+        // One extra row tells whether there is another page
+        let mut entries = stmt
+            .query_map(params![since, client, before, limit + 1], |row| {
+                Ok(AuditEntry {
+                    id: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    actor: row.get(2)?,
+                    input_filename: row.get(3)?,
+                    input_sha256: row.get(4)?,
+                    client_number: row.get(5)?,
+                    file_creation_number: row.get(6)?,
+                    file_creation_date: row.get(7)?,
+                    credit_count: row.get::<_, Option<i64>>(8)?.map(|v| v as u64),
+                    credit_total: row.get::<_, Option<i64>>(9)?.map(|v| v as u64),
+                    debit_count: row.get::<_, Option<i64>>(10)?.map(|v| v as u64),
+                    debit_total: row.get::<_, Option<i64>>(11)?.map(|v| v as u64),
+                    outcome: row.get(12)?,
+                    error_count: row.get::<_, i64>(13)? as usize,
+                    output_sha256: row.get(14)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let next = if entries.len() > limit as usize {
+            entries.truncate(limit as usize);
+            entries.last().map(|e| e.id)
+        } else {
+            None
+        };
+
+        Ok(AuditPage { entries, next })
+    }
+}
+
+/// Normalises `since` to the stored timestamp format so that it compares
+/// correctly as text
+fn parse_since(since: &str) -> Option<String> {
+    let since = since.trim();
+
+    let timestamp = match DateTime::parse_from_rfc3339(since) {
+        Ok(t) => t.with_timezone(&Utc),
+        Err(_) => Utc.from_utc_datetime(
+            &NaiveDate::parse_from_str(since, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)?,
+        ),
+    };
+
+    Some(timestamp.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+/// Conversions recorded in the audit log, newest first
+#[utoipa::path(
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "A page of audit entries", body = AuditPage),
+        (status = 400, description = "The filters are not valid", body = Problem, content_type = PROBLEM_JSON),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = PROBLEM_JSON),
+        (status = 404, description = "The audit log is not enabled", body = Problem, content_type = PROBLEM_JSON),
+    ),
+    security((), ("api_key" = []), ("bearer" = [])),
+)]
+#[get("/audit", wrap = "RequireApiKey")]
+async fn list(
+    req: HttpRequest,
+    q: web::Query<AuditQuery>,
+    config: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let audit = match &config.audit {
+        Some(audit) => audit.clone(),
+        None => {
+            return ApiError::NotFound("The audit log is not enabled".to_string())
+                .respond(req.headers())
+        }
+    };
+
+    let since = match q.since.as_deref().map(parse_since) {
+        Some(None) => {
+            return ApiError::BadRequest(
+                "since must be an RFC 3339 timestamp or a YYYY-MM-DD date".to_string(),
+            )
+            .respond(req.headers())
+        }
+        Some(Some(since)) => Some(since),
+        None => None,
+    };
+
+    let client = q.client.clone();
+    let before = q.before;
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let page =
+        web::block(move || audit.list(since.as_deref(), client.as_deref(), before, limit)).await;
+
+    match page {
+        Ok(Ok(page)) => HttpResponse::Ok().json(page),
+        Ok(Err(e)) => ApiError::Internal(format!("Could not read the audit log: {}", e))
+            .respond(req.headers()),
+        Err(e) => ApiError::Internal(e.to_string()).respond(req.headers()),
+    }
+}
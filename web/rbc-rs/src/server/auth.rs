@@ -0,0 +1,145 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderMap, AUTHORIZATION};
+use actix_web::{web, Error};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::path::Path;
+
+use super::problem::ApiError;
+use super::ServerConfig;
+
+/// Keys accepted by routes wrapped in [`RequireApiKey`]. Authentication is
+/// disabled when no keys are configured.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys {
+    keys: Vec<String>,
+}
+
+impl ApiKeys {
+    pub fn new<I: IntoIterator<Item = String>>(keys: I) -> Self {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|k| k.trim().to_string())
+                .filter(|k| !k.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Combines keys given directly with those read from `file`, one per
+    /// line. Blank lines and lines starting with `#` in the file are ignored.
+    pub fn load(keys: Vec<String>, file: Option<&Path>) -> std::io::Result<Self> {
+        let mut keys = keys;
+
+        if let Some(path) = file {
+            keys.extend(
+                std::fs::read_to_string(path)?
+                    .lines()
+                    .filter(|l| !l.trim_start().starts_with('#'))
+                    .map(str::to_string),
+            );
+        }
+
+        Ok(Self::new(keys))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// The key presented by a request, from either the `X-Api-Key` header or
+    /// a bearer token.
+    pub fn presented(headers: &HeaderMap) -> Option<&str> {
+        if let Some(key) = headers.get("x-api-key") {
+            return key.to_str().ok();
+        }
+
+        headers
+            .get(AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")
+    }
+
+    pub fn check(&self, headers: &HeaderMap) -> Result<(), &'static str> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        match Self::presented(headers) {
+            None => Err("Missing API key, send it in the X-Api-Key header"),
+            Some(key) if self.accepts(key) => Ok(()),
+            Some(_) => Err("Invalid API key"),
+        }
+    }
+
+    /// Whether `key` is one of the keys, however it was presented
+    pub fn accepts(&self, key: &str) -> bool {
+        self.keys.iter().any(|k| constant_time_eq(k, key))
+    }
+}
+
+/// Compares without exiting early, so response timing does not reveal how
+/// much of a key was guessed correctly.
+pub(super) fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+/// Middleware rejecting requests without a valid API key with a 401
+pub struct RequireApiKey;
+
+impl<S, B> Transform<S, ServiceRequest> for RequireApiKey
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireApiKeyMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireApiKeyMiddleware { service }))
+    }
+}
+
+pub struct RequireApiKeyMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireApiKeyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let result = match req.app_data::<web::Data<ServerConfig>>() {
+            Some(config) => config.api_keys.check(req.headers()),
+            None => Err("Authentication is not configured"),
+        };
+
+        if let Err(message) = result {
+            let resp = ApiError::Unauthorized(message).respond(req.headers());
+
+            return Box::pin(ready(Ok(req.into_response(resp).map_into_right_body())));
+        }
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move { fut.await.map(ServiceResponse::map_into_left_body) })
+    }
+}
@@ -0,0 +1,421 @@
+use actix_multipart::Multipart;
+use actix_web::dev::Decompress;
+use actix_web::http::header::{ContentType, CONTENT_ENCODING};
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use futures::{StreamExt, TryStreamExt};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{BufReader, Seek, Write};
+use tempfile::NamedTempFile;
+use utoipa::ToSchema;
+
+use super::audit::{actor, hex, AuditEntry, AuditLog};
+use super::auth::RequireApiKey;
+use super::downloads::{store_output, NO_DOWNLOADS};
+use super::filename::{attachment, download_name};
+use super::limits::{content_length, PayloadTooLarge};
+use super::logging::ConversionOutcome;
+use super::params::{ConvertParams, ConvertSettings, ResponseFormat};
+use super::problem::{ApiError, Problem, PROBLEM_JSON};
+use super::ratelimit::RateLimit;
+use super::sniff::{BinaryFormat, Sniffer};
+use super::utf8::Utf8Validator;
+use super::ServerConfig;
+use crate::csvconv::csv::{check_header_overrides, convert_reader_with_report, Conversion};
+use crate::csvconv::options::ConversionOptions;
+use crate::lib::error::ErrorLog;
+use crate::lib::report::ConversionReport;
+use crate::lib::types::RecordType;
+
+/// Name of the multipart part carrying the CSV file
+const FILE_FIELD: &str = "file";
+
+/// Response header carrying the SHA-256 of the converted file sent, so that
+/// clients can check what they keep against what the server produced
+pub const CONTENT_SHA256: &str = "X-Content-SHA256";
+
+/// Body of a `response=json` conversion
+#[derive(Serialize, ToSchema)]
+pub struct ConvertResponse {
+    filename: String,
+    content: String,
+    report: ConversionReport,
+    warnings: Vec<String>,
+}
+
+/// Form of a file upload, as described in the OpenAPI document. The
+/// conversion settings may be sent as further form fields.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    /// The CSV file, or for `/convert/zip` a zip archive of them
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+/// An uploaded CSV file spooled to disk
+pub(super) struct Upload {
+    pub file: NamedTempFile,
+    pub file_name: Option<String>,
+    pub sha256: String,
+    /// Values sent as form fields rather than as the file
+    pub fields: HashMap<String, String>,
+}
+
+/// Why an upload could not be converted and handed back
+pub(super) enum ConvertError {
+    Invalid(ErrorLog),
+    /// The file changes a value the profile of the request's API key fixes
+    Forbidden(String),
+    Io(std::io::Error),
+    Audit(String),
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::Invalid(log) => write!(f, "{}", log),
+            ConvertError::Forbidden(message) => write!(f, "{}", message),
+            ConvertError::Io(e) => write!(f, "Could not read upload: {}", e),
+            ConvertError::Audit(e) => {
+                write!(f, "Could not record conversion in the audit log: {}", e)
+            }
+        }
+    }
+}
+
+impl From<ConvertError> for ApiError {
+    fn from(e: ConvertError) -> Self {
+        match e {
+            ConvertError::Invalid(log) => ApiError::Invalid(log),
+            ConvertError::Forbidden(message) => ApiError::Forbidden(message),
+            e => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+/// Spools the `file` parts of the multipart body to a temporary file,
+/// enforcing the upload limits and checking that it is UTF-8 text as it
+/// arrives. Any other parts are collected as form fields. Files declared or
+/// found to be some other kind of file, such as a workbook, are refused
+/// without reading the rest.
+///
+/// A body sent with a `Content-Encoding` such as gzip is decompressed first,
+/// and the limits apply to its decompressed size.
+pub(super) async fn receive_upload(
+    req: &HttpRequest,
+    payload: web::Payload,
+    config: &ServerConfig,
+) -> Result<Upload, ApiError> {
+    receive(req, payload, config, true).await
+}
+
+/// Receives an upload like [`receive_upload`], but of a binary file such as
+/// an archive, which is not checked to be text
+pub(super) async fn receive_binary_upload(
+    req: &HttpRequest,
+    payload: web::Payload,
+    config: &ServerConfig,
+) -> Result<Upload, ApiError> {
+    receive(req, payload, config, false).await
+}
+
+async fn receive(
+    req: &HttpRequest,
+    payload: web::Payload,
+    config: &ServerConfig,
+    text: bool,
+) -> Result<Upload, ApiError> {
+    let limits = &config.limits;
+    let announced_size = content_length(req);
+    let compressed = req.headers().contains_key(CONTENT_ENCODING);
+
+    let mut body = Multipart::new(
+        req.headers(),
+        Decompress::from_headers(payload, req.headers()),
+    );
+
+    if let Some(size) = announced_size {
+        if size > limits.max_body_size {
+            return Err(PayloadTooLarge::new(limits.max_body_size, size).into());
+        }
+    }
+
+    // The upload is spooled to disk rather than held in memory. The temporary
+    // file is removed when it is dropped, which also covers early returns and
+    // the handler being cancelled by a client disconnect.
+    let mut file = config.temp_file().map_err(internal_error)?;
+    let mut file_name = None;
+    let mut body_size = 0usize;
+    let mut utf8 = Utf8Validator::new();
+    let mut sniffer = Sniffer::new();
+    let mut digest = Sha256::new();
+
+    let mut fields = HashMap::new();
+
+    while let Ok(Some(mut p)) = body.try_next().await {
+        let name = p.content_disposition().get_name().map(str::to_string);
+        let is_file = name.as_deref() == Some(FILE_FIELD);
+        let mut value = Vec::new();
+
+        if is_file {
+            file_name = p.content_disposition().get_filename().map(str::to_string);
+
+            let declared = p
+                .content_type()
+                .and_then(|mime| BinaryFormat::declared(mime.essence_str()));
+
+            if let (true, Some(format)) = (text, declared) {
+                return Err(format.into());
+            }
+        }
+
+        let mut field_size = 0usize;
+
+        while let Some(chunk) = p.next().await {
+            let chunk = chunk.map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+            field_size += chunk.len();
+            body_size += chunk.len();
+
+            if field_size > limits.max_field_size {
+                return Err(PayloadTooLarge::new(limits.max_field_size, field_size).into());
+            }
+
+            if body_size > limits.max_body_size {
+                // The announced size of a compressed body says nothing of
+                // how large it is once decompressed
+                let received = match announced_size {
+                    Some(size) if !compressed => size,
+                    _ => body_size,
+                };
+
+                return Err(PayloadTooLarge::new(limits.max_body_size, received).into());
+            }
+
+            if !is_file {
+                value.extend_from_slice(&chunk);
+                continue;
+            }
+
+            if text {
+                sniffer.feed(&chunk)?;
+
+                if let Err(e) = utf8.feed(&chunk) {
+                    // A binary file is named as such rather than as bad text
+                    sniffer.finish()?;
+                    return Err(ApiError::BadRequest(e.to_string()));
+                }
+            }
+
+            digest.update(&chunk);
+
+            file.write_all(&chunk).map_err(internal_error)?;
+        }
+
+        if let (false, Some(name)) = (is_file, name) {
+            let value = String::from_utf8(value).map_err(|_| {
+                ApiError::BadRequest(format!("Form field {} is not valid UTF-8", name))
+            })?;
+            fields.insert(name, value);
+        }
+    }
+
+    if text {
+        sniffer.finish()?;
+        utf8.finish()
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    }
+
+    Ok(Upload {
+        file,
+        file_name,
+        sha256: hex(&digest.finalize()),
+        fields,
+    })
+}
+
+/// Refuses an upload whose CSV header changes a value that `options` fix,
+/// as the profile of a request's API key does. Only the header is read.
+pub(super) fn check_fixed_values(
+    upload: &mut Upload,
+    options: &ConversionOptions,
+) -> Result<(), ConvertError> {
+    if options.header_defaults.is_empty() {
+        return Ok(());
+    }
+
+    upload.file.rewind().map_err(ConvertError::Io)?;
+
+    check_header_overrides(BufReader::new(upload.file.as_file()), options)
+        .map_err(ConvertError::Forbidden)
+}
+
+/// Converts an upload and records the outcome in the audit log, if one is
+/// kept. The conversion blocks, so this must be run on the conversion pool
+/// rather than an async worker.
+pub(super) fn convert_upload(
+    mut upload: Upload,
+    record_type: RecordType,
+    options: &ConversionOptions,
+    audit: Option<&AuditLog>,
+    actor: String,
+) -> Result<Conversion, ConvertError> {
+    check_fixed_values(&mut upload, options)?;
+
+    let mut file = upload.file;
+    file.rewind().map_err(ConvertError::Io)?;
+
+    let conversion =
+        convert_reader_with_report(BufReader::new(file.as_file()), record_type, options);
+
+    if let Some(audit) = audit {
+        record_conversion(audit, actor, upload.file_name, upload.sha256, &conversion)?;
+    }
+
+    conversion.map_err(ConvertError::Invalid)
+}
+
+/// Records the outcome of converting a file. A file is only handed out once
+/// it has been recorded.
+pub(super) fn record_conversion(
+    audit: &AuditLog,
+    actor: String,
+    file_name: Option<String>,
+    sha256: String,
+    conversion: &Result<Conversion, ErrorLog>,
+) -> Result<(), ConvertError> {
+    let entry = AuditEntry::new(actor, file_name, sha256);
+    let entry = match conversion {
+        Ok(c) => entry.converted(&c.report),
+        Err(log) => entry.rejected(log.get_error_list().len()),
+    };
+
+    audit
+        .record(&entry)
+        .map_err(|e| ConvertError::Audit(e.to_string()))
+}
+
+/// Converts a CSV file to CPA-005
+///
+/// Responds with the converted file as a download, or with `response=json`
+/// along with its report and warnings. With `store=true` the file is kept
+/// for download by link instead.
+#[utoipa::path(
+    tag = "conversion",
+    params(ConvertParams),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The converted file",
+            headers(("X-Content-SHA256" = String, description = "SHA-256 of the converted file")),
+            content(
+                (String = "text/plain"),
+                (ConvertResponse = "application/json"),
+            )),
+        (status = 400, description = "The settings are not valid or the file could not be converted", body = Problem, content_type = PROBLEM_JSON),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = PROBLEM_JSON),
+        (status = 403, description = "The request changes a value the profile of its API key fixes", body = Problem, content_type = PROBLEM_JSON),
+        (status = 413, description = "The upload is too large", body = Problem, content_type = PROBLEM_JSON),
+        (status = 415, description = "The upload is not a CSV file", body = Problem, content_type = PROBLEM_JSON),
+        (status = 429, description = "Too many requests", body = Problem, content_type = PROBLEM_JSON),
+        (status = 503, description = "Too many conversions are running", body = Problem, content_type = PROBLEM_JSON),
+        (status = 504, description = "The conversion took too long", body = Problem, content_type = PROBLEM_JSON),
+    ),
+    security((), ("api_key" = []), ("bearer" = [])),
+)]
+#[post("/convert", wrap = "RateLimit", wrap = "RequireApiKey")]
+async fn convert(
+    req: HttpRequest,
+    payload: web::Payload,
+    q: web::Query<ConvertParams>,
+    config: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let upload = match receive_upload(&req, payload, &config).await {
+        Ok(upload) => upload,
+        Err(e) => return e.respond(req.headers()),
+    };
+
+    let ConvertSettings {
+        record_type,
+        options,
+        response,
+        store,
+        ..
+    } = match q.resolve(&upload.fields, config.profiles.for_request(req.headers())) {
+        Ok(settings) => settings,
+        Err(e) => return e.respond(req.headers()),
+    };
+
+    if store && config.downloads.is_none() {
+        return ApiError::BadRequest(NO_DOWNLOADS.to_string()).respond(req.headers());
+    }
+
+    let file_name = upload.file_name.clone();
+    let audit = config.audit.clone();
+    let actor = actor(&req, &config.api_keys);
+
+    #[cfg(feature = "web")]
+    let started = std::time::Instant::now();
+    let conversion = config
+        .conversions
+        .run(move || convert_upload(upload, record_type, &options, audit.as_ref(), actor))
+        .await;
+
+    #[cfg(feature = "web")]
+    config.metrics.record(
+        match &conversion {
+            Ok(Ok(c)) => Some(c.report.total_credit_count + c.report.total_debit_count),
+            _ => None,
+        },
+        started.elapsed(),
+    );
+
+    respond_converted(&req, &config, conversion, file_name, response, store).await
+}
+
+/// Hands back the outcome of converting an upload named `file_name`, as
+/// asked for by `response` and `store`
+pub(super) async fn respond_converted(
+    req: &HttpRequest,
+    config: &ServerConfig,
+    conversion: Result<Result<Conversion, ConvertError>, ApiError>,
+    file_name: Option<String>,
+    response: ResponseFormat,
+    store: bool,
+) -> HttpResponse {
+    match conversion {
+        Ok(Ok(c)) => {
+            let outcome = ConversionOutcome::converted(&c.report, &c.log);
+            let name = download_name(file_name.as_deref(), c.report.file_creation_number);
+
+            if store {
+                return match store_output(config, c.output, name).await {
+                    Ok(link) => outcome.attach(HttpResponse::Ok().json(link)),
+                    Err(e) => e.respond(req.headers()),
+                };
+            }
+
+            outcome.attach(match response {
+                ResponseFormat::File => HttpResponse::Ok()
+                    .content_type(ContentType::plaintext())
+                    .insert_header(attachment(&name))
+                    .insert_header((CONTENT_SHA256, c.report.output_sha256))
+                    .body(c.output),
+                ResponseFormat::Json => HttpResponse::Ok().json(ConvertResponse {
+                    filename: name,
+                    warnings: c.log.get_warning_list(),
+                    content: c.output,
+                    report: c.report,
+                }),
+            })
+        }
+        Ok(Err(e)) => ApiError::from(e).respond(req.headers()),
+        Err(e) => e.respond(req.headers()),
+    }
+}
+
+pub(super) fn internal_error(e: std::io::Error) -> ApiError {
+    ApiError::Internal(format!("Could not store upload: {}", e))
+}
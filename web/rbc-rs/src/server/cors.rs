@@ -0,0 +1,39 @@
+use actix_cors::Cors;
+use actix_web::http::{header, Method};
+use actix_web::middleware::Condition;
+
+use super::convert::CONTENT_SHA256;
+
+/// Cross-origin access for frontends hosted separately from this server
+#[derive(Clone, Debug, Default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API. CORS is disabled, allowing only same
+    /// origin requests, when this is empty.
+    pub allowed_origins: Vec<String>,
+    pub allow_credentials: bool,
+}
+
+impl CorsConfig {
+    pub fn middleware(&self) -> Condition<Cors> {
+        let mut cors = Cors::default()
+            .allowed_methods([Method::GET, Method::POST])
+            .allowed_headers([
+                header::CONTENT_TYPE,
+                header::ACCEPT,
+                header::AUTHORIZATION,
+                header::HeaderName::from_static("x-api-key"),
+            ])
+            .expose_headers([CONTENT_SHA256])
+            .max_age(3600);
+
+        for origin in &self.allowed_origins {
+            cors = cors.allowed_origin(origin);
+        }
+
+        if self.allow_credentials {
+            cors = cors.supports_credentials();
+        }
+
+        Condition::new(!self.allowed_origins.is_empty(), cors)
+    }
+}
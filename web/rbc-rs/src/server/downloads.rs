@@ -0,0 +1,176 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::NamedTempFile;
+use utoipa::ToSchema;
+
+use super::convert::internal_error;
+use super::filename::attachment;
+use super::jobs::read_chunks;
+use super::problem::{ApiError, Problem, PROBLEM_JSON};
+use super::ServerConfig;
+
+pub const DEFAULT_DOWNLOAD_TTL: u64 = 15 * 60;
+
+pub(super) const NO_DOWNLOADS: &str = "This server does not keep converted files for download";
+
+/// Longest an abandoned file is kept past its expiry
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Stored {
+    file: NamedTempFile,
+    name: String,
+    expires: Instant,
+}
+
+type Files = Mutex<HashMap<String, Stored>>;
+
+/// Converted files kept on disk until they are downloaded once through an
+/// unguessable link, or until they expire after `ttl`
+#[derive(Clone)]
+pub struct DownloadStore {
+    files: Arc<Files>,
+    ttl: Duration,
+}
+
+/// What a client is told about a stored file
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DownloadLink {
+    pub url: String,
+    pub filename: String,
+    /// Seconds until the link stops working
+    pub expires_in: u64,
+}
+
+impl DownloadStore {
+    /// Creates the store along with a thread removing expired files, which
+    /// stops once the store is dropped
+    pub fn new(ttl: Duration) -> Self {
+        let files = Arc::new(Files::default());
+        let weak = Arc::downgrade(&files);
+
+        thread::spawn(move || sweep(weak, ttl));
+
+        Self { files, ttl }
+    }
+
+    fn store(&self, file: NamedTempFile, name: String) -> DownloadLink {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+
+        self.files.lock().unwrap().insert(
+            token.clone(),
+            Stored {
+                file,
+                name: name.clone(),
+                expires: Instant::now() + self.ttl,
+            },
+        );
+
+        DownloadLink {
+            url: format!("/downloads/{}", token),
+            filename: name,
+            expires_in: self.ttl.as_secs(),
+        }
+    }
+
+    /// Removes a file from the store, so that its link only works once
+    fn take(&self, token: &str) -> Result<Stored, ApiError> {
+        let stored = self.files.lock().unwrap().remove(token);
+
+        match stored {
+            Some(stored) if stored.expires > Instant::now() => Ok(stored),
+            Some(_) => Err(ApiError::Gone("The download link has expired".to_string())),
+            None => Err(ApiError::NotFound(
+                "No such download, it may already have been fetched".to_string(),
+            )),
+        }
+    }
+}
+
+fn sweep(files: Weak<Files>, ttl: Duration) {
+    loop {
+        thread::sleep(ttl.clamp(Duration::from_secs(1), SWEEP_INTERVAL));
+
+        let files = match files.upgrade() {
+            Some(files) => files,
+            None => return,
+        };
+
+        let now = Instant::now();
+        files
+            .lock()
+            .unwrap()
+            .retain(|_, stored| stored.expires > now);
+    }
+}
+
+/// Writes a converted file to disk and returns a link it can be downloaded
+/// from, if the server keeps converted files at all
+pub(super) async fn store_output(
+    config: &ServerConfig,
+    output: String,
+    name: String,
+) -> Result<DownloadLink, ApiError> {
+    let downloads = match &config.downloads {
+        Some(downloads) => downloads.clone(),
+        None => return Err(ApiError::BadRequest(NO_DOWNLOADS.to_string())),
+    };
+
+    let mut file = config.output_file().map_err(internal_error)?;
+
+    let file = web::block(move || file.write_all(output.as_bytes()).map(|_| file))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(internal_error)?;
+
+    Ok(downloads.store(file, name))
+}
+
+/// A converted file kept for download by link
+///
+/// Each link works once, until it expires.
+#[utoipa::path(
+    tag = "conversion",
+    operation_id = "download_stored",
+    params(("token" = String, Path, description = "The token of the link")),
+    responses(
+        (status = 200, description = "The converted file", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such download, or it was already fetched", body = Problem, content_type = PROBLEM_JSON),
+        (status = 410, description = "The link has expired", body = Problem, content_type = PROBLEM_JSON),
+    ),
+)]
+#[get("/downloads/{token}")]
+async fn download(
+    req: HttpRequest,
+    token: web::Path<String>,
+    config: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let stored = match &config.downloads {
+        Some(downloads) => downloads.take(&token),
+        None => Err(ApiError::NotFound("No such download".to_string())),
+    };
+
+    let stored = match stored {
+        Ok(stored) => stored,
+        Err(e) => return e.respond(req.headers()),
+    };
+
+    // The open file stays readable once the temporary file is removed
+    let file = match stored.file.reopen() {
+        Ok(file) => file,
+        Err(e) => {
+            return ApiError::Internal(format!("Could not read converted file: {}", e))
+                .respond(req.headers())
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .insert_header(attachment(&stored.name))
+        .streaming(read_chunks(file))
+}
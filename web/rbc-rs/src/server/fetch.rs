@@ -0,0 +1,294 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+use super::audit::{actor, hex};
+use super::auth::RequireApiKey;
+use super::convert::{convert_upload, internal_error, respond_converted, ConvertResponse, Upload};
+use super::downloads::NO_DOWNLOADS;
+use super::limits::PayloadTooLarge;
+use super::params::{ConvertParams, ConvertSettings};
+use super::problem::{ApiError, Problem, PROBLEM_JSON};
+use super::ratelimit::RateLimit;
+use super::sniff::Sniffer;
+use super::utf8::Utf8Validator;
+use super::ServerConfig;
+
+pub const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_MAX_REDIRECTS: usize = 3;
+
+/// Hosts files may be fetched from for `/convert/url`. A pattern is either a
+/// host name, matched exactly, or `*.` followed by a domain, matching any
+/// host within it. With no patterns nothing is fetched, as the endpoint
+/// would otherwise reach any host the server can on a client's behalf.
+#[derive(Clone, Debug, Default)]
+pub struct AllowedHosts(Vec<String>);
+
+impl AllowedHosts {
+    pub fn new(patterns: Vec<String>) -> Self {
+        Self(
+            patterns
+                .into_iter()
+                .map(|p| p.trim().to_ascii_lowercase())
+                .filter(|p| !p.is_empty())
+                .collect(),
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    /// Whether `url` may be fetched. Only `http` and `https` URLs are.
+    pub fn allows(&self, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return false,
+        };
+
+        self.0
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.ends_with('.') && sub.len() > 1),
+                None => host == *pattern,
+            })
+    }
+}
+
+/// Fetches the files of `/convert/url` requests
+#[derive(Clone)]
+pub struct UrlFetcher {
+    client: Client,
+    allowed: AllowedHosts,
+}
+
+impl UrlFetcher {
+    /// A fetcher giving up on a file after `timeout` and following at most
+    /// `max_redirects` redirects, each of which must stay within `allowed`
+    pub fn new(allowed: AllowedHosts, timeout: Duration, max_redirects: usize) -> Self {
+        let redirect_hosts = allowed.clone();
+        let policy = Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                attempt.error(format!("more than {} redirects", max_redirects))
+            } else if !redirect_hosts.allows(attempt.url()) {
+                let host = attempt.url().host_str().unwrap_or_default().to_string();
+                attempt.error(format!("redirected to {}, which is not allowed", host))
+            } else {
+                attempt.follow()
+            }
+        });
+
+        let client = Client::builder()
+            .timeout(timeout)
+            .redirect(policy)
+            .build()
+            .expect("HTTP client settings are valid");
+
+        Self { client, allowed }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.allowed.is_enabled()
+    }
+
+    /// Fetches the CSV file at `url` to a temporary file, checking it as an
+    /// upload would be. Failures name the host rather than the URL, which
+    /// may be signed.
+    pub(super) async fn fetch(&self, url: &str, config: &ServerConfig) -> Result<Upload, ApiError> {
+        let url = Url::parse(url.trim())
+            .map_err(|e| ApiError::BadRequest(format!("url is not a valid URL: {}", e)))?;
+
+        let host = url.host_str().unwrap_or_default().to_string();
+
+        if !self.allowed.allows(&url) {
+            return Err(ApiError::BadRequest(format!(
+                "Files may not be fetched from {}",
+                if host.is_empty() { url.scheme() } else { &host }
+            )));
+        }
+
+        let failed = |e: reqwest::Error| {
+            let reason = if e.is_timeout() {
+                "timed out".to_string()
+            } else if e.is_redirect() {
+                // Why the redirect was refused is only told by the source
+                std::error::Error::source(&e)
+                    .map_or_else(|| e.to_string(), |source| source.to_string())
+            } else {
+                e.without_url().to_string()
+            };
+            ApiError::Fetch(format!(
+                "Could not fetch the file from {}: {}",
+                host, reason
+            ))
+        };
+
+        let resp = self.client.get(url.clone()).send().await.map_err(failed)?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::Fetch(format!(
+                "{} answered {} for the file",
+                host,
+                resp.status()
+            )));
+        }
+
+        let limit = config.limits.max_field_size;
+        let too_large = |size: usize| PayloadTooLarge::of("Fetched file", limit, size);
+
+        if let Some(size) = resp.content_length() {
+            if size > limit as u64 {
+                return Err(too_large(size as usize).into());
+            }
+        }
+
+        let file_name = resp
+            .url()
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .map(str::to_string);
+
+        let mut file = config.temp_file().map_err(internal_error)?;
+        let mut size = 0usize;
+        let mut utf8 = Utf8Validator::new();
+        let mut sniffer = Sniffer::new();
+        let mut digest = Sha256::new();
+        let mut body = resp.bytes_stream();
+
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(failed)?;
+
+            size += chunk.len();
+            if size > limit {
+                return Err(too_large(size).into());
+            }
+
+            sniffer.feed(&chunk)?;
+            if let Err(e) = utf8.feed(&chunk) {
+                sniffer.finish()?;
+                return Err(ApiError::BadRequest(e.to_string()));
+            }
+
+            digest.update(&chunk);
+            file.write_all(&chunk).map_err(internal_error)?;
+        }
+
+        sniffer.finish()?;
+        utf8.finish()
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+        Ok(Upload {
+            file,
+            file_name,
+            sha256: hex(&digest.finalize()),
+            fields: HashMap::new(),
+        })
+    }
+}
+
+impl Default for UrlFetcher {
+    fn default() -> Self {
+        Self::new(
+            AllowedHosts::default(),
+            DEFAULT_FETCH_TIMEOUT,
+            DEFAULT_MAX_REDIRECTS,
+        )
+    }
+}
+
+/// Body of a `/convert/url` request, with the same settings as `/convert`
+/// takes in its query string
+#[derive(Deserialize, ToSchema)]
+pub struct UrlRequest {
+    /// Where the CSV file is fetched from, on one of the allowed hosts
+    #[schema(example = "https://erp.example.com/exports/payroll.csv")]
+    url: String,
+    #[serde(flatten)]
+    params: ConvertParams,
+}
+
+/// Converts a CSV file the server fetches
+///
+/// Only enabled when the server allows some hosts to fetch files from.
+/// Responds as `/convert` does.
+#[utoipa::path(
+    tag = "conversion",
+    request_body = UrlRequest,
+    responses(
+        (status = 200, description = "The converted file",
+            headers(("X-Content-SHA256" = String, description = "SHA-256 of the converted file")),
+            content(
+                (String = "text/plain"),
+                (ConvertResponse = "application/json"),
+            )),
+        (status = 400, description = "The settings or URL are not valid, or the file could not be converted", body = Problem, content_type = PROBLEM_JSON),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = PROBLEM_JSON),
+        (status = 403, description = "The request changes a value the profile of its API key fixes", body = Problem, content_type = PROBLEM_JSON),
+        (status = 404, description = "Converting files by URL is not enabled", body = Problem, content_type = PROBLEM_JSON),
+        (status = 413, description = "The file is too large", body = Problem, content_type = PROBLEM_JSON),
+        (status = 415, description = "The file is not a CSV file", body = Problem, content_type = PROBLEM_JSON),
+        (status = 429, description = "Too many requests", body = Problem, content_type = PROBLEM_JSON),
+        (status = 502, description = "The file could not be fetched", body = Problem, content_type = PROBLEM_JSON),
+        (status = 503, description = "Too many conversions are running", body = Problem, content_type = PROBLEM_JSON),
+        (status = 504, description = "The conversion took too long", body = Problem, content_type = PROBLEM_JSON),
+    ),
+    security((), ("api_key" = []), ("bearer" = [])),
+)]
+#[post("/convert/url", wrap = "RateLimit", wrap = "RequireApiKey")]
+async fn convert_url(
+    req: HttpRequest,
+    body: web::Json<UrlRequest>,
+    config: web::Data<ServerConfig>,
+) -> HttpResponse {
+    if !config.fetcher.is_enabled() {
+        return ApiError::NotFound("Converting files by URL is not enabled".to_string())
+            .respond(req.headers());
+    }
+
+    let UrlRequest { url, params } = body.into_inner();
+
+    let ConvertSettings {
+        record_type,
+        options,
+        response,
+        store,
+        ..
+    } = match params.resolve(&HashMap::new(), config.profiles.for_request(req.headers())) {
+        Ok(settings) => settings,
+        Err(e) => return e.respond(req.headers()),
+    };
+
+    if store && config.downloads.is_none() {
+        return ApiError::BadRequest(NO_DOWNLOADS.to_string()).respond(req.headers());
+    }
+
+    let upload = match config.fetcher.fetch(&url, &config).await {
+        Ok(upload) => upload,
+        Err(e) => return e.respond(req.headers()),
+    };
+
+    let file_name = upload.file_name.clone();
+    let audit = config.audit.clone();
+    let actor = actor(&req, &config.api_keys);
+
+    let conversion = config
+        .conversions
+        .run(move || convert_upload(upload, record_type, &options, audit.as_ref(), actor))
+        .await;
+
+    respond_converted(&req, &config, conversion, file_name, response, store).await
+}
@@ -0,0 +1,39 @@
+use actix_web::http::header::{
+    Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue,
+};
+
+use crate::csvconv::naming::sanitize_stem;
+
+/// Name of the converted file, the uploaded file's stem with a `.txt`
+/// extension, falling back to one derived from the file creation number.
+pub fn download_name(uploaded: Option<&str>, file_creation_number: u32) -> String {
+    match uploaded.and_then(sanitize_stem) {
+        Some(stem) => format!("{}.txt", stem),
+        None => format!("cpa005_{:0>4}.txt", file_creation_number),
+    }
+}
+
+/// Attachment disposition for `name`. Names outside of ASCII are sent as an
+/// RFC 5987 `filename*` alongside an ASCII approximation for older clients.
+pub fn attachment(name: &str) -> ContentDisposition {
+    if name.is_ascii() {
+        return ContentDisposition::attachment(name);
+    }
+
+    let fallback: String = name
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![
+            DispositionParam::Filename(fallback),
+            DispositionParam::FilenameExt(ExtendedValue {
+                charset: Charset::Ext("UTF-8".to_string()),
+                language_tag: None,
+                value: name.as_bytes().to_vec(),
+            }),
+        ],
+    }
+}
@@ -0,0 +1,41 @@
+use actix_web::{get, HttpResponse};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct Health {
+    /// Always `ok` while the server is accepting requests
+    #[schema(example = "ok")]
+    status: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Version {
+    version: &'static str,
+    commit: &'static str,
+    formats: [&'static str; 2],
+}
+
+/// Whether the server is up
+#[utoipa::path(
+    tag = "server",
+    responses((status = 200, description = "The server is up", body = Health)),
+)]
+#[get("/healthz")]
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(Health { status: "ok" })
+}
+
+/// The server's version and the formats it produces
+#[utoipa::path(
+    tag = "server",
+    responses((status = 200, description = "The server's version", body = Version)),
+)]
+#[get("/version")]
+async fn version() -> HttpResponse {
+    HttpResponse::Ok().json(Version {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("RBC_GIT_COMMIT"),
+        formats: ["CPA-005 PDS", "CPA-005 PAD"],
+    })
+}
@@ -0,0 +1,429 @@
+use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::NamedTempFile;
+use utoipa::ToSchema;
+
+use super::audit::{actor, AuditLog};
+use super::auth::RequireApiKey;
+use super::convert::{
+    check_fixed_values, convert_upload, internal_error, receive_upload, ConvertError, Upload,
+    UploadForm, CONTENT_SHA256,
+};
+use super::filename::{attachment, download_name};
+use super::params::ConvertParams;
+use super::problem::{ApiError, Problem, PROBLEM_JSON};
+use super::ratelimit::RateLimit;
+use super::ServerConfig;
+use crate::csvconv::options::ConversionOptions;
+use crate::lib::report::ConversionReport;
+use crate::lib::types::RecordType;
+
+pub const DEFAULT_JOB_WORKERS: usize = 2;
+pub const DEFAULT_JOB_QUEUE_SIZE: usize = 16;
+pub const DEFAULT_JOB_TTL: u64 = 60 * 60;
+
+/// Longest an idle worker waits before removing expired jobs
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+/// Seconds a client is asked to wait when the queue is full
+const QUEUE_FULL_RETRY_AFTER: u64 = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+struct Job {
+    /// Only this client may see the job, when authentication is enabled
+    owner: Option<String>,
+    status: JobStatus,
+    file_name: Option<String>,
+    report: Option<ConversionReport>,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+    /// The converted file, removed from disk when the job is dropped
+    output: Option<NamedTempFile>,
+    finished: Option<Instant>,
+}
+
+impl Job {
+    fn is_expired(&self, ttl: Duration) -> bool {
+        self.finished.is_some_and(|t| t.elapsed() >= ttl)
+    }
+
+    fn state(&self, id: &str, ttl: Duration) -> JobState {
+        JobState {
+            id: id.to_string(),
+            status: self.status,
+            report: self.report.clone(),
+            errors: self.errors.clone(),
+            warnings: self.warnings.clone(),
+            expires_in: self
+                .finished
+                .map(|t| ttl.saturating_sub(t.elapsed()).as_secs()),
+        }
+    }
+}
+
+/// What a client is told about a job
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobState {
+    pub id: String,
+    pub status: JobStatus,
+    pub report: Option<ConversionReport>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    /// Seconds until a finished job and its output are removed
+    pub expires_in: Option<u64>,
+}
+
+struct Task {
+    id: String,
+    upload: Upload,
+    output: NamedTempFile,
+    record_type: RecordType,
+    options: ConversionOptions,
+    audit: Option<AuditLog>,
+    actor: String,
+}
+
+type Jobs = Arc<Mutex<HashMap<String, Job>>>;
+
+/// Conversions run in the background by a fixed pool of worker threads. At
+/// most `capacity` jobs wait for a worker, further submissions are refused.
+/// Finished jobs are kept for `ttl` so their output can be downloaded.
+#[derive(Clone)]
+pub struct JobQueue {
+    sender: SyncSender<Task>,
+    jobs: Jobs,
+    ttl: Duration,
+}
+
+impl JobQueue {
+    pub fn new(workers: usize, capacity: usize, ttl: Duration) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let jobs = Jobs::default();
+
+        for _ in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            let jobs = jobs.clone();
+            thread::spawn(move || work(&receiver, &jobs, ttl));
+        }
+
+        Self { sender, jobs, ttl }
+    }
+
+    fn submit(&self, task: Task, owner: Option<String>) -> Option<JobState> {
+        let id = task.id.clone();
+        let job = Job {
+            owner,
+            status: JobStatus::Queued,
+            file_name: task.upload.file_name.clone(),
+            report: None,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            output: None,
+            finished: None,
+        };
+        let state = job.state(&id, self.ttl);
+
+        // Registered first so a worker picking the task up straight away
+        // finds it
+        self.jobs.lock().unwrap().insert(id.clone(), job);
+
+        if self.sender.try_send(task).is_err() {
+            self.jobs.lock().unwrap().remove(&id);
+            return None;
+        }
+
+        Some(state)
+    }
+
+    /// Looks up a job, hiding jobs that have expired or belong to another
+    /// client
+    fn find<T>(&self, id: &str, owner: &Option<String>, f: impl FnOnce(&Job) -> T) -> Option<T> {
+        let jobs = self.jobs.lock().unwrap();
+
+        match jobs.get(id) {
+            Some(job) if !job.is_expired(self.ttl) && job.owner == *owner => Some(f(job)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_JOB_WORKERS,
+            DEFAULT_JOB_QUEUE_SIZE,
+            Duration::from_secs(DEFAULT_JOB_TTL),
+        )
+    }
+}
+
+/// Runs queued jobs until every handle to the queue has been dropped
+fn work(receiver: &Mutex<Receiver<Task>>, jobs: &Mutex<HashMap<String, Job>>, ttl: Duration) {
+    loop {
+        let task = receiver
+            .lock()
+            .unwrap()
+            .recv_timeout(ttl.clamp(Duration::from_secs(1), SWEEP_INTERVAL));
+
+        match task {
+            Ok(task) => run(task, jobs),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        jobs.lock().unwrap().retain(|_, job| !job.is_expired(ttl));
+    }
+}
+
+fn run(mut task: Task, jobs: &Mutex<HashMap<String, Job>>) {
+    if let Some(job) = jobs.lock().unwrap().get_mut(&task.id) {
+        job.status = JobStatus::Running;
+    }
+
+    let conversion = convert_upload(
+        task.upload,
+        task.record_type,
+        &task.options,
+        task.audit.as_ref(),
+        task.actor,
+    )
+    .and_then(|c| {
+        task.output
+            .write_all(c.output.as_bytes())
+            .map_err(ConvertError::Io)?;
+        Ok(c)
+    });
+
+    let mut jobs = jobs.lock().unwrap();
+    let job = match jobs.get_mut(&task.id) {
+        Some(job) => job,
+        None => return,
+    };
+
+    match conversion {
+        Ok(c) => {
+            job.status = JobStatus::Done;
+            job.report = Some(c.report);
+            job.warnings = c.log.get_warning_list();
+            job.output = Some(task.output);
+        }
+        Err(ConvertError::Invalid(log)) => {
+            job.status = JobStatus::Failed;
+            job.errors = log.get_error_list();
+            job.warnings = log.get_warning_list();
+        }
+        Err(e) => {
+            job.status = JobStatus::Failed;
+            job.errors = vec![e.to_string()];
+        }
+    }
+
+    job.finished = Some(Instant::now());
+}
+
+/// Jobs are private to the API key that submitted them. Without
+/// authentication any client may see any job.
+fn owner(req: &HttpRequest, config: &ServerConfig) -> Option<String> {
+    if config.api_keys.is_enabled() {
+        Some(actor(req, &config.api_keys))
+    } else {
+        None
+    }
+}
+
+fn not_found() -> ApiError {
+    ApiError::NotFound("No such job, it may have expired".to_string())
+}
+
+/// Converts a CSV file in the background
+///
+/// Takes the same upload and settings as `/convert`. Poll the job's status
+/// until it is done, then download the converted file.
+#[utoipa::path(
+    tag = "jobs",
+    params(ConvertParams),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 202, description = "The job was queued", body = JobState,
+            headers(("Location" = String, description = "Where the job's status is polled"))),
+        (status = 400, description = "The settings are not valid", body = Problem, content_type = PROBLEM_JSON),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = PROBLEM_JSON),
+        (status = 403, description = "The request changes a value the profile of its API key fixes", body = Problem, content_type = PROBLEM_JSON),
+        (status = 413, description = "The upload is too large", body = Problem, content_type = PROBLEM_JSON),
+        (status = 415, description = "The upload is not a CSV file", body = Problem, content_type = PROBLEM_JSON),
+        (status = 429, description = "Too many requests", body = Problem, content_type = PROBLEM_JSON),
+        (status = 503, description = "Too many jobs are queued", body = Problem, content_type = PROBLEM_JSON),
+    ),
+    security((), ("api_key" = []), ("bearer" = [])),
+)]
+#[post("/jobs", wrap = "RateLimit", wrap = "RequireApiKey")]
+async fn submit(
+    req: HttpRequest,
+    payload: web::Payload,
+    q: web::Query<ConvertParams>,
+    config: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let mut upload = match receive_upload(&req, payload, &config).await {
+        Ok(upload) => upload,
+        Err(e) => return e.respond(req.headers()),
+    };
+
+    let settings = match q.resolve(&upload.fields, config.profiles.for_request(req.headers())) {
+        Ok(settings) => settings,
+        Err(e) => return e.respond(req.headers()),
+    };
+
+    // Refused now rather than once the job runs
+    if let Err(e) = check_fixed_values(&mut upload, &settings.options) {
+        return ApiError::from(e).respond(req.headers());
+    }
+
+    let output = match config.output_file() {
+        Ok(f) => f,
+        Err(e) => return internal_error(e).respond(req.headers()),
+    };
+
+    let task = Task {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        upload,
+        output,
+        record_type: settings.record_type,
+        options: settings.options,
+        audit: config.audit.clone(),
+        actor: actor(&req, &config.api_keys),
+    };
+
+    match config.jobs.submit(task, owner(&req, &config)) {
+        Some(state) => HttpResponse::Accepted()
+            .insert_header((LOCATION, format!("/jobs/{}", state.id)))
+            .json(state),
+        None => ApiError::Unavailable {
+            detail: "Too many conversions are queued, try again later".to_string(),
+            retry_after: QUEUE_FULL_RETRY_AFTER,
+        }
+        .respond(req.headers()),
+    }
+}
+
+/// Status of a background conversion
+#[utoipa::path(
+    tag = "jobs",
+    params(("id" = String, Path, description = "The job's id")),
+    responses(
+        (status = 200, description = "The job's status, with its report once done", body = JobState),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = PROBLEM_JSON),
+        (status = 404, description = "No such job, or it has expired", body = Problem, content_type = PROBLEM_JSON),
+    ),
+    security((), ("api_key" = []), ("bearer" = [])),
+)]
+#[get("/jobs/{id}", wrap = "RequireApiKey")]
+async fn status(
+    req: HttpRequest,
+    id: web::Path<String>,
+    config: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let ttl = config.jobs.ttl;
+
+    match config
+        .jobs
+        .find(&id, &owner(&req, &config), |job| job.state(&id, ttl))
+    {
+        Some(state) => HttpResponse::Ok().json(state),
+        None => not_found().respond(req.headers()),
+    }
+}
+
+/// The file a background conversion produced
+#[utoipa::path(
+    tag = "jobs",
+    operation_id = "download_job",
+    params(("id" = String, Path, description = "The job's id")),
+    responses(
+        (status = 200, description = "The converted file", body = String, content_type = "text/plain",
+            headers(("X-Content-SHA256" = String, description = "SHA-256 of the converted file"))),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = PROBLEM_JSON),
+        (status = 404, description = "No such job, or it has expired", body = Problem, content_type = PROBLEM_JSON),
+        (status = 409, description = "The job has not finished, or failed", body = Problem, content_type = PROBLEM_JSON),
+    ),
+    security((), ("api_key" = []), ("bearer" = [])),
+)]
+#[get("/jobs/{id}/download", wrap = "RequireApiKey")]
+async fn download(
+    req: HttpRequest,
+    id: web::Path<String>,
+    config: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let found = config.jobs.find(&id, &owner(&req, &config), |job| {
+        match (&job.output, &job.report) {
+            (Some(output), Some(report)) => Ok((
+                output.reopen(),
+                download_name(job.file_name.as_deref(), report.file_creation_number),
+                report.output_sha256.clone(),
+            )),
+            _ => Err(job.status),
+        }
+    });
+
+    let (file, name, sha256) = match found {
+        Some(Ok((Ok(file), name, sha256))) => (file, name, sha256),
+        Some(Ok((Err(e), _, _))) => {
+            return ApiError::Internal(format!("Could not read converted file: {}", e))
+                .respond(req.headers())
+        }
+        Some(Err(JobStatus::Failed)) => {
+            return ApiError::Conflict("The job failed, there is no file to download".to_string())
+                .respond(req.headers())
+        }
+        Some(Err(_)) => {
+            return ApiError::Conflict(
+                "The job has not finished, poll its status until it is done".to_string(),
+            )
+            .respond(req.headers())
+        }
+        None => return not_found().respond(req.headers()),
+    };
+
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .insert_header(attachment(&name))
+        .insert_header((CONTENT_SHA256, sha256))
+        .streaming(read_chunks(file))
+}
+
+/// Streams a file without blocking the async worker on disk reads
+pub(super) fn read_chunks(
+    file: File,
+) -> impl futures::Stream<Item = Result<web::Bytes, actix_web::Error>> {
+    futures::stream::try_unfold(file, |mut file| async move {
+        let (file, chunk) = web::block(move || {
+            let mut chunk = vec![0; DOWNLOAD_CHUNK_SIZE];
+            let n = file.read(&mut chunk)?;
+            chunk.truncate(n);
+            Ok::<_, std::io::Error>((file, chunk))
+        })
+        .await??;
+
+        if chunk.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some((web::Bytes::from(chunk), file)))
+        }
+    })
+}
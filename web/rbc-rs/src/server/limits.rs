@@ -0,0 +1,112 @@
+use actix_web::http::header::CONTENT_LENGTH;
+use actix_web::{error, web, HttpRequest};
+use std::fmt::Display;
+
+use super::problem::ApiError;
+
+pub const DEFAULT_MAX_UPLOAD_SIZE: usize = 25 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug)]
+pub struct UploadLimits {
+    pub max_body_size: usize,
+    pub max_field_size: usize,
+}
+
+impl UploadLimits {
+    pub fn new(max_body_size: usize, max_field_size: usize) -> Self {
+        Self {
+            max_body_size,
+            max_field_size,
+        }
+    }
+
+    pub fn json_config(&self) -> web::JsonConfig {
+        web::JsonConfig::default()
+            .limit(self.max_body_size)
+            .error_handler(|err, _req| match err {
+                error::JsonPayloadError::OverflowKnownLength { length, limit } => {
+                    ApiError::from(PayloadTooLarge::new(limit, length)).into()
+                }
+                error::JsonPayloadError::Overflow { limit } => {
+                    ApiError::from(PayloadTooLarge::new(limit, limit)).into()
+                }
+                err => err.into(),
+            })
+    }
+
+    pub fn payload_config(&self) -> web::PayloadConfig {
+        web::PayloadConfig::new(self.max_body_size)
+    }
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_UPLOAD_SIZE, DEFAULT_MAX_UPLOAD_SIZE)
+    }
+}
+
+pub const DEFAULT_MAX_ZIP_ENTRIES: usize = 100;
+pub const DEFAULT_MAX_ZIP_SIZE: usize = 4 * DEFAULT_MAX_UPLOAD_SIZE;
+
+/// Bounds on what a zip upload may expand to, as the compressed size says
+/// little about it
+#[derive(Clone, Copy, Debug)]
+pub struct ZipLimits {
+    /// Most CSV files an archive may hold
+    pub max_entries: usize,
+    /// Largest decompressed size of a single CSV file
+    pub max_entry_size: usize,
+    /// Largest decompressed size of all the CSV files together
+    pub max_total_size: usize,
+}
+
+impl Default for ZipLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ZIP_ENTRIES,
+            max_entry_size: DEFAULT_MAX_UPLOAD_SIZE,
+            max_total_size: DEFAULT_MAX_ZIP_SIZE,
+        }
+    }
+}
+
+/// Size of the request body as announced by the client, if any.
+pub fn content_length(req: &HttpRequest) -> Option<usize> {
+    req.headers()
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse::<usize>()
+        .ok()
+}
+
+#[derive(Debug)]
+pub struct PayloadTooLarge {
+    pub error: String,
+    pub limit: usize,
+    pub received: usize,
+}
+
+impl PayloadTooLarge {
+    pub fn new(limit: usize, received: usize) -> Self {
+        Self::of("Upload", limit, received)
+    }
+
+    /// A limit exceeded by `what`, e.g. a single file within an archive
+    pub fn of(what: &str, limit: usize, received: usize) -> Self {
+        Self {
+            error: format!(
+                "{} of {} bytes exceeds the {} byte limit",
+                what, received, limit
+            ),
+            limit,
+            received,
+        }
+    }
+}
+
+impl Display for PayloadTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
@@ -0,0 +1,141 @@
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpResponse};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::time::Instant;
+
+use super::audit::key_id;
+use super::auth::ApiKeys;
+use super::limits::content_length;
+use crate::lib::error::ErrorLog;
+use crate::lib::report::ConversionReport;
+
+/// Routes whose paths carry a secret, logged by their pattern instead
+const REDACTED_ROUTES: [&str; 1] = ["/downloads/{token}"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable, for development
+    #[default]
+    Pretty,
+    /// One JSON object per line, for log collectors
+    Json,
+}
+
+/// Sends log events to standard output in `format`
+pub fn init(format: LogFormat) {
+    let builder = tracing_subscriber::fmt().with_target(false);
+
+    match format {
+        LogFormat::Pretty => builder.init(),
+        LogFormat::Json => builder.json().flatten_event(true).init(),
+    }
+}
+
+/// Summary of a conversion a handler attaches to its response for the
+/// request log. Only counts are kept, never file names or payment details.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConversionOutcome {
+    pub rows: u64,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl ConversionOutcome {
+    pub fn converted(report: &ConversionReport, log: &ErrorLog) -> Self {
+        Self {
+            rows: report.total_credit_count + report.total_debit_count,
+            errors: 0,
+            warnings: log.get_warning_list().len(),
+        }
+    }
+
+    pub fn rejected(log: &ErrorLog) -> Self {
+        Self {
+            rows: 0,
+            errors: log.get_error_list().len(),
+            warnings: log.get_warning_list().len(),
+        }
+    }
+
+    /// Attaches the outcome to `resp`, where [`RequestLog`] finds it
+    pub fn attach(self, mut resp: HttpResponse) -> HttpResponse {
+        resp.extensions_mut().insert(self);
+        resp
+    }
+}
+
+/// Middleware emitting one `request` event for every request once it has
+/// been answered
+pub struct RequestLog;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestLogMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestLogMiddleware { service }))
+    }
+}
+
+pub struct RequestLogMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestLogMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let started = Instant::now();
+        let method = req.method().to_string();
+        let payload_size = content_length(req.request());
+        // Identifies the key without revealing it, whether or not it is
+        // accepted
+        let key_id = ApiKeys::presented(req.headers()).map(key_id);
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await?;
+
+            let path = match res.request().match_pattern() {
+                Some(pattern) if REDACTED_ROUTES.contains(&pattern.as_str()) => pattern,
+                _ => res.request().path().to_string(),
+            };
+            let outcome = res
+                .response()
+                .extensions()
+                .get::<ConversionOutcome>()
+                .copied();
+
+            tracing::info!(
+                method = method.as_str(),
+                path = path.as_str(),
+                status = res.status().as_u16(),
+                duration_ms = started.elapsed().as_millis() as u64,
+                payload_size = payload_size.map(|s| s as u64),
+                key_id = key_id.as_deref(),
+                rows = outcome.map(|o| o.rows),
+                errors = outcome.map(|o| o.errors as u64),
+                warnings = outcome.map(|o| o.warnings as u64),
+                "request"
+            );
+
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use actix_web::{get, web, HttpResponse};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::ServerConfig;
+
+/// Content type of the Prometheus text exposition format
+const PROMETHEUS_TEXT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds in seconds of the buckets conversion durations are counted
+/// in, the last bucket, `+Inf`, taking the rest
+const DURATION_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0];
+
+#[derive(Default)]
+struct Counters {
+    conversions: AtomicU64,
+    errors: AtomicU64,
+    rows: AtomicU64,
+    /// Conversions that took no longer than each of [`DURATION_BUCKETS`]
+    buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    /// Time spent converting, in microseconds
+    duration_sum: AtomicU64,
+}
+
+/// Counts of the conversions of `/convert`, for `/metrics` to expose to
+/// Prometheus. Clones share the same counts, so one can be handed to every
+/// worker.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Counters>,
+}
+
+impl Metrics {
+    /// Counts a conversion that took `duration`, and converted `rows`
+    /// payments if it succeeded or failed if none
+    pub fn record(&self, rows: Option<u64>, duration: Duration) {
+        let c = &self.counters;
+
+        c.conversions.fetch_add(1, Ordering::Relaxed);
+        match rows {
+            Some(rows) => c.rows.fetch_add(rows, Ordering::Relaxed),
+            None => c.errors.fetch_add(1, Ordering::Relaxed),
+        };
+
+        let seconds = duration.as_secs_f64();
+        for (bound, count) in DURATION_BUCKETS.iter().zip(&c.buckets) {
+            if seconds <= *bound {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        c.duration_sum
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// The counts in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let c = &self.counters;
+        let conversions = c.conversions.load(Ordering::Relaxed);
+        let mut text = String::new();
+
+        let counters = [
+            (
+                "conversions_total",
+                "Conversions requested of /convert",
+                conversions,
+            ),
+            (
+                "conversion_errors_total",
+                "Conversions of /convert that failed",
+                c.errors.load(Ordering::Relaxed),
+            ),
+            (
+                "rows_processed_total",
+                "Payments converted by /convert",
+                c.rows.load(Ordering::Relaxed),
+            ),
+        ];
+
+        // Writing to a String cannot fail
+        for (name, help, value) in counters {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} counter", name);
+            let _ = writeln!(text, "{} {}", name, value);
+        }
+
+        let name = "conversion_duration_seconds";
+        let _ = writeln!(
+            text,
+            "# HELP {} Time taken by conversions of /convert",
+            name
+        );
+        let _ = writeln!(text, "# TYPE {} histogram", name);
+        for (bound, count) in DURATION_BUCKETS.iter().zip(&c.buckets) {
+            let _ = writeln!(
+                text,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                bound,
+                count.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(text, "{}_bucket{{le=\"+Inf\"}} {}", name, conversions);
+        let _ = writeln!(
+            text,
+            "{}_sum {}",
+            name,
+            c.duration_sum.load(Ordering::Relaxed) as f64 / 1e6
+        );
+        let _ = writeln!(text, "{}_count {}", name, conversions);
+
+        text
+    }
+}
+
+/// Counts of the conversions made since the server started, for Prometheus
+/// to scrape
+#[get("/metrics")]
+async fn metrics(config: web::Data<ServerConfig>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(PROMETHEUS_TEXT)
+        .body(config.metrics.render())
+}
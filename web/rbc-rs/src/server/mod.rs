@@ -0,0 +1,124 @@
+use actix_web::web;
+use std::path::PathBuf;
+use tempfile::NamedTempFile;
+
+pub mod archive;
+pub mod audit;
+pub mod auth;
+pub mod convert;
+pub mod cors;
+pub mod downloads;
+pub mod fetch;
+pub mod filename;
+pub mod health;
+pub mod jobs;
+pub mod limits;
+pub mod logging;
+#[cfg(feature = "web")]
+pub mod metrics;
+pub mod openapi;
+pub mod params;
+pub mod pool;
+pub mod problem;
+pub mod profiles;
+pub mod ratelimit;
+pub mod schema;
+pub mod shutdown;
+pub mod simple;
+pub mod sniff;
+pub mod ui;
+pub mod utf8;
+
+use audit::AuditLog;
+use auth::ApiKeys;
+use cors::CorsConfig;
+use downloads::DownloadStore;
+use fetch::UrlFetcher;
+use jobs::JobQueue;
+use limits::{UploadLimits, ZipLimits};
+#[cfg(feature = "web")]
+use metrics::Metrics;
+use pool::ConversionPool;
+use profiles::Profiles;
+use ratelimit::RateLimiter;
+
+#[derive(Clone, Default)]
+pub struct ServerConfig {
+    pub limits: UploadLimits,
+    pub zip_limits: ZipLimits,
+    /// Directory uploads are spooled to, the system temporary directory if
+    /// unset
+    pub temp_dir: Option<PathBuf>,
+    pub cors: CorsConfig,
+    /// Directory the UI is served from instead of the embedded bundle
+    pub ui_dir: Option<PathBuf>,
+    pub api_keys: ApiKeys,
+    /// Shared by every worker, so limits apply across the whole server
+    pub rate_limiter: RateLimiter,
+    /// Where conversions are recorded, if anywhere
+    pub audit: Option<AuditLog>,
+    /// Threads requests are converted on, shared by every worker
+    pub conversions: ConversionPool,
+    /// Background conversions, shared by every worker
+    pub jobs: JobQueue,
+    /// Where converted files are kept for download by link, if anywhere
+    pub downloads: Option<DownloadStore>,
+    /// Fetches the files of `/convert/url`, which is disabled unless some
+    /// hosts are allowed
+    pub fetcher: UrlFetcher,
+    /// Settings applied to the conversions of particular API keys
+    pub profiles: Profiles,
+    /// Counts of the conversions made, shared by every worker
+    #[cfg(feature = "web")]
+    pub metrics: Metrics,
+}
+
+impl ServerConfig {
+    pub fn temp_file(&self) -> std::io::Result<NamedTempFile> {
+        self.named_temp_file("rbc-upload-", ".csv")
+    }
+
+    /// Where a background job's converted file is kept until it expires
+    pub fn output_file(&self) -> std::io::Result<NamedTempFile> {
+        self.named_temp_file("rbc-output-", ".txt")
+    }
+
+    fn named_temp_file(&self, prefix: &str, suffix: &str) -> std::io::Result<NamedTempFile> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(prefix).suffix(suffix);
+
+        match &self.temp_dir {
+            Some(dir) => builder.tempfile_in(dir),
+            None => builder.tempfile(),
+        }
+    }
+}
+
+pub fn configure(config: ServerConfig) -> impl FnOnce(&mut web::ServiceConfig) {
+    move |cfg| {
+        cfg.app_data(config.limits.json_config())
+            .app_data(config.limits.payload_config())
+            .app_data(web::Data::new(config))
+            .service(health::healthz)
+            .service(health::version)
+            .service(schema::schema)
+            .service(convert::convert)
+            .service(archive::convert_zip)
+            .service(fetch::convert_url)
+            .service(jobs::submit)
+            .service(jobs::status)
+            .service(jobs::download)
+            .service(downloads::download)
+            .service(audit::list)
+            .service(simple::form)
+            .service(simple::convert)
+            .service(openapi::spec)
+            .service(openapi::docs);
+
+        #[cfg(feature = "web")]
+        cfg.service(metrics::metrics);
+
+        // Answers any path not taken above, so it must come last
+        cfg.service(ui::asset);
+    }
+}
@@ -0,0 +1,89 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, HttpResponse};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use super::{archive, audit, convert, downloads, fetch, health, jobs, schema};
+
+/// Lets the document say how endpoints requiring an API key accept it
+struct ApiKeySchemes;
+
+impl Modify for ApiKeySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// The HTTP API, described from the handlers and the types they send and
+/// receive
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "RBC CSV to CPA-005 conversion",
+        description = "Converts CSV files of payments to the CPA-005 format. Endpoints marked \
+            with a lock need an API key when the server is started with any, sent in an \
+            `X-Api-Key` header or as a bearer token."
+    ),
+    paths(
+        health::healthz,
+        health::version,
+        schema::schema,
+        convert::convert,
+        archive::convert_zip,
+        fetch::convert_url,
+        jobs::submit,
+        jobs::status,
+        jobs::download,
+        downloads::download,
+        audit::list,
+    ),
+    modifiers(&ApiKeySchemes),
+    tags(
+        (name = "conversion", description = "Converting CSV files"),
+        (name = "jobs", description = "Converting large files in the background"),
+        (name = "server", description = "The server and the files it accepts"),
+        (name = "audit", description = "The record of conversions"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The OpenAPI document of the API
+#[get("/openapi.json")]
+async fn spec() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>RBC CSV to CPA-005 API</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+<script>
+SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+</script>
+</body>
+</html>
+"##;
+
+/// Swagger UI for exploring the API, loaded from a CDN so that it is not
+/// built into the server
+#[get("/docs")]
+async fn docs() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(SWAGGER_UI)
+}
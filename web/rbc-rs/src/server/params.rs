@@ -0,0 +1,281 @@
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use utoipa::{IntoParams, ToSchema};
+
+use super::problem::ApiError;
+use super::profiles::{Profile, PROFILE_PARAMS};
+use crate::csvconv::csv::parse_dollar_amount_to_cents;
+use crate::csvconv::options::ConversionOptions;
+use crate::lib::types::{PaymentType, RecordType, SortOrder};
+
+/// How `/convert` hands back the converted file
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// The file as a download
+    #[default]
+    File,
+    /// The file along with its report and warnings
+    Json,
+}
+
+/// Conversion settings given in the query string. Each may instead be sent
+/// as a form field alongside the file, which takes precedence.
+#[derive(Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct ConvertParams {
+    /// `PDS` for a credit file or `PAD` for a debit file, required
+    #[param(example = "PDS")]
+    #[schema(example = "PDS")]
+    convtype: Option<String>,
+    /// Trade name shown to recipients instead of the client name, at most
+    /// 15 characters
+    display_name: Option<String>,
+    /// File creation date written to the header, `YYYY-MM-DD`, defaults to
+    /// today
+    creation_date: Option<String>,
+    /// `true` to report validation failures as warnings instead of errors
+    lenient: Option<String>,
+    /// `true` to warn of payments dated on a weekend or Canadian statutory
+    /// holiday
+    business_days: Option<String>,
+    /// `true` for a prenote file, with every amount zero, to verify the
+    /// accounts before live payments are sent
+    prenote: Option<String>,
+    /// Transaction code of every segment of a prenote file, `000` by
+    /// default
+    prenote_transaction_code: Option<String>,
+    /// `file` for the converted file as a download, or `json` for it along
+    /// with its report and warnings
+    response: Option<String>,
+    /// `true` to reject a whole archive when any file in it fails
+    strict: Option<String>,
+    /// `true` to keep the converted file for download by link, when the
+    /// server allows it
+    store: Option<String>,
+    /// Dollar amount the payments must add up to
+    control_total: Option<String>,
+    /// Order detail records are written in: `source`, `account` or
+    /// `amount-desc`
+    sort: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Source {
+    Form,
+    Query,
+    Profile,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Form => write!(f, "form field"),
+            Source::Query => write!(f, "query parameter"),
+            Source::Profile => write!(f, "in the API key's profile"),
+        }
+    }
+}
+
+/// Settings for a conversion after combining both sources
+pub struct ConvertSettings {
+    pub record_type: RecordType,
+    pub options: ConversionOptions,
+    pub response: ResponseFormat,
+    /// Whether one file failing fails a whole batch of them
+    pub strict: bool,
+    /// Whether the converted file is kept for download by link rather than
+    /// sent in the response
+    pub store: bool,
+}
+
+impl ConvertParams {
+    /// Combines the query string with the form `fields` of the upload,
+    /// reporting which of the two supplied a value that is not valid. The
+    /// `profile` of the request's API key supplies values neither gives, and
+    /// requests changing a value it fixes are forbidden.
+    pub fn resolve(
+        &self,
+        fields: &HashMap<String, String>,
+        profile: Option<&Profile>,
+    ) -> Result<ConvertSettings, ApiError> {
+        if let Some(profile) = profile {
+            self.check_fixed(fields, profile)
+                .map_err(ApiError::Forbidden)?;
+        }
+
+        let mut settings = self
+            .settings(fields, profile)
+            .map_err(ApiError::BadRequest)?;
+
+        if let Some(profile) = profile {
+            settings.options.header_defaults = profile.header_defaults();
+        }
+
+        Ok(settings)
+    }
+
+    fn query(&self, name: &str) -> Option<&String> {
+        match name {
+            "convtype" => self.convtype.as_ref(),
+            "display_name" => self.display_name.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn check_fixed(
+        &self,
+        fields: &HashMap<String, String>,
+        profile: &Profile,
+    ) -> Result<(), String> {
+        for name in PROFILE_PARAMS {
+            let fixed = match profile.param(name) {
+                Some(fixed) if !profile.may_override(name) => fixed.trim(),
+                _ => continue,
+            };
+
+            let given = fields
+                .get(name)
+                .or_else(|| self.query(name))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty());
+
+            if let Some(v) = given.filter(|v| *v != fixed) {
+                return Err(format!(
+                    "{} is fixed to \"{}\" for this API key and cannot be changed to \"{}\"",
+                    name, fixed, v
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn settings(
+        &self,
+        fields: &HashMap<String, String>,
+        profile: Option<&Profile>,
+    ) -> Result<ConvertSettings, String> {
+        let value = |name: &str, query: &Option<String>| -> Option<(String, Source)> {
+            match fields.get(name) {
+                Some(v) => Some((v.trim().to_string(), Source::Form)),
+                None => query
+                    .as_ref()
+                    .map(|v| (v.trim().to_string(), Source::Query))
+                    .or_else(|| {
+                        let v = profile?.param(name)?;
+                        Some((v.trim().to_string(), Source::Profile))
+                    }),
+            }
+        };
+
+        let record_type = match value("convtype", &self.convtype) {
+            Some((v, source)) => v
+                .parse::<PaymentType>()
+                .map_err(|e| format!("convtype {} {}", source, e))?
+                .record_type(),
+            None => {
+                return Err("convtype must be given as a form field or query parameter".to_string())
+            }
+        };
+
+        let mut options = ConversionOptions {
+            originator_display_name: value("display_name", &self.display_name)
+                .map(|(v, _)| v)
+                .filter(|v| !v.is_empty()),
+            ..Default::default()
+        };
+
+        if let Some((v, source)) = value("creation_date", &self.creation_date) {
+            options.file_creation_date =
+                Some(NaiveDate::parse_from_str(&v, "%Y-%m-%d").map_err(|_| {
+                    format!(
+                        "creation_date {} must be a YYYY-MM-DD date, not \"{}\"",
+                        source, v
+                    )
+                })?);
+        }
+
+        if let Some((v, source)) = value("lenient", &self.lenient) {
+            options.validation.lenient = parse_bool("lenient", &v, source)?;
+        }
+
+        if let Some((v, source)) = value("business_days", &self.business_days) {
+            options.validation.business_days = parse_bool("business_days", &v, source)?;
+        }
+
+        if let Some((v, source)) = value("prenote", &self.prenote) {
+            options.prenote = parse_bool("prenote", &v, source)?;
+        }
+
+        if let Some((v, _)) = value("prenote_transaction_code", &self.prenote_transaction_code) {
+            options.prenote_transaction_code = v;
+        }
+
+        if let Some((v, source)) = value("control_total", &self.control_total) {
+            options.control_total_cents = Some(
+                parse_dollar_amount_to_cents(&v, &options.amount_format).ok_or_else(|| {
+                    format!(
+                        "control_total {} must be a dollar amount, not \"{}\"",
+                        source, v
+                    )
+                })?,
+            );
+        }
+
+        options.sort = match value("sort", &self.sort) {
+            None => SortOrder::SourceOrder,
+            Some((v, _)) if v == "source" => SortOrder::SourceOrder,
+            Some((v, _)) if v == "account" => SortOrder::ByAccount,
+            Some((v, _)) if v == "amount-desc" => SortOrder::ByAmountDesc,
+            Some((v, source)) => {
+                return Err(format!(
+                    "sort {} must be source, account or amount-desc, not \"{}\"",
+                    source, v
+                ))
+            }
+        };
+
+        let strict = match value("strict", &self.strict) {
+            Some((v, source)) => parse_bool("strict", &v, source)?,
+            None => false,
+        };
+
+        let store = match value("store", &self.store) {
+            Some((v, source)) => parse_bool("store", &v, source)?,
+            None => false,
+        };
+
+        let response = match value("response", &self.response) {
+            None => ResponseFormat::File,
+            Some((v, _)) if v == "file" => ResponseFormat::File,
+            Some((v, _)) if v == "json" => ResponseFormat::Json,
+            Some((v, source)) => {
+                return Err(format!(
+                    "response {} must be file or json, not \"{}\"",
+                    source, v
+                ))
+            }
+        };
+
+        Ok(ConvertSettings {
+            record_type,
+            options,
+            response,
+            strict,
+            store,
+        })
+    }
+}
+
+fn parse_bool(name: &str, v: &str, source: Source) -> Result<bool, String> {
+    match v {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(format!(
+            "{} {} must be true or false, not \"{}\"",
+            name, source, v
+        )),
+    }
+}
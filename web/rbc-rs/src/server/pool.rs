@@ -0,0 +1,140 @@
+use actix_web::rt::time;
+use futures::channel::oneshot;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::problem::ApiError;
+
+pub const DEFAULT_CONVERSION_WORKERS: usize = 4;
+pub const DEFAULT_CONVERSION_QUEUE_SIZE: usize = 32;
+pub const DEFAULT_CONVERSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Seconds a client is asked to wait when every worker is busy
+const BUSY_RETRY_AFTER: u64 = 5;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Threads conversions are run on while their request waits, keeping the
+/// async workers free to answer other requests. At most `capacity`
+/// conversions wait for a thread, further ones are refused. A request gives
+/// up on its conversion once it has waited `timeout` for it.
+#[derive(Clone)]
+pub struct ConversionPool {
+    sender: SyncSender<Task>,
+    timeout: Duration,
+}
+
+impl ConversionPool {
+    pub fn new(workers: usize, capacity: usize, timeout: Duration) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            thread::spawn(move || work(&receiver));
+        }
+
+        Self { sender, timeout }
+    }
+
+    /// When a conversion submitted now is given up on
+    pub fn deadline(&self) -> Deadline {
+        Deadline {
+            at: Instant::now() + self.timeout,
+            limit: self.timeout,
+        }
+    }
+
+    /// Runs `f` on one of the pool's threads. If the request is dropped,
+    /// as when the client disconnects or the timeout passes, before a thread
+    /// gets to `f` it is not run at all. Once running `f` cannot be stopped,
+    /// its result is dropped along with any temporary files it holds.
+    pub async fn run<F, T>(&self, f: F) -> Result<T, ApiError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result, received) = oneshot::channel();
+
+        let task: Task = Box::new(move || {
+            if !result.is_canceled() {
+                let _ = result.send(f());
+            }
+        });
+
+        match self.sender.try_send(task) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                return Err(ApiError::Unavailable {
+                    detail: "Too many conversions are in progress, try again later".to_string(),
+                    retry_after: BUSY_RETRY_AFTER,
+                })
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                return Err(ApiError::Internal(
+                    "The conversion workers have stopped".to_string(),
+                ))
+            }
+        }
+
+        let received = time::timeout(self.timeout, received)
+            .await
+            .map_err(|_| timed_out(self.timeout))?;
+
+        // The sender is only dropped without a result if `f` panicked
+        received.map_err(|_| ApiError::Internal("The conversion failed unexpectedly".to_string()))
+    }
+}
+
+fn timed_out(limit: Duration) -> ApiError {
+    ApiError::Timeout {
+        limit: limit.as_secs(),
+    }
+}
+
+/// Time by which a request gives up on its conversion. Conversions made of
+/// several steps, such as those of archives, check it between steps so that
+/// an abandoned conversion stops early rather than running to completion.
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline {
+    at: Instant,
+    limit: Duration,
+}
+
+impl Deadline {
+    pub fn check(&self) -> Result<(), ApiError> {
+        if Instant::now() >= self.at {
+            Err(timed_out(self.limit))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for ConversionPool {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_CONVERSION_WORKERS,
+            DEFAULT_CONVERSION_QUEUE_SIZE,
+            DEFAULT_CONVERSION_TIMEOUT,
+        )
+    }
+}
+
+/// Runs tasks until every handle to the pool has been dropped. A panicking
+/// task fails its own request without taking the thread down with it.
+fn work(receiver: &Mutex<Receiver<Task>>) {
+    loop {
+        let task = receiver.lock().unwrap().recv();
+
+        match task {
+            Ok(task) => {
+                let _ = panic::catch_unwind(AssertUnwindSafe(task));
+            }
+            Err(_) => return,
+        }
+    }
+}
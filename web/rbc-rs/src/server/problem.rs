@@ -0,0 +1,290 @@
+use actix_web::http::header::{
+    HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE,
+};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use std::fmt::Display;
+use utoipa::ToSchema;
+
+use super::limits::PayloadTooLarge;
+use super::logging::ConversionOutcome;
+use super::sniff::BinaryFormat;
+use crate::lib::error::ErrorLog;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// An RFC 7807 problem document
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Problem {
+    /// Identifies the kind of problem, relative to the API, e.g.
+    /// `/problems/invalid-file`
+    #[serde(rename = "type")]
+    pub problem_type: &'static str,
+    pub title: &'static str,
+    /// HTTP status code of the response
+    pub status: u16,
+    pub detail: String,
+    /// Every problem found, such as each invalid row of a rejected file
+    pub errors: Vec<String>,
+    /// Largest size accepted, in bytes, for uploads that are too large, or
+    /// seconds a conversion may take, for conversions that timed out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    /// Size received, in bytes, for uploads that are too large
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub received: Option<u64>,
+    /// Seconds to wait before trying again, for rate limited requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+    /// What the upload looks like, for files that are not CSV
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detected: Option<&'static str>,
+}
+
+/// An error from one of the API endpoints, sent to the client as an RFC 7807
+/// `application/problem+json` document
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    /// The uploaded file could not be converted
+    Invalid(ErrorLog),
+    /// The upload is some other kind of file than CSV
+    NotCsv(BinaryFormat),
+    PayloadTooLarge(PayloadTooLarge),
+    Unauthorized(&'static str),
+    /// The request tries to change what the profile of its API key fixes
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    /// Existed once but is no longer available
+    Gone(String),
+    RateLimited {
+        retry_after: u64,
+    },
+    /// The server is too busy to accept the request now
+    Unavailable {
+        detail: String,
+        retry_after: u64,
+    },
+    /// The file to convert could not be fetched from where it was said to be
+    Fetch(String),
+    /// The conversion took longer than the limit of `limit` seconds
+    Timeout {
+        limit: u64,
+    },
+    Internal(String),
+}
+
+impl ApiError {
+    /// Identifies the kind of problem, relative to the API
+    fn problem_type(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "/problems/bad-request",
+            ApiError::Invalid(_) => "/problems/invalid-file",
+            ApiError::NotCsv(_) => "/problems/not-csv",
+            ApiError::PayloadTooLarge(_) => "/problems/payload-too-large",
+            ApiError::Unauthorized(_) => "/problems/unauthorized",
+            ApiError::Forbidden(_) => "/problems/forbidden",
+            ApiError::NotFound(_) => "/problems/not-found",
+            ApiError::Conflict(_) => "/problems/conflict",
+            ApiError::Gone(_) => "/problems/gone",
+            ApiError::RateLimited { .. } => "/problems/rate-limited",
+            ApiError::Unavailable { .. } => "/problems/unavailable",
+            ApiError::Fetch(_) => "/problems/fetch-failed",
+            ApiError::Timeout { .. } => "/problems/timeout",
+            ApiError::Internal(_) => "/problems/internal",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "Bad request",
+            ApiError::Invalid(_) => "File could not be converted",
+            ApiError::NotCsv(_) => "Not a CSV file",
+            ApiError::PayloadTooLarge(_) => "Upload too large",
+            ApiError::Unauthorized(_) => "Missing or invalid API key",
+            ApiError::Forbidden(_) => "Not allowed for this API key",
+            ApiError::NotFound(_) => "Not found",
+            ApiError::Conflict(_) => "Not ready",
+            ApiError::Gone(_) => "No longer available",
+            ApiError::RateLimited { .. } => "Too many requests",
+            ApiError::Unavailable { .. } => "Service unavailable",
+            ApiError::Fetch(_) => "Could not fetch the file",
+            ApiError::Timeout { .. } => "Conversion timed out",
+            ApiError::Internal(_) => "Internal server error",
+        }
+    }
+
+    fn detail(&self) -> String {
+        match self {
+            ApiError::Invalid(log) => {
+                let count = log.get_error_list().len();
+                format!(
+                    "The file has {} error{}",
+                    count,
+                    if count == 1 { "" } else { "s" }
+                )
+            }
+            ApiError::RateLimited { retry_after } => {
+                format!("Too many requests, retry in {} seconds", retry_after)
+            }
+            ApiError::Timeout { limit } => format!(
+                "The conversion did not finish within {} seconds, submit large files to /jobs to convert them in the background",
+                limit
+            ),
+            ApiError::PayloadTooLarge(e) => e.to_string(),
+            ApiError::NotCsv(format) => format.to_string(),
+            ApiError::Unauthorized(message) => message.to_string(),
+            ApiError::BadRequest(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Gone(message)
+            | ApiError::Fetch(message)
+            | ApiError::Internal(message)
+            | ApiError::Unavailable {
+                detail: message, ..
+            } => message.clone(),
+        }
+    }
+
+    fn errors(&self) -> Vec<String> {
+        match self {
+            ApiError::Invalid(log) => log.get_error_list(),
+            e => vec![e.detail()],
+        }
+    }
+
+    fn problem(&self) -> Problem {
+        let mut problem = Problem {
+            problem_type: self.problem_type(),
+            title: self.title(),
+            status: self.status_code().as_u16(),
+            detail: self.detail(),
+            errors: self.errors(),
+            limit: None,
+            received: None,
+            retry_after: None,
+            detected: None,
+        };
+
+        match self {
+            ApiError::PayloadTooLarge(e) => {
+                problem.limit = Some(e.limit as u64);
+                problem.received = Some(e.received as u64);
+            }
+            ApiError::RateLimited { retry_after } => {
+                problem.retry_after = Some(*retry_after);
+            }
+            ApiError::Timeout { limit } => {
+                problem.limit = Some(*limit);
+            }
+            ApiError::NotCsv(format) => {
+                problem.detected = Some(format.name());
+            }
+            _ => (),
+        }
+
+        problem
+    }
+
+    fn response(&self) -> actix_web::HttpResponseBuilder {
+        let mut builder = HttpResponse::build(self.status_code());
+
+        match self {
+            ApiError::Unauthorized(_) => {
+                builder.insert_header((WWW_AUTHENTICATE, "Bearer"));
+            }
+            ApiError::RateLimited { retry_after } | ApiError::Unavailable { retry_after, .. } => {
+                builder.insert_header((RETRY_AFTER, HeaderValue::from(*retry_after)));
+            }
+            _ => (),
+        }
+
+        builder
+    }
+
+    /// Responds in plain text to clients that ask for it, such as the
+    /// original UI, and as a problem document to everyone else
+    pub fn respond(&self, headers: &HeaderMap) -> HttpResponse {
+        if !prefers_plain_text(headers) {
+            return self.error_response();
+        }
+
+        let body = match self {
+            ApiError::Invalid(log) => log.to_string(),
+            e => e.detail(),
+        };
+
+        self.with_outcome(
+            self.response()
+                .insert_header((CONTENT_TYPE, "text/plain; charset=utf-8"))
+                .body(body),
+        )
+    }
+
+    /// Rejected files are counted in the request log
+    fn with_outcome(&self, resp: HttpResponse) -> HttpResponse {
+        match self {
+            ApiError::Invalid(log) => ConversionOutcome::rejected(log).attach(resp),
+            _ => resp,
+        }
+    }
+}
+
+/// Whether the client asked for `text/plain` rather than JSON
+fn prefers_plain_text(headers: &HeaderMap) -> bool {
+    let accept = match headers.get(ACCEPT).and_then(|v| v.to_str().ok()) {
+        Some(accept) => accept,
+        None => return false,
+    };
+
+    accept.contains("text/plain") && !accept.contains("json")
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.detail())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) | ApiError::Invalid(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotCsv(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Gone(_) => StatusCode::GONE,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Fetch(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.with_outcome(
+            self.response()
+                .insert_header((CONTENT_TYPE, PROBLEM_JSON))
+                .body(serde_json::to_string(&self.problem()).expect("problems serialize")),
+        )
+    }
+}
+
+impl From<PayloadTooLarge> for ApiError {
+    fn from(e: PayloadTooLarge) -> Self {
+        ApiError::PayloadTooLarge(e)
+    }
+}
+
+impl From<BinaryFormat> for ApiError {
+    fn from(format: BinaryFormat) -> Self {
+        ApiError::NotCsv(format)
+    }
+}
@@ -0,0 +1,171 @@
+use actix_web::http::header::HeaderMap;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::auth::{constant_time_eq, ApiKeys};
+use crate::csvconv::options::HeaderDefault;
+use crate::csvconv::schema::{
+    CLIENT_NAME, CLIENT_NUMBER, CURRENCY_CODE, PAYMENT_DATE, PROCESSING_CENTRE, TRANSACTION_CODE,
+};
+
+/// Conversion settings sent as query parameters or form fields that a
+/// profile may give
+pub const PROFILE_PARAMS: [&str; 2] = ["convtype", "display_name"];
+
+/// Labelled rows at the top of a CSV file that a profile may give values
+/// for, by their name in the profile
+pub const PROFILE_ROWS: [(&str, &str); 6] = [
+    ("client_name", CLIENT_NAME),
+    ("client_number", CLIENT_NUMBER),
+    ("processing_centre", PROCESSING_CENTRE),
+    ("currency_code", CURRENCY_CODE),
+    ("payment_date", PAYMENT_DATE),
+    ("transaction_code", TRANSACTION_CODE),
+];
+
+/// Settings applied to every conversion requested with one API key, so that
+/// several clients can share a server without sending their client number
+/// and the like with each file. Values the profile gives are fixed unless
+/// named in `overridable`, and requests giving a different value for a
+/// fixed one are refused.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// The key the profile applies to, which the server accepts
+    pub api_key: String,
+    pub convtype: Option<String>,
+    pub display_name: Option<String>,
+    pub client_name: Option<String>,
+    pub client_number: Option<String>,
+    pub processing_centre: Option<String>,
+    pub currency_code: Option<String>,
+    /// YYYY/MM/DD, as in a CSV file
+    pub payment_date: Option<String>,
+    pub transaction_code: Option<String>,
+    /// Names of the values above that requests may give values of their own
+    /// for
+    #[serde(default)]
+    pub overridable: Vec<String>,
+}
+
+impl Profile {
+    /// The values given for the rows of [`PROFILE_ROWS`], in the same order
+    fn row_values(&self) -> [&Option<String>; 6] {
+        [
+            &self.client_name,
+            &self.client_number,
+            &self.processing_centre,
+            &self.currency_code,
+            &self.payment_date,
+            &self.transaction_code,
+        ]
+    }
+
+    pub fn may_override(&self, name: &str) -> bool {
+        self.overridable.iter().any(|n| n == name)
+    }
+
+    /// The value given for the query parameter or form field `name`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        match name {
+            "convtype" => self.convtype.as_deref(),
+            "display_name" => self.display_name.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Values for the labelled rows at the top of the CSV files converted
+    /// with the profile, by label
+    pub fn header_defaults(&self) -> HashMap<&'static str, HeaderDefault> {
+        PROFILE_ROWS
+            .into_iter()
+            .zip(self.row_values())
+            .filter_map(|((name, label), value)| {
+                let default = HeaderDefault {
+                    value: value.as_ref()?.trim().to_string(),
+                    overridable: self.may_override(name),
+                };
+                Some((label, default))
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    #[serde(default, rename = "profile")]
+    profiles: Vec<Profile>,
+}
+
+/// Profiles by the API key they apply to
+#[derive(Clone, Debug, Default)]
+pub struct Profiles(Vec<Profile>);
+
+impl Profiles {
+    /// Reads profiles from a TOML file with a `[[profile]]` table for each
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let file: ProfileFile = toml::from_str(text).map_err(|e| e.to_string())?;
+
+        Self::new(file.profiles)
+    }
+
+    pub fn new(profiles: Vec<Profile>) -> Result<Self, String> {
+        let names: Vec<&str> = PROFILE_PARAMS
+            .into_iter()
+            .chain(PROFILE_ROWS.map(|(name, _)| name))
+            .collect();
+
+        for (i, profile) in profiles.iter().enumerate() {
+            if profile.api_key.trim().is_empty() {
+                return Err(format!("Profile {} has no api_key", i + 1));
+            }
+
+            if profiles[..i]
+                .iter()
+                .any(|p| p.api_key.trim() == profile.api_key.trim())
+            {
+                return Err(format!(
+                    "Profile {} has the same api_key as an earlier one",
+                    i + 1
+                ));
+            }
+
+            if let Some(name) = profile
+                .overridable
+                .iter()
+                .find(|n| !names.contains(&n.as_str()))
+            {
+                return Err(format!(
+                    "Profile {} lists {} as overridable, which is not one of {}",
+                    i + 1,
+                    name,
+                    names.join(", ")
+                ));
+            }
+        }
+
+        Ok(Self(profiles))
+    }
+
+    /// The keys of every profile, which the server must accept
+    pub fn keys(&self) -> impl Iterator<Item = String> + '_ {
+        self.0.iter().map(|p| p.api_key.trim().to_string())
+    }
+
+    /// The profile of the API key a request presents, if it has one
+    pub fn for_request(&self, headers: &HeaderMap) -> Option<&Profile> {
+        let key = ApiKeys::presented(headers)?;
+
+        self.0
+            .iter()
+            .find(|p| constant_time_eq(p.api_key.trim(), key))
+    }
+}
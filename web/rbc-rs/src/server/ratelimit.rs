@@ -0,0 +1,147 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use super::auth::ApiKeys;
+use super::problem::ApiError;
+use super::ServerConfig;
+
+pub const DEFAULT_REQUESTS_PER_MINUTE: u32 = 60;
+pub const DEFAULT_BURST: u32 = 20;
+
+/// Buckets idle long enough to have refilled are dropped once this many
+/// clients are being tracked
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket rate limiter keyed by client. Clones share the same buckets,
+/// so one limiter can be handed to every worker.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    /// Tokens added per second and the bucket capacity, `None` when disabled
+    rate: Option<(f64, f64)>,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    /// A limiter allowing `requests_per_minute` on average with bursts of up to
+    /// `burst` requests. A rate of zero disables limiting.
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        Self {
+            rate: (requests_per_minute > 0)
+                .then(|| (requests_per_minute as f64 / 60.0, burst.max(1) as f64)),
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Takes a token from `client`'s bucket, or returns the number of seconds
+    /// until one is available.
+    pub fn acquire(&self, client: &str) -> Result<(), u64> {
+        let (per_second, capacity) = match self.rate {
+            Some(rate) => rate,
+            None => return Ok(()),
+        };
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * per_second < capacity
+            });
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / per_second).ceil().max(1.0) as u64)
+        }
+    }
+}
+
+/// Requests are counted against their API key when authentication is enabled,
+/// otherwise against the connecting address.
+fn client_key(req: &ServiceRequest, api_keys: &ApiKeys) -> String {
+    if api_keys.is_enabled() {
+        if let Some(key) = ApiKeys::presented(req.headers()) {
+            return format!("key:{}", key);
+        }
+    }
+
+    match req.peer_addr() {
+        Some(addr) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/// Middleware answering clients over their rate limit with a 429
+pub struct RateLimit;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let result = match req.app_data::<web::Data<ServerConfig>>() {
+            Some(config) => config
+                .rate_limiter
+                .acquire(&client_key(&req, &config.api_keys)),
+            None => Ok(()),
+        };
+
+        if let Err(retry_after) = result {
+            let resp = ApiError::RateLimited { retry_after }.respond(req.headers());
+
+            return Box::pin(ready(Ok(req.into_response(resp).map_into_right_body())));
+        }
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move { fut.await.map(ServiceResponse::map_into_left_body) })
+    }
+}
@@ -0,0 +1,16 @@
+use actix_web::{get, HttpResponse};
+
+use crate::csvconv::options::AmountFormat;
+use crate::csvconv::schema::{schema as csv_schema, Schema};
+
+/// Describes the CSV files `/convert` accepts
+///
+/// Built from the same rules the converter applies, so it is always current.
+#[utoipa::path(
+    tag = "server",
+    responses((status = 200, description = "What CSV files must look like", body = Schema)),
+)]
+#[get("/schema")]
+async fn schema() -> HttpResponse {
+    HttpResponse::Ok().json(csv_schema(&AmountFormat::default()))
+}
@@ -0,0 +1,93 @@
+use actix_web::dev::{Extensions, ServerHandle};
+use actix_web::rt::{signal, time::sleep};
+use futures::future::select;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
+
+/// Counts open client connections, so that shutdown can wait for them to
+/// close. Register [`Connections::track`] with `HttpServer::on_connect`.
+#[derive(Clone, Default)]
+pub struct Connections(Arc<AtomicUsize>);
+
+/// Stored in a connection's extensions, and so dropped along with it
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Connections {
+    pub fn track(&self, ext: &mut Extensions) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        ext.insert(ConnectionGuard(self.0.clone()));
+    }
+
+    pub fn open(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Resolves on SIGINT or, on Unix, SIGTERM
+async fn termination_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Could not listen for SIGTERM: {}", e);
+                let _ = signal::ctrl_c().await;
+                return;
+            }
+        };
+
+        let interrupt = Box::pin(signal::ctrl_c());
+        let _ = select(interrupt, Box::pin(terminate.recv())).await;
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = signal::ctrl_c().await;
+    }
+}
+
+/// Stops the server once a termination signal arrives. New connections are
+/// no longer accepted, and open ones are given `grace` to finish before the
+/// server stops. A second signal exits immediately.
+///
+/// The server must be built with `disable_signals()` so that its own handlers,
+/// which stop without draining on SIGINT, do not race this one.
+pub async fn stop_on_signal(handle: ServerHandle, connections: Connections, grace: Duration) {
+    termination_signal().await;
+    eprintln!("Shutting down, waiting for in-flight requests to finish. Signal again to exit immediately.");
+
+    actix_web::rt::spawn(async {
+        termination_signal().await;
+        eprintln!("Exiting without waiting for in-flight requests");
+        std::process::exit(1);
+    });
+
+    // Connections are drained here rather than by a graceful `stop`, since
+    // a worker can exit as soon as the acceptor stops, dropping whatever it
+    // was still serving.
+    handle.pause().await;
+
+    let deadline = Instant::now() + grace;
+    while connections.open() > 0 && Instant::now() < deadline {
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    if connections.open() > 0 {
+        eprintln!(
+            "Closing {} connections still open after {} seconds",
+            connections.open(),
+            grace.as_secs()
+        );
+    }
+
+    handle.stop(false).await;
+}
@@ -0,0 +1,204 @@
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, ResponseError};
+
+use super::audit::actor_presenting;
+use super::auth::ApiKeys;
+use super::convert::{convert_upload, receive_upload, ConvertError, CONTENT_SHA256};
+use super::filename::{attachment, download_name};
+use super::logging::ConversionOutcome;
+use super::params::{ConvertParams, ConvertSettings};
+use super::problem::ApiError;
+use super::ratelimit::RateLimit;
+use super::ServerConfig;
+use crate::lib::error::{ErrorLog, LogEntry};
+
+/// Form field carrying the API key, as a plain form cannot send headers
+pub const API_KEY_FIELD: &str = "api_key";
+
+/// Asks for the API key in the form posted to `/simple` when keys are
+/// configured
+const API_KEY_INPUT: &str = r#"<p><label>API key <input type="password" name="api_key" autocomplete="current-password" required></label></p>
+"#;
+
+/// Form posted to `/simple`, usable without scripts, following the API key
+/// input if there is one
+const FORM: &str = r#"<p><label>CSV file <input type="file" name="file" accept=".csv,text/csv" required></label></p>
+<p><label>File type <select name="convtype">
+<option value="PDS">PDS (credits, direct deposits)</option>
+<option value="PAD">PAD (debits, direct payments)</option>
+</select></label></p>
+<p><button type="submit">Convert</button></p>
+</form>"#;
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:50em;margin:2em auto;padding:0 1em}\
+table{border-collapse:collapse}th,td{border:1px solid #999;padding:.3em .6em;text-align:left}";
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// A whole page titled `title`, with `body` already escaped
+fn page(status: StatusCode, title: &str, body: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<style>{style}</style>\n</head>\n<body>\n\
+             <h1>{title}</h1>\n{body}\n</body>\n</html>\n",
+            title = escape(title),
+            style = STYLE,
+            body = body
+        ))
+}
+
+fn rows(entries: &[LogEntry], severity: &str) -> String {
+    entries
+        .iter()
+        .map(|e| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                e.line.map(|l| l.to_string()).unwrap_or_default(),
+                severity,
+                escape(&e.message)
+            )
+        })
+        .collect()
+}
+
+/// Lists every error and warning of a rejected file, by the row of the CSV
+/// file it was found on
+fn rejected(log: &ErrorLog) -> HttpResponse {
+    let count = log.errors().len();
+
+    let body = format!(
+        "<p>The file has {} error{}. Correct the rows listed below and convert it again.</p>\n\
+         <table>\n<thead><tr><th>Row</th><th>Severity</th><th>Message</th></tr></thead>\n\
+         <tbody>\n{}{}</tbody>\n</table>\n<p><a href=\"/simple\">Convert another file</a></p>",
+        count,
+        if count == 1 { "" } else { "s" },
+        rows(log.errors(), "Error"),
+        rows(log.warnings(), "Warning"),
+    );
+
+    ConversionOutcome::rejected(log).attach(page(
+        StatusCode::BAD_REQUEST,
+        "The file could not be converted",
+        &body,
+    ))
+}
+
+fn failed(e: &ApiError) -> HttpResponse {
+    if let ApiError::Invalid(log) = e {
+        return rejected(log);
+    }
+
+    page(
+        e.status_code(),
+        "The file could not be converted",
+        &format!(
+            "<p>{}</p>\n<p><a href=\"/simple\">Try again</a></p>",
+            escape(&e.to_string())
+        ),
+    )
+}
+
+/// A plain form converting a file, for browsers that do not run the UI's
+/// scripts
+#[get("/simple")]
+async fn form(config: web::Data<ServerConfig>) -> HttpResponse {
+    let api_key = if config.api_keys.is_enabled() {
+        API_KEY_INPUT
+    } else {
+        ""
+    };
+
+    page(
+        StatusCode::OK,
+        "Convert a CSV file to CPA-005",
+        &format!(
+            "<form method=\"post\" action=\"/simple\" enctype=\"multipart/form-data\">\n{}{}",
+            api_key, FORM
+        ),
+    )
+}
+
+/// Converts the file posted by the form, answering with the converted file
+/// or a page listing what is wrong with it. The API key, when keys are
+/// configured, may be sent in the `api_key` field of the form as well as in
+/// the headers `/convert` takes it in.
+#[post("/simple", wrap = "RateLimit")]
+async fn convert(
+    req: HttpRequest,
+    payload: web::Payload,
+    q: web::Query<ConvertParams>,
+    config: web::Data<ServerConfig>,
+) -> HttpResponse {
+    let upload = match receive_upload(&req, payload, &config).await {
+        Ok(upload) => upload,
+        Err(e) => return failed(&e),
+    };
+
+    let key = ApiKeys::presented(req.headers())
+        .or_else(|| upload.fields.get(API_KEY_FIELD).map(|key| key.trim()));
+
+    if config.api_keys.is_enabled() {
+        let unauthorized = match key {
+            None => Some("Enter the API key to convert files"),
+            Some(key) if !config.api_keys.accepts(key) => Some("Invalid API key"),
+            Some(_) => None,
+        };
+
+        if let Some(message) = unauthorized {
+            return failed(&ApiError::Unauthorized(message));
+        }
+    }
+
+    let ConvertSettings {
+        record_type,
+        options,
+        ..
+    } = match q.resolve(&upload.fields, config.profiles.for_request(req.headers())) {
+        Ok(settings) => settings,
+        Err(e) => return failed(&e),
+    };
+
+    let file_name = upload.file_name.clone();
+    let audit = config.audit.clone();
+    let actor = actor_presenting(&req, &config.api_keys, key);
+
+    let conversion = config
+        .conversions
+        .run(move || convert_upload(upload, record_type, &options, audit.as_ref(), actor))
+        .await;
+
+    match conversion {
+        Ok(Ok(c)) => {
+            let name = download_name(file_name.as_deref(), c.report.file_creation_number);
+
+            ConversionOutcome::converted(&c.report, &c.log).attach(
+                HttpResponse::Ok()
+                    .content_type(ContentType::plaintext())
+                    .insert_header(attachment(&name))
+                    .insert_header((CONTENT_SHA256, c.report.output_sha256))
+                    .body(c.output),
+            )
+        }
+        Ok(Err(ConvertError::Invalid(log))) => rejected(&log),
+        Ok(Err(e)) => failed(&e.into()),
+        Err(e) => failed(&e),
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt::Display;
+
+/// Bytes at the start of an upload looked at to tell what kind of file it is
+const SNIFF_LEN: usize = 512;
+
+/// A kind of file sometimes uploaded in place of a CSV export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryFormat {
+    Workbook,
+    Pdf,
+    Zip,
+    Image,
+}
+
+const SIGNATURES: [(&[u8], BinaryFormat); 6] = [
+    (b"PK\x03\x04", BinaryFormat::Zip),
+    // An archive with nothing in it
+    (b"PK\x05\x06", BinaryFormat::Zip),
+    // Compound files, the format of .xls workbooks
+    (b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", BinaryFormat::Workbook),
+    (b"%PDF-", BinaryFormat::Pdf),
+    (b"\x89PNG\r\n\x1a\n", BinaryFormat::Image),
+    (b"\xff\xd8\xff", BinaryFormat::Image),
+];
+
+/// Names of the parts of an .xlsx workbook, one of which comes first in
+/// the archive
+const WORKBOOK_PARTS: [&[u8]; 2] = [b"[Content_Types].xml", b"xl/"];
+
+/// Content types that are never a CSV file. `application/vnd.ms-excel` is
+/// left out, as Windows declares CSV files with it.
+const CONTENT_TYPES: [(&str, BinaryFormat); 6] = [
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        BinaryFormat::Workbook,
+    ),
+    ("application/pdf", BinaryFormat::Pdf),
+    ("application/zip", BinaryFormat::Zip),
+    ("application/x-zip-compressed", BinaryFormat::Zip),
+    ("image/png", BinaryFormat::Image),
+    ("image/jpeg", BinaryFormat::Image),
+];
+
+impl BinaryFormat {
+    /// Identifies the format in problem documents
+    pub fn name(&self) -> &'static str {
+        match self {
+            BinaryFormat::Workbook => "workbook",
+            BinaryFormat::Pdf => "pdf",
+            BinaryFormat::Zip => "zip",
+            BinaryFormat::Image => "image",
+        }
+    }
+
+    /// Tells from the start of a file whether it is one of the formats.
+    /// Text, including text starting with a byte order mark, never is.
+    pub fn sniff(head: &[u8]) -> Option<Self> {
+        let format = SIGNATURES
+            .iter()
+            .find(|(signature, _)| head.starts_with(signature))
+            .map(|(_, format)| *format)?;
+
+        if format == BinaryFormat::Zip
+            && WORKBOOK_PARTS
+                .iter()
+                .any(|part| head.windows(part.len()).any(|w| w == *part))
+        {
+            return Some(BinaryFormat::Workbook);
+        }
+
+        Some(format)
+    }
+
+    /// The format a declared content type is of, if it is one of them
+    pub fn declared(content_type: &str) -> Option<Self> {
+        CONTENT_TYPES
+            .iter()
+            .find(|(declared, _)| declared.eq_ignore_ascii_case(content_type))
+            .map(|(_, format)| *format)
+    }
+}
+
+impl Display for BinaryFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinaryFormat::Workbook => write!(
+                f,
+                "This looks like an Excel workbook, export it as CSV and upload that instead"
+            ),
+            BinaryFormat::Pdf => write!(
+                f,
+                "This looks like a PDF document, upload the payments exported as CSV instead"
+            ),
+            BinaryFormat::Zip => write!(
+                f,
+                "This looks like a zip archive, upload archives of CSV files to /convert/zip"
+            ),
+            BinaryFormat::Image => write!(
+                f,
+                "This looks like an image, upload the payments exported as CSV instead"
+            ),
+        }
+    }
+}
+
+/// Collects the start of an upload as it arrives, to be sniffed once enough
+/// of it has arrived
+#[derive(Debug, Default)]
+pub struct Sniffer {
+    head: Vec<u8>,
+    done: bool,
+}
+
+impl Sniffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), BinaryFormat> {
+        if self.done {
+            return Ok(());
+        }
+
+        let wanted = SNIFF_LEN - self.head.len();
+        self.head
+            .extend_from_slice(&chunk[..chunk.len().min(wanted)]);
+
+        if self.head.len() == SNIFF_LEN {
+            self.finish()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sniffs what has arrived so far, for uploads shorter than what is
+    /// looked at or that have already turned out not to be text
+    pub fn finish(&mut self) -> Result<(), BinaryFormat> {
+        if self.done {
+            return Ok(());
+        }
+
+        self.done = true;
+
+        match BinaryFormat::sniff(&self.head) {
+            Some(format) => Err(format),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{get, web, HttpResponse};
+use rust_embed::RustEmbed;
+use std::borrow::Cow;
+use std::path::{Component, Path};
+
+use super::ServerConfig;
+
+/// The built UI, embedded into the executable at compile time
+#[derive(RustEmbed)]
+#[folder = "../ui/dist"]
+struct Bundle;
+
+/// Whether the file name carries a content hash, e.g. `main.3f2a9c1b.js`, in
+/// which case its contents never change and it may be cached indefinitely.
+fn is_hashed(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let mut parts = name.split('.').rev().skip(1);
+
+    match parts.next() {
+        Some(hash) => hash.len() >= 8 && hash.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+fn is_safe(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn load(config: &ServerConfig, path: &str) -> Option<Cow<'static, [u8]>> {
+    match &config.ui_dir {
+        Some(dir) => std::fs::read(dir.join(path)).ok().map(Cow::Owned),
+        None => Bundle::get(path).map(|f| f.data),
+    }
+}
+
+#[get("/{path:.*}")]
+async fn asset(path: web::Path<String>, config: web::Data<ServerConfig>) -> HttpResponse {
+    let path = match path.as_str() {
+        "" => "index.html",
+        p => p,
+    };
+
+    if !is_safe(path) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let data = match load(&config, path) {
+        Some(data) => data,
+        None => return HttpResponse::NotFound().finish(),
+    };
+
+    let cache = if is_hashed(path) {
+        CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(31536000),
+            CacheDirective::Extension("immutable".to_string(), None),
+        ])
+    } else {
+        CacheControl(vec![CacheDirective::NoCache])
+    };
+
+    HttpResponse::Ok()
+        .content_type(ContentType(
+            mime_guess::from_path(path).first_or_octet_stream(),
+        ))
+        .insert_header(cache)
+        .body(data.into_owned())
+}
@@ -0,0 +1,77 @@
+use std::fmt::Display;
+
+/// Checks that an upload is valid UTF-8 as it arrives. A multi-byte character
+/// split across two chunks is carried over and checked once the rest of it
+/// arrives, rather than being reported as invalid.
+#[derive(Debug, Default)]
+pub struct Utf8Validator {
+    pending: Vec<u8>,
+    offset: usize,
+}
+
+impl Utf8Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), InvalidUtf8> {
+        if self.pending.is_empty() {
+            return self.check(chunk);
+        }
+
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(chunk);
+
+        self.check(&bytes)
+    }
+
+    /// Fails if the upload ended part way through a character
+    pub fn finish(self) -> Result<(), InvalidUtf8> {
+        if self.pending.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidUtf8 {
+                offset: self.offset,
+            })
+        }
+    }
+
+    fn check(&mut self, bytes: &[u8]) -> Result<(), InvalidUtf8> {
+        match std::str::from_utf8(bytes) {
+            Ok(_) => {
+                self.offset += bytes.len();
+                Ok(())
+            }
+            Err(e) => {
+                let valid = e.valid_up_to();
+
+                if e.error_len().is_some() {
+                    return Err(InvalidUtf8 {
+                        offset: self.offset + valid,
+                    });
+                }
+
+                // The chunk ends with the start of an incomplete character
+                self.offset += valid;
+                self.pending = bytes[valid..].to_vec();
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidUtf8 {
+    pub offset: usize,
+}
+
+impl Display for InvalidUtf8 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Uploaded file is not valid UTF-8 text, invalid byte at offset {}. Save the spreadsheet as \"CSV UTF-8\" and try again.",
+            self.offset
+        )
+    }
+}
@@ -0,0 +1,220 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn fixture(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn json_format_reports_each_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let bad = dir.path().join("bad.csv");
+    std::fs::write(&bad, "Client Name,Acme\n").unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_cli"))
+        .args(["--type", "PDS", "--format", "json", "--output-dir"])
+        .arg(dir.path())
+        .arg(fixture("payroll.csv"))
+        .arg(&bad)
+        .output()
+        .unwrap();
+
+    assert!(!out.status.success());
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let results: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(results.len(), 2);
+
+    for result in &results {
+        for key in ["input", "output", "ok", "summary", "errors", "warnings"] {
+            assert!(result.get(key).is_some(), "missing key {}", key);
+        }
+    }
+
+    let good = &results[0];
+    assert_eq!(good["ok"], true);
+    assert_eq!(good["summary"]["total_credit_count"], 3);
+    assert!(dir.path().join("payroll.txt").exists());
+
+    let bad = &results[1];
+    assert_eq!(bad["ok"], false);
+    assert!(bad["output"].is_null());
+    assert!(!bad["errors"].as_array().unwrap().is_empty());
+}
+
+fn pipe(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cli"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn stdin_converted_to_stdout() {
+    let out = pipe(
+        &["--type", "PDS", "--creation-date", "2045-01-15", "-"],
+        include_bytes!("fixtures/payroll.csv"),
+    );
+
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        include_str!("fixtures/payroll.pds.txt")
+    );
+}
+
+#[test]
+fn stdin_conversion_failure_exits_non_zero() {
+    let out = pipe(&["--type", "PDS", "-"], b"Client Name,Acme\n");
+
+    assert!(!out.status.success());
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8(out.stderr)
+        .unwrap()
+        .contains("<stdin>: error:"));
+}
+
+#[test]
+fn output_named_after_template() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_cli"))
+        .args(["--type", "PDS", "--output-name", "ACH_{stem}_{seq}.txt"])
+        .arg("--output-dir")
+        .arg(dir.path())
+        .arg(fixture("payroll.csv"))
+        .output()
+        .unwrap();
+
+    assert!(out.status.success());
+    assert!(dir.path().join("ACH_payroll_0001.txt").exists());
+}
+
+#[test]
+fn unknown_output_name_placeholder_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_cli"))
+        .args(["--type", "PDS", "--output-name", "{name}.txt"])
+        .arg("--output-dir")
+        .arg(dir.path())
+        .arg(fixture("payroll.csv"))
+        .output()
+        .unwrap();
+
+    assert!(!out.status.success());
+    assert!(String::from_utf8(out.stderr).unwrap().contains("{name}"));
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn summary_written_for_batch() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_cli"))
+        .args(["--type", "PDS", "--output-dir"])
+        .arg(dir.path())
+        .arg("--summary-dir")
+        .arg(dir.path())
+        .arg(fixture("payroll.csv"))
+        .output()
+        .unwrap();
+
+    assert!(out.status.success());
+
+    let summary = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.extension().is_some_and(|e| e == "txt") && p != &dir.path().join("payroll.txt"))
+        .unwrap();
+    let name = summary.file_name().unwrap().to_string_lossy().to_string();
+    assert!(name.starts_with("conversion_summary_"));
+    assert!(summary.with_extension("json").exists());
+
+    let text = std::fs::read_to_string(&summary).unwrap();
+    assert!(text.contains("Files: 1 converted, 0 failed\n"));
+    assert!(text.contains("Credits: 3 items, "));
+    assert!(String::from_utf8(out.stdout)
+        .unwrap()
+        .contains(&format!("summary -> {}", summary.display())));
+}
+
+#[test]
+fn payments_to_unlisted_transits_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let transits = dir.path().join("transits.csv");
+    std::fs::write(&transits, "003,01234\n001,00456\n").unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_cli"))
+        .args(["--type", "PDS", "--output-dir"])
+        .arg(dir.path())
+        .arg("--transits")
+        .arg(&transits)
+        .arg(fixture("payroll.csv"))
+        .output()
+        .unwrap();
+
+    assert!(!out.status.success());
+    assert!(String::from_utf8(out.stderr).unwrap().contains(
+        "error: Branch 00123 of institution 0004 for customer 1002 is not in the list of valid transits"
+    ));
+    assert!(!dir.path().join("payroll.txt").exists());
+}
+
+#[test]
+fn files_converted_at_once_reported_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let payroll = std::fs::read_to_string(fixture("payroll.csv")).unwrap();
+    let inputs: Vec<_> = (0..6)
+        .map(|i| {
+            let input = dir.path().join(format!("payroll{}.csv", i));
+            std::fs::write(&input, &payroll).unwrap();
+            input
+        })
+        .collect();
+
+    let convert = |jobs: &str| {
+        let output_dir = dir.path().join(format!("jobs{}", jobs));
+        std::fs::create_dir(&output_dir).unwrap();
+
+        let out = Command::new(env!("CARGO_BIN_EXE_cli"))
+            .args(["--type", "PDS", "--format", "json", "--jobs", jobs])
+            .args(["--creation-date", "2045-01-15", "--output-dir"])
+            .arg(&output_dir)
+            .args(&inputs)
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        let reported: Vec<String> = stdout
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["input"].to_string())
+            .collect();
+        let expected: Vec<String> = inputs
+            .iter()
+            .map(|input| serde_json::Value::from(input.to_str().unwrap()).to_string())
+            .collect();
+        assert_eq!(reported, expected);
+
+        (0..inputs.len())
+            .map(|i| std::fs::read(output_dir.join(format!("payroll{}.txt", i))).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(convert("4"), convert("1"));
+}
@@ -0,0 +1,86 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+use csvconv::coverage::check_split_coverage;
+use csvconv::csv::convert_to_cpa005;
+use csvconv::options::AmountFormat;
+use lib::types::RecordType;
+
+const HEADER: &str = "\
+Client Name,Acme Widgets Ltd,,,,,,,
+Client Number,0123456789,,,,,,,
+Processing Centre,00320,,,,,,,
+Currency Code,CAD,,,,,,,
+Payment Date,2045/02/01,,,,,,,
+Transaction Code,200,,,,,,,
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+";
+
+/// Rows of a master spreadsheet, the first two paid out and the last two
+/// collected, and one left out of both
+const ROWS: [&str; 5] = [
+    "1001,Jane Smith,003,01234,1234567,$100.00",
+    "1002,John Doe,004,00123,987654321,$250.00",
+    "1003,Ann Lee,003,01234,7654321,$75.50",
+    "1004,Raj Patel,003,00456,55501,\"$1,200.00\"",
+    "1005,Old Account,003,00456,55502,$10.00",
+];
+
+/// The spreadsheet with each row suspended unless `included` says otherwise
+fn csv(included: impl Fn(usize) -> bool) -> String {
+    let rows: String = ROWS
+        .iter()
+        .enumerate()
+        .map(|(i, row)| format!("{},{},,\n", row, if included(i) { "N" } else { "Y" }))
+        .collect();
+
+    format!("{}{}", HEADER, rows)
+}
+
+fn convert(included: impl Fn(usize) -> bool, record_type: RecordType) -> String {
+    convert_to_cpa005(csv(included), record_type).unwrap()
+}
+
+#[test]
+fn mixed_spreadsheet_split_covers_every_row_once() {
+    let master = csv(|i| i < 4);
+    let credits = convert(|i| i < 2, RecordType::Credit);
+    let debits = convert(|i| i == 2 || i == 3, RecordType::Debit);
+
+    let coverage =
+        check_split_coverage(&master, &credits, &debits, &AmountFormat::default()).unwrap();
+    assert!(coverage.is_complete(), "{:?}", coverage);
+
+    // Row 1002 in both files and 1004 in neither
+    let debits = convert(|i| i == 1 || i == 2, RecordType::Debit);
+    let coverage =
+        check_split_coverage(&master, &credits, &debits, &AmountFormat::default()).unwrap();
+
+    assert!(!coverage.is_complete());
+    let customers = |rows: &[csvconv::csv::PaymentRow]| -> Vec<(u64, String)> {
+        rows.iter()
+            .map(|r| (r.line, r.customer_number.clone()))
+            .collect()
+    };
+    assert_eq!(customers(&coverage.in_both), [(9, "1002".to_string())]);
+    assert_eq!(customers(&coverage.missing), [(11, "1004".to_string())]);
+}
+
+#[test]
+fn unreadable_converted_file_reported() {
+    let master = csv(|_| true);
+    let credits = convert(|_| true, RecordType::Credit);
+
+    let errors = check_split_coverage(&master, &credits, "not a file", &AmountFormat::default())
+        .unwrap_err();
+    assert!(
+        errors.iter().all(|e| e.starts_with("Debit file: ")),
+        "{:?}",
+        errors
+    );
+}
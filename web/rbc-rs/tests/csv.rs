@@ -0,0 +1,929 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+use csvconv::csv::{
+    convert_reader_split, convert_reader_with_hook, convert_reader_with_progress,
+    convert_reader_with_report, convert_to_cpa005, convert_to_cpa005_with_options,
+    convert_to_record, parse_dollar_amount_to_cents, parse_dollar_amount_with_direction,
+    AmountError, PROGRESS_INTERVAL,
+};
+use csvconv::options::{AmountFormat, BlankAmount, ConversionOptions};
+use lib::lint::{lint_cpa005, Severity};
+use lib::parse::{parse_cpa005, ParsedTrailer};
+use lib::payment::{BasicPaymentSegment, PRENOTE_TRANSACTION_CODE};
+use lib::types::{RecordType, SortOrder};
+use std::cell::RefCell;
+
+const HEADER: &str = "\
+Client Name,Acme Widgets Ltd,,,,,,,
+Client Number,0123456789,,,,,,,
+Processing Centre,00320,,,,,,,
+Currency Code,CAD,,,,,,,
+Payment Date,2045/02/01,,,,,,,
+Transaction Code,200,,,,,,,
+Customer Number,Customer Name,Bank,Branch,Account,Amount,Suspend,Todo,Total
+";
+
+// Field 12 of a detail record's first segment
+const SHORT_NAME_FIELD: std::ops::Range<usize> = 89..104;
+
+const ROWS: &str = "\
+1001,Jane Smith,003,01234,1234567,$100.00,N,,
+1002,John Doe,004,00123,987654321,$250.00,N,,
+";
+
+fn csv(rows: &str) -> String {
+    format!("{}{}", HEADER, rows)
+}
+
+#[test]
+fn suspended_rows_do_not_affect_numbering_or_totals() {
+    let output = convert_to_cpa005(
+        csv("1001,Jane Smith,003,01234,1234567,$100.00,N,,\n\
+             1002,John Doe,004,00123,987654321,$250.00,Y,,\n\
+             1003,Mary Major,001,00456,55512345,$75.25,N,,\n"),
+        RecordType::Credit,
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    let records: Vec<&str> = output.lines().collect();
+    assert_eq!(records.len(), 4);
+
+    assert_eq!(&records[1][0..10], "C000000002");
+    assert!(records[1].contains("Jane Smith"));
+    assert_eq!(&records[2][0..10], "C000000003");
+    assert!(records[2].contains("Mary Major"));
+
+    let trailer = records[3];
+    assert_eq!(&trailer[0..10], "Z000000004");
+    // Total amount and number of credit payments
+    assert_eq!(&trailer[46..60], "00000000017525");
+    assert_eq!(&trailer[60..68], "00000002");
+}
+
+#[test]
+fn converted_record_totals_match_trailer() {
+    let input = csv(&format!(
+        "{}1003,Mary Major,001,00456,55512345,$75.25,Y,,\n",
+        ROWS
+    ));
+    let record = convert_to_record(
+        input.clone(),
+        RecordType::Credit,
+        &ConversionOptions::default(),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    // The suspended row is left out
+    assert_eq!(record.basic_payment.len(), 2);
+    assert_eq!(
+        record.basic_payment[1].segments[0].customer_name,
+        "John Doe"
+    );
+    assert_eq!(record.total_credit_amount, 35000);
+
+    let built = record.build();
+    let trailer = parse_cpa005(&built)
+        .unwrap_or_else(|e| panic!("{:?}", e))
+        .trailer;
+    assert_eq!(
+        trailer,
+        ParsedTrailer {
+            total_debit_amount: record.total_debit_amount,
+            total_debit_count: record.total_debit_count,
+            total_credit_amount: record.total_credit_amount,
+            total_credit_count: record.total_credit_count,
+        }
+    );
+
+    assert_eq!(built, convert_to_cpa005(input, RecordType::Credit).unwrap());
+    // The warnings raised converting, each kept once
+    assert_eq!(
+        record.error_log.get_warning_list(),
+        ["Client short name \"Acme Widgets Lt\" was derived by truncating the client name to 15 characters; dropped \"d\""]
+    );
+}
+
+#[test]
+fn short_name_defaults_to_client_name() {
+    let output =
+        convert_to_cpa005(csv(ROWS), RecordType::Credit).unwrap_or_else(|e| panic!("{}", e));
+
+    for detail in output.lines().skip(1).take(2) {
+        assert_eq!(&detail[SHORT_NAME_FIELD], "Acme Widgets Lt");
+    }
+}
+
+#[test]
+fn short_name_truncated_by_character_with_warning() {
+    let input = csv(ROWS).replace("Acme Widgets Ltd", "Société Générale Québec");
+
+    let conversion = convert_reader_with_report(
+        input.as_bytes(),
+        RecordType::Credit,
+        &ConversionOptions::default(),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    // Accented characters take two bytes, so the field is located by character
+    let detail = conversion.output.lines().nth(1).unwrap();
+    let short_name: String = detail
+        .chars()
+        .skip(SHORT_NAME_FIELD.start)
+        .take(SHORT_NAME_FIELD.len())
+        .collect();
+    assert_eq!(short_name, "Société Général");
+
+    let warnings = conversion.log.get_warning_list();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("truncating the client name"));
+    assert!(warnings[0].ends_with("dropped \"e Québec\""));
+}
+
+#[test]
+fn accented_customer_name_truncated_on_request() {
+    let rows = "1001,Société Générale Investissements Ltée,003,01234,1234567,$100.00,N,,\n";
+    let options = ConversionOptions {
+        truncate_names: true,
+        ..Default::default()
+    };
+
+    let conversion = convert_reader_with_report(csv(rows).as_bytes(), RecordType::Credit, &options)
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    assert!(conversion.output.contains("Société Générale Investissemen"));
+    assert!(conversion
+        .log
+        .get_warning_list()
+        .contains(&"customer_name truncated to 30 characters; dropped \"ts Ltée\"".to_string()));
+
+    // Thirty characters, more bytes
+    let rows = "1001,Société Générale Investissemen,003,01234,1234567,$100.00,N,,\n";
+    assert!(convert_reader_with_report(
+        csv(rows).as_bytes(),
+        RecordType::Credit,
+        &ConversionOptions::default()
+    )
+    .is_ok());
+}
+
+#[test]
+fn long_customer_name_truncated_on_request() {
+    let rows = "1001,Jane Smith-Richardson Holdings Ltd,003,01234,1234567,$100.00,N,,\n";
+
+    let log = convert_reader_with_report(
+        csv(rows).as_bytes(),
+        RecordType::Credit,
+        &ConversionOptions::default(),
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        log.get_error_list(),
+        ["Customer Name must not exceed 30 characters"]
+    );
+
+    let options = ConversionOptions {
+        truncate_names: true,
+        ..Default::default()
+    };
+    let conversion = convert_reader_with_report(csv(rows).as_bytes(), RecordType::Credit, &options)
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    assert!(conversion.output.contains("Jane Smith-Richardson Holdings"));
+    let warning = conversion
+        .log
+        .warnings()
+        .iter()
+        .find(|w| w.message.starts_with("customer_name"))
+        .unwrap();
+    assert_eq!(
+        warning.message,
+        "customer_name truncated to 30 characters; dropped \" Ltd\""
+    );
+    assert_eq!(warning.line, Some(8));
+}
+
+#[test]
+fn names_set_by_segment_hook_truncated_on_request() {
+    let options = ConversionOptions {
+        truncate_names: true,
+        ..Default::default()
+    };
+    let hook = |segment: &mut BasicPaymentSegment| {
+        segment.client_sundry_information = "Invoice 2045-0001".to_string();
+    };
+
+    let conversion = convert_reader_with_hook(
+        csv(ROWS).as_bytes(),
+        RecordType::Credit,
+        &options,
+        Some(&hook),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    assert_eq!(
+        conversion.log.get_warning_list(),
+        [
+            "Client short name \"Acme Widgets Lt\" was derived by truncating the client name to 15 characters; dropped \"d\"",
+            "sundry truncated to 15 characters; dropped \"01\"",
+            "sundry truncated to 15 characters; dropped \"01\"",
+        ]
+    );
+}
+
+#[test]
+fn short_name_not_truncated_without_warning() {
+    let input = csv(ROWS).replace("Acme Widgets Ltd", "Acme Ltd");
+
+    let conversion = convert_reader_with_report(
+        input.as_bytes(),
+        RecordType::Credit,
+        &ConversionOptions::default(),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    assert!(conversion.log.get_warning_list().is_empty());
+}
+
+#[test]
+fn originator_display_name_used_for_every_short_name() {
+    let options = ConversionOptions {
+        originator_display_name: Some("Acme Shop".to_string()),
+        ..Default::default()
+    };
+
+    let output = convert_to_cpa005_with_options(csv(ROWS), RecordType::Credit, &options)
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    let details: Vec<&str> = output.lines().filter(|r| r.starts_with('C')).collect();
+    assert_eq!(details.len(), 2);
+
+    for detail in details {
+        assert_eq!(&detail[SHORT_NAME_FIELD], "Acme Shop      ");
+        // The legal name is still sent as the long name
+        assert!(detail.contains("Acme Widgets Ltd"));
+    }
+}
+
+#[test]
+fn originator_display_name_over_15_characters_rejected() {
+    let options = ConversionOptions {
+        originator_display_name: Some("Acme Widget Emporium".to_string()),
+        ..Default::default()
+    };
+
+    let log = match convert_to_cpa005_with_options(csv(ROWS), RecordType::Credit, &options) {
+        Ok(_) => panic!("display name over 15 characters was accepted"),
+        Err(log) => log,
+    };
+
+    assert_eq!(log.get_error_list().len(), 1);
+    assert!(log.to_string().contains("must not exceed 15 characters"));
+}
+
+// Total amount of credit payments in the trailer
+const CREDIT_TOTAL_FIELD: std::ops::Range<usize> = 46..60;
+
+#[test]
+fn comma_grouped_amounts_parsed_by_default() {
+    let output = convert_to_cpa005(
+        csv("1001,Jane Smith,003,01234,1234567,\"$1,000.50\",N,,\n"),
+        RecordType::Credit,
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    assert_eq!(
+        &output.lines().last().unwrap()[CREDIT_TOTAL_FIELD],
+        "00000000100050"
+    );
+}
+
+// Transaction code and amount of a detail record's first segment
+const TRANSACTION_CODE_FIELD: std::ops::Range<usize> = 24..27;
+const AMOUNT_FIELD: std::ops::Range<usize> = 27..37;
+
+// Number of credit payments in the trailer
+const CREDIT_COUNT_FIELD: std::ops::Range<usize> = 60..68;
+
+#[test]
+fn prenote_zeroes_every_amount() {
+    let options = ConversionOptions {
+        prenote: true,
+        ..Default::default()
+    };
+
+    // Amounts left blank or not yet filled in are never read
+    let conversion = convert_reader_with_report(
+        csv(&format!(
+            "{}1003,Mary Major,001,00456,55512345,,N,,\n",
+            ROWS
+        ))
+        .as_bytes(),
+        RecordType::Credit,
+        &options,
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    let records: Vec<&str> = conversion.output.lines().collect();
+    assert_eq!(records.len(), 5);
+
+    for detail in &records[1..4] {
+        assert_eq!(&detail[TRANSACTION_CODE_FIELD], PRENOTE_TRANSACTION_CODE);
+        assert_eq!(&detail[AMOUNT_FIELD], "0000000000");
+    }
+
+    // Every other field is kept
+    assert!(records[1].contains("Jane Smith"));
+    assert!(records[2].contains("987654321"));
+
+    let trailer = records[4];
+    assert_eq!(&trailer[CREDIT_TOTAL_FIELD], "00000000000000");
+    assert_eq!(&trailer[CREDIT_COUNT_FIELD], "00000003");
+    assert_eq!(conversion.report.total_credit_amount, 0);
+    assert_eq!(conversion.report.total_credit_count, 3);
+
+    let issues = lint_cpa005(&conversion.output);
+    assert!(
+        issues.iter().all(|i| i.severity != Severity::Error),
+        "{:?}",
+        issues
+    );
+}
+
+#[test]
+fn prenote_transaction_code_configurable() {
+    let options = ConversionOptions {
+        prenote: true,
+        prenote_transaction_code: "450".to_string(),
+        ..Default::default()
+    };
+
+    let output = convert_to_cpa005_with_options(csv(ROWS), RecordType::Credit, &options).unwrap();
+
+    let records: Vec<&str> = output.lines().collect();
+    assert_eq!(records.len(), 4);
+
+    for detail in &records[1..3] {
+        assert_eq!(&detail[TRANSACTION_CODE_FIELD], "450");
+        assert_eq!(&detail[AMOUNT_FIELD], "0000000000");
+    }
+}
+
+#[test]
+fn apostrophe_grouped_amounts_parsed_when_configured() {
+    let rows = "1001,Jane Smith,003,01234,1234567,1'000.50,N,,\n\
+                1002,John Doe,004,00123,987654321,12'345'678.00,N,,\n";
+
+    assert!(convert_to_cpa005(csv(rows), RecordType::Credit).is_err());
+
+    let options = ConversionOptions {
+        amount_format: AmountFormat::new(['\'']).unwrap(),
+        ..Default::default()
+    };
+    let output = convert_to_cpa005_with_options(csv(rows), RecordType::Credit, &options)
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    assert_eq!(
+        &output.lines().last().unwrap()[CREDIT_TOTAL_FIELD],
+        "00001234667850"
+    );
+}
+
+#[test]
+fn decimal_point_cannot_be_ignored() {
+    assert!(AmountFormat::new([',', '.']).is_err());
+    assert!(AmountFormat::new(['0']).is_err());
+}
+
+#[test]
+fn direction_indicators_read_when_configured() {
+    let format = AmountFormat::default().with_direction_indicators(true);
+
+    assert_eq!(
+        parse_dollar_amount_with_direction("50.00CR", &format),
+        Ok((5000, Some(RecordType::Credit)))
+    );
+    assert_eq!(
+        parse_dollar_amount_with_direction("50.00DR", &format),
+        Ok((5000, Some(RecordType::Debit)))
+    );
+    assert_eq!(
+        parse_dollar_amount_with_direction("$1,250.00 dr", &format),
+        Ok((125000, Some(RecordType::Debit)))
+    );
+    assert_eq!(
+        parse_dollar_amount_with_direction("50.00", &format),
+        Ok((5000, None))
+    );
+    assert_eq!(parse_dollar_amount_to_cents("50.00CR", &format), Some(5000));
+
+    assert_eq!(
+        parse_dollar_amount_to_cents("50.00CR", &AmountFormat::default()),
+        None
+    );
+}
+
+#[test]
+fn amounts_beyond_the_amount_field_rejected() {
+    let format = AmountFormat::default();
+    let huge = "9".repeat(400);
+
+    assert_eq!(
+        parse_dollar_amount_with_direction("1e400", &format),
+        Err(AmountError::Malformed)
+    );
+    assert_eq!(
+        parse_dollar_amount_with_direction(&huge, &format),
+        Err(AmountError::TooLarge)
+    );
+    assert_eq!(
+        parse_dollar_amount_with_direction("100000000.00", &format),
+        Err(AmountError::TooLarge)
+    );
+    assert_eq!(
+        parse_dollar_amount_with_direction("99999999.99", &format),
+        Ok((9_999_999_999, None))
+    );
+    assert_eq!(parse_dollar_amount_to_cents(&huge, &format), None);
+
+    let rows = format!(
+        "1001,Jane Smith,003,01234,1234567,1e400,N,,\n\
+         1002,John Doe,004,00123,987654321,{},N,,\n",
+        huge
+    );
+    let log = convert_reader_with_report(
+        csv(&rows).as_bytes(),
+        RecordType::Credit,
+        &ConversionOptions::default(),
+    )
+    .err()
+    .unwrap();
+
+    let errors = log.get_error_list();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0], "Failed to parse payment amount: 1e400");
+    assert_eq!(
+        errors[1],
+        format!(
+            "Payment amount {} is more than the 99999999.99 a payment can be",
+            huge
+        )
+    );
+}
+
+#[test]
+fn direction_indicators_must_match_record_type() {
+    let options = ConversionOptions {
+        amount_format: AmountFormat::default().with_direction_indicators(true),
+        ..Default::default()
+    };
+    let rows = "\
+1001,Jane Smith,003,01234,1234567,50.00CR,N,,
+1002,John Doe,004,00123,987654321,25.00,N,,
+";
+
+    let output = convert_to_cpa005_with_options(csv(rows), RecordType::Credit, &options)
+        .unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(
+        &output.lines().last().unwrap()[CREDIT_TOTAL_FIELD],
+        "00000000007500"
+    );
+
+    let log = convert_to_cpa005_with_options(csv(rows), RecordType::Debit, &options).unwrap_err();
+    assert_eq!(
+        log.get_error_list(),
+        ["Payment amount 50.00CR is marked as a credit, but this is a debit file"]
+    );
+    assert_eq!(log.errors()[0].line, Some(8));
+}
+
+const BLANK_AMOUNT_ROWS: &str = "\
+1001,Jane Smith,003,01234,1234567,$100.00,N,,
+1002,John Doe,004,00123,987654321,,N,,
+1003,Mary Major,001,00456,55512345,\"  \",N,,
+";
+
+#[test]
+fn blank_amount_rejected_by_default() {
+    let log = match convert_to_cpa005(csv(BLANK_AMOUNT_ROWS), RecordType::Credit) {
+        Ok(_) => panic!("rows with blank amounts were accepted"),
+        Err(log) => log,
+    };
+
+    assert_eq!(
+        log.get_error_list(),
+        [
+            "Payment amount is blank for customer 1002",
+            "Payment amount is blank for customer 1003",
+        ]
+    );
+}
+
+#[test]
+fn blank_amount_skipped_when_configured() {
+    let options = ConversionOptions {
+        on_blank_amount: BlankAmount::Skip,
+        ..Default::default()
+    };
+
+    let conversion = convert_reader_with_report(
+        csv(BLANK_AMOUNT_ROWS).as_bytes(),
+        RecordType::Credit,
+        &options,
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    assert_eq!(conversion.report.total_credit_count, 1);
+    assert_eq!(conversion.report.total_credit_amount, 10000);
+    // Header, the one payment and trailer
+    assert_eq!(conversion.output.lines().count(), 3);
+
+    let warnings = conversion.log.get_warning_list();
+    assert!(warnings.contains(&"Skipped customer 1002 with a blank payment amount".to_string()));
+    assert!(warnings.contains(&"Skipped customer 1003 with a blank payment amount".to_string()));
+}
+
+#[test]
+fn segment_hook_changes_reflected_in_trailer() {
+    let zero_amount = |segment: &mut BasicPaymentSegment| segment.amount = 0;
+
+    let conversion = convert_reader_with_hook(
+        csv(ROWS).as_bytes(),
+        RecordType::Credit,
+        &ConversionOptions::default(),
+        Some(&zero_amount),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    let trailer = conversion.output.lines().last().unwrap().to_string();
+    assert_eq!(&trailer[46..60], "00000000000000");
+    assert_eq!(&trailer[60..68], "00000002");
+    assert_eq!(conversion.report.total_credit_amount, 0);
+}
+
+#[test]
+fn progress_reported_every_interval_and_at_end() {
+    let rows: String = (0..PROGRESS_INTERVAL * 2 + 5)
+        .map(|i| format!("{},Customer,003,01234,1234567,$1.00,N,,\n", 1000 + i))
+        .collect();
+
+    let reported = RefCell::new(Vec::new());
+    convert_reader_with_progress(
+        csv(&rows).as_bytes(),
+        RecordType::Credit,
+        &ConversionOptions::default(),
+        &|rows| reported.borrow_mut().push(rows),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    assert_eq!(
+        reported.into_inner(),
+        [
+            PROGRESS_INTERVAL,
+            PROGRESS_INTERVAL * 2,
+            PROGRESS_INTERVAL * 2 + 5
+        ]
+    );
+}
+
+#[test]
+fn segment_hook_changes_validated() {
+    let mask_account = |segment: &mut BasicPaymentSegment| {
+        segment.account_number = "XXXX".to_string();
+    };
+
+    let log = match convert_reader_with_hook(
+        csv(ROWS).as_bytes(),
+        RecordType::Credit,
+        &ConversionOptions::default(),
+        Some(&mask_account),
+    ) {
+        Ok(_) => panic!("masked account numbers should be rejected"),
+        Err(log) => log,
+    };
+
+    assert_eq!(
+        log.get_error_list(),
+        ["Account number must only include digits"; 2]
+    );
+}
+
+#[test]
+fn sundry_set_by_segment_hook_checked_for_length() {
+    let hook = |segment: &mut BasicPaymentSegment| {
+        segment.client_sundry_information = "Invoice 2045-0001".to_string();
+    };
+
+    let log = match convert_reader_with_hook(
+        csv(ROWS).as_bytes(),
+        RecordType::Credit,
+        &ConversionOptions::default(),
+        Some(&hook),
+    ) {
+        Ok(_) => panic!("sundry information longer than its field should be rejected"),
+        Err(log) => log,
+    };
+
+    assert_eq!(
+        log.get_error_list(),
+        ["Client Sundry Information must not exceed 15 characters"; 2]
+    );
+}
+
+#[test]
+fn trailing_empty_columns_ignored() {
+    let output = convert_to_cpa005(
+        csv("1001,Jane Smith,003,01234,1234567,$100.00,N,,,,\n\
+             1002,John Doe,004,00123,987654321,$250.00,N,,\n"),
+        RecordType::Credit,
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    let expected =
+        convert_to_cpa005(csv(ROWS), RecordType::Credit).unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn row_missing_a_column_rejected() {
+    let log = match convert_to_cpa005(
+        csv("1001,Jane Smith,003,01234,$100.00,N\n\
+             1002,John Doe,004,00123,987654321,$250.00,N,,\n"),
+        RecordType::Credit,
+    ) {
+        Ok(_) => panic!("a row missing its account was accepted"),
+        Err(log) => log,
+    };
+
+    assert_eq!(
+        log.get_error_list(),
+        ["Row on line 8 has 6 columns, expected 9"]
+    );
+}
+
+#[test]
+fn row_with_extra_values_rejected() {
+    let log = match convert_to_cpa005(
+        csv("1001,Jane Smith,003,01234,1234567,$100.00,N,,,Extra\n"),
+        RecordType::Credit,
+    ) {
+        Ok(_) => panic!("a row with an extra value was accepted"),
+        Err(log) => log,
+    };
+
+    assert_eq!(
+        log.get_error_list(),
+        ["Row on line 8 has 10 columns, expected 9"]
+    );
+}
+
+#[test]
+fn matching_control_total_accepted() {
+    let options = ConversionOptions {
+        control_total_cents: Some(35000),
+        ..Default::default()
+    };
+
+    convert_to_cpa005_with_options(csv(ROWS), RecordType::Credit, &options)
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+#[test]
+fn mismatched_control_total_rejected_with_both_totals() {
+    let options = ConversionOptions {
+        control_total_cents: Some(45000),
+        ..Default::default()
+    };
+
+    let log = match convert_to_cpa005_with_options(csv(ROWS), RecordType::Credit, &options) {
+        Ok(_) => panic!("a file not matching its control total was accepted"),
+        Err(log) => log,
+    };
+
+    assert_eq!(
+        log.get_error_list(),
+        ["Payment total of 350.00 does not match the control total of 450.00"]
+    );
+}
+
+/// Customer names of the detail records of `output`, in order, after
+/// checking the file is still well formed
+fn customer_order(output: &str) -> Vec<String> {
+    let issues = lint_cpa005(output);
+    assert!(
+        issues.iter().all(|i| i.severity != Severity::Error),
+        "{:?}",
+        issues
+    );
+
+    output
+        .lines()
+        .filter(|r| r.starts_with('C'))
+        .enumerate()
+        .map(|(i, r)| {
+            assert_eq!(&r[1..10], format!("{:0>9}", i + 2));
+            r[24 + 80..24 + 110].trim_end().to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn detail_records_sorted_on_request() {
+    let rows = "1001,Jane Smith,003,01234,1234567,$100.00,N,,\n\
+                1002,John Doe,004,00123,987654321,$250.00,N,,\n\
+                1003,Mary Major,001,00456,55512345,$75.25,N,,\n";
+
+    let sorted = |sort| {
+        convert_to_cpa005_with_options(
+            csv(rows),
+            RecordType::Credit,
+            &ConversionOptions {
+                sort,
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|e| panic!("{}", e))
+    };
+
+    assert_eq!(
+        customer_order(&sorted(SortOrder::SourceOrder)),
+        ["Jane Smith", "John Doe", "Mary Major"]
+    );
+    assert_eq!(
+        customer_order(&sorted(SortOrder::ByAccount)),
+        ["Jane Smith", "Mary Major", "John Doe"]
+    );
+    assert_eq!(
+        customer_order(&sorted(SortOrder::ByAmountDesc)),
+        ["John Doe", "Jane Smith", "Mary Major"]
+    );
+
+    // The trailer is the same whatever the order
+    let trailer = |output: String| output.lines().last().unwrap().to_string();
+    assert_eq!(
+        trailer(sorted(SortOrder::ByAmountDesc)),
+        trailer(sorted(SortOrder::SourceOrder))
+    );
+}
+
+#[test]
+fn repeated_header_rows_reported_once() {
+    let log =
+        convert_to_cpa005(format!("{}{}{}", HEADER, HEADER, ROWS), RecordType::Credit).unwrap_err();
+
+    assert_eq!(
+        log.get_error_list(),
+        ["Header rows are repeated from here, starting with Client Name. Remove the repeated rows."]
+    );
+    assert_eq!(log.errors()[0].line, Some(8));
+}
+
+#[test]
+fn whole_dollars_only_rejects_cents() {
+    let options = ConversionOptions {
+        whole_dollars_only: true,
+        ..Default::default()
+    };
+    let rows = "\
+1001,Jane Smith,003,01234,1234567,$10.00,N,,
+1002,John Doe,004,00123,987654321,$10.50,N,,
+";
+
+    let log = convert_to_cpa005_with_options(csv(rows), RecordType::Credit, &options).unwrap_err();
+    assert_eq!(
+        log.get_error_list(),
+        ["Payment amount must be whole dollars: $10.50"]
+    );
+    assert_eq!(log.errors()[0].line, Some(9));
+
+    let whole = rows.replace("$10.50", "$10.00");
+    assert!(convert_to_cpa005_with_options(csv(&whole), RecordType::Credit, &options).is_ok());
+
+    // Cents are encoded as usual without the option
+    assert!(
+        convert_to_cpa005_with_options(csv(rows), RecordType::Credit, &Default::default()).is_ok()
+    );
+}
+
+const THREE_ROWS: &str = "\
+1001,Jane Smith,003,01234,1234567,$100.00,N,,
+1002,John Doe,004,00123,987654321,$250.00,N,,
+1003,Mary Major,001,00456,55512345,$75.25,N,,
+";
+
+// File creation number, in every record
+const CREATION_NUMBER_FIELD: std::ops::Range<usize> = 20..24;
+
+#[test]
+fn split_files_numbered_on_from_starting_creation_number() {
+    let options = ConversionOptions {
+        starting_creation_number: 41,
+        ..Default::default()
+    };
+
+    let files = convert_reader_split(csv(THREE_ROWS).as_bytes(), RecordType::Credit, &options, 2)
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].report.file_creation_number, 41);
+    assert_eq!(files[1].report.file_creation_number, 42);
+    assert_eq!(files[0].report.total_credit_amount, 35000);
+    assert_eq!(files[1].report.total_credit_amount, 7525);
+
+    for (file, number) in files.iter().zip(["41  ", "42  "]) {
+        let issues = lint_cpa005(&file.output);
+        assert!(
+            issues.iter().all(|i| i.severity != Severity::Error),
+            "{:?}",
+            issues
+        );
+        for record in file.output.lines() {
+            assert_eq!(&record[CREATION_NUMBER_FIELD], number);
+        }
+    }
+
+    // Each file is numbered from its own header
+    let second: Vec<&str> = files[1].output.lines().collect();
+    assert_eq!(&second[1][0..10], "C000000002");
+    assert!(second[1].contains("Mary Major"));
+}
+
+#[test]
+fn split_files_past_9999_rejected() {
+    let options = ConversionOptions {
+        starting_creation_number: 9998,
+        ..Default::default()
+    };
+
+    let files = convert_reader_split(csv(THREE_ROWS).as_bytes(), RecordType::Credit, &options, 2)
+        .unwrap_or_else(|e| panic!("{}", e));
+    let numbers: Vec<u32> = files
+        .iter()
+        .map(|f| f.report.file_creation_number)
+        .collect();
+    assert_eq!(numbers, vec![9998, 9999]);
+
+    let log = convert_reader_split(csv(THREE_ROWS).as_bytes(), RecordType::Credit, &options, 1)
+        .err()
+        .expect("a third file would be numbered 10000");
+    assert_eq!(
+        log.get_error_list(),
+        vec!["File creation numbers 9998 to 10000 of the 3 split files run past 9999. Start from a lower file creation number."]
+    );
+}
+
+#[test]
+fn starting_creation_number_over_four_digits_rejected() {
+    let options = ConversionOptions {
+        starting_creation_number: 10000,
+        ..Default::default()
+    };
+
+    let log = convert_to_cpa005_with_options(csv(ROWS), RecordType::Credit, &options).unwrap_err();
+    assert!(log
+        .get_error_list()
+        .contains(&"File creation number exceeds 4 digits".to_string()));
+}
+
+#[test]
+fn control_characters_rejected_where_found() {
+    let rows = "\
+1001,Jane Smith,003,01234,1234567,$100.00,N,,
+1002,John\x0cDoe,004,00123,987654321,$250.00,N,,
+1003,Mary Ma\u{85}jor,001,00456,55512345,$50.00,N,,
+";
+
+    let log = match convert_to_cpa005(csv(rows), RecordType::Credit) {
+        Ok(_) => panic!("a file with control characters was accepted"),
+        Err(log) => log,
+    };
+
+    let errors: Vec<(Option<u64>, &str)> = log
+        .errors()
+        .iter()
+        .map(|e| (e.line, e.message.as_str()))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            (
+                Some(9),
+                "Control character U+000C (form feed) at column 10. Remove it from the file, it may have been pasted in with the text."
+            ),
+            (
+                Some(10),
+                "Control character U+0085 at column 13. Remove it from the file, it may have been pasted in with the text."
+            ),
+        ]
+    );
+
+    // Tabs and Windows line breaks are not control characters to reject
+    let rows = "1001,Jane\tSmith,003,01234,1234567,$100.00,N,,\r\n";
+    assert!(convert_to_cpa005(csv(rows), RecordType::Credit).is_ok());
+}
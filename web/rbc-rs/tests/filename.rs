@@ -0,0 +1,52 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/server/filename.rs"]
+mod filename;
+
+use filename::{attachment, download_name};
+
+#[test]
+fn download_name_strips_path_components() {
+    assert_eq!(
+        download_name(Some("../../etc/payroll.csv"), 1),
+        "payroll.txt"
+    );
+    assert_eq!(
+        download_name(Some("C:\\Users\\me\\payroll.csv"), 1),
+        "payroll.txt"
+    );
+    assert_eq!(download_name(Some("../"), 12), "cpa005_0012.txt");
+}
+
+#[test]
+fn download_name_replaces_header_breaking_characters() {
+    assert_eq!(
+        download_name(Some("pay\"roll\r\nX-Injected: 1.csv"), 1),
+        "pay_roll__X-Injected_ 1.txt"
+    );
+    assert_eq!(download_name(Some("\"\";.csv"), 7), "cpa005_0007.txt");
+    assert_eq!(download_name(None, 7), "cpa005_0007.txt");
+}
+
+#[test]
+fn download_name_caps_length() {
+    let name = download_name(Some(&format!("{}.csv", "a".repeat(500))), 1);
+    assert_eq!(name, format!("{}.txt", "a".repeat(64)));
+}
+
+#[test]
+fn download_name_keeps_non_ascii_names() {
+    let name = download_name(Some("paie été.csv"), 1);
+    assert_eq!(name, "paie été.txt");
+
+    let header = attachment(&name).to_string();
+    assert!(header.is_ascii());
+    assert!(header.contains("filename=\"paie _t_.txt\""));
+    assert!(header.contains("filename*=UTF-8''paie%20%C3%A9t%C3%A9.txt"));
+}
@@ -0,0 +1,415 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+use lib::header::CPA005Record;
+use lib::payment::{BasicPayment, BasicPaymentSegment};
+use lib::types::{AccountJustify, CurrencyType, RecordType};
+
+// Character positions 194-202 and 203-214 of a detail record, fields 17 and
+// 18 of its first segment
+const RETURNS_INSTITUTION_FIELD: std::ops::Range<usize> = 193..202;
+const RETURNS_ACCOUNT_FIELD: std::ops::Range<usize> = 202..214;
+
+fn payment() -> BasicPayment {
+    let mut segment = BasicPaymentSegment::new();
+    segment
+        .set_transaction_code("200".to_string())
+        .set_amount(1000)
+        .set_payment_date(2024, 100)
+        .set_financial_institution_number("003".to_string())
+        .set_financial_institution_branch_number("01234".to_string())
+        .set_account_number("7654321".to_string())
+        .set_client_short_name("Acme".to_string())
+        .set_customer_name("Jane Smith".to_string())
+        .set_client_name("Acme Widgets Ltd".to_string())
+        .set_client_number("0123456789".to_string())
+        .set_customer_number("1001".to_string());
+
+    let mut payment = BasicPayment::new();
+    payment.set_client_number("0123456789".to_string());
+    payment.segments.push(segment);
+
+    payment
+}
+
+fn detail_record(record: &CPA005Record) -> String {
+    record.build().lines().nth(1).unwrap().to_string()
+}
+
+#[test]
+fn returns_account_placed_in_detail_records() {
+    let mut record = CPA005Record::new();
+    record
+        .set_client_number("0123456789".to_string())
+        .set_returns_account("3".to_string(), "12345".to_string(), "1234567".to_string())
+        .add_basic_payment(payment());
+
+    let detail = detail_record(&record);
+    assert_eq!(&detail[RETURNS_INSTITUTION_FIELD], "000312345");
+    assert_eq!(&detail[RETURNS_ACCOUNT_FIELD], "1234567     ");
+}
+
+#[test]
+fn returns_account_applies_to_payments_already_added() {
+    let mut record = CPA005Record::new();
+    record.add_basic_payment(payment()).set_returns_account(
+        "3".to_string(),
+        "12345".to_string(),
+        "1234567".to_string(),
+    );
+
+    let detail = detail_record(&record);
+    assert_eq!(&detail[RETURNS_INSTITUTION_FIELD], "000312345");
+    assert_eq!(&detail[RETURNS_ACCOUNT_FIELD], "1234567     ");
+}
+
+#[test]
+fn returns_account_defaults_to_fill() {
+    let mut record = CPA005Record::new();
+    record.add_basic_payment(payment());
+
+    let detail = detail_record(&record);
+    assert_eq!(&detail[RETURNS_INSTITUTION_FIELD], "000000000");
+    assert_eq!(&detail[RETURNS_ACCOUNT_FIELD], " ".repeat(12));
+}
+
+#[test]
+fn returns_account_rejects_invalid_numbers() {
+    let mut record = CPA005Record::new();
+    record.set_returns_account("00003".to_string(), "12345".to_string(), "1".to_string());
+    record.set_returns_account("3".to_string(), "1234a".to_string(), "1".to_string());
+    record.set_returns_account(
+        "3".to_string(),
+        "12345".to_string(),
+        "1234567890123".to_string(),
+    );
+
+    assert_eq!(record.error_log.get_error_list().len(), 3);
+    assert!(record.returns_institution.is_empty());
+}
+
+#[test]
+fn segments_beyond_six_reported_not_written() {
+    let mut crowded = payment();
+    for _ in 0..6 {
+        crowded.segments.push(payment().segments.remove(0));
+    }
+
+    let mut record = CPA005Record::new();
+    record.add_basic_payment(crowded);
+
+    assert_eq!(
+        record.error_log.get_error_list(),
+        ["Detail record 2 has 7 segments, at most 6 fit in a record"]
+    );
+    assert_eq!(detail_record(&record).len(), 1464);
+}
+
+#[test]
+fn records_numbered_with_nine_digit_sequence() {
+    let mut record = CPA005Record::new();
+    record
+        .set_client_number("0123456789".to_string())
+        .add_basic_payment(payment())
+        .add_basic_payment(payment());
+
+    let numbers: Vec<String> = record
+        .build()
+        .lines()
+        .map(|line| line[1..10].to_string())
+        .collect();
+
+    assert_eq!(
+        numbers,
+        ["000000001", "000000002", "000000003", "000000004"]
+    );
+}
+
+#[test]
+fn reset_record_builds_same_file_as_new_record() {
+    let second = |record: &mut CPA005Record| {
+        record
+            .set_client_number("0123456789".to_string())
+            .set_file_creation_number(7)
+            .set_file_creation_date(2024, 100)
+            .add_basic_payment(payment());
+    };
+
+    let mut record = CPA005Record::new();
+    record
+        .set_client_number("9876543210".to_string())
+        .set_file_creation_number(42)
+        .set_file_creation_date(2023, 5)
+        .set_returns_account("3".to_string(), "12345".to_string(), "1234567".to_string())
+        .set_client_number("not a number".to_string())
+        .add_basic_payment(payment())
+        .add_basic_payment(payment());
+    record.build();
+
+    record.reset();
+    second(&mut record);
+
+    let mut fresh = CPA005Record::new();
+    second(&mut fresh);
+
+    assert_eq!(record.build(), fresh.build());
+    assert!(record.error_log.get_error_list().is_empty());
+    assert_eq!(record.total_credit_count, fresh.total_credit_count);
+}
+
+fn file_records(record: &CPA005Record) -> Vec<String> {
+    let mut records = vec![record.build_header_record()];
+    records.extend(record.basic_payment.iter().map(|p| record.build_detail(p)));
+    records.push(record.build_trailer_record());
+    records
+}
+
+fn two_payment_record() -> CPA005Record {
+    let mut record = CPA005Record::new();
+    record
+        .set_client_number("0123456789".to_string())
+        .add_basic_payment(payment())
+        .add_basic_payment(payment());
+    record
+}
+
+#[test]
+fn records_built_while_streaming_match_whole_file() {
+    let mut record = CPA005Record::new();
+    record.set_client_number("0123456789".to_string());
+
+    // Each detail record written as soon as its payment is added
+    let mut records = vec![record.build_header_record()];
+    for _ in 0..3 {
+        record.add_basic_payment(payment());
+        let added = record.basic_payment.last().unwrap();
+        records.push(record.build_detail(added));
+    }
+    records.push(record.build_trailer_record());
+
+    assert_eq!(records.join("\n"), record.build());
+    assert_eq!(records.concat(), record.build_with_terminator(None, false));
+}
+
+#[test]
+fn records_separated_by_newline_by_default() {
+    let record = two_payment_record();
+    let file = record.build();
+
+    assert_eq!(file, file_records(&record).join("\n"));
+    assert!(!file.ends_with('\n'));
+    assert_eq!(file, record.build_with_terminator(Some("\n"), false));
+}
+
+#[test]
+fn crlf_terminates_every_record_including_last() {
+    let record = two_payment_record();
+    let file = record.build_with_terminator(Some("\r\n"), true);
+
+    let expected: String = file_records(&record)
+        .iter()
+        .map(|r| format!("{}\r\n", r))
+        .collect();
+
+    assert_eq!(file, expected);
+    assert_eq!(file.matches("\r\n").count(), 4);
+}
+
+#[test]
+fn records_written_back_to_back_without_terminator() {
+    let record = two_payment_record();
+    let file = record.build_with_terminator(None, true);
+
+    assert_eq!(file, file_records(&record).concat());
+    assert!(!file.contains('\n') && !file.contains('\r'));
+}
+
+#[test]
+fn zero_file_creation_number_rejected() {
+    let mut record = CPA005Record::new();
+    record.set_file_creation_number(0);
+    assert_eq!(
+        record.error_log.get_error_list(),
+        ["File creation number is 0"]
+    );
+
+    let mut record = CPA005Record::new();
+    record
+        .set_file_creation_number(9999)
+        .set_file_creation_number(10000);
+    assert_eq!(record.file_creation_number, 9999);
+    assert_eq!(
+        record.error_log.get_error_list(),
+        ["File creation number exceeds 4 digits"]
+    );
+}
+
+#[test]
+fn zero_file_creation_date_rejected() {
+    for (year, day) in [(0, 100), (2024, 0)] {
+        let mut record = CPA005Record::new();
+        record.set_file_creation_date(year, day);
+        assert_eq!(
+            record.error_log.get_error_list().len(),
+            1,
+            "{}/{}",
+            year,
+            day
+        );
+        assert!(record.error_log.get_error_list()[0].ends_with("is 0"));
+    }
+
+    let mut record = CPA005Record::new();
+    record.set_file_creation_date(2024, 1000);
+    assert_eq!(
+        record.error_log.get_error_list(),
+        ["File Creation Date: Day number exceeds 3 digits"]
+    );
+}
+
+/// Reserved fields of each record by their character positions in the RBC
+/// CPA-005 credit and debit specifications, counting from 1, with the fill
+/// the specifications require of them
+const HEADER_RESERVED: [(usize, usize, char); 2] = [(36, 55, ' '), (59, 1464, ' ')];
+const SEGMENT_RESERVED: [(usize, usize, char); 7] = [
+    (65, 86, '0'),
+    (87, 89, '0'),
+    (194, 202, '0'),
+    (203, 214, ' '),
+    (230, 251, ' '),
+    (252, 253, ' '),
+    (254, 264, ' '),
+];
+// Debit totals are reserved in a file of credits
+const TRAILER_RESERVED: [(usize, usize, char); 2] = [(25, 46, '0'), (69, 1464, '0')];
+
+fn assert_filled(record: &str, reserved: &[(usize, usize, char)], offset: usize) {
+    for &(first, last, fill) in reserved {
+        let region = &record[offset + first - 1..offset + last];
+        assert!(
+            region.chars().all(|c| c == fill),
+            "positions {}-{} hold {:?} rather than {:?} fill",
+            offset + first,
+            offset + last,
+            region,
+            fill
+        );
+    }
+}
+
+#[test]
+fn reserved_fields_hold_spec_fill() {
+    let mut two_segments = payment();
+    two_segments.segments.extend(payment().segments);
+
+    let mut record = CPA005Record::new();
+    record
+        .set_client_number("0123456789".to_string())
+        .add_basic_payment(two_segments);
+
+    let records = file_records(&record);
+    assert_filled(&records[0], &HEADER_RESERVED, 0);
+    // Segments after the first are laid out as the first, 240 characters on
+    assert_filled(&records[1], &SEGMENT_RESERVED, 0);
+    assert_filled(&records[1], &SEGMENT_RESERVED, 240);
+    assert_filled(&records[2], &TRAILER_RESERVED, 0);
+}
+
+#[test]
+fn client_number_separators_stripped() {
+    for no in ["123-456-7890", "123 456 7890"] {
+        let mut record = CPA005Record::new();
+        record.set_client_number(no.to_string());
+
+        assert!(record.error_log.get_error_list().is_empty(), "{}", no);
+        assert_eq!(&record.build_header_record()[10..20], "1234567890");
+    }
+
+    let mut record = CPA005Record::new();
+    record.set_client_number("123 456 789O".to_string());
+    assert_eq!(
+        record.error_log.get_error_list(),
+        ["Client number must be exactly 10 numeric digits long"]
+    );
+}
+
+#[test]
+fn over_long_client_number_with_separators_rejected() {
+    let mut record = CPA005Record::new();
+    record.set_client_number("123-456-7890-12".to_string());
+
+    let mut payment = BasicPayment::new();
+    payment.set_client_number("123-456-7890-12".to_string());
+
+    for log in [&record.error_log, &payment.error_log] {
+        assert_eq!(
+            log.get_error_list(),
+            ["Client number must be exactly 10 numeric digits long"]
+        );
+    }
+    assert_eq!(record.client_number, "");
+}
+
+/// A file of credits and debits of one to six segments each, with accounts
+/// justified both ways and names that are not all ASCII
+fn varied_record() -> CPA005Record {
+    let mut record = CPA005Record::new();
+    record
+        .set_client_number("0123456789".to_string())
+        .set_file_creation_number(317)
+        .set_file_creation_date(2045, 32)
+        .set_destination_currency_code(CurrencyType::USD)
+        .set_returns_account("3".to_string(), "12345".to_string(), "1234567".to_string());
+
+    let names = ["Jane Smith", "Zoë Côté", "O'Brien & Sons", "Ångström Ltd"];
+
+    for i in 0..24u64 {
+        let mut payment = BasicPayment::new();
+        payment.set_client_number("0123456789".to_string());
+        if i % 3 == 2 {
+            payment.record_type = RecordType::Debit;
+        }
+
+        for s in 0..=(i % 6) {
+            let mut segment = BasicPaymentSegment::new();
+            segment
+                .set_transaction_code(if i % 3 == 2 { "450" } else { "200" }.to_string())
+                .set_amount(i * 100_003 + s * 7)
+                .set_payment_date(2045, 32 + i)
+                .set_financial_institution_number((i % 9 + 1).to_string())
+                .set_financial_institution_branch_number(format!("{:05}", 1000 + i))
+                .set_account_number(format!("{}", 1_000_003 * (i + 1) + s))
+                .set_client_short_name("Acme".to_string())
+                .set_customer_name(names[(i + s) as usize % names.len()].to_string())
+                .set_client_name("Acme Widgets Ltd".to_string())
+                .set_client_number("0123456789".to_string())
+                .set_customer_number(format!("C{}-{}", i, s))
+                .set_customer_sundry_information(format!("Invoice {}", i));
+            if i % 2 == 1 {
+                segment.set_account_justify(AccountJustify::RightZeroPad);
+            }
+            payment.segments.push(segment);
+        }
+
+        record.add_basic_payment(payment);
+    }
+
+    record
+}
+
+#[test]
+fn built_file_unchanged() {
+    // Built before the record writer was reworked, which must not have
+    // changed a byte of it
+    let built = include_str!("fixtures/varied.pds.txt");
+    let record = varied_record();
+    assert!(record.error_log.get_error_list().is_empty());
+
+    assert_eq!(record.build(), built);
+    assert_eq!(file_records(&record).join("\n"), built);
+    assert_eq!(
+        record.build_with_terminator(Some("\r\n"), true),
+        format!("{}\r\n", built.replace('\n', "\r\n"))
+    );
+}
@@ -0,0 +1,152 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+use lib::header::CPA005Record;
+use lib::layout::{
+    mask_record_accounts, FieldLayout, DETAIL_FIELDS, HEADER_FIELDS, SEGMENT_FIELDS, TRAILER_FIELDS,
+};
+use lib::lint::{MAX_SEGMENTS, RECORD_LENGTH, SEGMENT_LENGTH};
+use lib::payment::{
+    BasicPayment, BasicPaymentSegment, CLIENT_NUMBER_LEN, MAX_ACCOUNT_NUMBER_LEN,
+    MAX_BRANCH_NUMBER_LEN, MAX_CUSTOMER_NUMBER_LEN, MAX_INSTITUTION_NUMBER_LEN, MAX_NAME_LEN,
+    MAX_SHORT_NAME_LEN, TRANSACTION_CODE_LEN,
+};
+
+/// Checks that `fields` follow one another from the start of the record
+/// without gaps, and returns the length they cover
+fn contiguous_length(fields: &[FieldLayout]) -> usize {
+    fields.iter().fold(0, |end, (name, start, length)| {
+        assert_eq!(
+            *start, end,
+            "{} starts at {}, expected {}",
+            name, start, end
+        );
+        start + length
+    })
+}
+
+fn length(fields: &[FieldLayout], name: &str) -> usize {
+    fields.iter().find(|(n, _, _)| *n == name).unwrap().2
+}
+
+fn value<'a>(record: &'a str, fields: &[FieldLayout], name: &str) -> &'a str {
+    let (_, start, length) = fields.iter().find(|(n, _, _)| *n == name).unwrap();
+    &record[*start..start + length]
+}
+
+#[test]
+fn fields_cover_whole_records() {
+    assert_eq!(contiguous_length(HEADER_FIELDS), RECORD_LENGTH);
+    assert_eq!(contiguous_length(DETAIL_FIELDS), RECORD_LENGTH);
+    assert_eq!(contiguous_length(TRAILER_FIELDS), RECORD_LENGTH);
+    assert_eq!(contiguous_length(SEGMENT_FIELDS), SEGMENT_LENGTH);
+
+    assert_eq!(RECORD_LENGTH, 1464);
+    let segments = DETAIL_FIELDS
+        .iter()
+        .filter(|(n, _, _)| n.starts_with("segment_"));
+    assert_eq!(segments.count(), MAX_SEGMENTS);
+}
+
+#[test]
+fn input_widths_match_layout() {
+    let widths = [
+        ("transaction_code", TRANSACTION_CODE_LEN),
+        ("institution_number", MAX_INSTITUTION_NUMBER_LEN),
+        ("branch_number", MAX_BRANCH_NUMBER_LEN),
+        ("account_number", MAX_ACCOUNT_NUMBER_LEN),
+        ("client_short_name", MAX_SHORT_NAME_LEN),
+        ("customer_name", MAX_NAME_LEN),
+        ("client_name", MAX_NAME_LEN),
+        ("client_number", CLIENT_NUMBER_LEN),
+        ("customer_number", MAX_CUSTOMER_NUMBER_LEN),
+    ];
+
+    for (name, width) in widths {
+        assert_eq!(length(SEGMENT_FIELDS, name), width, "{}", name);
+    }
+}
+
+#[test]
+fn records_built_to_layout() {
+    let mut segment = BasicPaymentSegment::new();
+    segment
+        .set_transaction_code("200".to_string())
+        .set_amount(125000)
+        .set_payment_date(2024, 100)
+        .set_financial_institution_number("003".to_string())
+        .set_financial_institution_branch_number("01234".to_string())
+        .set_account_number("7654321".to_string())
+        .set_client_short_name("Acme".to_string())
+        .set_customer_name("Jane Smith".to_string())
+        .set_client_name("Acme Widgets".to_string())
+        .set_client_number("0123456789".to_string())
+        .set_customer_number("1001".to_string());
+
+    let mut payment = BasicPayment::new();
+    payment.set_client_number("0123456789".to_string());
+    payment.segments.push(segment);
+
+    let mut record = CPA005Record::new();
+    record
+        .set_client_number("0123456789".to_string())
+        .set_file_creation_number(7)
+        .add_basic_payment(payment);
+
+    let file = record.build();
+    let records: Vec<&str> = file.lines().collect();
+    let (header, detail, trailer) = (records[0], records[1], records[2]);
+
+    assert_eq!(value(header, HEADER_FIELDS, "record_type"), "A");
+    assert_eq!(value(header, HEADER_FIELDS, "record_number"), "000000001");
+    assert_eq!(value(header, HEADER_FIELDS, "file_creation_number"), "7   ");
+    assert_eq!(value(header, HEADER_FIELDS, "currency_code"), "CAD");
+
+    assert_eq!(value(detail, DETAIL_FIELDS, "record_number"), "000000002");
+    let segment = value(detail, DETAIL_FIELDS, "segment_1");
+    assert_eq!(value(segment, SEGMENT_FIELDS, "amount"), "0000125000");
+    assert_eq!(value(segment, SEGMENT_FIELDS, "payment_date"), "024100");
+    assert_eq!(
+        value(segment, SEGMENT_FIELDS, "customer_name").trim_end(),
+        "Jane Smith"
+    );
+
+    assert_eq!(value(trailer, TRAILER_FIELDS, "record_type"), "Z");
+    assert_eq!(value(trailer, TRAILER_FIELDS, "record_number"), "000000003");
+    assert_eq!(
+        value(trailer, TRAILER_FIELDS, "total_credit_amount"),
+        "00000000125000"
+    );
+    assert_eq!(
+        value(trailer, TRAILER_FIELDS, "total_credit_count"),
+        "00000001"
+    );
+}
+
+#[test]
+fn accounts_masked_in_shown_records() {
+    let file = include_str!("fixtures/payroll.pds.txt");
+    let records: Vec<&str> = file.lines().collect();
+
+    // Records without payments are shown as they are
+    assert_eq!(mask_record_accounts(records[0]), records[0]);
+    assert_eq!(mask_record_accounts(records[4]), records[4]);
+
+    let detail = records[1];
+    let masked = mask_record_accounts(detail);
+    let segment = value(&masked, DETAIL_FIELDS, "segment_1");
+    assert_eq!(
+        value(segment, SEGMENT_FIELDS, "account_number"),
+        "***4567     "
+    );
+
+    // Nothing else of the record changes
+    assert_eq!(masked.len(), detail.len());
+    let changed = masked
+        .chars()
+        .zip(detail.chars())
+        .filter(|(m, d)| m != d)
+        .count();
+    assert_eq!(changed, 3);
+}
@@ -0,0 +1,185 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+use lib::lint::{issues_to_csv, lint_cpa005, Severity, ValidationIssue};
+
+const FILE: &str = include_str!("fixtures/payroll.pds.txt");
+
+fn errors(issues: &[ValidationIssue]) -> Vec<&ValidationIssue> {
+    issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .collect()
+}
+
+/// `FILE` with `f` applied to the record on `line`
+fn corrupt(line: usize, f: impl Fn(&str) -> String) -> String {
+    FILE.lines()
+        .enumerate()
+        .map(|(i, record)| {
+            if i + 1 == line {
+                f(record)
+            } else {
+                record.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn converted_file_has_no_errors() {
+    let issues = lint_cpa005(FILE);
+
+    assert!(errors(&issues).is_empty(), "{:?}", issues);
+
+    // Detail records carry a single segment rather than being padded
+    assert_eq!(issues.len(), 3);
+    assert!(issues[0].message.contains("264 characters long"));
+}
+
+#[test]
+fn wrong_record_length_reported() {
+    let issues = lint_cpa005(&corrupt(3, |r| r[..200].to_string()));
+    let errors = errors(&issues);
+
+    // The short record's segment is also missing from the trailer's totals
+    assert!(!errors.is_empty(), "{:?}", issues);
+    assert_eq!(errors[0].line, Some(3));
+    assert_eq!(
+        errors[0].message,
+        "Record is 200 characters long, expected 1464"
+    );
+}
+
+#[test]
+fn wrong_trailer_total_reported() {
+    // The credit total follows the debit amount and count
+    let issues = lint_cpa005(&corrupt(5, |r| {
+        format!("{}00000000999999{}", &r[..46], &r[60..])
+    }));
+    let errors = errors(&issues);
+
+    assert_eq!(errors.len(), 1, "{:?}", issues);
+    assert_eq!(errors[0].line, Some(5));
+    assert_eq!(errors[0].field, Some("total_credit_amount"));
+    assert_eq!(
+        errors[0].message,
+        "Trailer states 9999.99 but the detail records add up to 1625.75"
+    );
+}
+
+#[test]
+fn trailer_checked_against_header() {
+    // The file creation number follows the record number and client number
+    let issues = lint_cpa005(&corrupt(5, |r| format!("{}2   {}", &r[..20], &r[24..])));
+    let found = errors(&issues);
+
+    assert_eq!(found.len(), 1, "{:?}", issues);
+    assert_eq!(
+        found[0].to_string(),
+        "line 5: file_creation_number: Does not match the file creation number of the header, expected \"1\" but found \"2\""
+    );
+
+    let issues = lint_cpa005(&corrupt(5, |r| {
+        format!("{}9876543210{}", &r[..10], &r[20..])
+    }));
+    let errors = errors(&issues);
+
+    assert_eq!(errors.len(), 1, "{:?}", issues);
+    assert_eq!(errors[0].line, Some(5));
+    assert_eq!(errors[0].field, Some("client_number"));
+}
+
+#[test]
+fn field_characters_checked() {
+    // The amount of the first segment
+    let issues = lint_cpa005(&corrupt(2, |r| {
+        format!("{}00000012X0{}", &r[..27], &r[37..])
+    }));
+    let errors = errors(&issues);
+
+    // The amount no longer adds up with the others either
+    assert_eq!(errors.len(), 2, "{:?}", issues);
+    assert_eq!(errors[0].field, Some("amount"));
+    assert_eq!(
+        errors[0].to_string(),
+        "line 2: amount: Expected digits at column 28, found \"00000012X0\""
+    );
+}
+
+#[test]
+fn account_numbers_masked_in_messages() {
+    // The account number of the first segment
+    let issues = lint_cpa005(&corrupt(2, |r| {
+        format!("{}12345X789012{}", &r[..52], &r[64..])
+    }));
+    let found = errors(&issues);
+
+    assert_eq!(found.len(), 1, "{:?}", issues);
+    assert_eq!(
+        found[0].to_string(),
+        "line 2: account_number: Expected digits followed by blanks at column 53, found \"********9012\""
+    );
+}
+
+#[test]
+fn record_order_and_numbering_checked() {
+    let records: Vec<&str> = FILE.lines().collect();
+
+    // Trailer missing and a detail record dropped
+    let file = [records[0], records[1], records[3]].join("\n");
+    let issues = lint_cpa005(&file);
+    let messages: Vec<String> = errors(&issues).iter().map(|i| i.to_string()).collect();
+
+    assert!(messages.contains(&"line 3: record_number: Record number is 4, expected 3".to_string()));
+    assert!(messages.contains(&"File has no trailer record".to_string()));
+
+    // Header after a detail record
+    let file = [records[1], records[0], records[4]].join("\n");
+    let messages: Vec<String> = errors(&lint_cpa005(&file))
+        .iter()
+        .map(|i| i.to_string())
+        .collect();
+
+    assert!(messages.contains(&"line 1: File must start with a header record".to_string()));
+    assert!(messages.contains(&"line 2: Header record must be the first record".to_string()));
+
+    // Trailer ahead of the detail records
+    let file = [records[0], records[4], records[1]].join("\n");
+    let messages: Vec<String> = errors(&lint_cpa005(&file))
+        .iter()
+        .map(|i| i.to_string())
+        .collect();
+
+    assert!(messages.contains(&"line 2: Trailer record must be the last record".to_string()));
+    assert!(messages.contains(&"line 3: Detail record follows the trailer".to_string()));
+}
+
+#[test]
+fn empty_file_reported() {
+    assert_eq!(lint_cpa005("")[0].message, "File is empty");
+}
+
+#[test]
+fn issues_written_as_csv() {
+    let mut issues = lint_cpa005(&corrupt(3, |r| r[..200].to_string()));
+    issues.push(ValidationIssue {
+        file: Some("payroll, March.csv".to_string()),
+        line: Some(4),
+        field: None,
+        severity: Severity::Warning,
+        message: "Name \"Acme, Inc.\" is too long".to_string(),
+    });
+
+    let csv = issues_to_csv(&issues);
+    let mut lines = csv.lines();
+
+    assert_eq!(lines.next(), Some("file,line,field,severity,message"));
+    assert_eq!(lines.count(), issues.len());
+
+    assert!(csv.contains(",3,,error,\"Record is 200 characters long, expected 1464\"\n"));
+    assert!(csv
+        .ends_with("\"payroll, March.csv\",4,,warning,\"Name \"\"Acme, Inc.\"\" is too long\"\n"));
+}
@@ -0,0 +1,35 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+use csvconv::naming::{check_output_name, render_output_name, DEFAULT_OUTPUT_NAME};
+
+#[test]
+fn seq_expands_to_padded_creation_number() {
+    let name = render_output_name("RBC_{stem}_{seq}.txt", "payroll", 42);
+    assert_eq!(name, "RBC_payroll_0042.txt");
+    assert!(name.contains("0042"));
+}
+
+#[test]
+fn default_name_keeps_input_stem() {
+    assert_eq!(
+        render_output_name(DEFAULT_OUTPUT_NAME, "payroll march", 1),
+        "payroll march.txt"
+    );
+}
+
+#[test]
+fn unknown_placeholders_rejected() {
+    assert!(check_output_name("{stem}_{seq}.txt").is_ok());
+
+    let err = check_output_name("{stem}_{date}.txt").unwrap_err();
+    assert!(err.contains("{date}"), "{}", err);
+
+    assert!(check_output_name("{stem.txt").is_err());
+    assert!(check_output_name(" ").is_err());
+}
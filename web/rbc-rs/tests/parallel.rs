@@ -0,0 +1,43 @@
+#[path = "../src/csvconv/parallel.rs"]
+mod parallel;
+
+use parallel::{default_jobs, map_in_parallel};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn results_in_order_of_items() {
+    let items: Vec<u64> = (0..50).collect();
+
+    // Earlier items take longer, so they finish last
+    let results = map_in_parallel(&items, 4, |i, item| {
+        std::thread::sleep(std::time::Duration::from_millis(50 - *item));
+        (i, item * 2)
+    });
+
+    assert_eq!(
+        results,
+        (0..50).map(|i| (i as usize, i * 2)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn no_more_than_jobs_run_at_once() {
+    let running = AtomicUsize::new(0);
+    let most = AtomicUsize::new(0);
+
+    map_in_parallel(&[(); 20], 3, |_, _| {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        most.fetch_max(now, Ordering::SeqCst);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        running.fetch_sub(1, Ordering::SeqCst);
+    });
+
+    assert!(most.into_inner() <= 3);
+    assert!(default_jobs() >= 1);
+}
+
+#[test]
+fn no_items_no_results() {
+    let results: Vec<()> = map_in_parallel(&[] as &[u8], 0, |_, _| ());
+    assert!(results.is_empty());
+}
@@ -0,0 +1,133 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+use lib::parse::parse_cpa005;
+use lib::validate::ValidationOptions;
+
+const FILE: &str = include_str!("fixtures/payroll.pds.txt");
+
+#[test]
+fn golden_file_read_back() {
+    let file = parse_cpa005(FILE).unwrap();
+
+    assert_eq!(file.header.client_number, "0123456789");
+    assert_eq!(file.header.file_creation_number, 1);
+    assert_eq!(file.header.file_creation_date, "2045-01-15");
+    assert_eq!(file.header.currency_code, "CAD");
+
+    assert_eq!(file.details.len(), 3);
+    assert_eq!(file.segment_count(), 3);
+    assert_eq!(file.details[0].line, 2);
+    assert_eq!(file.details[0].record_type, 'C');
+
+    let segment = &file.details[0].segments[0];
+    assert_eq!(segment.amount, 125000);
+    assert_eq!(segment.customer_name, "Jane Smith");
+    assert_eq!(segment.client_short_name, "Acme Widgets Lt");
+}
+
+#[test]
+fn totals_reconstructed_from_detail_records() {
+    let file = parse_cpa005(FILE).unwrap();
+    let report = file.report();
+
+    assert_eq!(report.total_credit_amount, 162575);
+    assert_eq!(report.total_credit_count, 3);
+    assert_eq!(report.total_debit_amount, 0);
+    assert_eq!(report.total_debit_count, 0);
+
+    assert_eq!(report.total_credit_amount, file.trailer.total_credit_amount);
+    assert_eq!(report.total_credit_count, file.trailer.total_credit_count);
+}
+
+#[test]
+fn invalid_file_not_read() {
+    let errors = parse_cpa005(&FILE.replace("CAD", "EUR")).unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert!(
+        errors[0].starts_with("line 1: currency_code:"),
+        "{:?}",
+        errors
+    );
+}
+
+#[test]
+fn parsed_file_rebuilt_in_order() {
+    let record = parse_cpa005(FILE).unwrap().to_record();
+    assert!(record
+        .validate(&ValidationOptions::default())
+        .get_error_list()
+        .is_empty());
+
+    let rebuilt = record.build();
+    let lines: Vec<&str> = rebuilt.lines().collect();
+
+    assert!(lines[0].starts_with('A'));
+    assert!(lines[lines.len() - 1].starts_with('Z'));
+    assert_eq!(rebuilt, FILE);
+}
+
+#[test]
+fn parsed_file_exported_as_ndjson() {
+    let file = parse_cpa005(FILE).unwrap();
+    let ndjson = file.to_ndjson();
+
+    assert!(ndjson.ends_with('\n'));
+    let lines: Vec<serde_json::Value> = ndjson
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1 + file.segment_count());
+
+    let summary = &lines[0];
+    assert_eq!(summary["kind"], "summary");
+    assert_eq!(summary["header"]["client_number"], "0123456789");
+    assert_eq!(summary["header"]["file_creation_date"], "2045-01-15");
+    assert_eq!(summary["trailer"]["total_credit_amount"], 162575);
+    assert_eq!(summary["segment_count"], 3);
+    assert_eq!(summary["sha256"], file.sha256);
+
+    let payments = &lines[1..];
+    assert!(payments.iter().all(|p| p["kind"] == "payment"));
+    assert_eq!(payments[0]["client_number"], "0123456789");
+    assert_eq!(payments[0]["record_type"], "C");
+    assert_eq!(payments[0]["record_number"], 2);
+    assert_eq!(payments[0]["line"], 2);
+    assert_eq!(payments[0]["segment"], 1);
+    assert_eq!(payments[0]["amount"], 125000);
+    assert_eq!(payments[0]["customer_name"], "Jane Smith");
+
+    let total: u64 = payments.iter().map(|p| p["amount"].as_u64().unwrap()).sum();
+    assert_eq!(total, file.trailer.total_credit_amount);
+}
+
+#[test]
+fn header_and_trailer_agree_after_round_trip() {
+    let record = parse_cpa005(FILE).unwrap().to_record();
+    assert!(record
+        .validate(&ValidationOptions::default())
+        .get_error_list()
+        .is_empty());
+
+    // Client number and file creation number
+    let rebuilt = record.build();
+    let lines: Vec<&str> = rebuilt.lines().collect();
+    assert_eq!(lines[0][10..24], lines[lines.len() - 1][10..24]);
+    assert_eq!(&lines[0][10..24], "01234567891   ");
+}
+
+#[test]
+fn trailer_of_another_file_not_read() {
+    let mut records: Vec<String> = FILE.lines().map(str::to_string).collect();
+    let last = records.len() - 1;
+    records[last].replace_range(20..24, "2   ");
+
+    let errors = parse_cpa005(&records.join("\n")).unwrap_err();
+
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].starts_with(
+        "line 5: file_creation_number: Does not match the file creation number of the header"
+    ));
+}
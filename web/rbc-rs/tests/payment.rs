@@ -0,0 +1,134 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+use lib::payment::{BasicPayment, BasicPaymentSegment};
+use lib::types::AccountJustify;
+
+const INSTITUTION_FIELD: std::ops::Range<usize> = 19..23;
+const BRANCH_FIELD: std::ops::Range<usize> = 23..28;
+
+// Field 9 follows the transaction code, amount, payment date and
+// institution/branch fields in a payment segment
+const ACCOUNT_FIELD: std::ops::Range<usize> = 28..40;
+
+fn segment() -> BasicPaymentSegment {
+    let mut segment = BasicPaymentSegment::new();
+    segment
+        .set_transaction_code("450".to_string())
+        .set_amount(1000)
+        .set_payment_date(2023, 32)
+        .set_financial_institution_number("003".to_string())
+        .set_financial_institution_branch_number("01234".to_string());
+
+    segment
+}
+
+#[test]
+fn account_number_left_justified_by_default() {
+    let mut segment = segment();
+    segment.set_account_number("123".to_string());
+
+    assert_eq!(&segment.build()[ACCOUNT_FIELD], "123         ");
+}
+
+#[test]
+fn account_number_right_zero_padded() {
+    let mut segment = segment();
+    segment
+        .set_account_number("123".to_string())
+        .set_account_justify(AccountJustify::RightZeroPad);
+
+    assert_eq!(&segment.build()[ACCOUNT_FIELD], "000000000123");
+}
+
+#[test]
+fn numeric_fields_reject_signs_and_blanks() {
+    let mut segment = segment();
+    segment
+        .set_client_number("+123456789".to_string())
+        .set_account_number(String::new())
+        .set_financial_institution_branch_number("+1234".to_string())
+        .set_financial_institution_number("12345".to_string());
+
+    assert_eq!(
+        segment.error_log.get_error_list(),
+        [
+            "Client number must not contain non-numeric digits",
+            "Account number must only include digits",
+            "Branch number must be 5 digits",
+            "Institution number must be at most 4 digits",
+        ]
+    );
+}
+
+#[test]
+fn numeric_fields_keep_leading_zeros() {
+    let mut segment = segment();
+    segment
+        .set_client_number("0000000001".to_string())
+        .set_account_number("0001234".to_string());
+
+    assert!(segment.error_log.get_error_list().is_empty());
+    assert_eq!(segment.client_number, "0000000001");
+    assert_eq!(&segment.build()[ACCOUNT_FIELD], "0001234     ");
+}
+
+#[test]
+fn institution_number_padded_to_four_digits() {
+    for no in ["010", "0010", "00010"] {
+        let mut segment = segment();
+        segment.set_financial_institution_number(no.to_string());
+
+        assert!(segment.error_log.get_error_list().is_empty(), "{}", no);
+        assert_eq!(&segment.build()[INSTITUTION_FIELD], "0010", "{}", no);
+    }
+}
+
+#[test]
+fn branch_number_longer_than_five_digits_rejected() {
+    let mut segment = segment();
+    segment.set_financial_institution_branch_number("1234".to_string());
+    assert!(segment.error_log.get_error_list().is_empty());
+    assert_eq!(&segment.build()[BRANCH_FIELD], "01234");
+
+    segment.set_financial_institution_branch_number("123456".to_string());
+    assert_eq!(
+        segment.error_log.get_error_list(),
+        ["Branch number must be 5 digits"]
+    );
+    // The branch number set before is kept rather than cut short
+    assert_eq!(segment.financial_institution_branch_number, "01234");
+}
+
+#[test]
+fn client_number_separators_stripped() {
+    for no in ["123-456-7890", "123 456 7890"] {
+        let mut segment = segment();
+        segment.set_client_number(no.to_string());
+        assert!(segment.error_log.get_error_list().is_empty(), "{}", no);
+        assert_eq!(segment.client_number, "1234567890");
+
+        let mut payment = BasicPayment::new();
+        payment.set_client_number(no.to_string());
+        assert!(payment.error_log.get_error_list().is_empty(), "{}", no);
+        assert_eq!(payment.client_number, "1234567890");
+    }
+}
+
+#[test]
+fn client_number_non_numeric_after_stripping_rejected() {
+    let mut segment = segment();
+    segment.set_client_number("123-45A-7890".to_string());
+    assert_eq!(
+        segment.error_log.get_error_list(),
+        ["Client number must not contain non-numeric digits"]
+    );
+
+    let mut payment = BasicPayment::new();
+    payment.set_client_number("123-45A-7890".to_string());
+    assert_eq!(
+        payment.error_log.get_error_list(),
+        ["Client number must be exactly 10 numeric digits long"]
+    );
+}
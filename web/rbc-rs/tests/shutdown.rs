@@ -0,0 +1,145 @@
+#![cfg(unix)]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const BOUNDARY: &str = "----rbcachboundary";
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn start_server(port: u16) -> Child {
+    let server = Command::new(env!("CARGO_BIN_EXE_web"))
+        .args(["--bind", &format!("127.0.0.1:{}", port)])
+        .args(["--shutdown-timeout", "10"])
+        .env_clear()
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // The port is bound before the workers start, so wait for a response
+    // rather than just a connection
+    let started = Instant::now();
+    while !healthy(port) {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "server did not start"
+        );
+        sleep(Duration::from_millis(50));
+    }
+
+    server
+}
+
+fn healthy(port: u16) -> bool {
+    let mut conn = match TcpStream::connect(("127.0.0.1", port)) {
+        Ok(c) => c,
+        Err(_) => return false,
+    };
+
+    let mut response = String::new();
+    conn.write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .and_then(|_| conn.read_to_string(&mut response))
+        .is_ok()
+        && response.starts_with("HTTP/1.1 200")
+}
+
+fn signal(server: &Child, name: &str) {
+    let status = Command::new("kill")
+        .args(["-s", name, &server.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn wait_for_exit(server: &mut Child) -> std::process::ExitStatus {
+    let started = Instant::now();
+
+    loop {
+        if let Some(status) = server.try_wait().unwrap() {
+            return status;
+        }
+
+        if started.elapsed() > Duration::from_secs(10) {
+            server.kill().unwrap();
+            panic!("server did not exit");
+        }
+
+        sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn in_flight_request_completes_after_sigterm() {
+    let port = free_port();
+    let mut server = start_server(port);
+
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"payroll.csv\"\r\nContent-Type: text/csv\r\n\r\n{c}\r\n--{b}--\r\n",
+        b = BOUNDARY,
+        c = include_str!("fixtures/payroll.csv")
+    );
+    let (first, rest) = body.split_at(body.len() / 2);
+
+    let mut conn = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(
+        conn,
+        "POST /convert?convtype=PDS HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: multipart/form-data; boundary={}\r\nContent-Length: {}\r\n\r\n{}",
+        BOUNDARY,
+        body.len(),
+        first
+    )
+    .unwrap();
+
+    // Let the server start on the request before asking it to stop
+    sleep(Duration::from_millis(200));
+    signal(&server, "TERM");
+    sleep(Duration::from_millis(200));
+
+    assert!(
+        server.try_wait().unwrap().is_none(),
+        "server exited with a request in flight"
+    );
+
+    conn.write_all(rest.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    conn.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.contains("Z000000005"));
+
+    assert!(wait_for_exit(&mut server).success());
+}
+
+#[test]
+fn second_signal_forces_exit() {
+    let port = free_port();
+    let mut server = start_server(port);
+
+    // An upload that never finishes would hold up a graceful shutdown
+    let mut conn = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(
+        conn,
+        "POST /convert?convtype=PDS HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary={}\r\nContent-Length: 100000\r\n\r\n--{}\r\n",
+        BOUNDARY, BOUNDARY
+    )
+    .unwrap();
+
+    sleep(Duration::from_millis(200));
+    signal(&server, "INT");
+    sleep(Duration::from_millis(200));
+    assert!(server.try_wait().unwrap().is_none());
+
+    signal(&server, "INT");
+
+    assert!(!wait_for_exit(&mut server).success());
+}
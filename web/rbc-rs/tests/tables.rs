@@ -0,0 +1,46 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+use chrono::NaiveDate;
+use lib::holidays::statutory_holidays;
+use lib::tables::{holiday, HOLIDAYS, HOLIDAY_TABLE_BUILDS, HOLIDAY_YEARS};
+use std::sync::atomic::Ordering;
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+#[test]
+fn holidays_found_in_shared_table() {
+    assert_eq!(holiday(date(2024, 7, 1)), Some("Canada Day"));
+    // Christmas 2022 fell on a Sunday, moving Boxing Day on to the Tuesday
+    assert_eq!(holiday(date(2022, 12, 26)), Some("Christmas Day"));
+    assert_eq!(holiday(date(2022, 12, 27)), Some("Boxing Day"));
+    assert_eq!(holiday(date(2024, 7, 2)), None);
+
+    let year = *HOLIDAY_YEARS.start();
+    for (day, name) in statutory_holidays(year) {
+        assert_eq!(HOLIDAYS.get(&day), Some(&name));
+    }
+}
+
+#[test]
+fn holidays_outside_table_still_found() {
+    let year = HOLIDAY_YEARS.end() + 1;
+    assert_eq!(holiday(date(year, 7, 1)), Some("Canada Day"));
+    assert!(!HOLIDAYS.contains_key(&date(year, 7, 1)));
+}
+
+#[test]
+fn shared_table_built_once() {
+    let threads: Vec<_> = (0..8)
+        .map(|_| std::thread::spawn(|| HOLIDAYS.len()))
+        .collect();
+
+    for thread in threads {
+        assert!(thread.join().unwrap() > 0);
+    }
+
+    assert_eq!(HOLIDAY_TABLE_BUILDS.load(Ordering::Relaxed), 1);
+}
@@ -0,0 +1,89 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+use chrono::NaiveDate;
+use lib::utils::{is_all_ascii_digits, mask_account, n_digits, to_cpa_date};
+
+#[test]
+fn digits_accepted() {
+    assert!(is_all_ascii_digits("0123456789"));
+    assert!(is_all_ascii_digits("7"));
+}
+
+#[test]
+fn leading_zeros_kept_as_digits() {
+    assert!(is_all_ascii_digits("0000"));
+    assert!(is_all_ascii_digits("00123"));
+}
+
+#[test]
+fn empty_string_not_digits() {
+    assert!(!is_all_ascii_digits(""));
+}
+
+#[test]
+fn mixed_content_not_digits() {
+    for s in [
+        "12a4", " 123", "123 ", "+123", "-123", "1.5", "1,000", "١٢٣",
+    ] {
+        assert!(!is_all_ascii_digits(s), "{:?} accepted as digits", s);
+    }
+}
+
+#[test]
+fn longer_than_any_integer_still_digits() {
+    assert!(is_all_ascii_digits(&"9".repeat(40)));
+}
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+#[test]
+fn cpa_date_counts_days_from_january_first() {
+    assert_eq!(to_cpa_date(date(2045, 1, 1)), (2045, 1));
+}
+
+#[test]
+fn cpa_date_of_leap_day() {
+    assert_eq!(to_cpa_date(date(2024, 2, 29)), (2024, 60));
+}
+
+#[test]
+fn cpa_date_of_last_day_of_year() {
+    assert_eq!(to_cpa_date(date(2023, 12, 31)), (2023, 365));
+    assert_eq!(to_cpa_date(date(2024, 12, 31)), (2024, 366));
+}
+
+#[test]
+fn zero_has_one_digit() {
+    assert_eq!(n_digits(0), 1);
+}
+
+#[test]
+fn digits_counted_at_each_power_of_ten() {
+    assert_eq!(n_digits(9), 1);
+    assert_eq!(n_digits(10), 2);
+    assert_eq!(n_digits(9999), 4);
+    assert_eq!(n_digits(10000), 5);
+    assert_eq!(n_digits(u64::MAX), 20);
+}
+
+#[test]
+fn account_masked_but_for_last_four() {
+    assert_eq!(mask_account("123456789012"), "********9012");
+    assert_eq!(mask_account("12345"), "*2345");
+
+    // Padding out to the field is kept, and does not count as shown
+    assert_eq!(mask_account("7654321     "), "***4321     ");
+    assert_eq!(mask_account("000007654321"), "********4321");
+}
+
+#[test]
+fn short_account_masked_whole() {
+    assert_eq!(mask_account("1234"), "****");
+    assert_eq!(mask_account("7"), "*");
+    assert_eq!(mask_account("12  "), "**  ");
+    assert_eq!(mask_account(""), "");
+}
@@ -0,0 +1,332 @@
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+use chrono::NaiveDate;
+use lib::header::CPA005Record;
+use lib::holidays::statutory_holidays;
+use lib::payment::{BasicPayment, BasicPaymentSegment};
+use lib::transits::TransitList;
+use lib::types::RecordType;
+use lib::validate::ValidationOptions;
+
+fn record(creation: (u32, u32), payment_date: (u64, u64)) -> CPA005Record {
+    let mut record = CPA005Record::new();
+    record.set_file_creation_date(creation.0, creation.1);
+
+    let mut segment = BasicPaymentSegment::new();
+    segment
+        .set_customer_number("1001".to_string())
+        .set_payment_date(payment_date.0, payment_date.1);
+
+    let mut payment = BasicPayment::new();
+    payment.segments.push(segment);
+    record.add_basic_payment(payment);
+
+    record
+}
+
+#[test]
+fn past_dated_payment_is_an_error() {
+    let log = record((2024, 100), (2024, 99)).validate(&ValidationOptions::default());
+
+    assert_eq!(log.get_error_list().len(), 1);
+    assert!(log.get_error_list()[0].contains("before the file creation date"));
+}
+
+#[test]
+fn past_dated_payment_is_a_warning_when_lenient() {
+    let log = record((2024, 100), (2023, 200)).validate(&ValidationOptions {
+        lenient: true,
+        ..Default::default()
+    });
+
+    assert!(log.get_error_list().is_empty());
+    assert_eq!(log.get_warning_list().len(), 1);
+}
+
+#[test]
+fn future_dated_payment_is_ok() {
+    let log = record((2024, 100), (2024, 100)).validate(&ValidationOptions::default());
+    assert!(log.get_error_list().is_empty());
+
+    let log = record((2024, 100), (2025, 1)).validate(&ValidationOptions::default());
+    assert!(log.get_error_list().is_empty());
+}
+
+#[test]
+fn payment_date_across_century_boundary_is_ok() {
+    // Encoded as year 00, which must be read as 2100 rather than 2000
+    let log = record((2099, 360), (2100, 5)).validate(&ValidationOptions::default());
+    assert!(log.get_error_list().is_empty());
+
+    let log = record((2100, 5), (2099, 360)).validate(&ValidationOptions::default());
+    assert_eq!(log.get_error_list().len(), 1);
+}
+
+#[test]
+fn payment_date_checked_without_creation_date() {
+    // Left at (0, 0), as when the header's date could not be read
+    let mut record = CPA005Record::new();
+
+    let mut segment = BasicPaymentSegment::new();
+    segment
+        .set_customer_number("1001".to_string())
+        .set_payment_date(2075, 100);
+
+    let mut payment = BasicPayment::new();
+    payment.segments.push(segment);
+    record.add_basic_payment(payment);
+
+    let log = record.validate(&ValidationOptions {
+        business_days: true,
+        uniform_payment_date: true,
+        ..Default::default()
+    });
+    assert!(log.get_error_list().is_empty());
+}
+
+fn credit_record() -> CPA005Record {
+    let mut record = record((2024, 100), (2024, 100));
+
+    let mut segment = BasicPaymentSegment::new();
+    segment
+        .set_customer_number("1002".to_string())
+        .set_amount(1250)
+        .set_payment_date(2024, 100);
+
+    let mut stray = BasicPayment::new();
+    stray.record_type = RecordType::Debit;
+    stray.segments.push(segment);
+    record.add_basic_payment(stray);
+
+    record
+}
+
+#[test]
+fn stray_debit_in_credit_file_is_an_error() {
+    let log = credit_record().validate(&ValidationOptions {
+        record_type: Some(RecordType::Credit),
+        ..Default::default()
+    });
+
+    assert_eq!(log.get_error_list().len(), 1);
+    assert!(log.get_error_list()[0].contains("1 debit payments totalling 12.50"));
+}
+
+#[test]
+fn opposing_totals_not_checked_without_declared_type() {
+    let log = credit_record().validate(&ValidationOptions::default());
+    assert!(log.get_error_list().is_empty());
+
+    let log = credit_record().validate(&ValidationOptions {
+        record_type: Some(RecordType::Debit),
+        lenient: true,
+        ..Default::default()
+    });
+    assert!(log.get_error_list().is_empty());
+    assert_eq!(log.get_warning_list().len(), 1);
+}
+
+#[test]
+fn records_out_of_order_are_errors() {
+    let mut record = credit_record();
+    record.basic_payment[0].record_type = RecordType::Trailer;
+    record.basic_payment.swap(0, 1);
+
+    let errors = record
+        .validate(&ValidationOptions::default())
+        .get_error_list();
+
+    assert!(errors.contains(
+        &"Detail record 2 has record type Z, only the header may be A and the trailer Z"
+            .to_string()
+    ));
+    assert!(errors.contains(&"Detail record 1 is numbered 3, expected 2".to_string()));
+    assert!(errors.contains(&"Detail record 2 is numbered 2, expected 3".to_string()));
+
+    // A payment removed after it was numbered
+    let mut record = credit_record();
+    record.basic_payment.pop();
+
+    let errors = record
+        .validate(&ValidationOptions::default())
+        .get_error_list();
+    assert_eq!(
+        errors,
+        ["Trailer record would be numbered 4, expected 3 after 1 detail records"]
+    );
+}
+
+#[test]
+fn weekend_payment_date_warns_of_business_days() {
+    // 2024-06-29 is a Saturday
+    let log = record((2024, 100), (2024, 181)).validate(&ValidationOptions {
+        business_days: true,
+        ..Default::default()
+    });
+
+    assert!(log.get_error_list().is_empty());
+    assert_eq!(
+        log.get_warning_list(),
+        ["Payment date 2024-06-29 for customer 1001 is a Saturday, not a business day, and will be processed on the next business day"]
+    );
+
+    // Off by default
+    let log = record((2024, 100), (2024, 181)).validate(&ValidationOptions::default());
+    assert!(log.get_warning_list().is_empty());
+}
+
+#[test]
+fn holiday_payment_date_warns_of_business_days() {
+    // Canada Day, a Monday in 2024
+    let log = record((2024, 100), (2024, 183)).validate(&ValidationOptions {
+        business_days: true,
+        ..Default::default()
+    });
+
+    assert_eq!(log.get_warning_list().len(), 1);
+    assert!(log.get_warning_list()[0].contains("2024-07-01 for customer 1001 is Canada Day"));
+}
+
+#[test]
+fn weekday_payment_date_is_a_business_day() {
+    // Tuesday 2024-07-02
+    let log = record((2024, 100), (2024, 184)).validate(&ValidationOptions {
+        business_days: true,
+        ..Default::default()
+    });
+
+    assert!(log.get_warning_list().is_empty());
+    assert!(log.get_error_list().is_empty());
+}
+
+#[test]
+fn weekend_holidays_observed_on_next_weekday() {
+    let holidays = statutory_holidays(2021);
+    let date = |m, d| NaiveDate::from_ymd_opt(2021, m, d).unwrap();
+
+    // Christmas and Boxing Day fell on a Saturday and Sunday
+    assert!(holidays.contains(&(date(12, 27), "Christmas Day")));
+    assert!(holidays.contains(&(date(12, 28), "Boxing Day")));
+    assert!(holidays.contains(&(date(4, 2), "Good Friday")));
+    assert!(holidays.contains(&(date(5, 24), "Victoria Day")));
+    assert!(holidays.contains(&(date(9, 30), "National Day for Truth and Reconciliation")));
+}
+
+#[test]
+fn differing_payment_dates_warn_when_uniform_date_expected() {
+    let mut record = record((2024, 100), (2024, 150));
+
+    let mut segment = BasicPaymentSegment::new();
+    segment
+        .set_customer_number("1002".to_string())
+        .set_payment_date(2024, 151);
+    let mut payment = BasicPayment::new();
+    payment.segments.push(segment);
+    record.add_basic_payment(payment);
+
+    let options = ValidationOptions {
+        uniform_payment_date: true,
+        ..Default::default()
+    };
+    let log = record.validate(&options);
+
+    assert!(log.get_error_list().is_empty());
+    assert_eq!(
+        log.get_warning_list(),
+        ["Payment date 2024/151 for customer 1002 differs from 2024/150 for customer 1001, expected every payment on one date"]
+    );
+
+    // Not checked unless asked for
+    assert!(record
+        .validate(&ValidationOptions::default())
+        .get_warning_list()
+        .is_empty());
+}
+
+const TRANSITS: &str = "institution,branch
+# Royal Bank
+003,01234
+003,00002
+004,10392
+";
+
+fn record_with_transit(institution: &str, branch: &str) -> CPA005Record {
+    let mut record = CPA005Record::new();
+    record.set_file_creation_date(2024, 100);
+
+    let mut segment = BasicPaymentSegment::new();
+    segment
+        .set_customer_number("1001".to_string())
+        .set_financial_institution_number(institution.to_string())
+        .set_financial_institution_branch_number(branch.to_string())
+        .set_payment_date(2024, 101);
+
+    let mut payment = BasicPayment::new();
+    payment.segments.push(segment);
+    record.add_basic_payment(payment);
+
+    record
+}
+
+#[test]
+fn listed_transit_passes() {
+    let options = ValidationOptions {
+        transits: Some(TransitList::parse(TRANSITS).unwrap()),
+        ..Default::default()
+    };
+
+    // However many leading zeros either is written with
+    let log = record_with_transit("3", "2").validate(&options);
+
+    assert!(log.get_error_list().is_empty());
+    assert!(log.get_warning_list().is_empty());
+}
+
+#[test]
+fn branch_of_another_institution_is_an_error() {
+    let options = ValidationOptions {
+        transits: Some(TransitList::parse(TRANSITS).unwrap()),
+        ..Default::default()
+    };
+
+    let log = record_with_transit("003", "10392").validate(&options);
+
+    assert_eq!(
+        log.get_error_list(),
+        ["Branch 10392 of institution 0003 for customer 1001 is not in the list of valid transits"]
+    );
+
+    // Not checked at all without a list
+    let log = record_with_transit("003", "10392").validate(&ValidationOptions::default());
+    assert!(log.get_error_list().is_empty());
+}
+
+#[test]
+fn unlisted_transit_is_a_warning_when_lenient() {
+    let options = ValidationOptions {
+        lenient: true,
+        transits: Some(TransitList::parse(TRANSITS).unwrap()),
+        ..Default::default()
+    };
+
+    let log = record_with_transit("004", "01234").validate(&options);
+
+    assert!(log.get_error_list().is_empty());
+    assert_eq!(log.get_warning_list().len(), 1);
+}
+
+#[test]
+fn malformed_transit_list_rejected() {
+    let transits = TransitList::parse(TRANSITS).unwrap();
+    assert_eq!(transits.len(), 3);
+
+    assert_eq!(
+        TransitList::parse("003,01234\n003-01234\n"),
+        Err(
+            "Line 2 of the transit list is not an institution and branch number: 003-01234"
+                .to_string()
+        )
+    );
+}
@@ -0,0 +1,2601 @@
+use actix_web::http::StatusCode;
+use actix_web::{test, App};
+use sha2::{Digest, Sha256};
+
+#[path = "../src/lib/mod.rs"]
+#[allow(dead_code)]
+mod lib;
+
+#[path = "../src/csvconv/mod.rs"]
+#[allow(dead_code)]
+mod csvconv;
+
+#[path = "../src/server/mod.rs"]
+#[allow(dead_code)]
+mod server;
+use server::audit::AuditLog;
+use server::auth::ApiKeys;
+use server::cors::CorsConfig;
+use server::downloads::DownloadStore;
+use server::fetch::{AllowedHosts, UrlFetcher};
+use server::jobs::JobQueue;
+use server::limits::{UploadLimits, ZipLimits};
+use server::pool::{ConversionPool, DEFAULT_CONVERSION_TIMEOUT};
+use server::profiles::Profiles;
+use server::ratelimit::RateLimiter;
+use server::ServerConfig;
+
+const BOUNDARY: &str = "----rbcachboundary";
+
+fn multipart_body(file_name: &str, contents: &str) -> String {
+    format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{f}\"\r\nContent-Type: text/csv\r\n\r\n{c}\r\n--{b}--\r\n",
+        b = BOUNDARY,
+        f = file_name,
+        c = contents
+    )
+}
+
+/// A multipart body with `fields` sent as form values ahead of the file
+fn multipart_form(fields: &[(&str, &str)], file_name: &str, contents: &str) -> String {
+    let mut body = String::new();
+    for (name, value) in fields {
+        body.push_str(&format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"{n}\"\r\n\r\n{v}\r\n",
+            b = BOUNDARY,
+            n = name,
+            v = value
+        ));
+    }
+    body + &multipart_body(file_name, contents)
+}
+
+fn config(limits: UploadLimits) -> ServerConfig {
+    ServerConfig {
+        limits,
+        ..Default::default()
+    }
+}
+
+fn multipart_request(uri: &str, body: String) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(uri)
+        .insert_header((
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        ))
+        .insert_header(("content-length", body.len().to_string()))
+        .set_payload(body)
+}
+
+#[actix_web::test]
+async fn convert_rejects_body_over_limit() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::new(64, 64)))),
+    )
+    .await;
+
+    let body = multipart_body("payroll.csv", &"A".repeat(256));
+    let body_len = body.len();
+
+    let req = multipart_request("/convert?convtype=PDS", body).to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["limit"], 64);
+    assert_eq!(json["received"], body_len);
+    assert!(json["detail"].is_string());
+}
+
+#[actix_web::test]
+async fn convert_rejects_field_over_limit() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::new(4096, 64)))),
+    )
+    .await;
+
+    let req = multipart_request(
+        "/convert?convtype=PDS",
+        multipart_body("payroll.csv", &"A".repeat(256)),
+    )
+    .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["limit"], 64);
+    assert!(json["received"].as_u64().unwrap() > 64);
+}
+
+#[actix_web::test]
+async fn convert_streams_large_upload_without_leaving_temp_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    let mut csv = include_str!("fixtures/payroll.csv").to_string();
+    let row = "1004,Richard Roe,003,01234,7654321,$10.00,N,,\n";
+    let rows = 4 * 1024 * 1024 / row.len();
+    csv.push_str(&row.repeat(rows));
+
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        temp_dir: Some(temp_dir.path().to_path_buf()),
+        ..Default::default()
+    })))
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request("/convert?convtype=PDS", multipart_body("payroll.csv", &csv))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body = test::read_body(resp).await;
+    let output = std::str::from_utf8(&body).unwrap();
+
+    // Header, the three fixture payments, the appended payments and trailer
+    assert_eq!(output.lines().count(), 1 + 3 + rows + 1);
+    assert!(output.lines().last().unwrap().starts_with('Z'));
+
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[actix_web::test]
+async fn convert_removes_temp_file_on_error() {
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        limits: UploadLimits::new(4096, 4096),
+        temp_dir: Some(temp_dir.path().to_path_buf()),
+        ..Default::default()
+    })))
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS",
+            multipart_body("payroll.csv", "not a payment file"),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[actix_web::test]
+async fn healthz_reports_ok() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/healthz").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["status"], "ok");
+}
+
+#[actix_web::test]
+async fn version_reports_build_information() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/version").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert!(json["commit"].is_string());
+    assert_eq!(json["formats"][0], "CPA-005 PDS");
+    assert_eq!(json["formats"][1], "CPA-005 PAD");
+}
+
+#[cfg(feature = "web")]
+#[actix_web::test]
+async fn metrics_count_conversions() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp
+        .headers()
+        .get("content-type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain; version=0.0.4"));
+
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert!(lines.contains(&"conversions_total 1"));
+    assert!(lines.contains(&"conversion_errors_total 0"));
+    assert!(lines.contains(&"rows_processed_total 3"));
+    assert!(lines.contains(&"# TYPE conversion_duration_seconds histogram"));
+    assert!(lines.contains(&"conversion_duration_seconds_count 1"));
+}
+
+#[actix_web::test]
+async fn schema_describes_csv_columns() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/schema").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+
+    let customer_name = json["columns"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["name"] == "Customer Name")
+        .unwrap();
+    assert_eq!(customer_name["type"], "text");
+    assert_eq!(customer_name["max_length"], lib::payment::MAX_NAME_LEN);
+
+    assert_eq!(json["metadata"][0]["name"], "Client Name");
+    assert_eq!(json["metadata"][4]["format"], "YYYY/MM/DD");
+    assert_eq!(json["date_formats"][0], "YYYY/MM/DD");
+    assert_eq!(json["suspend"]["tokens"][0], "Y");
+    assert_eq!(json["amount"]["ignored_characters"][0], ",");
+}
+
+#[actix_web::test]
+async fn openapi_document_describes_api() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/openapi.json").to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body = test::read_body(resp).await;
+    let spec: utoipa::openapi::OpenApi = serde_json::from_slice(&body).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    assert!(json["openapi"].as_str().unwrap().starts_with("3."));
+    assert!(spec.paths.paths["/convert"].post.is_some());
+    assert!(spec.paths.paths["/jobs/{id}"].get.is_some());
+
+    let schemas = &json["components"]["schemas"];
+    assert!(schemas["ConversionReport"]["properties"]["output_sha256"].is_object());
+    assert!(schemas["Problem"]["properties"]["type"].is_object());
+    assert_eq!(
+        json["paths"]["/convert"]["post"]["responses"]["400"]["content"]
+            ["application/problem+json"]["schema"]["$ref"],
+        "#/components/schemas/Problem"
+    );
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/docs").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let page = test::read_body(resp).await;
+    assert!(std::str::from_utf8(&page)
+        .unwrap()
+        .contains("/openapi.json"));
+}
+
+async fn cors_preflight(cors: CorsConfig, origin: &str) -> actix_web::dev::ServiceResponse {
+    let config = ServerConfig {
+        cors,
+        ..Default::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .wrap(config.cors.middleware())
+            .configure(server::configure(config)),
+    )
+    .await;
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/convert?convtype=PDS")
+        .insert_header(("origin", origin))
+        .insert_header(("access-control-request-method", "POST"))
+        .insert_header(("access-control-request-headers", "content-type"))
+        .to_request();
+
+    test::call_service(&app, req).await.map_into_boxed_body()
+}
+
+fn intranet_cors() -> CorsConfig {
+    CorsConfig {
+        allowed_origins: vec!["https://intranet.example.com".to_string()],
+        allow_credentials: false,
+    }
+}
+
+#[actix_web::test]
+async fn cors_preflight_from_allowed_origin() {
+    let resp = cors_preflight(intranet_cors(), "https://intranet.example.com").await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("access-control-allow-origin").unwrap(),
+        "https://intranet.example.com"
+    );
+    assert!(resp
+        .headers()
+        .get("access-control-allow-credentials")
+        .is_none());
+}
+
+#[actix_web::test]
+async fn cors_preflight_from_disallowed_origin() {
+    let resp = cors_preflight(intranet_cors(), "https://evil.example.com").await;
+
+    assert!(!resp.status().is_success());
+    assert!(resp.headers().get("access-control-allow-origin").is_none());
+}
+
+#[actix_web::test]
+async fn cors_disabled_without_origins() {
+    let resp = cors_preflight(CorsConfig::default(), "https://intranet.example.com").await;
+
+    assert!(resp.headers().get("access-control-allow-origin").is_none());
+}
+
+#[actix_web::test]
+async fn convert_responds_with_txt_attachment() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS",
+            multipart_body("../payroll march.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"payroll march.txt\""
+    );
+}
+
+#[actix_web::test]
+async fn convert_returns_report_as_json() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS&response=json",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get("content-disposition").is_none());
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["filename"], "payroll.txt");
+    // The fixture's client name is one character too long for the short name
+    assert_eq!(json["warnings"].as_array().unwrap().len(), 1);
+
+    // Credit total and count of the trailer
+    let trailer = json["content"]
+        .as_str()
+        .unwrap()
+        .lines()
+        .last()
+        .unwrap()
+        .to_string();
+    let report = &json["report"];
+    assert_eq!(
+        trailer[46..60].parse::<u64>().unwrap(),
+        report["total_credit_amount"].as_u64().unwrap()
+    );
+    assert_eq!(
+        trailer[60..68].parse::<u64>().unwrap(),
+        report["total_credit_count"].as_u64().unwrap()
+    );
+    assert_eq!(report["total_credit_count"], 3);
+}
+
+#[actix_web::test]
+async fn converted_file_hash_sent_and_recorded() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        audit: Some(AuditLog::open(&dir.path().join("audit.db")).unwrap()),
+        ..Default::default()
+    })))
+    .await;
+
+    let convert = |uri: &str| {
+        multipart_request(
+            uri,
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request()
+    };
+
+    let resp = test::call_service(&app, convert("/convert?convtype=PDS")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let header = resp.headers().get("x-content-sha256").unwrap().clone();
+    let body = test::read_body(resp).await;
+    let sha256: String = Sha256::digest(&body)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(header, sha256.as_str());
+
+    let resp = test::call_service(&app, convert("/convert?convtype=PDS&response=json")).await;
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["report"]["output_sha256"], sha256);
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/audit").to_request()).await;
+    let page: serde_json::Value = test::read_body_json(resp).await;
+    for entry in page["entries"].as_array().unwrap() {
+        assert_eq!(entry["output_sha256"], sha256);
+    }
+}
+
+#[actix_web::test]
+async fn convert_rejects_unknown_response_format() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS&response=xml",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+async fn convert_form(uri: &str, fields: &[(&str, &str)]) -> actix_web::dev::ServiceResponse {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    test::call_service(
+        &app,
+        multipart_request(
+            uri,
+            multipart_form(fields, "payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await
+}
+
+#[actix_web::test]
+async fn convert_accepts_settings_as_form_fields() {
+    let resp = convert_form(
+        "/convert",
+        &[
+            ("convtype", "PDS"),
+            ("creation_date", "2045-01-15"),
+            ("response", "json"),
+        ],
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["report"]["file_creation_date"], "2045-01-15");
+    assert_eq!(json["report"]["total_credit_count"], 3);
+}
+
+#[actix_web::test]
+async fn convert_form_fields_take_precedence_over_query() {
+    let resp = convert_form(
+        "/convert?convtype=PAD&response=file",
+        &[("convtype", "PDS"), ("response", "json")],
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["report"]["total_credit_count"], 3);
+    assert_eq!(json["report"]["total_debit_count"], 0);
+}
+
+#[actix_web::test]
+async fn convert_settings_fall_back_to_query() {
+    let resp = convert_form("/convert?convtype=PDS", &[("display_name", "Acme")]).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get("content-disposition").is_some());
+}
+
+#[actix_web::test]
+async fn convert_setting_errors_name_their_source() {
+    for (uri, fields, message) in [
+        (
+            "/convert?convtype=PDS",
+            &[("convtype", "ABC")][..],
+            "convtype form field must be PDS or PAD, not \"ABC\"",
+        ),
+        (
+            "/convert?convtype=PDS&creation_date=15/01/2045",
+            &[][..],
+            "creation_date query parameter must be a YYYY-MM-DD date, not \"15/01/2045\"",
+        ),
+        (
+            "/convert",
+            &[][..],
+            "convtype must be given as a form field or query parameter",
+        ),
+    ] {
+        let resp = convert_form(uri, fields).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let json: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(json["detail"], message);
+    }
+}
+
+/// Asserts `resp` is an RFC 7807 problem document and returns it
+async fn problem(resp: actix_web::dev::ServiceResponse, status: StatusCode) -> serde_json::Value {
+    assert_eq!(resp.status(), status);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/problem+json"
+    );
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert!(json["type"].is_string());
+    assert!(json["title"].is_string());
+    assert!(json["detail"].is_string());
+    assert_eq!(json["status"], status.as_u16());
+    assert!(json["errors"].is_array());
+    json
+}
+
+#[actix_web::test]
+async fn bad_convtype_is_a_problem() {
+    let json = problem(
+        convert_form("/convert?convtype=XYZ", &[]).await,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+
+    assert_eq!(json["type"], "/problems/bad-request");
+    assert_eq!(json["errors"][0], json["detail"]);
+}
+
+#[actix_web::test]
+async fn invalid_csv_is_a_problem_listing_every_error() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let csv = include_str!("fixtures/payroll.csv")
+        .replace("1234567", "12x4567")
+        .replace("$300.50", "three hundred");
+
+    let resp = test::call_service(
+        &app,
+        multipart_request("/convert?convtype=PDS", multipart_body("payroll.csv", &csv))
+            .to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::BAD_REQUEST).await;
+
+    assert_eq!(json["type"], "/problems/invalid-file");
+    let errors: Vec<&str> = json["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e.as_str().unwrap())
+        .collect();
+    assert!(errors.contains(&"Account number must only include digits"));
+    assert!(errors.contains(&"Failed to parse payment amount: three hundred"));
+    assert_eq!(
+        json["detail"],
+        format!("The file has {} errors", errors.len())
+    );
+}
+
+#[actix_web::test]
+async fn oversized_upload_is_a_problem() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::new(64, 64)))),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS",
+            multipart_body("payroll.csv", &"A".repeat(256)),
+        )
+        .to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::PAYLOAD_TOO_LARGE).await;
+
+    assert_eq!(json["type"], "/problems/payload-too-large");
+    assert_eq!(json["limit"], 64);
+}
+
+#[actix_web::test]
+async fn errors_in_plain_text_when_asked_for() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS",
+            multipart_body("bad.csv", "Client Name,Acme\n"),
+        )
+        .insert_header(("accept", "text/plain"))
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/plain; charset=utf-8"
+    );
+
+    let body = test::read_body(resp).await;
+    let text = std::str::from_utf8(&body).unwrap();
+    assert!(!text.starts_with('{'));
+    assert!(text.contains("Client Number"));
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    use std::io::Read;
+
+    let mut decoded = Vec::new();
+    flate2::read::GzDecoder::new(data)
+        .read_to_end(&mut decoded)
+        .unwrap();
+    decoded
+}
+
+fn gzipped_request(uri: &str, body: String) -> test::TestRequest {
+    let compressed = gzip(body.as_bytes());
+
+    test::TestRequest::post()
+        .uri(uri)
+        .insert_header((
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        ))
+        .insert_header(("content-encoding", "gzip"))
+        .insert_header(("content-length", compressed.len().to_string()))
+        .set_payload(compressed)
+}
+
+#[actix_web::test]
+async fn convert_accepts_gzipped_upload() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let body = multipart_body("payroll.csv", include_str!("fixtures/payroll.csv"));
+
+    let resp = test::call_service(
+        &app,
+        gzipped_request("/convert?convtype=PDS", body.clone()).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let from_gzip = test::read_body(resp).await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request("/convert?convtype=PDS", body).to_request(),
+    )
+    .await;
+    let from_plain = test::read_body(resp).await;
+
+    assert_eq!(from_gzip, from_plain);
+}
+
+#[actix_web::test]
+async fn convert_limits_decompressed_size_of_gzipped_upload() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::new(4096, 4096)))),
+    )
+    .await;
+
+    // Compresses to well under the limit
+    let req = gzipped_request(
+        "/convert?convtype=PDS",
+        multipart_body("payroll.csv", &"A".repeat(64 * 1024)),
+    );
+
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert!(json["received"].as_u64().unwrap() > 4096);
+}
+
+#[actix_web::test]
+async fn convert_response_compressed_when_accepted() {
+    let app = test::init_service(
+        App::new()
+            .wrap(actix_web::middleware::Compress::default())
+            .configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let request = || {
+        multipart_request(
+            "/convert?convtype=PDS",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+    };
+
+    let resp = test::call_service(
+        &app,
+        request()
+            .insert_header(("accept-encoding", "gzip"))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("content-encoding").unwrap(), "gzip");
+    assert_eq!(
+        resp.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"payroll.txt\""
+    );
+    let compressed = test::read_body(resp).await;
+
+    let resp = test::call_service(&app, request().to_request()).await;
+    assert!(resp.headers().get("content-encoding").is_none());
+    let plain = test::read_body(resp).await;
+
+    assert_eq!(gunzip(&compressed), plain);
+}
+
+#[actix_web::test]
+async fn ui_index_served_from_bundle() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    for uri in ["/", "/index.html"] {
+        let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "text/html");
+
+        let body = test::read_body(resp).await;
+        assert_eq!(body, include_str!("../../ui/dist/index.html"));
+    }
+}
+
+#[actix_web::test]
+async fn ui_assets_served_from_directory() {
+    let ui_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(ui_dir.path().join("static")).unwrap();
+    std::fs::write(
+        ui_dir.path().join("static/main.3f2a9c1b.js"),
+        "console.log(1);",
+    )
+    .unwrap();
+    std::fs::write(ui_dir.path().join("styles.css"), "body {}").unwrap();
+
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        ui_dir: Some(ui_dir.path().to_path_buf()),
+        ..Default::default()
+    })))
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/static/main.3f2a9c1b.js")
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/javascript"
+    );
+    assert!(resp
+        .headers()
+        .get("cache-control")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .contains("immutable"));
+    assert_eq!(test::read_body(resp).await, "console.log(1);");
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/styles.css").to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "text/css");
+    assert_eq!(resp.headers().get("cache-control").unwrap(), "no-cache");
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/missing.js").to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/static/../../Cargo.toml")
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+async fn convert_with_headers(headers: &[(&str, &str)]) -> actix_web::dev::ServiceResponse {
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        api_keys: ApiKeys::new(["first-key".to_string(), "second-key".to_string()]),
+        ..Default::default()
+    })))
+    .await;
+
+    let mut req = multipart_request(
+        "/convert?convtype=PDS",
+        multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+    );
+    for header in headers {
+        req = req.insert_header(*header);
+    }
+
+    test::call_service(&app, req.to_request()).await
+}
+
+#[actix_web::test]
+async fn convert_requires_api_key() {
+    let resp = convert_with_headers(&[]).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert!(json["detail"].as_str().unwrap().contains("Missing"));
+}
+
+#[actix_web::test]
+async fn convert_rejects_wrong_api_key() {
+    let resp = convert_with_headers(&[("x-api-key", "wrong-key")]).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert!(json["detail"].as_str().unwrap().contains("Invalid"));
+}
+
+#[actix_web::test]
+async fn convert_accepts_correct_api_key() {
+    let resp = convert_with_headers(&[("x-api-key", "second-key")]).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = convert_with_headers(&[("authorization", "Bearer first-key")]).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn health_and_ui_stay_open_with_api_keys() {
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        api_keys: ApiKeys::new(["first-key".to_string()]),
+        ..Default::default()
+    })))
+    .await;
+
+    for uri in ["/healthz", "/version", "/schema", "/"] {
+        let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn api_keys_from_file_skip_comments_and_blank_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("keys");
+    std::fs::write(&path, "# deploy keys\nfirst-key\n\n  second-key  \n").unwrap();
+
+    let keys = ApiKeys::load(vec!["first-key".to_string()], Some(&path)).unwrap();
+    let mut headers = actix_web::http::header::HeaderMap::new();
+    headers.insert(
+        actix_web::http::header::HeaderName::from_static("x-api-key"),
+        actix_web::http::header::HeaderValue::from_static("second-key"),
+    );
+
+    assert!(keys.is_enabled());
+    assert!(keys.check(&headers).is_ok());
+}
+
+const PROFILES: &str = r#"
+[[profile]]
+api_key = "acme-key"
+convtype = "PDS"
+client_name = "Acme Widgets Ltd"
+client_number = "0123456789"
+processing_centre = "00320"
+transaction_code = "200"
+payment_date = "2045/03/01"
+overridable = ["payment_date"]
+
+[[profile]]
+api_key = "globex-key"
+convtype = "PDS"
+display_name = "GLOBEX"
+client_name = "Globex Corporation"
+client_number = "0987654321"
+processing_centre = "00390"
+transaction_code = "200"
+"#;
+
+fn profile_app_config() -> ServerConfig {
+    let profiles = Profiles::parse(PROFILES).unwrap();
+
+    ServerConfig {
+        api_keys: ApiKeys::new(profiles.keys()),
+        profiles,
+        ..Default::default()
+    }
+}
+
+/// The payroll fixture with the rows a profile supplies left blank
+fn payroll_without_client() -> String {
+    include_str!("fixtures/payroll.csv")
+        .replace("Acme Widgets Ltd", "")
+        .replace("0123456789", "")
+        .replace("Processing Centre,00320", "Processing Centre,")
+}
+
+async fn convert_with_key(
+    app: &impl actix_web::dev::Service<
+        actix_http::Request,
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+    >,
+    uri: &str,
+    key: &str,
+    csv: &str,
+) -> actix_web::dev::ServiceResponse {
+    let req = multipart_request(uri, multipart_body("payroll.csv", csv))
+        .insert_header(("x-api-key", key))
+        .to_request();
+
+    test::call_service(app, req).await
+}
+
+#[actix_web::test]
+async fn profiles_supply_client_of_each_api_key() {
+    let app =
+        test::init_service(App::new().configure(server::configure(profile_app_config()))).await;
+    let csv = payroll_without_client();
+
+    for (key, client_number) in [("acme-key", "0123456789"), ("globex-key", "0987654321")] {
+        let resp = convert_with_key(&app, "/convert", key, &csv).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{}", key);
+
+        let body = test::read_body(resp).await;
+        let output = std::str::from_utf8(&body).unwrap();
+        let header = output.lines().next().unwrap();
+        let detail = output.lines().nth(1).unwrap();
+
+        assert_eq!(&header[10..20], client_number, "{}", key);
+        // Field 15 of the first segment
+        assert_eq!(&detail[164..174], client_number, "{}", key);
+    }
+}
+
+#[actix_web::test]
+async fn profiles_refuse_changing_fixed_values() {
+    let app =
+        test::init_service(App::new().configure(server::configure(profile_app_config()))).await;
+
+    // The fixture names Acme's client number, which Globex's key fixes to its own
+    let resp = convert_with_key(
+        &app,
+        "/convert",
+        "globex-key",
+        include_str!("fixtures/payroll.csv"),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["type"], "/problems/forbidden");
+    assert!(json["detail"]
+        .as_str()
+        .unwrap()
+        .contains("Client Name is fixed to Globex Corporation"));
+
+    let resp = convert_with_key(
+        &app,
+        "/convert?display_name=OTHER",
+        "globex-key",
+        &payroll_without_client(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert!(json["detail"].as_str().unwrap().contains("display_name"));
+
+    // Acme's profile lets files give their own payment date
+    let resp = convert_with_key(
+        &app,
+        "/convert",
+        "acme-key",
+        include_str!("fixtures/payroll.csv"),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn profiles_reject_unknown_overridable_names() {
+    let e =
+        Profiles::parse("[[profile]]\napi_key = \"acme-key\"\noverridable = [\"client_numbr\"]\n")
+            .unwrap_err();
+    assert!(e.contains("client_numbr"), "{}", e);
+
+    let e = Profiles::parse(
+        "[[profile]]\napi_key = \"acme-key\"\n\n[[profile]]\napi_key = \"acme-key\"\n",
+    )
+    .unwrap_err();
+    assert!(e.contains("same api_key"), "{}", e);
+}
+
+#[actix_web::test]
+async fn convert_keeps_character_split_across_chunks() {
+    let app =
+        test::init_service(App::new().configure(server::configure(ServerConfig::default()))).await;
+
+    let csv = include_str!("fixtures/payroll.csv").replace("Jane Smith", "Zoë Tremblé");
+    let body = multipart_body("payroll.csv", &csv).into_bytes();
+    let split = body.iter().position(|b| *b == 0xc3).unwrap() + 1;
+
+    let (mut sender, payload) = actix_http::h1::Payload::create(false);
+    sender.feed_data(body[..split].to_vec().into());
+
+    let (req, _) = multipart_request("/convert?convtype=PDS", String::new())
+        .insert_header(("content-length", body.len().to_string()))
+        .to_request()
+        .replace_payload(payload.into());
+
+    let rest = body[split..].to_vec();
+    actix_web::rt::spawn(async move {
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+        sender.feed_data(rest.into());
+        sender.feed_eof();
+    });
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body = test::read_body(resp).await;
+    let output = std::str::from_utf8(&body).unwrap();
+    assert!(output.contains("Zoë Tremblé"));
+    assert!(!output.contains('\u{fffd}'));
+}
+
+#[actix_web::test]
+async fn convert_rejects_invalid_utf8() {
+    let app =
+        test::init_service(App::new().configure(server::configure(ServerConfig::default()))).await;
+
+    // "Jos\xe9" as saved by a Windows-1252 spreadsheet
+    let mut body = multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")).into_bytes();
+    let at = body.windows(4).position(|w| w == b"Jane").unwrap();
+    body.splice(at..at + 4, b"Jos\xe9".iter().copied());
+
+    let req = test::TestRequest::post()
+        .uri("/convert?convtype=PDS")
+        .insert_header((
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        ))
+        .insert_header(("content-length", body.len().to_string()))
+        .set_payload(body)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let body = test::read_body(resp).await;
+    assert!(std::str::from_utf8(&body)
+        .unwrap()
+        .contains("not valid UTF-8"));
+}
+
+fn convert_from(addr: &str) -> actix_http::Request {
+    multipart_request(
+        "/convert?convtype=PDS",
+        multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+    )
+    .peer_addr(addr.parse().unwrap())
+    .to_request()
+}
+
+#[actix_web::test]
+async fn convert_rate_limited_per_client() {
+    // 10 requests a second with a burst of 2, so the bucket refills quickly
+    let config = ServerConfig {
+        rate_limiter: RateLimiter::new(600, 2),
+        ..Default::default()
+    };
+
+    // Two apps sharing one limiter, as the server's workers do
+    let first = test::init_service(App::new().configure(server::configure(config.clone()))).await;
+    let second = test::init_service(App::new().configure(server::configure(config))).await;
+
+    let resp = test::call_service(&first, convert_from("10.0.0.1:5000")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&second, convert_from("10.0.0.1:5001")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&first, convert_from("10.0.0.1:5002")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "1");
+
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["retry_after"], 1);
+
+    // Other clients have their own bucket
+    let resp = test::call_service(&first, convert_from("10.0.0.2:5000")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(150)).await;
+
+    let resp = test::call_service(&second, convert_from("10.0.0.1:5003")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn conversions_recorded_in_audit_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        api_keys: ApiKeys::new(["audit-key".to_string()]),
+        audit: Some(AuditLog::open(&dir.path().join("audit.db")).unwrap()),
+        ..Default::default()
+    })))
+    .await;
+
+    let other_client = include_str!("fixtures/payroll.csv").replace("0123456789", "5555500000");
+    for (name, csv) in [
+        (
+            "march.csv",
+            include_str!("fixtures/payroll.csv").to_string(),
+        ),
+        ("april.csv", other_client),
+    ] {
+        let req = multipart_request("/convert?convtype=PDS", multipart_body(name, &csv))
+            .insert_header(("x-api-key", "audit-key"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/audit").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let audit = |uri: &str| {
+        test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("x-api-key", "audit-key"))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, audit("/audit")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = test::read_body(resp).await;
+    let text = std::str::from_utf8(&body).unwrap();
+    // Account numbers from the fixture are never stored
+    assert!(!text.contains("987654321"));
+    assert!(!text.contains("55512345"));
+
+    let page: serde_json::Value = serde_json::from_str(text).unwrap();
+    let entries = page["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(page["next"].is_null());
+
+    // Newest first
+    assert_eq!(entries[0]["input_filename"], "april.csv");
+    let march = &entries[1];
+    assert_eq!(march["input_filename"], "march.csv");
+    assert_eq!(march["client_number"], "0123456789");
+    assert_eq!(march["outcome"], "converted");
+    assert_eq!(march["credit_count"], 3);
+    assert_eq!(march["credit_total"], 162575);
+    assert_eq!(march["debit_count"], 0);
+    assert_eq!(march["input_sha256"].as_str().unwrap().len(), 64);
+    assert!(march["actor"].as_str().unwrap().starts_with("key:"));
+    assert!(!march["actor"].as_str().unwrap().contains("audit-key"));
+
+    let resp = test::call_service(&app, audit("/audit?client=0123456789")).await;
+    let page: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(page["entries"].as_array().unwrap().len(), 1);
+
+    let resp = test::call_service(&app, audit("/audit?limit=1")).await;
+    let page: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(page["entries"][0]["input_filename"], "april.csv");
+    let next = page["next"].as_i64().unwrap();
+
+    let resp = test::call_service(&app, audit(&format!("/audit?limit=1&before={}", next))).await;
+    let page: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(page["entries"][0]["input_filename"], "march.csv");
+    assert!(page["next"].is_null());
+
+    let resp = test::call_service(&app, audit("/audit?since=2999-01-01")).await;
+    let page: serde_json::Value = test::read_body_json(resp).await;
+    assert!(page["entries"].as_array().unwrap().is_empty());
+
+    let resp = test::call_service(&app, audit("/audit?since=yesterday")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn rejected_conversion_recorded_in_audit_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        audit: Some(AuditLog::open(&dir.path().join("audit.db")).unwrap()),
+        ..Default::default()
+    })))
+    .await;
+
+    let req = multipart_request(
+        "/convert?convtype=PDS",
+        multipart_body("bad.csv", "Client Name,Acme\n"),
+    )
+    .peer_addr("10.0.0.7:4000".parse().unwrap())
+    .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/audit").to_request()).await;
+    let page: serde_json::Value = test::read_body_json(resp).await;
+    let entry = &page["entries"][0];
+
+    assert_eq!(entry["outcome"], "rejected");
+    assert_eq!(entry["actor"], "ip:10.0.0.7");
+    assert!(entry["error_count"].as_u64().unwrap() > 0);
+    assert!(entry["client_number"].is_null());
+}
+
+async fn poll_job<S>(app: &S, location: &str, headers: &[(&str, &str)]) -> serde_json::Value
+where
+    S: actix_web::dev::Service<
+        actix_http::Request,
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+    >,
+{
+    for _ in 0..500 {
+        let mut req = test::TestRequest::get().uri(location);
+        for header in headers {
+            req = req.insert_header(*header);
+        }
+
+        let resp = test::call_service(app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let job: serde_json::Value = test::read_body_json(resp).await;
+        if job["status"] != "queued" && job["status"] != "running" {
+            return job;
+        }
+
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    panic!("job at {} did not finish", location);
+}
+
+#[actix_web::test]
+async fn job_converted_in_background_and_downloaded() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        temp_dir: Some(temp_dir.path().to_path_buf()),
+        ..Default::default()
+    })))
+    .await;
+
+    let csv = include_str!("fixtures/payroll.csv");
+
+    let resp = test::call_service(
+        &app,
+        multipart_request("/jobs?convtype=PDS", multipart_body("payroll.csv", csv)).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    let location = resp
+        .headers()
+        .get("location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let submitted: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(
+        location,
+        format!("/jobs/{}", submitted["id"].as_str().unwrap())
+    );
+    assert_eq!(submitted["status"], "queued");
+
+    let job = poll_job(&app, &location, &[]).await;
+    assert_eq!(job["status"], "done");
+    assert_eq!(job["report"]["total_credit_count"], 3);
+    assert_eq!(job["report"]["total_credit_amount"], 162575);
+    assert!(job["expires_in"].as_u64().unwrap() > 0);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri(&format!("{}/download", location))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"payroll.txt\""
+    );
+    let downloaded = test::read_body(resp).await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request("/convert?convtype=PDS", multipart_body("payroll.csv", csv)).to_request(),
+    )
+    .await;
+    let converted = test::read_body(resp).await;
+
+    // Header and trailer carry the creation date, which is the same for both
+    assert_eq!(downloaded, converted);
+
+    // Only the converted file is kept while the job is available
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+}
+
+#[actix_web::test]
+async fn failed_job_reports_errors() {
+    let app = test::init_service(
+        App::new().configure(server::configure(config(UploadLimits::default()))),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/jobs?convtype=PAD",
+            multipart_body("bad.csv", "Client Name,Acme\n"),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let location = resp
+        .headers()
+        .get("location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let job = poll_job(&app, &location, &[]).await;
+    assert_eq!(job["status"], "failed");
+    assert!(!job["errors"].as_array().unwrap().is_empty());
+    assert!(job["report"].is_null());
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri(&format!("{}/download", location))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/jobs/unknown").to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn jobs_expire_and_are_removed() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        temp_dir: Some(temp_dir.path().to_path_buf()),
+        jobs: JobQueue::new(1, 4, std::time::Duration::from_millis(200)),
+        ..Default::default()
+    })))
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/jobs?convtype=PDS",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await;
+    let location = resp
+        .headers()
+        .get("location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    assert_eq!(poll_job(&app, &location, &[]).await["status"], "done");
+
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(250)).await;
+
+    for uri in [location.clone(), format!("{}/download", location)] {
+        let resp = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    // Expired jobs are swept at least once a second
+    for _ in 0..30 {
+        if std::fs::read_dir(temp_dir.path()).unwrap().count() == 0 {
+            return;
+        }
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("converted file of expired job was not removed");
+}
+
+#[actix_web::test]
+async fn jobs_private_to_submitting_key() {
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        api_keys: ApiKeys::new(["first-key".to_string(), "second-key".to_string()]),
+        ..Default::default()
+    })))
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/jobs?convtype=PDS",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .insert_header(("x-api-key", "first-key"))
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let location = resp
+        .headers()
+        .get("location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let job = poll_job(&app, &location, &[("x-api-key", "first-key")]).await;
+    assert_eq!(job["status"], "done");
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri(&location)
+            .insert_header(("x-api-key", "second-key"))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri(&location).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn zip_archive(entries: &[(&str, &str)]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, contents) in entries {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+fn unzip(data: &[u8]) -> std::collections::HashMap<String, String> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+    (0..archive.len())
+        .map(|i| {
+            let mut file = archive.by_index(i).unwrap();
+            let mut contents = String::new();
+            std::io::Read::read_to_string(&mut file, &mut contents).unwrap();
+            (file.name().to_string(), contents)
+        })
+        .collect()
+}
+
+fn zip_request(uri: &str, archive: &[u8]) -> test::TestRequest {
+    file_request(uri, "export.zip", "application/zip", archive)
+}
+
+/// A multipart upload of a file that need not be text
+fn file_request(
+    uri: &str,
+    file_name: &str,
+    content_type: &str,
+    contents: &[u8],
+) -> test::TestRequest {
+    let mut body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{f}\"\r\nContent-Type: {t}\r\n\r\n",
+        b = BOUNDARY,
+        f = file_name,
+        t = content_type
+    )
+    .into_bytes();
+    body.extend_from_slice(contents);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+
+    test::TestRequest::post()
+        .uri(uri)
+        .insert_header((
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        ))
+        .insert_header(("content-length", body.len().to_string()))
+        .set_payload(body)
+}
+
+fn payroll_archive() -> Vec<u8> {
+    let payroll = include_str!("fixtures/payroll.csv");
+    let invalid = payroll.replace("$300.50", "three hundred");
+
+    zip_archive(&[
+        ("payroll.csv", payroll),
+        ("march/invalid.csv", &invalid),
+        ("notes.md", "not converted"),
+    ])
+}
+
+fn lib_convert(csv: &str) -> String {
+    csvconv::csv::convert_to_cpa005_with_options(
+        csv.to_string(),
+        lib::types::RecordType::Credit,
+        &csvconv::options::ConversionOptions::default(),
+    )
+    .unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[actix_web::test]
+async fn zip_entries_converted_with_failures_reported() {
+    let app =
+        test::init_service(App::new().configure(server::configure(ServerConfig::default()))).await;
+
+    let resp = test::call_service(
+        &app,
+        zip_request("/convert/zip?convtype=PDS", &payroll_archive()).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/zip"
+    );
+    assert_eq!(
+        resp.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"export-cpa005.zip\""
+    );
+
+    let files = unzip(&test::read_body(resp).await);
+    let mut names: Vec<&String> = files.keys().collect();
+    names.sort();
+    assert_eq!(names, ["payroll.txt", "report.json"]);
+
+    let expected = lib_convert(include_str!("fixtures/payroll.csv"));
+    assert_eq!(files["payroll.txt"], expected);
+
+    let report: serde_json::Value = serde_json::from_str(&files["report.json"]).unwrap();
+    assert_eq!(report.as_array().unwrap().len(), 2);
+    assert_eq!(report[0]["input"], "payroll.csv");
+    assert_eq!(report[0]["output"], "payroll.txt");
+    assert_eq!(report[0]["ok"], true);
+    assert_eq!(report[1]["input"], "march/invalid.csv");
+    assert_eq!(report[1]["ok"], false);
+    assert!(report[1]["output"].is_null());
+    assert_eq!(
+        report[1]["errors"][0],
+        "Failed to parse payment amount: three hundred"
+    );
+}
+
+#[actix_web::test]
+async fn strict_zip_rejected_when_any_entry_fails() {
+    let app =
+        test::init_service(App::new().configure(server::configure(ServerConfig::default()))).await;
+
+    let resp = test::call_service(
+        &app,
+        zip_request("/convert/zip?convtype=PDS&strict=true", &payroll_archive()).to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::BAD_REQUEST).await;
+
+    assert_eq!(json["type"], "/problems/invalid-file");
+    assert_eq!(
+        json["errors"][0],
+        "march/invalid.csv: Failed to parse payment amount: three hundred"
+    );
+}
+
+#[actix_web::test]
+async fn zip_entry_outside_archive_rejected() {
+    let app =
+        test::init_service(App::new().configure(server::configure(ServerConfig::default()))).await;
+
+    let archive = zip_archive(&[("../payroll.csv", include_str!("fixtures/payroll.csv"))]);
+
+    let resp = test::call_service(
+        &app,
+        zip_request("/convert/zip?convtype=PDS", &archive).to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::BAD_REQUEST).await;
+
+    assert_eq!(
+        json["detail"],
+        "Archive entry \"../payroll.csv\" has a path outside of the archive"
+    );
+}
+
+#[actix_web::test]
+async fn zip_limits_enforced_on_decompressed_entries() {
+    let limited = |zip_limits: ZipLimits| ServerConfig {
+        zip_limits,
+        ..Default::default()
+    };
+    let payroll = include_str!("fixtures/payroll.csv");
+    let archive = zip_archive(&[("a.csv", payroll), ("b.csv", payroll)]);
+
+    let app = test::init_service(App::new().configure(server::configure(limited(ZipLimits {
+        max_entries: 1,
+        ..Default::default()
+    }))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        zip_request("/convert/zip?convtype=PDS", &archive).to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::BAD_REQUEST).await;
+    assert_eq!(json["detail"], "Archive has more than 1 CSV files");
+
+    let app = test::init_service(App::new().configure(server::configure(limited(ZipLimits {
+        max_entry_size: payroll.len() - 1,
+        ..Default::default()
+    }))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        zip_request("/convert/zip?convtype=PDS", &archive).to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::PAYLOAD_TOO_LARGE).await;
+    assert_eq!(json["received"], payroll.len());
+
+    let app = test::init_service(App::new().configure(server::configure(limited(ZipLimits {
+        max_total_size: payroll.len() + 1,
+        ..Default::default()
+    }))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        zip_request("/convert/zip?convtype=PDS", &archive).to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::PAYLOAD_TOO_LARGE).await;
+    assert_eq!(json["limit"], payroll.len() + 1);
+    assert_eq!(json["received"], 2 * payroll.len());
+}
+
+async fn store_payroll<S>(app: &S) -> serde_json::Value
+where
+    S: actix_web::dev::Service<
+        actix_http::Request,
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+    >,
+{
+    let resp = test::call_service(
+        app,
+        multipart_request(
+            "/convert?convtype=PDS&store=true",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    test::read_body_json(resp).await
+}
+
+#[actix_web::test]
+async fn stored_file_downloaded_once() {
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        downloads: Some(DownloadStore::new(std::time::Duration::from_secs(60))),
+        ..Default::default()
+    })))
+    .await;
+
+    let link = store_payroll(&app).await;
+    assert_eq!(link["filename"], "payroll.txt");
+    assert_eq!(link["expires_in"], 60);
+
+    let url = link["url"].as_str().unwrap();
+    assert!(url.starts_with("/downloads/"));
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri(url).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"payroll.txt\""
+    );
+    let body = test::read_body(resp).await;
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        lib_convert(include_str!("fixtures/payroll.csv"))
+    );
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri(url).to_request()).await;
+    problem(resp, StatusCode::NOT_FOUND).await;
+}
+
+#[actix_web::test]
+async fn stored_files_expire_and_are_removed() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        temp_dir: Some(temp_dir.path().to_path_buf()),
+        downloads: Some(DownloadStore::new(std::time::Duration::ZERO)),
+        ..Default::default()
+    })))
+    .await;
+
+    // Fetched once expired, before the sweeper has removed it
+    let link = store_payroll(&app).await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri(link["url"].as_str().unwrap())
+            .to_request(),
+    )
+    .await;
+    assert!(
+        [StatusCode::GONE, StatusCode::NOT_FOUND].contains(&resp.status()),
+        "{}",
+        resp.status()
+    );
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+    // Never fetched, left for the sweeper
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        temp_dir: Some(temp_dir.path().to_path_buf()),
+        downloads: Some(DownloadStore::new(std::time::Duration::from_secs(1))),
+        ..Default::default()
+    })))
+    .await;
+
+    let link = store_payroll(&app).await;
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+    actix_web::rt::time::sleep(std::time::Duration::from_secs(3)).await;
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri(link["url"].as_str().unwrap())
+            .to_request(),
+    )
+    .await;
+    problem(resp, StatusCode::NOT_FOUND).await;
+}
+
+#[actix_web::test]
+async fn store_refused_unless_enabled() {
+    let app =
+        test::init_service(App::new().configure(server::configure(ServerConfig::default()))).await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS&store=true",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::BAD_REQUEST).await;
+    assert_eq!(
+        json["detail"],
+        "This server does not keep converted files for download"
+    );
+}
+
+type Events = std::sync::Arc<std::sync::Mutex<Vec<std::collections::HashMap<String, String>>>>;
+
+/// Records the fields of every event, formatted with `Debug`
+#[derive(Clone, Default)]
+struct CaptureEvents(Events);
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for CaptureEvents {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        struct Fields<'a>(&'a mut std::collections::HashMap<String, String>);
+
+        impl tracing::field::Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                self.0
+                    .insert(field.name().to_string(), format!("{:?}", value));
+            }
+        }
+
+        let mut fields = std::collections::HashMap::new();
+        event.record(&mut Fields(&mut fields));
+        self.0.lock().unwrap().push(fields);
+    }
+}
+
+#[actix_web::test]
+async fn requests_logged_with_conversion_outcome() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let capture = CaptureEvents::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+
+    let app = test::init_service(App::new().wrap(server::logging::RequestLog).configure(
+        server::configure(ServerConfig {
+            api_keys: ApiKeys::new(["first-key".to_string()]),
+            ..Default::default()
+        }),
+    ))
+    .await;
+
+    let body = multipart_body("payroll.csv", include_str!("fixtures/payroll.csv"));
+    let body_len = body.len();
+    let resp = test::call_service(
+        &app,
+        multipart_request("/convert?convtype=PDS", body)
+            .insert_header(("x-api-key", "first-key"))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let invalid = include_str!("fixtures/payroll.csv").replace("$300.50", "three hundred");
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS",
+            multipart_body("payroll.csv", &invalid),
+        )
+        .insert_header(("x-api-key", "first-key"))
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/healthz").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let events = capture.0.lock().unwrap();
+    assert_eq!(events.len(), 3);
+
+    let converted = &events[0];
+    assert_eq!(converted["message"], "request");
+    assert_eq!(converted["method"], "\"POST\"");
+    assert_eq!(converted["path"], "\"/convert\"");
+    assert_eq!(converted["status"], "200");
+    assert_eq!(converted["payload_size"], body_len.to_string());
+    assert_eq!(
+        converted["key_id"],
+        format!("{:?}", server::audit::key_id("first-key"))
+    );
+    assert_eq!(converted["rows"], "3");
+    assert_eq!(converted["errors"], "0");
+    assert!(converted.contains_key("duration_ms"));
+
+    let rejected = &events[1];
+    assert_eq!(rejected["status"], "400");
+    assert_eq!(rejected["rows"], "0");
+    assert_eq!(rejected["errors"], "1");
+
+    // Neither a key nor an outcome for requests that have none
+    let health = &events[2];
+    assert_eq!(health["path"], "\"/healthz\"");
+    assert!(!health.contains_key("key_id"));
+    assert!(!health.contains_key("rows"));
+}
+
+#[actix_web::test]
+async fn concurrent_conversions_all_correct() {
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        conversions: ConversionPool::new(2, 16, DEFAULT_CONVERSION_TIMEOUT),
+        ..Default::default()
+    })))
+    .await;
+
+    let conversions = (0..8).map(|_| {
+        test::call_service(
+            &app,
+            multipart_request(
+                "/convert?convtype=PDS",
+                multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+            )
+            .to_request(),
+        )
+    });
+    let health = test::call_service(&app, test::TestRequest::get().uri("/healthz").to_request());
+
+    let (responses, health) = futures::join!(futures::future::join_all(conversions), health);
+    assert_eq!(health.status(), StatusCode::OK);
+
+    let expected = lib_convert(include_str!("fixtures/payroll.csv"));
+    for resp in responses {
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = test::read_body(resp).await;
+        assert_eq!(std::str::from_utf8(&body).unwrap(), expected);
+    }
+}
+
+/// Occupies the only worker of `pool` until the returned sender is dropped
+async fn occupy(
+    pool: &ConversionPool,
+) -> (
+    std::sync::mpsc::Sender<()>,
+    impl std::future::Future<Output = Result<(), server::problem::ApiError>> + '_,
+) {
+    let (started, wait_started) = std::sync::mpsc::channel();
+    let (release, wait_release) = std::sync::mpsc::channel::<()>();
+
+    let mut running = Box::pin(pool.run(move || {
+        started.send(()).unwrap();
+        let _ = wait_release.recv();
+    }));
+    assert!(futures::poll!(&mut running).is_pending());
+    wait_started
+        .recv_timeout(std::time::Duration::from_secs(5))
+        .unwrap();
+
+    (release, running)
+}
+
+#[actix_web::test]
+async fn conversions_refused_while_pool_busy() {
+    let pool = ConversionPool::new(1, 1, DEFAULT_CONVERSION_TIMEOUT);
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        conversions: pool.clone(),
+        ..Default::default()
+    })))
+    .await;
+
+    let (release, running) = occupy(&pool).await;
+    let mut queued = Box::pin(pool.run(|| ()));
+    assert!(futures::poll!(&mut queued).is_pending());
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "5");
+    let json = problem(resp, StatusCode::SERVICE_UNAVAILABLE).await;
+    assert!(json["detail"]
+        .as_str()
+        .unwrap()
+        .contains("Too many conversions"));
+
+    // Health checks do not wait on conversions
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/healthz").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    drop(release);
+    running.await.unwrap();
+    queued.await.unwrap();
+}
+
+#[actix_web::test]
+async fn abandoned_conversions_not_run() {
+    let pool = ConversionPool::new(1, 1, DEFAULT_CONVERSION_TIMEOUT);
+    let (release, running) = occupy(&pool).await;
+
+    let ran = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let flag = ran.clone();
+    let mut abandoned = Box::pin(pool.run(move || {
+        flag.store(true, std::sync::atomic::Ordering::SeqCst);
+    }));
+    assert!(futures::poll!(&mut abandoned).is_pending());
+
+    // The queue is full while the abandoned conversion waits
+    assert!(pool.run(|| ()).await.is_err());
+
+    // As happens when the client disconnects
+    drop(abandoned);
+    drop(release);
+    running.await.unwrap();
+
+    // Run after the abandoned conversion has been taken off the queue
+    assert_eq!(pool.run(|| 42).await.unwrap(), 42);
+    assert!(!ran.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[actix_web::test]
+async fn panicking_conversion_fails_only_its_request() {
+    let pool = ConversionPool::new(1, 1, DEFAULT_CONVERSION_TIMEOUT);
+
+    assert!(pool.run(|| panic!("conversion bug")).await.is_err());
+    assert_eq!(pool.run(|| 42).await.unwrap(), 42);
+}
+
+fn workbook() -> Vec<u8> {
+    zip_archive(&[
+        ("[Content_Types].xml", "<Types/>"),
+        ("xl/workbook.xml", "<workbook/>"),
+    ])
+}
+
+#[actix_web::test]
+async fn workbook_upload_refused() {
+    let app =
+        test::init_service(App::new().configure(server::configure(ServerConfig::default()))).await;
+
+    // Told apart by its contents, whatever it is declared as
+    for content_type in [
+        "application/octet-stream",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ] {
+        let resp = test::call_service(
+            &app,
+            file_request(
+                "/convert?convtype=PDS",
+                "payroll.xlsx",
+                content_type,
+                &workbook(),
+            )
+            .to_request(),
+        )
+        .await;
+
+        let json = problem(resp, StatusCode::UNSUPPORTED_MEDIA_TYPE).await;
+        assert_eq!(json["detected"], "workbook", "{}", content_type);
+        assert_eq!(json["errors"].as_array().unwrap().len(), 1);
+        assert!(json["detail"]
+            .as_str()
+            .unwrap()
+            .contains("Excel workbook, export it as CSV"));
+    }
+
+    let archive = zip_archive(&[("payroll.csv", include_str!("fixtures/payroll.csv"))]);
+    let resp = test::call_service(
+        &app,
+        file_request("/convert?convtype=PDS", "payroll.csv", "text/csv", &archive).to_request(),
+    )
+    .await;
+    assert_eq!(
+        problem(resp, StatusCode::UNSUPPORTED_MEDIA_TYPE).await["detected"],
+        "zip"
+    );
+}
+
+#[actix_web::test]
+async fn pdf_upload_refused() {
+    let app =
+        test::init_service(App::new().configure(server::configure(ServerConfig::default()))).await;
+
+    let pdf = b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n1 0 obj\n<< /Type /Catalog >>\nendobj\n";
+    let resp = test::call_service(
+        &app,
+        file_request("/convert?convtype=PDS", "payroll.csv", "text/csv", pdf).to_request(),
+    )
+    .await;
+
+    let json = problem(resp, StatusCode::UNSUPPORTED_MEDIA_TYPE).await;
+    assert_eq!(json["detected"], "pdf");
+    assert!(json["detail"].as_str().unwrap().contains("PDF document"));
+}
+
+#[actix_web::test]
+async fn csv_with_byte_order_mark_converted() {
+    let app =
+        test::init_service(App::new().configure(server::configure(ServerConfig::default()))).await;
+
+    let csv = format!("\u{feff}{}", include_str!("fixtures/payroll.csv"));
+    let resp = test::call_service(
+        &app,
+        file_request(
+            "/convert?convtype=PDS",
+            "payroll.csv",
+            "application/vnd.ms-excel",
+            csv.as_bytes(),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body = test::read_body(resp).await;
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        lib_convert(include_str!("fixtures/payroll.csv"))
+    );
+}
+
+#[actix_web::test]
+async fn slow_conversion_times_out() {
+    use actix_web::ResponseError;
+
+    let pool = ConversionPool::new(1, 1, std::time::Duration::from_millis(100));
+
+    let slow = pool.run(|| {
+        csvconv::csv::convert_reader_with_hook(
+            include_str!("fixtures/payroll.csv").as_bytes(),
+            lib::types::RecordType::Credit,
+            &Default::default(),
+            Some(&|_| std::thread::sleep(std::time::Duration::from_millis(200))),
+        )
+        .is_ok()
+    });
+
+    let e = slow.await.err().unwrap();
+    assert!(matches!(e, server::problem::ApiError::Timeout { .. }));
+    assert_eq!(e.status_code(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(e.to_string().contains("/jobs"));
+}
+
+#[actix_web::test]
+async fn timed_out_upload_removed() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let pool = ConversionPool::new(1, 1, std::time::Duration::from_millis(100));
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        temp_dir: Some(temp_dir.path().to_path_buf()),
+        conversions: pool.clone(),
+        ..Default::default()
+    })))
+    .await;
+
+    let (release, running) = occupy(&pool).await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/convert?convtype=PDS",
+            multipart_body("payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+        .to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::GATEWAY_TIMEOUT).await;
+    assert_eq!(json["type"], "/problems/timeout");
+    assert!(json["detail"].as_str().unwrap().contains("/jobs"));
+
+    // Whether the occupying conversion also timed out depends on how soon
+    // it finishes once released
+    drop(release);
+    let _ = running.await;
+
+    // The upload is removed once the worker takes the abandoned conversion
+    // off the queue, which may take longer than the pool's short timeout
+    let removed = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while std::fs::read_dir(temp_dir.path()).unwrap().count() > 0 {
+        assert!(std::time::Instant::now() < removed, "upload not removed");
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+}
+
+/// Serves `routes` over HTTP on a local port until the test ends, answering
+/// a request for each path with the raw response given for it, or 404 for
+/// paths without one. Returns the address it is served at.
+fn mock_server(routes: Vec<(&'static str, String)>) -> String {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = format!("http://{}", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let path = request_line.split(' ').nth(1).unwrap_or("").to_string();
+
+            // The headers of the request go unread
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            let resp = routes
+                .iter()
+                .find(|(p, _)| *p == path)
+                .map(|(_, resp)| resp.clone())
+                .unwrap_or_else(|| {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                });
+            let _ = stream.write_all(resp.as_bytes());
+        }
+    });
+
+    addr
+}
+
+fn ok_response(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+fn redirect_response(location: &str) -> String {
+    format!(
+        "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        location
+    )
+}
+
+fn url_config(allowed: &[&str], max_redirects: usize) -> ServerConfig {
+    ServerConfig {
+        fetcher: UrlFetcher::new(
+            AllowedHosts::new(allowed.iter().map(|h| h.to_string()).collect()),
+            std::time::Duration::from_secs(5),
+            max_redirects,
+        ),
+        ..Default::default()
+    }
+}
+
+fn url_request(url: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/convert/url")
+        .set_json(serde_json::json!({ "url": url, "convtype": "PDS" }))
+}
+
+#[actix_web::test]
+async fn url_converted() {
+    let addr = mock_server(vec![(
+        "/exports/payroll.csv?signature=secret",
+        ok_response(include_str!("fixtures/payroll.csv")),
+    )]);
+    let app =
+        test::init_service(App::new().configure(server::configure(url_config(&["127.0.0.1"], 3))))
+            .await;
+
+    let resp = test::call_service(
+        &app,
+        url_request(&format!("{}/exports/payroll.csv?signature=secret", addr)).to_request(),
+    )
+    .await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let disposition = resp.headers().get("content-disposition").unwrap();
+    assert!(disposition.to_str().unwrap().contains("payroll"));
+    let body = test::read_body(resp).await;
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        lib_convert(include_str!("fixtures/payroll.csv"))
+    );
+}
+
+#[actix_web::test]
+async fn url_conversion_disabled_by_default() {
+    let app = test::init_service(App::new().configure(server::configure(Default::default()))).await;
+
+    let resp = test::call_service(
+        &app,
+        url_request("http://127.0.0.1/payroll.csv").to_request(),
+    )
+    .await;
+    problem(resp, StatusCode::NOT_FOUND).await;
+}
+
+#[actix_web::test]
+async fn url_host_must_be_allowed() {
+    let app = test::init_service(
+        App::new().configure(server::configure(url_config(&["*.example.com"], 3))),
+    )
+    .await;
+
+    for url in [
+        "http://127.0.0.1/payroll.csv",
+        "https://example.com/payroll.csv",
+        "file:///etc/passwd",
+    ] {
+        let resp = test::call_service(&app, url_request(url).to_request()).await;
+        let json = problem(resp, StatusCode::BAD_REQUEST).await;
+        assert!(json["detail"]
+            .as_str()
+            .unwrap()
+            .starts_with("Files may not be fetched from"));
+    }
+}
+
+#[actix_web::test]
+async fn url_fetch_failure_told_apart_from_conversion_failure() {
+    let addr = mock_server(vec![
+        ("/invalid.csv", ok_response("not,a,payment\n")),
+        ("/moved.csv", redirect_response("/elsewhere.csv")),
+        (
+            "/away.csv",
+            redirect_response("http://localhost/payroll.csv"),
+        ),
+    ]);
+    let app =
+        test::init_service(App::new().configure(server::configure(url_config(&["127.0.0.1"], 3))))
+            .await;
+
+    let resp = test::call_service(
+        &app,
+        url_request(&format!("{}/missing.csv?signature=secret", addr)).to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::BAD_GATEWAY).await;
+    assert_eq!(json["type"], "/problems/fetch-failed");
+    assert_eq!(
+        json["detail"],
+        "127.0.0.1 answered 404 Not Found for the file"
+    );
+
+    // Redirects are followed, but only to allowed hosts
+    let resp = test::call_service(
+        &app,
+        url_request(&format!("{}/moved.csv", addr)).to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::BAD_GATEWAY).await;
+    assert!(json["detail"].as_str().unwrap().contains("404 Not Found"));
+
+    let resp = test::call_service(
+        &app,
+        url_request(&format!("{}/away.csv", addr)).to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::BAD_GATEWAY).await;
+    assert_eq!(
+        json["detail"],
+        "Could not fetch the file from 127.0.0.1: redirected to localhost, which is not allowed"
+    );
+
+    let resp = test::call_service(
+        &app,
+        url_request(&format!("{}/invalid.csv", addr)).to_request(),
+    )
+    .await;
+    let json = problem(resp, StatusCode::BAD_REQUEST).await;
+    assert_eq!(json["type"], "/problems/invalid-file");
+}
+
+#[actix_web::test]
+async fn url_redirects_and_size_limited() {
+    let addr = mock_server(vec![
+        ("/one.csv", redirect_response("/two.csv")),
+        ("/two.csv", redirect_response("/payroll.csv")),
+        (
+            "/payroll.csv",
+            ok_response(include_str!("fixtures/payroll.csv")),
+        ),
+    ]);
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        limits: UploadLimits::new(100_000, 64),
+        ..url_config(&["127.0.0.1"], 1)
+    })))
+    .await;
+
+    let resp =
+        test::call_service(&app, url_request(&format!("{}/one.csv", addr)).to_request()).await;
+    let json = problem(resp, StatusCode::BAD_GATEWAY).await;
+    assert!(json["detail"]
+        .as_str()
+        .unwrap()
+        .contains("more than 1 redirects"));
+
+    let resp =
+        test::call_service(&app, url_request(&format!("{}/two.csv", addr)).to_request()).await;
+    let json = problem(resp, StatusCode::PAYLOAD_TOO_LARGE).await;
+    assert_eq!(json["limit"], 64);
+}
+
+#[actix_web::test]
+async fn simple_form_served_without_scripts() {
+    let app = test::init_service(App::new().configure(server::configure(Default::default()))).await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/simple").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/html; charset=utf-8"
+    );
+
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains(r#"<form method="post" action="/simple" enctype="multipart/form-data">"#));
+    assert!(body.contains(r#"<input type="file" name="file""#));
+    assert!(body.contains(r#"<select name="convtype">"#));
+    assert!(!body.contains("<script"));
+}
+
+#[actix_web::test]
+async fn simple_form_converts_file() {
+    let app = test::init_service(App::new().configure(server::configure(Default::default()))).await;
+
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/simple",
+            multipart_form(
+                &[("convtype", "PDS")],
+                "payroll.csv",
+                include_str!("fixtures/payroll.csv"),
+            ),
+        )
+        .to_request(),
+    )
+    .await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"payroll.txt\""
+    );
+    let body = test::read_body(resp).await;
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        lib_convert(include_str!("fixtures/payroll.csv"))
+    );
+}
+
+#[actix_web::test]
+async fn simple_form_takes_api_key_as_field() {
+    let app = test::init_service(App::new().configure(server::configure(ServerConfig {
+        api_keys: ApiKeys::new(["first-key".to_string()]),
+        ..Default::default()
+    })))
+    .await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/simple").to_request()).await;
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains(r#"<input type="password" name="api_key""#));
+
+    let post = |fields: &[(&str, &str)]| {
+        multipart_request(
+            "/simple",
+            multipart_form(fields, "payroll.csv", include_str!("fixtures/payroll.csv")),
+        )
+    };
+
+    for (fields, message) in [
+        (&[("convtype", "PDS")][..], "Enter the API key"),
+        (
+            &[("api_key", "wrong-key"), ("convtype", "PDS")][..],
+            "Invalid API key",
+        ),
+    ] {
+        let resp = test::call_service(&app, post(fields).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "text/html; charset=utf-8"
+        );
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains(message), "{}", body);
+    }
+
+    let resp = test::call_service(
+        &app,
+        post(&[("api_key", "first-key"), ("convtype", "PDS")]).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Scripts and other clients may still send it in a header
+    let resp = test::call_service(
+        &app,
+        post(&[("convtype", "PDS")])
+            .insert_header(("x-api-key", "first-key"))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn simple_form_lists_errors_by_row() {
+    let app = test::init_service(App::new().configure(server::configure(Default::default()))).await;
+
+    let csv = include_str!("fixtures/payroll.csv").replace("$300.50", "<b>");
+    let resp = test::call_service(
+        &app,
+        multipart_request(
+            "/simple",
+            multipart_form(&[("convtype", "PDS")], "payroll.csv", &csv),
+        )
+        .to_request(),
+    )
+    .await;
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/html; charset=utf-8"
+    );
+
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains("The file has 1 error."), "{}", body);
+    // The row of the spreadsheet, with the amount escaped
+    assert!(body.contains(
+        "<tr><td>9</td><td>Error</td><td>Failed to parse payment amount: &lt;b&gt;</td></tr>"
+    ));
+    assert!(!body.contains("<script"));
+}